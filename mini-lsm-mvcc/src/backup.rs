@@ -0,0 +1,409 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An incremental backup engine. Every backup is a consistent view of a database, exported the
+//! same way as a checkpoint. The SSTs and value log files are immutable and never reuse their
+//! ids, so they are stored once in the `shared` directory by their names and sizes, and referenced
+//! by every backup that contains them, while the WALs and the compacted manifest of a backup are
+//! stored in its own directory under `private`. A backup only exists once its file list, with the
+//! crc32 checksum of every file, is written to `meta`.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail, ensure};
+use serde::{Deserialize, Serialize};
+
+use crate::checkpoint::ExportedFileKind;
+use crate::comparator::Comparator;
+use crate::lsm_storage::MiniLsm;
+use crate::manifest::Manifest;
+use crate::salvage;
+use crate::table::{FileObject, SsTable};
+use crate::ttl;
+
+const SHARED_DIR: &str = "shared";
+const PRIVATE_DIR: &str = "private";
+const META_DIR: &str = "meta";
+/// The file that holds the id of the next backup, so that the ids of deleted backups are not
+/// reused.
+const NEXT_ID_FILE: &str = "NEXT_ID";
+
+#[derive(Clone, Serialize, Deserialize)]
+struct BackupFile {
+    /// The name of the file in the database.
+    name: String,
+    /// The path of the file relative to the backup directory.
+    path: String,
+    checksum: u32,
+    size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupMeta {
+    /// The time the backup was created, in milliseconds since the Unix epoch.
+    timestamp: u64,
+    files: Vec<BackupFile>,
+}
+
+/// Describes a backup in the backup directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupInfo {
+    pub id: u32,
+    /// The time the backup was created, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The total size of the files of the backup, including the files shared with other backups.
+    pub size: u64,
+    pub num_files: usize,
+}
+
+/// A file of a backup, taken while the database does not change.
+enum PinnedFile {
+    /// A shared file of an earlier backup.
+    Shared(BackupFile),
+    /// A file to copy to `path` in the backup directory. It is kept open, so that it can still be
+    /// read after the database deletes it, and only its first `size` bytes are copied, as a WAL may
+    /// be appended to in the meantime.
+    Open {
+        name: String,
+        path: String,
+        file: File,
+        size: u64,
+    },
+}
+
+/// Stores successive backups of a database in a backup directory.
+pub struct BackupEngine {
+    path: PathBuf,
+}
+
+impl BackupEngine {
+    /// Open the backup directory, creating it if it does not exist. The files left behind by the
+    /// backups that did not finish are removed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        for dir in [SHARED_DIR, PRIVATE_DIR, META_DIR] {
+            std::fs::create_dir_all(path.join(dir)).context("failed to create backup dir")?;
+        }
+        let engine = Self {
+            path: path.to_path_buf(),
+        };
+        engine.purge()?;
+        Ok(engine)
+    }
+
+    fn meta_path(&self, id: u32) -> PathBuf {
+        self.path.join(META_DIR).join(id.to_string())
+    }
+
+    fn private_dir(&self, id: u32) -> PathBuf {
+        self.path.join(PRIVATE_DIR).join(id.to_string())
+    }
+
+    /// Take the id of a new backup. The next id is persisted before the id is used, so that an id
+    /// is never given to two backups, even if the first one is deleted or does not finish.
+    fn take_next_id(&self) -> Result<u32> {
+        let path = self.path.join(NEXT_ID_FILE);
+        let id = if path.exists() {
+            std::fs::read_to_string(&path)?
+                .trim()
+                .parse::<u32>()
+                .with_context(|| format!("invalid {NEXT_ID_FILE} file"))?
+        } else {
+            // The backup directories created before the counter continue after the last backup.
+            match self.backup_ids()?.last() {
+                Some(id) => id.checked_add(1).context("too many backups")?,
+                None => 1,
+            }
+        };
+        let next_id = id.checked_add(1).context("too many backups")?;
+        write_file(&path, next_id.to_string().as_bytes())?;
+        File::open(&self.path)?.sync_all()?;
+        Ok(id)
+    }
+
+    /// The shared files of the finished backups by their names in the database and sizes.
+    fn shared_files(&self) -> Result<HashMap<(String, u64), BackupFile>> {
+        let mut shared = HashMap::new();
+        for id in self.backup_ids()? {
+            for file in self.read_meta(id)?.files {
+                if file.path.starts_with(SHARED_DIR) {
+                    shared.insert((file.name.clone(), file.size), file);
+                }
+            }
+        }
+        Ok(shared)
+    }
+
+    /// The ids of the finished backups in ascending order.
+    fn backup_ids(&self) -> Result<Vec<u32>> {
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(self.path.join(META_DIR))? {
+            if let Some(id) = entry?
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<u32>().ok())
+            {
+                ids.push(id);
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn read_meta(&self, id: u32) -> Result<BackupMeta> {
+        let data = std::fs::read(self.meta_path(id))
+            .with_context(|| format!("backup {id} does not exist"))?;
+        serde_json::from_slice(&data).with_context(|| format!("invalid meta file of backup {id}"))
+    }
+
+    /// Back up the database, returning the id of the new backup. Only the SSTs and value log files
+    /// that are not in an earlier backup are copied. Flushes and compactions of the database only
+    /// wait until the files are opened, and the files are copied afterwards.
+    pub fn create_backup(&self, storage: &MiniLsm) -> Result<u32> {
+        let id = self.take_next_id()?;
+        let private_dir = self.private_dir(id);
+        if private_dir.exists() {
+            std::fs::remove_dir_all(&private_dir)?;
+        }
+        std::fs::create_dir_all(&private_dir)?;
+
+        let shared_files = self.shared_files()?;
+        let mut pinned = Vec::new();
+        let records = storage.inner.export_files(|path, name, kind| {
+            let file = File::open(path).with_context(|| format!("failed to open {name}"))?;
+            let size = file.metadata()?.len();
+            let path = match kind {
+                ExportedFileKind::Immutable => {
+                    // The files are never rewritten under the same name, so a file of the same
+                    // name and size is the same file.
+                    if let Some(shared) = shared_files.get(&(name.to_string(), size)) {
+                        pinned.push(PinnedFile::Shared(shared.clone()));
+                        return Ok(());
+                    }
+                    let (stem, extension) = name.split_once('.').unwrap_or((name, ""));
+                    format!("{SHARED_DIR}/{stem}_{size}.{extension}")
+                }
+                ExportedFileKind::Wal => format!("{PRIVATE_DIR}/{id}/{name}"),
+            };
+            pinned.push(PinnedFile::Open {
+                name: name.to_string(),
+                path,
+                file,
+                size,
+            });
+            Ok(())
+        })?;
+
+        let comparator = storage.inner.options.comparator;
+        let mut files = Vec::with_capacity(pinned.len() + 1);
+        for file in pinned {
+            files.push(match file {
+                PinnedFile::Shared(file) => file,
+                PinnedFile::Open {
+                    name,
+                    path,
+                    file,
+                    size,
+                } => self.copy_file(name, path, file, size, comparator)?,
+            });
+        }
+
+        let manifest = Manifest::create(private_dir.join("MANIFEST"))?;
+        for record in records {
+            manifest.add_record_when_init(record)?;
+        }
+        drop(manifest);
+        let data = std::fs::read(private_dir.join("MANIFEST"))?;
+        files.push(BackupFile {
+            name: "MANIFEST".to_string(),
+            path: format!("{PRIVATE_DIR}/{id}/MANIFEST"),
+            checksum: crc32fast::hash(&data),
+            size: data.len() as u64,
+        });
+        File::open(&private_dir)?.sync_all()?;
+        File::open(self.path.join(SHARED_DIR))?.sync_all()?;
+
+        let meta = BackupMeta {
+            timestamp: ttl::now_millis(),
+            files,
+        };
+        write_file(&self.meta_path(id), &serde_json::to_vec(&meta)?)?;
+        File::open(self.path.join(META_DIR))?.sync_all()?;
+        Ok(id)
+    }
+
+    /// Copy the first `size` bytes of a file of the database to `path` in the backup directory. The
+    /// blocks of an SST are checked against their checksums once it is copied, so that a corrupted
+    /// SST is not shared with the later backups.
+    fn copy_file(
+        &self,
+        name: String,
+        path: String,
+        file: File,
+        size: u64,
+        comparator: &'static dyn Comparator,
+    ) -> Result<BackupFile> {
+        let mut data = Vec::with_capacity(size as usize);
+        file.take(size)
+            .read_to_end(&mut data)
+            .with_context(|| format!("failed to read {name}"))?;
+        ensure!(
+            data.len() as u64 == size,
+            "{name} has {} bytes, expected {size}",
+            data.len()
+        );
+        let dest = self.path.join(&path);
+        write_file(&dest, &data)?;
+        if name.ends_with(".sst") {
+            let table =
+                SsTable::open_with_comparator(0, None, FileObject::open(&dest)?, comparator)
+                    .and_then(|table| salvage::verify_table(&table));
+            if let Err(e) = table {
+                std::fs::remove_file(&dest)?;
+                return Err(e.context(format!("{name} is corrupted")));
+            }
+        }
+        Ok(BackupFile {
+            name,
+            path,
+            checksum: crc32fast::hash(&data),
+            size,
+        })
+    }
+
+    /// List the backups in ascending order of their ids.
+    pub fn backups(&self) -> Result<Vec<BackupInfo>> {
+        let mut backups = Vec::new();
+        for id in self.backup_ids()? {
+            let meta = self.read_meta(id)?;
+            backups.push(BackupInfo {
+                id,
+                timestamp: meta.timestamp,
+                size: meta.files.iter().map(|file| file.size).sum(),
+                num_files: meta.files.len(),
+            });
+        }
+        Ok(backups)
+    }
+
+    /// Read a file of a backup, checking its size and checksum.
+    fn read_file(&self, id: u32, file: &BackupFile) -> Result<Vec<u8>> {
+        let data = std::fs::read(self.path.join(&file.path))
+            .with_context(|| format!("backup {id}: failed to read {}", file.path))?;
+        ensure!(
+            data.len() as u64 == file.size,
+            "backup {id}: {} has {} bytes, expected {}",
+            file.path,
+            data.len(),
+            file.size
+        );
+        ensure!(
+            crc32fast::hash(&data) == file.checksum,
+            "backup {id}: checksum mismatched in {}",
+            file.path
+        );
+        Ok(data)
+    }
+
+    /// Check that every file of a backup exists with the size and checksum it was backed up with.
+    pub fn verify_backup(&self, id: u32) -> Result<()> {
+        for file in self.read_meta(id)?.files {
+            self.read_file(id, &file)?;
+        }
+        Ok(())
+    }
+
+    /// Restore a backup into `dir`, which must not exist yet. The files are verified as they are
+    /// copied, and the restored directory opens as a database.
+    pub fn restore(&self, id: u32, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        let meta = self.read_meta(id)?;
+        ensure!(
+            !dir.exists(),
+            "restore directory {} already exists",
+            dir.display()
+        );
+        std::fs::create_dir_all(dir).context("failed to create restore dir")?;
+        for file in &meta.files {
+            let data = self.read_file(id, file)?;
+            write_file(&dir.join(&file.name), &data)?;
+        }
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Delete a backup, and the shared files that no other backup refers to.
+    pub fn delete_backup(&self, id: u32) -> Result<()> {
+        let meta_path = self.meta_path(id);
+        if !meta_path.exists() {
+            bail!("backup {id} does not exist");
+        }
+        std::fs::remove_file(meta_path)?;
+        File::open(self.path.join(META_DIR))?.sync_all()?;
+        self.purge()
+    }
+
+    /// Remove the files that no finished backup refers to.
+    fn purge(&self) -> Result<()> {
+        let ids = self.backup_ids()?;
+        let mut referenced = HashSet::new();
+        for id in &ids {
+            referenced.extend(self.read_meta(*id)?.files.into_iter().map(|file| file.path));
+        }
+        for entry in std::fs::read_dir(self.path.join(SHARED_DIR))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !referenced.contains(&format!("{SHARED_DIR}/{name}")) {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        for entry in std::fs::read_dir(self.path.join(PRIVATE_DIR))? {
+            let entry = entry?;
+            let finished = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<u32>().ok())
+                .is_some_and(|id| ids.contains(&id));
+            if !finished {
+                std::fs::remove_dir_all(entry.path())?;
+            }
+        }
+        for entry in std::fs::read_dir(self.path.join(META_DIR))? {
+            let entry = entry?;
+            if entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.ends_with(".tmp"))
+            {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write a file through a temporary file, so that the file only exists once it is complete.
+fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    std::fs::write(&tmp_path, data)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    File::open(&tmp_path)?.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
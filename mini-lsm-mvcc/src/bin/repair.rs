@@ -0,0 +1,47 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod options;
+mod wrapper;
+
+use anyhow::Result;
+use clap::Parser;
+use options::StorageArgs;
+use wrapper::mini_lsm_wrapper::repair::{LOST_DIR, repair};
+
+/// Rebuild the manifest of a database from its SSTs and WALs. The database is repaired for the
+/// options that it is opened with afterwards.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    storage: StorageArgs,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let options = args.storage.to_options()?;
+    let report = repair(&args.storage.path, &options)?;
+    for (file, reason) in &report.corrupted {
+        println!("{}: {}", file, reason);
+    }
+    for file in &report.archived {
+        println!("moved {} into {}", file, LOST_DIR);
+    }
+    println!(
+        "{} SSTs recovered, {} WALs flushed",
+        report.tables, report.wals
+    );
+    Ok(())
+}
@@ -16,5 +16,28 @@ pub mod mini_lsm_wrapper {
     pub use mini_lsm_mvcc::*;
 }
 
+/// The options that the binaries open a database with, before the command line options apply.
+#[allow(dead_code)]
+pub fn default_options() -> mini_lsm_wrapper::lsm_storage::LsmStorageOptions {
+    Default::default()
+}
+
+/// Set the block compression named on the command line.
+#[allow(dead_code)]
+pub fn set_compression(
+    options: &mut mini_lsm_wrapper::lsm_storage::LsmStorageOptions,
+    compression: &str,
+) -> anyhow::Result<()> {
+    use mini_lsm_wrapper::table::CompressionType;
+    options.compression = match compression {
+        "none" => CompressionType::None,
+        "lz4" => CompressionType::Lz4,
+        "snappy" => CompressionType::Snappy,
+        "zstd" => CompressionType::Zstd,
+        _ => anyhow::bail!("unknown block compression {compression:?}"),
+    };
+    Ok(())
+}
+
 #[allow(dead_code)]
 fn main() {}
@@ -13,25 +13,186 @@
 // limitations under the License.
 
 mod builder;
+mod hash_index;
 mod iterator;
 
 use anyhow::{Context, Result, ensure};
-pub use builder::BlockBuilder;
+pub use builder::{BlockBuilder, DEFAULT_RESTART_INTERVAL};
 use bytes::{BufMut, Bytes};
 pub use iterator::BlockIterator;
 
+use crate::comparator::{BYTEWISE_COMPARATOR, Comparator};
+use crate::iterators::ValueKind;
+use crate::varint::get_uvarint_len;
+use hash_index::{BUCKET_COLLISION, HASH_INDEX_FLAG};
+
 pub(crate) const SIZEOF_U16: usize = std::mem::size_of::<u16>();
 
+/// Before SST format version 9, the kind of a block entry is stored in the top byte of its
+/// timestamp.
+const VALUE_KIND_SHIFT: u32 = 56;
+/// The largest timestamp that a block entry can hold before SST format version 9.
+const MAX_PACKED_TS: u64 = (1 << VALUE_KIND_SHIFT) - 1;
+
+/// Pack the timestamp and the value kind of an entry into the on-disk timestamp, as SST format
+/// versions 3 to 8 store them.
+#[cfg(test)]
+pub(crate) fn encode_entry_ts(ts: u64, kind: ValueKind) -> u64 {
+    debug_assert!(ts <= MAX_PACKED_TS);
+    ts | (u64::from(kind.to_u8()) << VALUE_KIND_SHIFT)
+}
+
+/// Split an on-disk timestamp of SST format versions 3 to 8 into the timestamp and the value kind
+/// of an entry. Earlier versions only have values, whose top byte is zero.
+pub(crate) fn decode_entry_ts(raw_ts: u64) -> (u64, Option<ValueKind>) {
+    (
+        raw_ts & MAX_PACKED_TS,
+        ValueKind::from_u8((raw_ts >> VALUE_KIND_SHIFT) as u8),
+    )
+}
+
+/// How the lengths in a block entry are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockFormat {
+    /// SST format versions 0 to 3: the key overlap, key length and value length are u16.
+    Fixed,
+    /// SST format version 4: the key overlap, key length and value length are varints.
+    Varint,
+    /// The lengths are varints, and each key shares its prefix with the previous key instead of
+    /// the first key of the block. Every few entries are restart points that store the whole key,
+    /// and the block ends with the offsets of the restart points instead of every entry.
+    Restart,
+    /// SST format version 9: the restart format, with the value kind of every entry in a byte after
+    /// its timestamp instead of in the top byte of the timestamp.
+    RestartWithKind,
+}
+
+/// The decoded header of a block entry. Offsets are relative to the start of the entry.
+pub(crate) struct EntryHeader {
+    /// Length of the prefix shared with the first key of the block, or with the previous key in the
+    /// restart format.
+    pub overlap: usize,
+    pub key_offset: usize,
+    /// Length of the rest of the key.
+    pub key_len: usize,
+    pub ts: u64,
+    /// The value kind, or `None` if it is unknown.
+    pub kind: Option<ValueKind>,
+    pub value_offset: usize,
+    pub value_len: usize,
+}
+
+impl BlockFormat {
+    fn get_len(self, buf: &mut &[u8], what: &str) -> Result<usize> {
+        match self {
+            BlockFormat::Fixed => {
+                ensure!(buf.len() >= SIZEOF_U16, "{what} is truncated");
+                let value = u16::from_be_bytes([buf[0], buf[1]]);
+                *buf = &buf[SIZEOF_U16..];
+                Ok(usize::from(value))
+            }
+            BlockFormat::Varint | BlockFormat::Restart | BlockFormat::RestartWithKind => {
+                get_uvarint_len(buf, what)
+            }
+        }
+    }
+
+    /// Whether the blocks have restart points.
+    pub(crate) fn has_restarts(self) -> bool {
+        matches!(self, BlockFormat::Restart | BlockFormat::RestartWithKind)
+    }
+
+    /// Decode the header of the entry at the start of `entry`, checking that the key and the value
+    /// are within the buffer.
+    pub(crate) fn decode_entry_header(self, entry: &[u8]) -> Result<EntryHeader> {
+        let mut cursor = entry;
+        let overlap = self.get_len(&mut cursor, "block key overlap")?;
+        let key_len = self.get_len(&mut cursor, "block key length")?;
+        let key_offset = entry.len() - cursor.len();
+        let ts_end = key_len
+            .checked_add(std::mem::size_of::<u64>())
+            .context("block key length overflow")?;
+        ensure!(
+            cursor.len() >= ts_end,
+            "block key or timestamp is truncated"
+        );
+        let raw_ts = u64::from_be_bytes(
+            cursor[key_len..ts_end]
+                .try_into()
+                .expect("timestamp is 8 bytes"),
+        );
+        cursor = &cursor[ts_end..];
+        let (ts, kind) = if self == BlockFormat::RestartWithKind {
+            ensure!(!cursor.is_empty(), "block value kind is truncated");
+            let kind = ValueKind::from_u8(cursor[0]);
+            cursor = &cursor[1..];
+            (raw_ts, kind)
+        } else {
+            decode_entry_ts(raw_ts)
+        };
+        let value_len = self.get_len(&mut cursor, "block value length")?;
+        let value_offset = entry.len() - cursor.len();
+        ensure!(cursor.len() >= value_len, "block value is truncated");
+        Ok(EntryHeader {
+            overlap,
+            key_offset,
+            key_len,
+            ts,
+            kind,
+            value_offset,
+            value_len,
+        })
+    }
+}
+
 /// A block is the smallest unit of read and caching in LSM tree. It is a collection of sorted
 /// key-value pairs.
 pub struct Block {
     pub(crate) data: Vec<u8>,
+    /// Offsets of every entry. In the restart format they are not stored, but found when the block
+    /// is decoded.
     pub(crate) offsets: Vec<u16>,
+    /// Indices of the entries that are restart points. Empty if the format has no restart points,
+    /// in which case every entry can be decoded on its own.
+    pub(crate) restarts: Vec<u16>,
+    /// Buckets of the hash index, empty if the block has none.
+    pub(crate) hash_buckets: Vec<u16>,
+    pub(crate) format: BlockFormat,
+    /// Orders the keys of the block.
+    pub(crate) comparator: &'static dyn Comparator,
+}
+
+/// Take `count` u16s that end at `end` from the back of a block.
+fn take_u16s_before(data: &[u8], end: usize, count: usize, what: &str) -> Result<Vec<u16>> {
+    let size = count
+        .checked_mul(SIZEOF_U16)
+        .with_context(|| format!("{what} is too large"))?;
+    ensure!(size <= end, "{what} is truncated");
+    Ok(data[end - size..end]
+        .chunks(SIZEOF_U16)
+        .map(|x| u16::from_be_bytes([x[0], x[1]]))
+        .collect())
 }
 
 impl Block {
     pub fn encode(&self) -> Bytes {
         let mut buf = self.data.clone();
+        if self.format.has_restarts() {
+            for restart in &self.restarts {
+                buf.put_u16(self.offsets[usize::from(*restart)]);
+            }
+            if self.hash_buckets.is_empty() {
+                // Adds number of restart points at the end of the block
+                buf.put_u16(self.restarts.len() as u16);
+            } else {
+                for bucket in &self.hash_buckets {
+                    buf.put_u16(*bucket);
+                }
+                buf.put_u16(self.hash_buckets.len() as u16);
+                buf.put_u16(self.restarts.len() as u16 | HASH_INDEX_FLAG);
+            }
+            return buf.into();
+        }
         let offsets_len = self.offsets.len();
         for offset in &self.offsets {
             buf.put_u16(*offset);
@@ -42,83 +203,180 @@ impl Block {
     }
 
     pub fn decode(data: &[u8]) -> Self {
-        Self::decode_checked(data).expect("invalid block encoding")
+        Self::decode_checked(data, BlockFormat::RestartWithKind, &BYTEWISE_COMPARATOR)
+            .expect("invalid block encoding")
     }
 
-    pub(crate) fn decode_checked(data: &[u8]) -> Result<Self> {
-        // get number of elements in the block
+    /// Decode a block of `format`, whose keys are ordered by `comparator`.
+    pub(crate) fn decode_checked(
+        data: &[u8],
+        format: BlockFormat,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self> {
+        // get number of elements (or restart points) in the block
         ensure!(data.len() >= SIZEOF_U16, "block footer is truncated");
-        let entry_offsets_len =
-            u16::from_be_bytes([data[data.len() - 2], data[data.len() - 1]]) as usize;
+        let mut data_end = data.len() - SIZEOF_U16;
+        let mut entry_offsets_len = u16::from_be_bytes([data[data_end], data[data_end + 1]]);
+        let mut hash_buckets = Vec::new();
+        if format.has_restarts() && entry_offsets_len & HASH_INDEX_FLAG != 0 {
+            entry_offsets_len &= !HASH_INDEX_FLAG;
+            let num_buckets = take_u16s_before(data, data_end, 1, "block hash index size")?[0];
+            ensure!(num_buckets > 0, "block hash index has no buckets");
+            data_end -= SIZEOF_U16;
+            hash_buckets =
+                take_u16s_before(data, data_end, usize::from(num_buckets), "block hash index")?;
+            data_end -= hash_buckets.len() * SIZEOF_U16;
+        }
         ensure!(entry_offsets_len > 0, "block has no entries");
-        let offsets_size = entry_offsets_len
-            .checked_mul(SIZEOF_U16)
-            .context("block offset table is too large")?;
-        let footer_size = offsets_size
-            .checked_add(SIZEOF_U16)
-            .context("block footer is too large")?;
-        ensure!(footer_size <= data.len(), "block offset table is truncated");
-        let data_end = data.len() - footer_size;
-        let offsets_raw = &data[data_end..data.len() - SIZEOF_U16];
         // get offset array
-        let offsets: Vec<u16> = offsets_raw
-            .chunks(SIZEOF_U16)
-            .map(|x| u16::from_be_bytes([x[0], x[1]]))
-            .collect();
+        let footer_offsets = take_u16s_before(
+            data,
+            data_end,
+            usize::from(entry_offsets_len),
+            "block offset table",
+        )?;
+        data_end -= footer_offsets.len() * SIZEOF_U16;
         ensure!(
-            offsets[0] == 0,
+            footer_offsets[0] == 0,
             "first block entry must start at offset zero"
         );
         ensure!(
-            offsets.windows(2).all(|pair| pair[0] < pair[1]),
+            footer_offsets.windows(2).all(|pair| pair[0] < pair[1]),
             "block entry offsets are not strictly increasing"
         );
         ensure!(
-            offsets.iter().all(|offset| usize::from(*offset) < data_end),
+            footer_offsets
+                .iter()
+                .all(|offset| usize::from(*offset) < data_end),
             "block entry offset is outside the data section"
         );
 
+        let (offsets, restarts) = if format.has_restarts() {
+            Self::check_restart_entries(&data[..data_end], &footer_offsets, format)?
+        } else {
+            Self::check_entries(&data[..data_end], &footer_offsets, format)?;
+            (footer_offsets, Vec::new())
+        };
+        ensure!(
+            hash_buckets
+                .iter()
+                .all(|bucket| *bucket >= BUCKET_COLLISION || usize::from(*bucket) < offsets.len()),
+            "block hash index points outside the block"
+        );
+        // retrieve data
+        let data = data[0..data_end].to_vec();
+        Ok(Self {
+            data,
+            offsets,
+            restarts,
+            hash_buckets,
+            format,
+            comparator,
+        })
+    }
+
+    /// Check the entries of a block whose footer lists the offset of every entry.
+    fn check_entries(data: &[u8], offsets: &[u16], format: BlockFormat) -> Result<()> {
         let mut first_key_len = None;
         for (idx, offset) in offsets.iter().enumerate() {
             let entry_start = usize::from(*offset);
             let entry_end = offsets
                 .get(idx + 1)
-                .map_or(data_end, |offset| usize::from(*offset));
+                .map_or(data.len(), |offset| usize::from(*offset));
             let entry = &data[entry_start..entry_end];
-            ensure!(entry.len() >= 14, "block entry header is truncated");
-            let overlap = u16::from_be_bytes([entry[0], entry[1]]) as usize;
-            let key_len = u16::from_be_bytes([entry[2], entry[3]]) as usize;
+            let header = format.decode_entry_header(entry)?;
             if idx == 0 {
-                ensure!(overlap == 0, "first block key has a nonzero overlap");
-                first_key_len = Some(key_len);
+                ensure!(header.overlap == 0, "first block key has a nonzero overlap");
+                first_key_len = Some(header.key_len);
             } else {
                 ensure!(
-                    overlap <= first_key_len.context("block is missing its first key")?,
+                    header.overlap <= first_key_len.context("block is missing its first key")?,
                     "block key overlap exceeds the first key"
                 );
             }
-            let key_end = 4usize
-                .checked_add(key_len)
-                .context("block key length overflow")?;
-            let value_len_offset = key_end
-                .checked_add(std::mem::size_of::<u64>())
-                .context("block timestamp offset overflow")?;
-            let value_len_end = value_len_offset
-                .checked_add(SIZEOF_U16)
-                .context("block value header overflow")?;
-            ensure!(
-                value_len_end <= entry.len(),
-                "block key, timestamp, or value length is truncated"
-            );
-            let value_len =
-                u16::from_be_bytes([entry[value_len_offset], entry[value_len_offset + 1]]) as usize;
-            let entry_len = value_len_end
-                .checked_add(value_len)
-                .context("block value length overflow")?;
-            ensure!(entry_len == entry.len(), "block value length is invalid");
+            Self::check_entry(entry, &header)?;
         }
-        // retrieve data
-        let data = data[0..data_end].to_vec();
-        Ok(Self { data, offsets })
+        Ok(())
+    }
+
+    /// Walk the entries of a block in the restart format. Returns the offsets of the entries and
+    /// the indices of the restart points.
+    fn check_restart_entries(
+        data: &[u8],
+        restart_offsets: &[u16],
+        format: BlockFormat,
+    ) -> Result<(Vec<u16>, Vec<u16>)> {
+        let mut offsets = Vec::new();
+        let mut restarts = Vec::with_capacity(restart_offsets.len());
+        let mut prev_key_len = 0;
+        let mut entry_start = 0;
+        while entry_start < data.len() {
+            let offset = u16::try_from(entry_start).context("block entry offset is too large")?;
+            let entry_idx = u16::try_from(offsets.len()).context("block has too many entries")?;
+            let header = format.decode_entry_header(&data[entry_start..])?;
+            if restart_offsets.get(restarts.len()) == Some(&offset) {
+                ensure!(
+                    header.overlap == 0,
+                    "block restart key has a nonzero overlap"
+                );
+                restarts.push(entry_idx);
+            } else {
+                ensure!(
+                    header.overlap <= prev_key_len,
+                    "block key overlap exceeds the previous key"
+                );
+            }
+            let entry_len = header.value_offset + header.value_len;
+            Self::check_entry(&data[entry_start..entry_start + entry_len], &header)?;
+            prev_key_len = header.overlap + header.key_len;
+            offsets.push(offset);
+            entry_start += entry_len;
+        }
+        ensure!(
+            restarts.len() == restart_offsets.len(),
+            "block restart point is not at the start of an entry"
+        );
+        Ok((offsets, restarts))
+    }
+
+    fn check_entry(entry: &[u8], header: &EntryHeader) -> Result<()> {
+        ensure!(
+            header.kind.is_some(),
+            "block entry has an unknown value kind"
+        );
+        ensure!(
+            header.value_offset + header.value_len == entry.len(),
+            "block value length is invalid"
+        );
+        Ok(())
+    }
+
+    /// The number of entries that a seek can start decoding from.
+    pub(crate) fn num_restarts(&self) -> usize {
+        if self.restarts.is_empty() {
+            self.offsets.len()
+        } else {
+            self.restarts.len()
+        }
+    }
+
+    /// The index of the entry at the `restart_idx`-th restart point.
+    pub(crate) fn restart_entry(&self, restart_idx: usize) -> usize {
+        if self.restarts.is_empty() {
+            restart_idx
+        } else {
+            usize::from(self.restarts[restart_idx])
+        }
+    }
+
+    /// The index of the closest restart point at or before the `entry_idx`-th entry.
+    pub(crate) fn restart_before(&self, entry_idx: usize) -> usize {
+        if self.restarts.is_empty() {
+            return entry_idx;
+        }
+        let restart_idx = self
+            .restarts
+            .partition_point(|restart| usize::from(*restart) <= entry_idx);
+        usize::from(self.restarts[restart_idx - 1])
     }
 }
@@ -14,9 +14,13 @@
 
 use bytes::BufMut;
 
+use crate::comparator::BYTEWISE_COMPARATOR;
+use crate::iterators::ValueKind;
 use crate::key::{KeySlice, KeyVec};
+use crate::varint::put_uvarint;
 
-use super::{Block, SIZEOF_U16};
+use super::hash_index::build_buckets;
+use super::{Block, BlockFormat, SIZEOF_U16};
 
 /// Builds a block.
 pub struct BlockBuilder {
@@ -26,17 +30,31 @@ pub struct BlockBuilder {
     data: Vec<u8>,
     /// The expected block size.
     block_size: usize,
-    /// The first key in the block
-    first_key: KeyVec,
+    /// Indices of the entries that are restart points.
+    restarts: Vec<u16>,
+    /// Number of entries between two restart points.
+    restart_interval: usize,
+    /// The last key in the block
+    last_key: KeyVec,
+    /// The hash of every user key and the index of its first entry, if the block gets a hash
+    /// index.
+    hash_index: Option<Vec<(u32, u16)>>,
 }
 
-fn compute_overlap(first_key: KeySlice, key: KeySlice) -> usize {
+/// The most entries a block holds. The top bit of the number of restart points is the hash index
+/// flag, and the largest entry indices are the markers of the hash index buckets.
+const MAX_ENTRIES: usize = 0x7fff;
+
+/// The default number of entries between two restart points.
+pub const DEFAULT_RESTART_INTERVAL: usize = 16;
+
+fn compute_overlap(last_key: KeySlice, key: KeySlice) -> usize {
     let mut i = 0;
     loop {
-        if i >= first_key.key_len() || i >= key.key_len() {
+        if i >= last_key.key_len() || i >= key.key_len() {
             break;
         }
-        if first_key.key_ref()[i] != key.key_ref()[i] {
+        if last_key.key_ref()[i] != key.key_ref()[i] {
             break;
         }
         i += 1;
@@ -47,36 +65,62 @@ fn compute_overlap(first_key: KeySlice, key: KeySlice) -> usize {
 impl BlockBuilder {
     /// Creates a new block builder.
     pub fn new(block_size: usize) -> Self {
+        Self::new_with_restart_interval(block_size, DEFAULT_RESTART_INTERVAL)
+    }
+
+    /// Creates a new block builder that stores the whole key every `restart_interval` entries.
+    pub fn new_with_restart_interval(block_size: usize, restart_interval: usize) -> Self {
+        assert!(restart_interval > 0, "restart interval must be positive");
         Self {
             offsets: Vec::new(),
             data: Vec::new(),
             block_size,
-            first_key: KeyVec::new(),
+            restarts: Vec::new(),
+            restart_interval,
+            last_key: KeyVec::new(),
+            hash_index: None,
         }
     }
 
+    /// Creates a new block builder that also appends a hash index of the user keys.
+    pub fn new_with_hash_index(block_size: usize, restart_interval: usize) -> Self {
+        let mut builder = Self::new_with_restart_interval(block_size, restart_interval);
+        builder.hash_index = Some(Vec::new());
+        builder
+    }
+
     fn estimated_size(&self) -> usize {
-        SIZEOF_U16 /* number of key-value pairs in the block */ +  self.offsets.len() * SIZEOF_U16 /* offsets */ + self.data.len()
-        // key-value pairs
+        SIZEOF_U16 /* number of restart points in the block */ +  self.restarts.len() * SIZEOF_U16 /* restart offsets */ + self.data.len() /* key-value pairs */
+            + self.hash_index.as_ref().map_or(0, |keys| SIZEOF_U16 /* number of buckets */ + keys.len() * SIZEOF_U16 * 4 / 3 /* buckets */)
     }
 
     /// Adds a key-value pair to the block. Returns false when the block is full.
     #[must_use]
     pub fn add(&mut self, key: KeySlice, value: &[u8]) -> bool {
+        self.add_with_kind(key, ValueKind::Value, value)
+    }
+
+    /// Adds an entry whose value is stored as `kind` to the block. Returns false when the block is
+    /// full.
+    #[must_use]
+    pub fn add_with_kind(&mut self, key: KeySlice, kind: ValueKind, value: &[u8]) -> bool {
         assert!(!key.is_empty(), "key must not be empty");
-        let Ok(key_len) = u16::try_from(key.key_len()) else {
-            return false;
-        };
-        let Ok(value_len) = u16::try_from(value.len()) else {
-            return false;
+        let is_restart = self.offsets.len().is_multiple_of(self.restart_interval);
+        let overlap = if is_restart {
+            0
+        } else {
+            compute_overlap(self.last_key.as_key_slice(), key)
         };
-        let entry_size = key
-            .raw_len()
+        let rest_key_len = key.key_len() - overlap;
+        // The overlap, key length and value length take one to a few bytes each, the value kind
+        // takes one byte, and a restart point adds an offset to the footer.
+        let entry_size = (key.raw_len() - overlap)
             .saturating_add(value.len())
-            .saturating_add(SIZEOF_U16 * 3);
+            .saturating_add(SIZEOF_U16 * 3 + 1)
+            .saturating_add(if is_restart { SIZEOF_U16 } else { 0 });
         let block_is_full = self.estimated_size().saturating_add(entry_size) > self.block_size;
         let offset_is_full = self.data.len() > usize::from(u16::MAX);
-        let count_is_full = self.offsets.len() >= usize::from(u16::MAX);
+        let count_is_full = self.offsets.len() >= MAX_ENTRIES;
         if !self.is_empty() && (block_is_full || offset_is_full || count_is_full) {
             return false;
         }
@@ -84,27 +128,32 @@ impl BlockBuilder {
         let Ok(offset) = u16::try_from(self.data.len()) else {
             return false;
         };
+        let offset_idx = self.offsets.len() as u16;
+        if is_restart {
+            self.restarts.push(offset_idx);
+        }
         self.offsets.push(offset);
-        let overlap = compute_overlap(self.first_key.as_key_slice(), key);
-        let Ok(overlap) = u16::try_from(overlap) else {
-            return false;
-        };
         // Encode key overlap.
-        self.data.put_u16(overlap);
+        put_uvarint(&mut self.data, overlap as u64);
         // Encode key length.
-        self.data.put_u16(key_len - overlap);
+        put_uvarint(&mut self.data, rest_key_len as u64);
         // Encode key content.
-        self.data.put(&key.key_ref()[usize::from(overlap)..]);
-        // Encode key ts
+        self.data.put(&key.key_ref()[overlap..]);
+        // Encode key ts.
         self.data.put_u64(key.ts());
+        // Encode value kind.
+        self.data.put_u8(kind.to_u8());
         // Encode value length.
-        self.data.put_u16(value_len);
+        put_uvarint(&mut self.data, value.len() as u64);
         // Encode value content.
         self.data.put(value);
 
-        if self.first_key.is_empty() {
-            self.first_key = key.to_key_vec();
+        if let Some(keys) = self.hash_index.as_mut()
+            && (self.offsets.len() == 1 || self.last_key.key_ref() != key.key_ref())
+        {
+            keys.push((farmhash::fingerprint32(key.key_ref()), offset_idx));
         }
+        self.last_key.set_from_slice(key);
 
         true
     }
@@ -122,6 +171,13 @@ impl BlockBuilder {
         Block {
             data: self.data,
             offsets: self.offsets,
+            restarts: self.restarts,
+            hash_buckets: self
+                .hash_index
+                .map(|keys| build_buckets(&keys))
+                .unwrap_or_default(),
+            format: BlockFormat::RestartWithKind,
+            comparator: &BYTEWISE_COMPARATOR,
         }
     }
 }
@@ -0,0 +1,78 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The optional hash index of a data block, which maps the hash of a user key to the first entry
+//! with that key so that point lookups can skip the binary search. The buckets are stored after
+//! the restart points, and the top bit of the number of restart points tells whether a block has
+//! them:
+//!
+//! ```text
+//! | entries | restart offsets (u16) | buckets (u16) | num_buckets (u16) | num_restarts | 0x8000 (u16) |
+//! ```
+
+use super::Block;
+
+/// Set in the number of restart points of a block that has a hash index.
+pub(crate) const HASH_INDEX_FLAG: u16 = 0x8000;
+/// A bucket that no user key maps to.
+pub(crate) const BUCKET_EMPTY: u16 = u16::MAX;
+/// A bucket that several user keys map to.
+pub(crate) const BUCKET_COLLISION: u16 = u16::MAX - 1;
+/// The number of user keys per bucket.
+const UTIL_RATIO: f64 = 0.75;
+
+fn bucket_of(hash: u32, num_buckets: usize) -> usize {
+    hash as usize % num_buckets
+}
+
+/// Build the buckets for the user keys of a block, given as their hashes and the index of their
+/// first entry.
+pub(crate) fn build_buckets(keys: &[(u32, u16)]) -> Vec<u16> {
+    let num_buckets = ((keys.len() as f64 / UTIL_RATIO).ceil() as usize).clamp(1, 0x7fff);
+    let mut buckets = vec![BUCKET_EMPTY; num_buckets];
+    for &(hash, entry_idx) in keys {
+        let bucket = &mut buckets[bucket_of(hash, num_buckets)];
+        *bucket = if *bucket == BUCKET_EMPTY {
+            entry_idx
+        } else {
+            BUCKET_COLLISION
+        };
+    }
+    buckets
+}
+
+/// The result of looking up a user key in the hash index of a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum HashLookup {
+    /// The block has no hash index, or another user key shares the bucket.
+    Unknown,
+    /// No entry of the block has the user key.
+    Absent,
+    /// The first entry with the user key, if the key is in the block.
+    Candidate(usize),
+}
+
+impl Block {
+    pub(crate) fn hash_lookup(&self, user_key: &[u8]) -> HashLookup {
+        if self.hash_buckets.is_empty() {
+            return HashLookup::Unknown;
+        }
+        let bucket = bucket_of(farmhash::fingerprint32(user_key), self.hash_buckets.len());
+        match self.hash_buckets[bucket] {
+            BUCKET_EMPTY => HashLookup::Absent,
+            BUCKET_COLLISION => HashLookup::Unknown,
+            entry_idx => HashLookup::Candidate(usize::from(entry_idx)),
+        }
+    }
+}
@@ -12,16 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cmp::Ordering;
 use std::sync::Arc;
 
-use bytes::Buf;
-
 use crate::{
-    block::SIZEOF_U16,
+    block::EntryHeader,
+    iterators::ValueKind,
     key::{KeySlice, KeyVec},
 };
 
 use super::Block;
+use super::hash_index::HashLookup;
 
 /// Iterates on a block.
 pub struct BlockIterator {
@@ -31,6 +32,8 @@ pub struct BlockIterator {
     key: KeyVec,
     /// the current value range in the block.data, corresponds to the current key
     value_range: (usize, usize),
+    /// how the current value is stored
+    value_kind: ValueKind,
     /// the current index at the iterator position
     idx: usize,
     /// the first key in the block
@@ -38,13 +41,16 @@ pub struct BlockIterator {
 }
 
 impl Block {
+    fn entry_header(&self, offset: usize) -> EntryHeader {
+        self.format
+            .decode_entry_header(&self.data[offset..])
+            .expect("block entries are validated when decoding the block")
+    }
+
     fn get_first_key(&self) -> KeyVec {
-        let mut buf = &self.data[..];
-        buf.get_u16();
-        let key_len = buf.get_u16() as usize;
-        let key = &buf[..key_len];
-        buf.advance(key_len);
-        KeyVec::from_vec_with_ts(key.to_vec(), buf.get_u64())
+        let header = self.entry_header(0);
+        let key = &self.data[header.key_offset..header.key_offset + header.key_len];
+        KeyVec::from_vec_with_ts(key.to_vec(), header.ts)
     }
 }
 
@@ -55,6 +61,7 @@ impl BlockIterator {
             block,
             key: KeyVec::new(),
             value_range: (0, 0),
+            value_kind: ValueKind::Value,
             idx: 0,
         }
     }
@@ -73,18 +80,75 @@ impl BlockIterator {
         iter
     }
 
+    /// Creates a block iterator and seek to the last entry.
+    pub fn create_and_seek_to_last(block: Arc<Block>) -> Self {
+        let mut iter = Self::new(block);
+        iter.seek_to_last();
+        iter
+    }
+
+    /// Creates a block iterator and seek to the last key that <= `key`.
+    pub fn create_and_seek_for_prev(block: Arc<Block>, key: KeySlice) -> Self {
+        let mut iter = Self::new(block);
+        iter.seek_for_prev(key);
+        iter
+    }
+
+    /// Creates a block iterator for a point lookup and seek to the first key that >= `key`. Returns
+    /// `None` if the hash index of the block shows that no entry has the user key of `key`.
+    pub(crate) fn create_and_seek_for_get(block: Arc<Block>, key: KeySlice) -> Option<Self> {
+        let mut iter = Self::new(block);
+        match iter.block.hash_lookup(key.key_ref()) {
+            HashLookup::Unknown => iter.seek_to_key(key),
+            HashLookup::Absent => return None,
+            HashLookup::Candidate(idx) => {
+                iter.seek_to(idx);
+                if iter.key().key_ref() != key.key_ref() {
+                    return None;
+                }
+                while iter.is_valid() && iter.compare_key(key).is_lt() {
+                    iter.next();
+                }
+            }
+        }
+        Some(iter)
+    }
+
+    /// Creates a block iterator and seek to the idx-th entry.
+    pub(crate) fn create_and_seek_to_idx(block: Arc<Block>, idx: usize) -> Self {
+        let mut iter = Self::new(block);
+        iter.seek_to(idx);
+        iter
+    }
+
+    /// Returns the index of the current entry in the block.
+    pub(crate) fn entry_idx(&self) -> usize {
+        self.idx
+    }
+
     /// Returns the key of the current entry.
     pub fn key(&self) -> KeySlice<'_> {
         debug_assert!(!self.key.is_empty(), "invalid iterator");
         self.key.as_key_slice()
     }
 
+    /// Compares the key of the current entry with `key`, by the comparator of the block.
+    fn compare_key(&self, key: KeySlice) -> Ordering {
+        self.block.comparator.compare_keys(self.key(), key)
+    }
+
     /// Returns the value of the current entry.
     pub fn value(&self) -> &[u8] {
         debug_assert!(!self.key.is_empty(), "invalid iterator");
         &self.block.data[self.value_range.0..self.value_range.1]
     }
 
+    /// Returns how the value of the current entry is stored.
+    pub fn value_kind(&self) -> ValueKind {
+        debug_assert!(!self.key.is_empty(), "invalid iterator");
+        self.value_kind
+    }
+
     /// Returns true if the iterator is valid.
     pub fn is_valid(&self) -> bool {
         !self.key.is_empty()
@@ -95,62 +159,103 @@ impl BlockIterator {
         self.seek_to(0);
     }
 
-    /// Seeks to the idx-th key in the block.
+    /// Seeks to the last key in the block.
+    pub fn seek_to_last(&mut self) {
+        self.seek_to(self.block.offsets.len() - 1);
+    }
+
+    /// Seeks to the idx-th key in the block. In the restart format, the entries are decoded from the
+    /// closest restart point, or from the current entry if it is between the two.
     fn seek_to(&mut self, idx: usize) {
         if idx >= self.block.offsets.len() {
             self.key.clear();
             self.value_range = (0, 0);
+            self.idx = idx;
             return;
         }
-        let offset = self.block.offsets[idx] as usize;
-        self.seek_to_offset(offset);
+        let mut next_idx = self.block.restart_before(idx);
+        if self.is_valid() && (next_idx..idx).contains(&self.idx) {
+            next_idx = self.idx + 1;
+        }
+        for entry_idx in next_idx..=idx {
+            let offset = self.block.offsets[entry_idx] as usize;
+            self.seek_to_offset(offset);
+        }
         self.idx = idx;
     }
 
     /// Move to the next key in the block.
     pub fn next(&mut self) {
-        self.idx += 1;
-        self.seek_to(self.idx);
+        self.seek_to(self.idx + 1);
     }
 
-    /// Seek to the specified position and update the current `key` and `value`
+    /// Move to the previous key in the block. The iterator is invalid after the first key.
+    pub fn prev(&mut self) {
+        if !self.is_valid() {
+            return;
+        }
+        if self.idx == 0 {
+            self.key.clear();
+            self.value_range = (0, 0);
+            self.idx = self.block.offsets.len();
+        } else {
+            self.seek_to(self.idx - 1);
+        }
+    }
+
+    /// Seek to the specified position and update the current `key` and `value`. In the restart
+    /// format, the current key must be the previous entry unless the position is a restart point.
     /// Index update will be handled by caller
     fn seek_to_offset(&mut self, offset: usize) {
-        let mut entry = &self.block.data[offset..];
-        // Since `get_u16()` will automatically move the ptr 2 bytes ahead here,
-        // we don't need to manually advance it
-        let overlap_len = entry.get_u16() as usize;
-        let key_len = entry.get_u16() as usize;
-        let key = &entry[..key_len];
-        self.key.clear();
-        self.key.append(&self.first_key.key_ref()[..overlap_len]);
-        self.key.append(key);
-        entry.advance(key_len);
-        let ts = entry.get_u64();
-        self.key.set_ts(ts);
-        let value_len = entry.get_u16() as usize;
-        // REMEMBER TO CHANGE THIS every time you change the encoding!
-        let value_offset_begin =
-            offset + SIZEOF_U16 + SIZEOF_U16 + std::mem::size_of::<u64>() + key_len + SIZEOF_U16;
-        let value_offset_end = value_offset_begin + value_len;
-        self.value_range = (value_offset_begin, value_offset_end);
-        entry.advance(value_len);
-    }
-
-    /// Seek to the first key that is >= `key`.
+        let header = self.block.entry_header(offset);
+        let key_begin = offset + header.key_offset;
+        if self.block.format.has_restarts() {
+            let mut key = std::mem::take(&mut self.key).into_inner();
+            key.truncate(header.overlap);
+            self.key = KeyVec::from_vec_with_ts(key, 0);
+        } else {
+            self.key.clear();
+            self.key.append(&self.first_key.key_ref()[..header.overlap]);
+        }
+        self.key
+            .append(&self.block.data[key_begin..key_begin + header.key_len]);
+        self.key.set_ts(header.ts);
+        self.value_kind = header
+            .kind
+            .expect("value kinds are validated when decoding the block");
+        let value_offset_begin = offset + header.value_offset;
+        self.value_range = (value_offset_begin, value_offset_begin + header.value_len);
+    }
+
+    /// Seek to the first key that is >= `key`. Binary search finds the first restart point whose
+    /// key is not less than `key`, and the entries are scanned from the restart point before it.
     pub fn seek_to_key(&mut self, key: KeySlice) {
+        let block = self.block.clone();
         let mut low = 0;
-        let mut high = self.block.offsets.len();
+        let mut high = block.num_restarts();
         while low < high {
             let mid = low + (high - low) / 2;
-            self.seek_to(mid);
+            self.seek_to(block.restart_entry(mid));
             assert!(self.is_valid());
-            match self.key().cmp(&key) {
-                std::cmp::Ordering::Less => low = mid + 1,
-                std::cmp::Ordering::Greater => high = mid,
-                std::cmp::Ordering::Equal => return,
+            match self.compare_key(key) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return,
             }
         }
-        self.seek_to(low);
+        self.seek_to(block.restart_entry(low.saturating_sub(1)));
+        while self.is_valid() && self.compare_key(key).is_lt() {
+            self.next();
+        }
+    }
+
+    /// Seek to the last key that is <= `key`.
+    pub fn seek_for_prev(&mut self, key: KeySlice) {
+        self.seek_to_key(key);
+        if !self.is_valid() {
+            self.seek_to_last();
+        } else if self.compare_key(key).is_gt() {
+            self.prev();
+        }
     }
 }
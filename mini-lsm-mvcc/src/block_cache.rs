@@ -0,0 +1,135 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use moka::sync::Cache;
+
+use crate::block::Block;
+use crate::table::bloom::Bloom;
+
+/// Identifies an SST metadata block in the metadata cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MetaCacheKey {
+    /// An index partition: SST id and partition index.
+    Index(usize, usize),
+    /// The bloom filter of an SST.
+    Bloom(usize),
+}
+
+#[derive(Clone)]
+pub(crate) enum MetaBlock {
+    Index(Arc<Block>),
+    Bloom(Arc<Bloom>),
+}
+
+fn block_weight(block: &Block) -> u32 {
+    let size = block.data.len()
+        + (block.offsets.len() + block.restarts.len()) * std::mem::size_of::<u16>();
+    u32::try_from(size).unwrap_or(u32::MAX)
+}
+
+/// Caches the blocks read from SSTs. Data blocks and metadata (index partitions and bloom
+/// filters) are kept in separate caches, each bounded by its own capacity in bytes, so that a
+/// large scan cannot evict the metadata that every read depends on.
+pub struct BlockCache {
+    pub(crate) data: Cache<(usize, usize), Arc<Block>>,
+    pub(crate) meta: Cache<MetaCacheKey, MetaBlock>,
+}
+
+impl BlockCache {
+    /// Create a cache holding up to `data_capacity` bytes of data blocks and `meta_capacity` bytes
+    /// of index partitions and bloom filters.
+    pub fn new(data_capacity: u64, meta_capacity: u64) -> Self {
+        Self {
+            data: Cache::builder()
+                .max_capacity(data_capacity)
+                .weigher(|_, block: &Arc<Block>| block_weight(block))
+                .build(),
+            meta: Cache::builder()
+                .max_capacity(meta_capacity)
+                .weigher(|_, meta: &MetaBlock| match meta {
+                    MetaBlock::Index(block) => block_weight(block),
+                    MetaBlock::Bloom(bloom) => {
+                        u32::try_from(bloom.filter.len()).unwrap_or(u32::MAX)
+                    }
+                })
+                .build(),
+        }
+    }
+
+    /// Get a data block, reading it with `init` on a cache miss.
+    pub(crate) fn get_data_block(
+        &self,
+        sst_id: usize,
+        block_idx: usize,
+        init: impl FnOnce() -> Result<Arc<Block>>,
+    ) -> Result<Arc<Block>> {
+        self.data
+            .try_get_with((sst_id, block_idx), init)
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Get an index partition, reading it with `init` on a cache miss.
+    pub(crate) fn get_index_partition(
+        &self,
+        sst_id: usize,
+        partition_idx: usize,
+        init: impl FnOnce() -> Result<Arc<Block>>,
+    ) -> Result<Arc<Block>> {
+        let meta = self
+            .meta
+            .try_get_with(MetaCacheKey::Index(sst_id, partition_idx), || {
+                init().map(MetaBlock::Index)
+            })
+            .map_err(|e| anyhow!("{}", e))?;
+        match meta {
+            MetaBlock::Index(block) => Ok(block),
+            MetaBlock::Bloom(_) => unreachable!("index partitions are cached as blocks"),
+        }
+    }
+
+    /// Get a bloom filter, reading it with `init` on a cache miss.
+    pub(crate) fn get_bloom(
+        &self,
+        sst_id: usize,
+        init: impl FnOnce() -> Result<Arc<Bloom>>,
+    ) -> Result<Arc<Bloom>> {
+        let meta = self
+            .meta
+            .try_get_with(MetaCacheKey::Bloom(sst_id), || init().map(MetaBlock::Bloom))
+            .map_err(|e| anyhow!("{}", e))?;
+        match meta {
+            MetaBlock::Bloom(bloom) => Ok(bloom),
+            MetaBlock::Index(_) => unreachable!("bloom filters are cached as blooms"),
+        }
+    }
+
+    /// Insert a bloom filter that was just built or decoded.
+    pub(crate) fn insert_bloom(&self, sst_id: usize, bloom: Arc<Bloom>) {
+        self.meta
+            .insert(MetaCacheKey::Bloom(sst_id), MetaBlock::Bloom(bloom));
+    }
+
+    /// Returns true if the metadata cache holds the given block.
+    pub fn contains_meta(&self, key: MetaCacheKey) -> bool {
+        self.meta.contains_key(&key)
+    }
+
+    /// Returns true if the data cache holds the given block.
+    pub fn contains_data_block(&self, sst_id: usize, block_idx: usize) -> bool {
+        self.data.contains_key(&(sst_id, block_idx))
+    }
+}
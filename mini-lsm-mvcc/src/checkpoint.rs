@@ -0,0 +1,172 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Online checkpoints, which are consistent copies of a live database that open as independent
+//! databases. The SSTs and value log files are immutable, so they are hard-linked into the
+//! checkpoint, and a compacted manifest describes the state at the time of the checkpoint.
+
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Context, Result, ensure};
+
+use crate::lsm_storage::{LsmStorageInner, LsmStorageState};
+use crate::manifest::{Manifest, ManifestRecord};
+
+/// The kind of a file passed to [`LsmStorageInner::export_files`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ExportedFileKind {
+    /// An SST or a value log file, which is never modified once written.
+    Immutable,
+    /// A WAL, which may still be appended to.
+    Wal,
+}
+
+impl LsmStorageInner {
+    /// Create a checkpoint of the database in `dir`, which must not exist yet. The WALs are copied
+    /// into the checkpoint if they are enabled, otherwise the mem-tables are flushed first. `dir`
+    /// must be on the same file system as the database.
+    pub fn create_checkpoint(&self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        ensure!(
+            !dir.exists(),
+            "checkpoint directory {} already exists",
+            dir.display()
+        );
+        std::fs::create_dir_all(dir).context("failed to create checkpoint dir")?;
+        let records = self.export_files(|path, name, kind| {
+            match kind {
+                ExportedFileKind::Immutable => std::fs::hard_link(path, dir.join(name))
+                    .with_context(|| format!("failed to link {name}"))?,
+                ExportedFileKind::Wal => {
+                    std::fs::copy(path, dir.join(name))
+                        .with_context(|| format!("failed to copy {name}"))?;
+                }
+            }
+            Ok(())
+        })?;
+
+        let manifest = Manifest::create(dir.join("MANIFEST"))?;
+        for record in records {
+            manifest.add_record_when_init(record)?;
+        }
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Pass every file of a consistent view of the database to `export`, with its path and file
+    /// name, and return the records of a compacted manifest that recovers the view from the files.
+    /// The mem-tables are flushed first if the WALs are disabled. The state does not change until
+    /// `export` has seen every file, so the files it is given are not deleted in the meantime.
+    pub(crate) fn export_files(
+        &self,
+        mut export: impl FnMut(&Path, &str, ExportedFileKind) -> Result<()>,
+    ) -> Result<Vec<ManifestRecord>> {
+        self.ensure_writable()?;
+        if !self.options.enable_wal {
+            if !self.is_memtable_empty() {
+                self.force_freeze_memtable(&self.state_lock.lock())?;
+            }
+            while !self.state.read().imm_memtables.is_empty() {
+                self.force_flush_next_imm_memtable()?;
+            }
+        }
+
+        // Compaction and value log garbage collection delete the files they replace after they
+        // update the state, so both are held off until every file is exported.
+        let _gc_lock = self.value_log.gc_lock.lock();
+        let _state_lock = self.state_lock.lock();
+        let snapshot = self.state.read().clone();
+        let value_log = self.value_log.snapshot();
+
+        for state in std::iter::once(snapshot.as_ref())
+            .chain(snapshot.column_families.values().map(|x| x.state.as_ref()))
+        {
+            for sst_id in state.sstables.keys() {
+                export(
+                    &self.path_of_sst(*sst_id),
+                    &format!("{:05}.sst", sst_id),
+                    ExportedFileKind::Immutable,
+                )?;
+            }
+        }
+        for file_id in value_log.keys() {
+            export(
+                &self.value_log.path_of_file(*file_id),
+                &format!("{:05}.vlog", file_id),
+                ExportedFileKind::Immutable,
+            )?;
+        }
+
+        // The WAL of the current mem-table is still being written. A frame that is only partially
+        // exported is dropped when the exported database is opened.
+        let mut memtables = Vec::new();
+        if self.options.enable_wal {
+            snapshot.memtable.sync_wal()?;
+            for memtable in snapshot
+                .imm_memtables
+                .iter()
+                .rev()
+                .chain(std::iter::once(&snapshot.memtable))
+            {
+                export(
+                    &self.path_of_wal(memtable.id()),
+                    &format!("{:05}.wal", memtable.id()),
+                    ExportedFileKind::Wal,
+                )?;
+                memtables.push(memtable.id());
+            }
+        }
+        let value_logs = value_log.keys().copied().collect::<Vec<_>>();
+        Ok(self.snapshot_manifest_records(&snapshot, &memtables, &value_logs))
+    }
+
+    /// The records of a compacted manifest that recovers `state`, the column families, the
+    /// mem-tables with the ids in `memtables` and the value log files with the ids in `value_logs`.
+    pub(crate) fn snapshot_manifest_records(
+        &self,
+        state: &LsmStorageState,
+        memtables: &[usize],
+        value_logs: &[usize],
+    ) -> Vec<ManifestRecord> {
+        let mut records = vec![ManifestRecord::Comparator(
+            self.options.comparator.name().to_string(),
+        )];
+        for (name, column_family) in &state.column_families {
+            records.push(ManifestRecord::CreateColumnFamily(
+                name.clone(),
+                column_family.id,
+                column_family.compaction_options.clone(),
+            ));
+        }
+        for (id, state) in std::iter::once((0, state)).chain(
+            state
+                .column_families
+                .values()
+                .map(|column_family| (column_family.id, column_family.state.as_ref())),
+        ) {
+            records.push(ManifestRecord::Snapshot {
+                column_family: id,
+                l0_sstables: state.l0_sstables.clone(),
+                levels: state.levels.clone(),
+            });
+        }
+        records.push(ManifestRecord::ValueLogFiles(value_logs.to_vec()));
+        records.push(ManifestRecord::ValueLogDiscarded(
+            self.value_log.discarded(value_logs.iter().copied()),
+        ));
+        records.extend(memtables.iter().copied().map(ManifestRecord::NewMemtable));
+        records
+    }
+}
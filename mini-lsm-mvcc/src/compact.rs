@@ -20,22 +20,30 @@ use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use bytes::Bytes;
 pub use leveled::{LeveledCompactionController, LeveledCompactionOptions, LeveledCompactionTask};
+use parking_lot::MutexGuard;
 use serde::{Deserialize, Serialize};
 pub use simple_leveled::{
     SimpleLeveledCompactionController, SimpleLeveledCompactionOptions, SimpleLeveledCompactionTask,
 };
 pub use tiered::{TieredCompactionController, TieredCompactionOptions, TieredCompactionTask};
 
-use crate::iterators::StorageIterator;
 use crate::iterators::concat_iterator::SstConcatIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
-use crate::key::KeySlice;
-use crate::lsm_storage::{CompactionFilter, LsmStorageInner, LsmStorageState};
+use crate::iterators::{StorageIterator, ValueKind};
+use crate::key::{KeySlice, KeyVec};
+use crate::lsm_storage::{
+    CompactionDecision, CompactionFilter, DEFAULT_COLUMN_FAMILY, LsmStorageInner, LsmStorageState,
+};
 use crate::manifest::ManifestRecord;
+use crate::merge_operator::{MergeOperator, merge_operands};
+use crate::range_tombstone::{RangeTombstone, is_covered};
 use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
+use crate::ttl;
+use crate::value_log::{ValuePointer, resolve_value};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum CompactionTask {
@@ -57,6 +65,63 @@ impl CompactionTask {
             CompactionTask::Tiered(task) => task.bottom_tier_included,
         }
     }
+
+    /// The level that the task writes to. Tiered compaction replaces the compacted tiers with a
+    /// single tier, and the latest tier is level 1.
+    fn output_level(&self, snapshot: &LsmStorageState) -> usize {
+        match self {
+            CompactionTask::ForceFullCompaction { .. } => 1,
+            CompactionTask::Leveled(task) => task.lower_level,
+            CompactionTask::Simple(task) => task.lower_level,
+            CompactionTask::Tiered(task) => {
+                // The output tier takes the place of the last compacted tier.
+                let last_compacted_tier = snapshot
+                    .levels
+                    .iter()
+                    .rposition(|(tier_id, _)| task.tiers.iter().any(|(id, _)| id == tier_id))
+                    .unwrap_or_default();
+                (last_compacted_tier + 2)
+                    .saturating_sub(task.tiers.len())
+                    .max(1)
+            }
+        }
+    }
+
+    /// The ids of the SSTs that the task compacts.
+    fn input_sst_ids(&self) -> Vec<usize> {
+        match self {
+            CompactionTask::ForceFullCompaction {
+                l0_sstables,
+                l1_sstables,
+            } => l0_sstables.iter().chain(l1_sstables).copied().collect(),
+            CompactionTask::Leveled(LeveledCompactionTask {
+                upper_level_sst_ids,
+                lower_level_sst_ids,
+                ..
+            })
+            | CompactionTask::Simple(SimpleLeveledCompactionTask {
+                upper_level_sst_ids,
+                lower_level_sst_ids,
+                ..
+            }) => upper_level_sst_ids
+                .iter()
+                .chain(lower_level_sst_ids)
+                .copied()
+                .collect(),
+            CompactionTask::Tiered(TieredCompactionTask { tiers, .. }) => tiers
+                .iter()
+                .flat_map(|(_, ssts)| ssts.iter().copied())
+                .collect(),
+        }
+    }
+}
+
+/// A range tombstone of an SST being compacted.
+struct CompactionRangeTombstone {
+    tombstone: RangeTombstone,
+    /// Whether the range overlaps an SST outside of the compaction, which may hold versions that
+    /// the tombstone deletes.
+    overlaps_other_tables: bool,
 }
 
 pub(crate) enum CompactionController {
@@ -67,6 +132,21 @@ pub(crate) enum CompactionController {
 }
 
 impl CompactionController {
+    pub fn new(options: &CompactionOptions) -> Self {
+        match options {
+            CompactionOptions::Leveled(options) => {
+                Self::Leveled(LeveledCompactionController::new(options.clone()))
+            }
+            CompactionOptions::Tiered(options) => {
+                Self::Tiered(TieredCompactionController::new(options.clone()))
+            }
+            CompactionOptions::Simple(options) => {
+                Self::Simple(SimpleLeveledCompactionController::new(options.clone()))
+            }
+            CompactionOptions::NoCompaction => Self::NoCompaction,
+        }
+    }
+
     pub fn generate_compaction_task(&self, snapshot: &LsmStorageState) -> Option<CompactionTask> {
         match self {
             CompactionController::Leveled(ctrl) => ctrl
@@ -99,6 +179,26 @@ impl CompactionController {
             (CompactionController::Tiered(ctrl), CompactionTask::Tiered(task)) => {
                 ctrl.apply_compaction_result(snapshot, task, output)
             }
+            (
+                CompactionController::NoCompaction,
+                CompactionTask::ForceFullCompaction {
+                    l0_sstables,
+                    l1_sstables,
+                },
+            ) => {
+                let mut snapshot = snapshot.clone();
+                let mut l0_sstables_map = l0_sstables.iter().copied().collect::<HashSet<_>>();
+                snapshot.l0_sstables.retain(|x| !l0_sstables_map.remove(x));
+                assert!(l0_sstables_map.is_empty());
+                assert_eq!(&snapshot.levels[0].1, l1_sstables);
+                snapshot.levels[0].1 = output.to_vec();
+                let files_to_remove = l0_sstables
+                    .iter()
+                    .chain(l1_sstables.iter())
+                    .copied()
+                    .collect();
+                (snapshot, files_to_remove)
+            }
             _ => unreachable!(),
         }
     }
@@ -113,7 +213,7 @@ impl CompactionController {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CompactionOptions {
     /// Leveled compaction with partial compaction + dynamic level support (= RocksDB's Leveled
     /// Compaction)
@@ -126,12 +226,273 @@ pub enum CompactionOptions {
     NoCompaction,
 }
 
+/// Run the compaction filters in order on a value, each seeing the value changed by the previous
+/// ones, until one of them removes it.
+fn run_compaction_filters(
+    compaction_filters: &[Arc<dyn CompactionFilter>],
+    level: usize,
+    key: KeySlice,
+    value: &[u8],
+) -> Result<CompactionDecision> {
+    let mut decision = CompactionDecision::Keep;
+    for filter in compaction_filters {
+        let current_value = match &decision {
+            CompactionDecision::ChangeValue(value) => value.as_ref(),
+            _ => value,
+        };
+        let new_decision = filter
+            .filter(level, key.key_ref(), key.ts(), current_value)
+            .with_context(|| format!("compaction filter {} failed", filter.name()))?;
+        match new_decision {
+            CompactionDecision::Keep => {}
+            CompactionDecision::Remove => return Ok(CompactionDecision::Remove),
+            CompactionDecision::ChangeValue(value) => {
+                decision = CompactionDecision::ChangeValue(value)
+            }
+        }
+    }
+    Ok(decision)
+}
+
 impl LsmStorageInner {
+    /// Write the discard counters that compactions changed to the manifest.
+    fn record_value_log_discards(&self, state_lock: &MutexGuard<'_, ()>) -> Result<()> {
+        let discarded = self.value_log.take_updated_discarded();
+        if !discarded.is_empty() {
+            self.manifest()
+                .add_record(state_lock, ManifestRecord::ValueLogDiscarded(discarded))?;
+        }
+        Ok(())
+    }
+
+    /// Account for an entry that compaction drops, if its value is in the value log.
+    fn discard_value(&self, kind: ValueKind, value: &[u8]) -> Result<()> {
+        if kind == ValueKind::ValuePointer {
+            self.value_log.discard(ValuePointer::decode(value)?);
+        }
+        Ok(())
+    }
+
+    /// Run the compaction filters on the current entry of `iter`, folding it with the earlier
+    /// versions first if it is a merge operand. Returns `None` to keep the entry as it is, or the
+    /// entries that replace it after moving `iter` past it.
+    fn filter_entry<I>(
+        &self,
+        iter: &mut I,
+        compaction_filters: &[Arc<dyn CompactionFilter>],
+        level: usize,
+        covering_tombstones: &[RangeTombstone],
+        compact_to_bottom_level: bool,
+        now: u64,
+    ) -> Result<Option<Vec<(KeyVec, ValueKind, Bytes)>>>
+    where
+        I: for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>> + 'static,
+    {
+        let kind = iter.value_kind();
+        if kind == ValueKind::MergeOperand {
+            let Some(merge_operator) = &self.options.merge_operator else {
+                return Ok(None);
+            };
+            let mut entries = self.fold_merge_operands(
+                iter,
+                merge_operator.as_ref(),
+                covering_tombstones,
+                compact_to_bottom_level,
+                now,
+            )?;
+            if let [(key, ValueKind::Value, value)] = &mut entries[..]
+                && !value.is_empty()
+            {
+                match run_compaction_filters(compaction_filters, level, key.as_key_slice(), value)?
+                {
+                    CompactionDecision::Keep => {}
+                    CompactionDecision::Remove => *value = Bytes::new(),
+                    CompactionDecision::ChangeValue(new_value) => *value = new_value,
+                }
+            }
+            return Ok(Some(entries));
+        }
+        if compaction_filters.is_empty() || (kind == ValueKind::Value && iter.value().is_empty()) {
+            return Ok(None);
+        }
+        let key = iter.key().to_key_vec();
+        let mut expire_at = None;
+        let value = match kind {
+            ValueKind::Value => Bytes::copy_from_slice(iter.value()),
+            ValueKind::ValuePointer => {
+                resolve_value(&self.value_log.snapshot(), key.key_ref(), iter.value())?
+            }
+            ValueKind::ExpiringValue => {
+                let (expiry, value) = ttl::decode_expiring_value(iter.value())?;
+                if expiry <= now {
+                    return Ok(None);
+                }
+                expire_at = Some(expiry);
+                Bytes::copy_from_slice(value)
+            }
+            ValueKind::MergeOperand => unreachable!(),
+        };
+        let entry =
+            match run_compaction_filters(compaction_filters, level, key.as_key_slice(), &value)? {
+                CompactionDecision::Keep => return Ok(None),
+                CompactionDecision::ChangeValue(value) if !value.is_empty() => match expire_at {
+                    // The changed value expires at the same time.
+                    Some(expire_at) => (
+                        key,
+                        ValueKind::ExpiringValue,
+                        ttl::encode_expiring_value(expire_at, &value).into(),
+                    ),
+                    None => (key, ValueKind::Value, value),
+                },
+                CompactionDecision::Remove | CompactionDecision::ChangeValue(_) => {
+                    (key, ValueKind::Value, Bytes::new())
+                }
+            };
+        self.discard_value(kind, iter.value())?;
+        iter.next()?;
+        Ok(Some(vec![entry]))
+    }
+
+    /// Fold the merge operands from the current entry of `iter` into a single value if the
+    /// compaction sees every version they apply to, moving `iter` past the operands. Returns the
+    /// entries to write for them, which are the operands themselves if they cannot be folded.
+    fn fold_merge_operands<I>(
+        &self,
+        iter: &mut I,
+        merge_operator: &dyn MergeOperator,
+        covering_tombstones: &[RangeTombstone],
+        compact_to_bottom_level: bool,
+        now: u64,
+    ) -> Result<Vec<(KeyVec, ValueKind, Bytes)>>
+    where
+        I: for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
+    {
+        let key = iter.key().to_key_vec();
+        let mut operand_keys = Vec::new();
+        let mut operands = Vec::new();
+        let mut complete = false;
+        let mut existing = None;
+        while iter.is_valid() && iter.key().key_ref() == key.key_ref() {
+            if is_covered(
+                covering_tombstones,
+                iter.key().key_ref(),
+                iter.key().ts(),
+                self.options.comparator,
+            ) {
+                complete = true;
+                break;
+            }
+            match iter.value_kind() {
+                ValueKind::MergeOperand => {
+                    operand_keys.push(iter.key().to_key_vec());
+                    operands.push(Bytes::copy_from_slice(iter.value()));
+                }
+                ValueKind::Value => {
+                    if !iter.value().is_empty() {
+                        existing = Some(Bytes::copy_from_slice(iter.value()));
+                    }
+                    complete = true;
+                    break;
+                }
+                ValueKind::ValuePointer => {
+                    existing = Some(resolve_value(
+                        &self.value_log.snapshot(),
+                        key.key_ref(),
+                        iter.value(),
+                    )?);
+                    complete = true;
+                    break;
+                }
+                ValueKind::ExpiringValue => {
+                    existing = ttl::unexpired_value(iter.value(), now)?.map(Bytes::copy_from_slice);
+                    complete = true;
+                    break;
+                }
+            }
+            iter.next()?;
+        }
+        // Without a base version, the operands may still apply to a version in a lower level.
+        if !complete && !compact_to_bottom_level {
+            let mut entries = Vec::with_capacity(operands.len());
+            for (key, operand) in operand_keys.into_iter().zip(operands) {
+                entries.push((key, ValueKind::MergeOperand, operand));
+            }
+            return Ok(entries);
+        }
+        let value = merge_operands(
+            merge_operator,
+            key.key_ref(),
+            existing.as_deref(),
+            &operands,
+        )?;
+        Ok(vec![(key, ValueKind::Value, value)])
+    }
+
+    /// Collect the range tombstones of the input SSTs of a compaction.
+    fn compaction_range_tombstones(
+        snapshot: &LsmStorageState,
+        task: &CompactionTask,
+    ) -> Vec<CompactionRangeTombstone> {
+        let input_sst_ids = task.input_sst_ids();
+        let mut range_tombstones = Vec::new();
+        for id in &input_sst_ids {
+            for tombstone in snapshot.sstables[id].range_tombstones() {
+                let overlaps_other_tables = snapshot.sstables.iter().any(|(id, table)| {
+                    !input_sst_ids.contains(id)
+                        && tombstone.overlaps_keys(
+                            table.first_key().key_ref(),
+                            table.last_key().key_ref(),
+                            table.comparator(),
+                        )
+                });
+                range_tombstones.push(CompactionRangeTombstone {
+                    tombstone: tombstone.clone(),
+                    overlaps_other_tables,
+                });
+            }
+        }
+        range_tombstones
+    }
+
+    /// Add the parts of the range tombstones before `split_key`, which is the first key of the
+    /// next SST, to the SST that ends there. The rest of them is kept for the next SSTs, so the
+    /// tombstones of every SST stay within its key range.
+    fn split_range_tombstones(
+        &self,
+        builder: &mut SsTableBuilder,
+        tombstones: &mut Vec<RangeTombstone>,
+        split_key: &[u8],
+    ) {
+        let comparator = self.options.comparator;
+        tombstones.retain_mut(|tombstone| {
+            if comparator.compare(&tombstone.start, split_key).is_ge() {
+                return true;
+            }
+            if comparator.compare(&tombstone.end, split_key).is_le() {
+                builder.add_range_tombstone(tombstone.clone());
+                return false;
+            }
+            let split_key = Bytes::copy_from_slice(split_key);
+            builder.add_range_tombstone(RangeTombstone::new(
+                tombstone.start.clone(),
+                split_key.clone(),
+                tombstone.ts,
+            ));
+            tombstone.start = split_key;
+            true
+        });
+    }
+
     fn compact_generate_sst_from_iter(
         &self,
-        mut iter: impl for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
+        mut iter: impl for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>> + 'static,
         compact_to_bottom_level: bool,
+        level: usize,
+        range_tombstones: Vec<CompactionRangeTombstone>,
+        column_family: u32,
     ) -> Result<Vec<Arc<SsTable>>> {
+        let new_builder =
+            || SsTableBuilder::new_with_options(&self.options).with_column_family(column_family);
         let mut builder = None;
         let mut entries_in_builder: usize = 0;
         let mut new_sst = Vec::new();
@@ -139,9 +500,27 @@ impl LsmStorageInner {
         let mut last_key = Vec::<u8>::new();
         let mut first_key_below_watermark = false;
         let compaction_filters = self.compaction_filters.lock().clone();
-        'outer: while iter.is_valid() {
+        let now = ttl::now_millis();
+        // The tombstones below the watermark are visible to every reader, so the versions they
+        // cover can be dropped. Once they are also in the bottom level and no other SST may hold
+        // the versions they cover, the tombstones themselves are dropped.
+        let mut covering_tombstones = Vec::new();
+        let mut pending_tombstones = Vec::new();
+        for CompactionRangeTombstone {
+            tombstone,
+            overlaps_other_tables,
+        } in range_tombstones
+        {
+            if tombstone.ts <= watermark {
+                covering_tombstones.push(tombstone.clone());
+            }
+            if !compact_to_bottom_level || tombstone.ts > watermark || overlaps_other_tables {
+                pending_tombstones.push(tombstone);
+            }
+        }
+        while iter.is_valid() {
             if builder.is_none() {
-                builder = Some(SsTableBuilder::new(self.options.block_size));
+                builder = Some(new_builder());
             }
 
             let same_as_last_key = iter.key().key_ref() == last_key;
@@ -149,6 +528,22 @@ impl LsmStorageInner {
                 first_key_below_watermark = true;
             }
 
+            if is_covered(
+                &covering_tombstones,
+                iter.key().key_ref(),
+                iter.key().ts(),
+                self.options.comparator,
+            ) {
+                self.discard_value(iter.value_kind(), iter.value())?;
+                if !same_as_last_key {
+                    last_key.clear();
+                    last_key.extend(iter.key().key_ref());
+                }
+                first_key_below_watermark = false;
+                iter.next()?;
+                continue;
+            }
+
             if compact_to_bottom_level
                 && !same_as_last_key
                 && iter.key().ts() <= watermark
@@ -163,23 +558,72 @@ impl LsmStorageInner {
 
             if iter.key().ts() <= watermark {
                 if !first_key_below_watermark {
+                    self.discard_value(iter.value_kind(), iter.value())?;
                     iter.next()?;
                     continue;
                 }
 
                 first_key_below_watermark = false;
 
-                if !compaction_filters.is_empty() {
-                    for filter in &compaction_filters {
-                        match filter {
-                            CompactionFilter::Prefix(x) => {
-                                if iter.key().key_ref().starts_with(x) {
-                                    iter.next()?;
-                                    continue 'outer;
-                                }
-                            }
+                // An expired value hides the earlier versions of its key, so it is only dropped
+                // once no lower level may hold them.
+                if compact_to_bottom_level
+                    && iter.value_kind() == ValueKind::ExpiringValue
+                    && ttl::unexpired_value(iter.value(), now)?.is_none()
+                {
+                    if !same_as_last_key {
+                        last_key.clear();
+                        last_key.extend(iter.key().key_ref());
+                    }
+                    iter.next()?;
+                    continue;
+                }
+
+                if let Some(entries) = self.filter_entry(
+                    &mut iter,
+                    &compaction_filters,
+                    level,
+                    &covering_tombstones,
+                    compact_to_bottom_level,
+                    now,
+                )? {
+                    // The earlier versions are dropped, so there is nothing left to delete.
+                    let drop_deletion = compact_to_bottom_level
+                        && matches!(&entries[..], [(_, ValueKind::Value, value)] if value.is_empty());
+                    if !drop_deletion {
+                        if builder.as_ref().unwrap().estimated_size()
+                            >= self.options.target_sst_size
+                            && !same_as_last_key
+                            && entries_in_builder > 0
+                        {
+                            let sst_id = self.next_sst_id();
+                            let mut old_builder = builder.take().unwrap();
+                            self.split_range_tombstones(
+                                &mut old_builder,
+                                &mut pending_tombstones,
+                                entries[0].0.key_ref(),
+                            );
+                            let sst = Arc::new(old_builder.build(
+                                sst_id,
+                                Some(self.block_cache.clone()),
+                                self.path_of_sst(sst_id),
+                            )?);
+                            new_sst.push(sst);
+                            builder = Some(new_builder());
+                            entries_in_builder = 0;
                         }
+                        let builder_inner = builder.as_mut().unwrap();
+                        for (key, kind, value) in &entries {
+                            builder_inner.add_with_kind(key.as_key_slice(), *kind, value);
+                            entries_in_builder += 1;
+                        }
+                    }
+                    if !same_as_last_key {
+                        last_key.clear();
+                        last_key.extend(entries[0].0.key_ref());
                     }
+                    // `iter` is already past the entries they replace.
+                    continue;
                 }
             }
 
@@ -190,19 +634,24 @@ impl LsmStorageInner {
                 && entries_in_builder > 0
             {
                 let sst_id = self.next_sst_id();
-                let old_builder = builder.take().unwrap();
+                let mut old_builder = builder.take().unwrap();
+                self.split_range_tombstones(
+                    &mut old_builder,
+                    &mut pending_tombstones,
+                    iter.key().key_ref(),
+                );
                 let sst = Arc::new(old_builder.build(
                     sst_id,
                     Some(self.block_cache.clone()),
                     self.path_of_sst(sst_id),
                 )?);
                 new_sst.push(sst);
-                builder = Some(SsTableBuilder::new(self.options.block_size));
+                builder = Some(new_builder());
                 entries_in_builder = 0;
             }
 
             let builder_inner = builder.as_mut().unwrap();
-            builder_inner.add(iter.key(), iter.value());
+            builder_inner.add_with_kind(iter.key(), iter.value_kind(), iter.value());
             entries_in_builder += 1;
 
             if !same_as_last_key {
@@ -212,8 +661,17 @@ impl LsmStorageInner {
 
             iter.next()?;
         }
+        // The last SST takes the rest of the range tombstones. It may have no keys at all if
+        // every entry is dropped.
+        let has_range_tombstones = !pending_tombstones.is_empty();
+        if has_range_tombstones {
+            let builder = builder.get_or_insert_with(new_builder);
+            for tombstone in pending_tombstones {
+                builder.add_range_tombstone(tombstone);
+            }
+        }
         if let Some(builder) = builder
-            && entries_in_builder > 0
+            && (entries_in_builder > 0 || has_range_tombstones)
         {
             let sst_id = self.next_sst_id(); // lock dropped here
             let sst = Arc::new(builder.build(
@@ -226,11 +684,16 @@ impl LsmStorageInner {
         Ok(new_sst)
     }
 
-    fn compact(&self, task: &CompactionTask) -> Result<Vec<Arc<SsTable>>> {
-        let snapshot = {
-            let state = self.state.read();
-            state.clone()
-        };
+    /// Run a compaction task on the state of the column family with the id.
+    fn compact(
+        &self,
+        snapshot: &LsmStorageState,
+        task: &CompactionTask,
+        column_family: u32,
+    ) -> Result<Vec<Arc<SsTable>>> {
+        let range_tombstones = Self::compaction_range_tombstones(snapshot, task);
+        let level = task.output_level(snapshot);
+        let comparator = self.options.comparator;
         match task {
             CompactionTask::ForceFullCompaction {
                 l0_sstables,
@@ -246,11 +709,18 @@ impl LsmStorageInner {
                 for id in l1_sstables.iter() {
                     l1_iters.push(snapshot.sstables.get(id).unwrap().clone());
                 }
-                let iter = TwoMergeIterator::create(
-                    MergeIterator::create(l0_iters),
+                let iter = TwoMergeIterator::create_with_comparator(
+                    MergeIterator::create_with_comparator(l0_iters, comparator),
                     SstConcatIterator::create_and_seek_to_first(l1_iters)?,
+                    comparator,
                 )?;
-                self.compact_generate_sst_from_iter(iter, task.compact_to_bottom_level())
+                self.compact_generate_sst_from_iter(
+                    iter,
+                    task.compact_to_bottom_level(),
+                    level,
+                    range_tombstones,
+                    column_family,
+                )
             }
             CompactionTask::Simple(SimpleLeveledCompactionTask {
                 upper_level,
@@ -278,8 +748,13 @@ impl LsmStorageInner {
                     }
                     let lower_iter = SstConcatIterator::create_and_seek_to_first(lower_ssts)?;
                     self.compact_generate_sst_from_iter(
-                        TwoMergeIterator::create(upper_iter, lower_iter)?,
+                        TwoMergeIterator::create_with_comparator(
+                            upper_iter, lower_iter, comparator,
+                        )?,
                         task.compact_to_bottom_level(),
+                        level,
+                        range_tombstones,
+                        column_family,
                     )
                 }
                 None => {
@@ -289,15 +764,20 @@ impl LsmStorageInner {
                             snapshot.sstables.get(id).unwrap().clone(),
                         )?));
                     }
-                    let upper_iter = MergeIterator::create(upper_iters);
+                    let upper_iter = MergeIterator::create_with_comparator(upper_iters, comparator);
                     let mut lower_ssts = Vec::with_capacity(lower_level_sst_ids.len());
                     for id in lower_level_sst_ids.iter() {
                         lower_ssts.push(snapshot.sstables.get(id).unwrap().clone());
                     }
                     let lower_iter = SstConcatIterator::create_and_seek_to_first(lower_ssts)?;
                     self.compact_generate_sst_from_iter(
-                        TwoMergeIterator::create(upper_iter, lower_iter)?,
+                        TwoMergeIterator::create_with_comparator(
+                            upper_iter, lower_iter, comparator,
+                        )?,
                         task.compact_to_bottom_level(),
+                        level,
+                        range_tombstones,
+                        column_family,
                     )
                 }
             },
@@ -311,17 +791,23 @@ impl LsmStorageInner {
                     iters.push(Box::new(SstConcatIterator::create_and_seek_to_first(ssts)?));
                 }
                 self.compact_generate_sst_from_iter(
-                    MergeIterator::create(iters),
+                    MergeIterator::create_with_comparator(iters, comparator),
                     task.compact_to_bottom_level(),
+                    level,
+                    range_tombstones,
+                    column_family,
                 )
             }
         }
     }
 
     pub fn force_full_compaction(&self) -> Result<()> {
+        self.ensure_writable()?;
         let CompactionOptions::NoCompaction = self.options.compaction_options else {
             panic!("full compaction can only be called with compaction is not enabled")
         };
+        // Compaction drops entries, so it must not run while the value log is being collected.
+        let _gc_lock = self.value_log.gc_lock.lock();
 
         let snapshot = {
             let state = self.state.read();
@@ -337,7 +823,7 @@ impl LsmStorageInner {
 
         println!("force full compaction: {:?}", compaction_task);
 
-        let sstables = self.compact(&compaction_task)?;
+        let sstables = self.compact(&snapshot, &compaction_task, 0)?;
         let mut ids = Vec::with_capacity(sstables.len());
 
         {
@@ -368,6 +854,7 @@ impl LsmStorageInner {
                 &state_lock,
                 ManifestRecord::Compaction(compaction_task, ids.clone()),
             )?;
+            self.record_value_log_discards(&state_lock)?;
         }
         for sst in l0_sstables.iter().chain(l1_sstables.iter()) {
             std::fs::remove_file(self.path_of_sst(*sst))?;
@@ -379,32 +866,57 @@ impl LsmStorageInner {
     }
 
     fn trigger_compaction(&self) -> Result<()> {
-        let snapshot = {
-            let state = self.state.read();
-            state.clone()
+        let column_families = self
+            .state
+            .read()
+            .column_families
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        self.trigger_column_family_compaction(DEFAULT_COLUMN_FAMILY)?;
+        for column_family in column_families {
+            self.trigger_column_family_compaction(&column_family)?;
+        }
+        Ok(())
+    }
+
+    fn trigger_column_family_compaction(&self, column_family: &str) -> Result<()> {
+        // Ingestion places SSTs in the levels under the lock, so the task is generated from a
+        // snapshot that stays valid until the result is applied.
+        let _gc_lock = self.value_log.gc_lock.lock();
+        let snapshot = self.column_family_snapshot(column_family)?;
+        let (id, family_controller) = match self.state.read().column_families.get(column_family) {
+            Some(family) => (
+                family.id,
+                Some(CompactionController::new(&family.compaction_options)),
+            ),
+            None => (0, None),
         };
-        let task = self
-            .compaction_controller
-            .generate_compaction_task(&snapshot);
+        let compaction_controller = family_controller
+            .as_ref()
+            .unwrap_or(&self.compaction_controller);
+        if let CompactionController::NoCompaction = compaction_controller {
+            return Ok(());
+        }
+        let task = compaction_controller.generate_compaction_task(&snapshot);
         let Some(task) = task else {
             return Ok(());
         };
         self.dump_structure();
         println!("running compaction task: {:?}", task);
-        let sstables = self.compact(&task)?;
+        let sstables = self.compact(&snapshot, &task, id)?;
         let output = sstables.iter().map(|x| x.sst_id()).collect::<Vec<_>>();
         let ssts_to_remove = {
             let state_lock = self.state_lock.lock();
-            let mut snapshot = self.state.read().as_ref().clone();
+            let mut snapshot = self.column_family_snapshot(column_family)?.as_ref().clone();
             let mut new_sst_ids = Vec::new();
             for file_to_add in sstables {
                 new_sst_ids.push(file_to_add.sst_id());
                 let result = snapshot.sstables.insert(file_to_add.sst_id(), file_to_add);
                 assert!(result.is_none());
             }
-            let (mut snapshot, files_to_remove) = self
-                .compaction_controller
-                .apply_compaction_result(&snapshot, &task, &output, false);
+            let (mut snapshot, files_to_remove) =
+                compaction_controller.apply_compaction_result(&snapshot, &task, &output, false);
 
             let mut ssts_to_remove = Vec::with_capacity(files_to_remove.len());
             for file_to_remove in &files_to_remove {
@@ -413,11 +925,23 @@ impl LsmStorageInner {
                 ssts_to_remove.push(result.unwrap());
             }
             let mut state = self.state.write();
-            *state = Arc::new(snapshot);
+            let record = if column_family == DEFAULT_COLUMN_FAMILY {
+                *state = Arc::new(snapshot);
+                ManifestRecord::Compaction(task, new_sst_ids)
+            } else {
+                let mut default_state = state.as_ref().clone();
+                let family = default_state
+                    .column_families
+                    .get_mut(column_family)
+                    .unwrap();
+                family.state = Arc::new(snapshot);
+                *state = Arc::new(default_state);
+                ManifestRecord::ColumnFamilyCompaction(id, task, new_sst_ids)
+            };
             drop(state);
             self.sync_dir()?;
-            self.manifest()
-                .add_record(&state_lock, ManifestRecord::Compaction(task, new_sst_ids))?;
+            self.manifest().add_record(&state_lock, record)?;
+            self.record_value_log_discards(&state_lock)?;
             ssts_to_remove
         };
         println!(
@@ -438,25 +962,35 @@ impl LsmStorageInner {
         self: &Arc<Self>,
         rx: crossbeam_channel::Receiver<()>,
     ) -> Result<Option<std::thread::JoinHandle<()>>> {
-        if let CompactionOptions::Leveled(_)
-        | CompactionOptions::Simple(_)
-        | CompactionOptions::Tiered(_) = self.options.compaction_options
-        {
-            let this = self.clone();
-            let handle = std::thread::spawn(move || {
-                let ticker = crossbeam_channel::tick(Duration::from_millis(50));
-                loop {
-                    crossbeam_channel::select! {
-                        recv(ticker) -> _ => if let Err(e) = this.trigger_compaction() {
+        // The compaction thread always runs, as the column families may be compacted even if the
+        // default column family is not.
+        let value_log_gc_enabled = self.options.value_separation_threshold.is_some();
+        let this = self.clone();
+        let handle = std::thread::spawn(move || {
+            let ticker = crossbeam_channel::tick(Duration::from_millis(50));
+            let gc_ticker = if value_log_gc_enabled {
+                crossbeam_channel::tick(Duration::from_secs(10))
+            } else {
+                crossbeam_channel::never()
+            };
+            loop {
+                crossbeam_channel::select! {
+                    recv(ticker) -> _ => {
+                        if let Err(e) = this.trigger_compaction() {
                             eprintln!("compaction failed: {}", e);
-                        },
-                        recv(rx) -> _ => return
-                    }
+                        }
+                        if let Err(e) = this.trigger_manifest_rotation() {
+                            eprintln!("manifest rotation failed: {}", e);
+                        }
+                    },
+                    recv(gc_ticker) -> _ => if let Err(e) = this.trigger_value_log_gc() {
+                        eprintln!("value log gc failed: {}", e);
+                    },
+                    recv(rx) -> _ => return
                 }
-            });
-            return Ok(Some(handle));
-        }
-        Ok(None)
+            }
+        });
+        Ok(Some(handle))
     }
 
     fn trigger_flush(&self) -> Result<()> {
@@ -16,6 +16,7 @@ use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 
+use crate::key::KeyBytes;
 use crate::lsm_storage::LsmStorageState;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,7 +29,7 @@ pub struct LeveledCompactionTask {
     pub is_lower_level_bottom_level: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LeveledCompactionOptions {
     pub level_size_multiplier: usize,
     pub level0_file_num_compaction_trigger: usize,
@@ -51,24 +52,35 @@ impl LeveledCompactionController {
         sst_ids: &[usize],
         in_level: usize,
     ) -> Vec<usize> {
+        // The SSTs of a storage share the comparator of its options.
+        let comparator = snapshot.sstables[&sst_ids[0]].comparator();
+        let compare = |a: &&KeyBytes, b: &&KeyBytes| {
+            comparator.compare_keys(a.as_key_slice(), b.as_key_slice())
+        };
         let begin_key = sst_ids
             .iter()
             .map(|id| snapshot.sstables[id].first_key())
-            .min()
+            .min_by(compare)
             .cloned()
             .unwrap();
         let end_key = sst_ids
             .iter()
             .map(|id| snapshot.sstables[id].last_key())
-            .max()
+            .max_by(compare)
             .cloned()
             .unwrap();
         let mut overlap_ssts = Vec::new();
         for sst_id in &snapshot.levels[in_level - 1].1 {
             let sst = &snapshot.sstables[sst_id];
-            let first_key = sst.first_key();
-            let last_key = sst.last_key();
-            if !(last_key < &begin_key || first_key > &end_key) {
+            let first_key = sst.first_key().as_key_slice();
+            let last_key = sst.last_key().as_key_slice();
+            if !(comparator
+                .compare_keys(last_key, begin_key.as_key_slice())
+                .is_lt()
+                || comparator
+                    .compare_keys(first_key, end_key.as_key_slice())
+                    .is_gt())
+            {
                 overlap_ssts.push(*sst_id);
             }
         }
@@ -234,12 +246,10 @@ impl LeveledCompactionController {
         // Don't sort the SST IDs during recovery because actual SSTs are not loaded at that point
         if !in_recovery {
             new_lower_level_ssts.sort_by(|x, y| {
-                snapshot
-                    .sstables
-                    .get(x)
-                    .unwrap()
-                    .first_key()
-                    .cmp(snapshot.sstables.get(y).unwrap().first_key())
+                let x = snapshot.sstables.get(x).unwrap();
+                let y = snapshot.sstables.get(y).unwrap();
+                x.comparator()
+                    .compare_keys(x.first_key().as_key_slice(), y.first_key().as_key_slice())
             });
         }
         snapshot.levels[task.lower_level - 1].1 = new_lower_level_ssts;
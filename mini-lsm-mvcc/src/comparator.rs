@@ -0,0 +1,110 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! User key comparators. The comparator of a database orders its user keys everywhere: in the
+//! memtables, in the blocks and the metadata of the SSTs, and in the iterators that merge them.
+//! The versions of a user key are always ordered from the latest to the earliest.
+
+use std::cmp::Ordering;
+
+use crate::key::{KeyBytes, KeySlice};
+
+/// Orders user keys.
+///
+/// Only identical keys may compare equal, and the order must never change for the lifetime of a
+/// database, which records the name of its comparator.
+pub trait Comparator: Send + Sync {
+    /// Identifies the comparator. A database can only be opened with a comparator of the same
+    /// name as the one it was created with.
+    fn name(&self) -> &str;
+
+    /// Compares two user keys.
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering;
+}
+
+impl dyn Comparator + '_ {
+    /// Compares two keys: the user keys with the comparator, then the timestamps from the latest to
+    /// the earliest.
+    pub fn compare_keys(&self, a: KeySlice, b: KeySlice) -> Ordering {
+        self.compare(a.key_ref(), b.key_ref())
+            .then_with(|| b.ts().cmp(&a.ts()))
+    }
+}
+
+/// A key of a skip list, which cannot be given a comparator, so its keys are ordered by the
+/// comparator they carry. The owner of a skip list makes all of its keys with its own comparator.
+#[derive(Clone)]
+pub(crate) struct OrderedKey {
+    key: KeyBytes,
+    comparator: &'static dyn Comparator,
+}
+
+impl OrderedKey {
+    pub(crate) fn new(key: KeyBytes, comparator: &'static dyn Comparator) -> Self {
+        Self { key, comparator }
+    }
+
+    pub(crate) fn key(&self) -> &KeyBytes {
+        &self.key
+    }
+}
+
+impl PartialEq for OrderedKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for OrderedKey {}
+
+impl PartialOrd for OrderedKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrderedKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.comparator
+            .compare_keys(self.key.as_key_slice(), other.key.as_key_slice())
+    }
+}
+
+impl std::fmt::Debug for dyn Comparator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Orders user keys by their bytes, lexicographically. This is the default comparator.
+#[derive(Debug, Clone, Copy)]
+pub struct BytewiseComparator;
+
+impl Comparator for BytewiseComparator {
+    fn name(&self) -> &str {
+        "bytewise"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+pub static BYTEWISE_COMPARATOR: BytewiseComparator = BytewiseComparator;
+
+/// Returns true if `comparator` orders keys by their bytes, so that the keys with a common prefix
+/// are next to each other.
+pub fn is_bytewise(comparator: &dyn Comparator) -> bool {
+    comparator.name() == BYTEWISE_COMPARATOR.name()
+}
@@ -0,0 +1,287 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bulk loading of sorted data. [`SstFileWriter`] writes an SST outside of a database, and
+//! ingestion moves it into the levels of the LSM tree without going through the mem-table, the
+//! WAL and L0.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail, ensure};
+
+use crate::compact::CompactionController;
+use crate::comparator::Comparator;
+use crate::iterators::{StorageIterator, ValueKind};
+use crate::key::{KeySlice, TS_DEFAULT};
+use crate::lsm_storage::{
+    DEFAULT_COLUMN_FAMILY, LsmStorageInner, LsmStorageOptions, LsmStorageState, add_flushed_sst,
+};
+use crate::manifest::ManifestRecord;
+use crate::table::{FileObject, Footer, SsTable, SsTableBuilder, SsTableIterator};
+
+/// Writes an SST of keys added in ascending order, to be ingested into a database with
+/// [`LsmStorageInner::ingest_external_files`].
+pub struct SstFileWriter {
+    builder: SsTableBuilder,
+    path: PathBuf,
+    comparator: &'static dyn Comparator,
+    last_key: Option<Vec<u8>>,
+}
+
+impl SstFileWriter {
+    /// Create a writer of an SST at `path`, with the block size, compression and comparator of the
+    /// options of the database that the SST is ingested into.
+    pub fn create(path: impl AsRef<Path>, options: &LsmStorageOptions) -> Self {
+        Self {
+            builder: SsTableBuilder::new_with_options(options),
+            path: path.as_ref().to_path_buf(),
+            comparator: options.comparator,
+            last_key: None,
+        }
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        ensure!(!value.is_empty(), "value cannot be empty");
+        self.add(key, value)
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.add(key, b"")
+    }
+
+    fn add(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        ensure!(!key.is_empty(), "key cannot be empty");
+        if let Some(last_key) = &self.last_key {
+            ensure!(
+                self.comparator.compare(last_key, key).is_lt(),
+                "keys must be added in ascending order without duplicates"
+            );
+        }
+        // The keys get the commit timestamp of the ingestion when they are ingested.
+        self.builder
+            .add(KeySlice::from_slice_with_ts(key, TS_DEFAULT), value);
+        self.last_key = Some(key.to_vec());
+        Ok(())
+    }
+
+    /// Write the SST to its path. The SST must have at least one key.
+    pub fn finish(self) -> Result<()> {
+        ensure!(self.last_key.is_some(), "cannot write an SST without keys");
+        self.builder.build(0, None, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Whether the key ranges of two SSTs overlap.
+fn overlaps(a: &SsTable, b: &SsTable) -> bool {
+    let comparator = a.comparator();
+    comparator
+        .compare(a.first_key().key_ref(), b.last_key().key_ref())
+        .is_le()
+        && comparator
+            .compare(b.first_key().key_ref(), a.last_key().key_ref())
+            .is_le()
+}
+
+impl LsmStorageInner {
+    /// Ingest SSTs written by [`SstFileWriter`] into the default column family, returning the
+    /// commit timestamp that their keys are written with. See
+    /// [`LsmStorageInner::ingest_external_files_cf`].
+    pub fn ingest_external_files(&self, paths: &[impl AsRef<Path>]) -> Result<u64> {
+        self.ingest_external_files_cf(DEFAULT_COLUMN_FAMILY, paths)
+    }
+
+    /// Ingest SSTs written by [`SstFileWriter`] into a column family, returning the commit
+    /// timestamp that their keys are written with. The SSTs must not overlap each other. Each SST
+    /// is copied into the database and placed in the lowest level that neither it nor the levels
+    /// above overlap, or in L0. The copies are written before the commit timestamp is assigned,
+    /// and get it as the global timestamp in their footers, so writes only wait while the SSTs are
+    /// placed.
+    pub fn ingest_external_files_cf(
+        &self,
+        column_family: &str,
+        paths: &[impl AsRef<Path>],
+    ) -> Result<u64> {
+        self.ensure_writable()?;
+        let comparator = self.options.comparator;
+        let (family_id, _) = self.state.read().column_family(column_family)?;
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = path.as_ref();
+            let file = SsTable::open_with_comparator(
+                0,
+                None,
+                FileObject::open(path).context("failed to open external SST")?,
+                comparator,
+            )
+            .with_context(|| format!("invalid external SST {}", path.display()))?;
+            ensure!(
+                file.range_tombstones().is_empty(),
+                "external SST {} has range tombstones",
+                path.display()
+            );
+            files.push((path, Arc::new(file)));
+        }
+        files.sort_by(|(_, a), (_, b)| {
+            a.comparator()
+                .compare_keys(a.first_key().as_key_slice(), b.first_key().as_key_slice())
+        });
+        for pair in files.windows(2) {
+            ensure!(
+                !overlaps(&pair[0].1, &pair[1].1),
+                "external SSTs {} and {} overlap",
+                pair[0].0.display(),
+                pair[1].0.display()
+            );
+        }
+
+        let mut sst_ids = Vec::with_capacity(files.len());
+        for (path, file) in files {
+            let sst_id = self.next_sst_id();
+            let mut builder = SsTableBuilder::new_with_value_log(&self.options, sst_id)
+                .with_column_family(family_id);
+            let mut iter = SsTableIterator::create_and_seek_to_first(file)?;
+            while iter.is_valid() {
+                if iter.value_kind() == ValueKind::ValuePointer {
+                    bail!("external SST {} has values in a value log", path.display());
+                }
+                builder.add_with_kind(
+                    KeySlice::from_slice_with_ts(iter.key().key_ref(), TS_DEFAULT),
+                    iter.value_kind(),
+                    iter.value(),
+                );
+                iter.next()?;
+            }
+            let has_value_log = builder.has_separated_values();
+            // The table is opened again once its footer has the global timestamp.
+            builder.build(sst_id, None, self.path_of_sst(sst_id))?;
+            sst_ids.push((sst_id, has_value_log));
+        }
+        self.sync_dir()?;
+        for (sst_id, has_value_log) in &sst_ids {
+            if *has_value_log {
+                self.value_log.add_file(*sst_id)?;
+            }
+        }
+
+        // Compaction generates its tasks under the lock, so the levels do not change while a
+        // compaction is running. The lock is taken before the write lock, which is held for as
+        // short as possible: writes never wait for a compaction.
+        let _gc_lock = self.value_log.gc_lock.lock();
+        let _lck = self.mvcc().write_lock.lock();
+        let ts = self.mvcc().latest_commit_ts() + 1;
+        let mut ssts = Vec::with_capacity(sst_ids.len());
+        for (sst_id, _) in sst_ids {
+            let path = self.path_of_sst(sst_id);
+            Footer::write_global_ts(&path, ts)?;
+            ssts.push(Arc::new(SsTable::open_with_comparator(
+                sst_id,
+                Some(self.block_cache.clone()),
+                FileObject::open(&path)?,
+                comparator,
+            )?));
+        }
+
+        let state_lock = self.state_lock.lock();
+        let mut placements = Vec::with_capacity(ssts.len());
+        {
+            let mut guard = self.state.write();
+            let mut snapshot = guard.as_ref().clone();
+            let (controller, family_state) = if column_family == DEFAULT_COLUMN_FAMILY {
+                (None, &mut snapshot)
+            } else {
+                let family = snapshot.column_families.get_mut(column_family).unwrap();
+                (
+                    Some(CompactionController::new(&family.compaction_options)),
+                    Arc::make_mut(&mut family.state),
+                )
+            };
+            let controller = controller.as_ref().unwrap_or(&self.compaction_controller);
+            for sst in ssts {
+                let (level, index) = place_ingested_sst(family_state, controller, &sst);
+                println!(
+                    "ingested {}.sst to level {} of column family {:?} with size={}",
+                    sst.sst_id(),
+                    level,
+                    column_family,
+                    sst.table_size()
+                );
+                placements.push((level, index, sst.sst_id()));
+                family_state.sstables.insert(sst.sst_id(), sst);
+            }
+            *guard = Arc::new(snapshot);
+        }
+        let record = if column_family == DEFAULT_COLUMN_FAMILY {
+            ManifestRecord::IngestExternalFiles(placements)
+        } else {
+            ManifestRecord::ColumnFamilyIngestExternalFiles(family_id, placements)
+        };
+        self.manifest().add_record(&state_lock, record)?;
+        self.mvcc().update_commit_ts(ts);
+        Ok(ts)
+    }
+}
+
+/// Add an ingested SST to the lowest level of a column family that neither it nor the levels
+/// above overlap, returning the level and the index of the SST in the level. The level is 0 if the
+/// SST is flushed to L0, or to a new tier with tiered compaction.
+fn place_ingested_sst(
+    state: &mut LsmStorageState,
+    controller: &CompactionController,
+    sst: &SsTable,
+) -> (usize, usize) {
+    let overlaps_ssts = |state: &LsmStorageState, ssts: &[usize]| {
+        ssts.iter().any(|id| overlaps(sst, &state.sstables[id]))
+    };
+    let mut level = 0;
+    if controller.flush_to_l0() && !overlaps_ssts(state, &state.l0_sstables) {
+        for (idx, (_, ssts)) in state.levels.iter().enumerate() {
+            if overlaps_ssts(state, ssts) {
+                break;
+            }
+            level = idx + 1;
+        }
+    }
+    if level == 0 {
+        add_flushed_sst(state, controller, sst.sst_id());
+        return (0, 0);
+    }
+    let ssts = &state.levels[level - 1].1;
+    let index = ssts.partition_point(|id| {
+        sst.comparator()
+            .compare_keys(
+                state.sstables[id].first_key().as_key_slice(),
+                sst.first_key().as_key_slice(),
+            )
+            .is_lt()
+    });
+    state.levels[level - 1].1.insert(index, sst.sst_id());
+    (level, index)
+}
+
+/// Replay the placements of ingested SSTs recorded in the manifest.
+pub(crate) fn apply_ingest_placements(
+    state: &mut LsmStorageState,
+    controller: &CompactionController,
+    placements: &[(usize, usize, usize)],
+) {
+    for &(level, index, sst_id) in placements {
+        if level == 0 {
+            add_flushed_sst(state, controller, sst_id);
+        } else {
+            state.levels[level - 1].1.insert(index, sst_id);
+        }
+    }
+}
@@ -16,6 +16,42 @@ pub mod concat_iterator;
 pub mod merge_iterator;
 pub mod two_merge_iterator;
 
+use crate::key::KeySlice;
+
+/// Describes how the value of an entry is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueKind {
+    /// The value is stored inline.
+    Value,
+    /// The value is an encoded `ValuePointer` to a record in a value log file.
+    ValuePointer,
+    /// The value is an operand of the merge operator, to be combined with the earlier versions.
+    MergeOperand,
+    /// The value is prefixed with the time it expires at, after which it is deleted.
+    ExpiringValue,
+}
+
+impl ValueKind {
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            ValueKind::Value => 0,
+            ValueKind::ValuePointer => 1,
+            ValueKind::MergeOperand => 2,
+            ValueKind::ExpiringValue => 3,
+        }
+    }
+
+    pub(crate) fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(ValueKind::Value),
+            1 => Some(ValueKind::ValuePointer),
+            2 => Some(ValueKind::MergeOperand),
+            3 => Some(ValueKind::ExpiringValue),
+            _ => None,
+        }
+    }
+}
+
 pub trait StorageIterator {
     type KeyType<'a>: PartialEq + Eq + PartialOrd + Ord
     where
@@ -33,8 +69,30 @@ pub trait StorageIterator {
     /// Move to the next position.
     fn next(&mut self) -> anyhow::Result<()>;
 
+    /// Move to the previous position. The iterators that merge several iterators only move in
+    /// the direction they are created for, so they must be created for reverse iteration before
+    /// `prev` is called.
+    fn prev(&mut self) -> anyhow::Result<()> {
+        anyhow::bail!("the iterator does not support reverse iteration")
+    }
+
+    /// Get how the current value is stored. Only iterators over SSTs may yield value pointers.
+    fn value_kind(&self) -> ValueKind {
+        ValueKind::Value
+    }
+
     /// Number of underlying active iterators for this iterator.
     fn num_active_iterators(&self) -> usize {
         1
     }
 }
+
+/// An iterator over internal keys that can be moved to another key after it is created. The
+/// iterators over a range of a mem-table stay within the range.
+pub trait SeekableIterator: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>> {
+    /// Move to the first key that >= `key`.
+    fn seek_to_key(&mut self, key: KeySlice) -> anyhow::Result<()>;
+
+    /// Move to the last key that <= `key`.
+    fn seek_for_prev(&mut self, key: KeySlice) -> anyhow::Result<()>;
+}
@@ -21,7 +21,7 @@ use crate::{
     table::{SsTable, SsTableIterator},
 };
 
-use super::StorageIterator;
+use super::{SeekableIterator, StorageIterator, ValueKind};
 
 /// Concat multiple iterators ordered in key order and their key ranges do not overlap. We do not want to create the
 /// iterators when initializing this iterator to reduce the overhead of seeking.
@@ -34,11 +34,26 @@ pub struct SstConcatIterator {
 impl SstConcatIterator {
     fn check_sst_valid(sstables: &[Arc<SsTable>]) {
         for sst in sstables {
-            assert!(sst.first_key() <= sst.last_key());
+            assert!(
+                sst.comparator()
+                    .compare_keys(
+                        sst.first_key().as_key_slice(),
+                        sst.last_key().as_key_slice()
+                    )
+                    .is_le()
+            );
         }
         if !sstables.is_empty() {
             for i in 0..(sstables.len() - 1) {
-                assert!(sstables[i].last_key() < sstables[i + 1].first_key());
+                assert!(
+                    sstables[i]
+                        .comparator()
+                        .compare_keys(
+                            sstables[i].last_key().as_key_slice(),
+                            sstables[i + 1].first_key().as_key_slice()
+                        )
+                        .is_lt()
+                );
             }
         }
     }
@@ -64,9 +79,28 @@ impl SstConcatIterator {
     }
 
     pub fn create_and_seek_to_key(sstables: Vec<Arc<SsTable>>, key: KeySlice) -> Result<Self> {
+        Self::create_and_seek_with(sstables, key, SsTableIterator::create_and_seek_to_key)
+    }
+
+    /// Create a new iterator for a point lookup, which seeks in the first table with
+    /// `SsTableIterator::create_and_seek_for_get`.
+    pub fn create_and_seek_for_get(sstables: Vec<Arc<SsTable>>, key: KeySlice) -> Result<Self> {
+        Self::create_and_seek_with(sstables, key, SsTableIterator::create_and_seek_for_get)
+    }
+
+    fn create_and_seek_with(
+        sstables: Vec<Arc<SsTable>>,
+        key: KeySlice,
+        seek: impl FnOnce(Arc<SsTable>, KeySlice) -> Result<SsTableIterator>,
+    ) -> Result<Self> {
         Self::check_sst_valid(&sstables);
         let idx: usize = sstables
-            .partition_point(|table| table.first_key().as_key_slice() <= key)
+            .partition_point(|table| {
+                table
+                    .comparator()
+                    .compare_keys(table.first_key().as_key_slice(), key)
+                    .is_le()
+            })
             .saturating_sub(1);
         if idx >= sstables.len() {
             return Ok(Self {
@@ -76,10 +110,7 @@ impl SstConcatIterator {
             });
         }
         let mut iter = Self {
-            current: Some(SsTableIterator::create_and_seek_to_key(
-                sstables[idx].clone(),
-                key,
-            )?),
+            current: Some(seek(sstables[idx].clone(), key)?),
             next_sst_idx: idx + 1,
             sstables,
         };
@@ -87,6 +118,53 @@ impl SstConcatIterator {
         Ok(iter)
     }
 
+    pub fn create_and_seek_to_last(sstables: Vec<Arc<SsTable>>) -> Result<Self> {
+        Self::check_sst_valid(&sstables);
+        let Some(last) = sstables.last() else {
+            return Ok(Self {
+                current: None,
+                next_sst_idx: 0,
+                sstables,
+            });
+        };
+        let mut iter = Self {
+            current: Some(SsTableIterator::create_and_seek_to_last(last.clone())?),
+            next_sst_idx: sstables.len(),
+            sstables,
+        };
+        iter.move_until_valid_rev()?;
+        Ok(iter)
+    }
+
+    /// Create a new iterator and seek to the last key which <= `key`.
+    pub fn create_and_seek_for_prev(sstables: Vec<Arc<SsTable>>, key: KeySlice) -> Result<Self> {
+        Self::check_sst_valid(&sstables);
+        // The last table whose first key <= `key` has the key, unless every table is after it.
+        let idx = sstables.partition_point(|table| {
+            table
+                .comparator()
+                .compare_keys(table.first_key().as_key_slice(), key)
+                .is_le()
+        });
+        if idx == 0 {
+            return Ok(Self {
+                current: None,
+                next_sst_idx: 0,
+                sstables,
+            });
+        }
+        let mut iter = Self {
+            current: Some(SsTableIterator::create_and_seek_for_prev(
+                sstables[idx - 1].clone(),
+                key,
+            )?),
+            next_sst_idx: idx,
+            sstables,
+        };
+        iter.move_until_valid_rev()?;
+        Ok(iter)
+    }
+
     fn move_until_valid(&mut self) -> Result<()> {
         while let Some(iter) = self.current.as_mut() {
             if iter.is_valid() {
@@ -103,6 +181,25 @@ impl SstConcatIterator {
         }
         Ok(())
     }
+
+    /// Move to the last key of the previous tables until the current one is valid. The current
+    /// table is the one before `next_sst_idx`.
+    fn move_until_valid_rev(&mut self) -> Result<()> {
+        while let Some(iter) = self.current.as_mut() {
+            if iter.is_valid() {
+                break;
+            }
+            if self.next_sst_idx <= 1 {
+                self.current = None;
+            } else {
+                self.next_sst_idx -= 1;
+                self.current = Some(SsTableIterator::create_and_seek_to_last(
+                    self.sstables[self.next_sst_idx - 1].clone(),
+                )?);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl StorageIterator for SstConcatIterator {
@@ -116,6 +213,10 @@ impl StorageIterator for SstConcatIterator {
         self.current.as_ref().unwrap().value()
     }
 
+    fn value_kind(&self) -> ValueKind {
+        self.current.as_ref().unwrap().value_kind()
+    }
+
     fn is_valid(&self) -> bool {
         if let Some(current) = &self.current {
             assert!(current.is_valid());
@@ -131,7 +232,25 @@ impl StorageIterator for SstConcatIterator {
         Ok(())
     }
 
+    fn prev(&mut self) -> Result<()> {
+        self.current.as_mut().unwrap().prev()?;
+        self.move_until_valid_rev()?;
+        Ok(())
+    }
+
     fn num_active_iterators(&self) -> usize {
         1
     }
 }
+
+impl SeekableIterator for SstConcatIterator {
+    fn seek_to_key(&mut self, key: KeySlice) -> Result<()> {
+        *self = Self::create_and_seek_to_key(std::mem::take(&mut self.sstables), key)?;
+        Ok(())
+    }
+
+    fn seek_for_prev(&mut self, key: KeySlice) -> Result<()> {
+        *self = Self::create_and_seek_for_prev(std::mem::take(&mut self.sstables), key)?;
+        Ok(())
+    }
+}
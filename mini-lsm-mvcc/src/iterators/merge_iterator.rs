@@ -16,129 +16,138 @@ use std::cmp::{self};
 use std::collections::BinaryHeap;
 use std::collections::binary_heap::PeekMut;
 
-use anyhow::Result;
+use anyhow::{Result, ensure};
 
+use crate::comparator::{BYTEWISE_COMPARATOR, Comparator};
 use crate::key::KeySlice;
 
-use super::StorageIterator;
+use super::{SeekableIterator, StorageIterator, ValueKind};
 
-struct HeapWrapper<I: StorageIterator>(pub usize, pub Box<I>);
+/// An iterator in the heap with its index, whether the merge iterator moves in reverse, in which
+/// case the largest key is on the top of the heap, and the comparator of the keys.
+struct HeapWrapper<I: StorageIterator>(pub usize, pub Box<I>, pub bool, &'static dyn Comparator);
 
-impl<I: StorageIterator> PartialEq for HeapWrapper<I> {
+impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> PartialEq
+    for HeapWrapper<I>
+{
     fn eq(&self, other: &Self) -> bool {
         self.cmp(other) == cmp::Ordering::Equal
     }
 }
 
-impl<I: StorageIterator> Eq for HeapWrapper<I> {}
+impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> Eq for HeapWrapper<I> {}
 
-impl<I: StorageIterator> PartialOrd for HeapWrapper<I> {
+impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> PartialOrd
+    for HeapWrapper<I>
+{
     fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<I: StorageIterator> Ord for HeapWrapper<I> {
+impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> Ord for HeapWrapper<I> {
     fn cmp(&self, other: &Self) -> cmp::Ordering {
-        self.1
-            .key()
-            .cmp(&other.1.key())
-            .then(self.0.cmp(&other.0))
-            .reverse()
+        let ordering = self.3.compare_keys(self.1.key(), other.1.key());
+        let ordering = if self.2 { ordering } else { ordering.reverse() };
+        ordering.then(self.0.cmp(&other.0).reverse())
     }
 }
 
 /// Merge multiple iterators of the same type. If the same key occurs multiple times in some
-/// iterators, prefer the one with smaller index.
+/// iterators, prefer the one with smaller index. A merge iterator created for reverse iteration
+/// moves with `prev` over the iterators positioned at their last keys.
 pub struct MergeIterator<I: StorageIterator> {
     iters: BinaryHeap<HeapWrapper<I>>,
     current: Option<HeapWrapper<I>>,
+    /// The invalid iterators, which are kept so that a seek can move them back into the heap.
+    exhausted: Vec<HeapWrapper<I>>,
+    reverse: bool,
+    /// Orders the keys of the iterators.
+    comparator: &'static dyn Comparator,
 }
 
-impl<I: StorageIterator> MergeIterator<I> {
+impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> MergeIterator<I> {
+    /// Create a merge iterator over iterators with the keys ordered bytewise.
     pub fn create(iters: Vec<Box<I>>) -> Self {
-        if iters.is_empty() {
-            return Self {
-                iters: BinaryHeap::new(),
-                current: None,
-            };
-        }
+        Self::create_with_direction(iters, false, &BYTEWISE_COMPARATOR)
+    }
 
-        let mut heap = BinaryHeap::new();
+    /// Create a merge iterator for reverse iteration over the iterators, which are positioned at
+    /// the last keys to merge, with the keys ordered bytewise.
+    pub fn create_rev(iters: Vec<Box<I>>) -> Self {
+        Self::create_with_direction(iters, true, &BYTEWISE_COMPARATOR)
+    }
 
-        if iters.iter().all(|x| !x.is_valid()) {
-            // All invalid, select the last one as the current.
-            let mut iters = iters;
-            return Self {
-                iters: heap,
-                current: Some(HeapWrapper(0, iters.pop().unwrap())),
-            };
-        }
+    /// Create a merge iterator over iterators with the keys ordered by `comparator`.
+    pub(crate) fn create_with_comparator(
+        iters: Vec<Box<I>>,
+        comparator: &'static dyn Comparator,
+    ) -> Self {
+        Self::create_with_direction(iters, false, comparator)
+    }
 
+    /// Create a merge iterator in the given direction over iterators with the keys ordered by
+    /// `comparator`.
+    pub(crate) fn create_with_direction(
+        iters: Vec<Box<I>>,
+        reverse: bool,
+        comparator: &'static dyn Comparator,
+    ) -> Self {
+        let mut heap = BinaryHeap::new();
+        let mut exhausted = Vec::new();
         for (idx, iter) in iters.into_iter().enumerate() {
-            if iter.is_valid() {
-                heap.push(HeapWrapper(idx, iter));
+            let iter = HeapWrapper(idx, iter, reverse, comparator);
+            if iter.1.is_valid() {
+                heap.push(iter);
+            } else {
+                exhausted.push(iter);
             }
         }
 
-        let current = heap.pop().unwrap();
+        // If all iterators are invalid, select the last one as the current.
+        let current = heap.pop().or_else(|| exhausted.pop());
         Self {
             iters: heap,
-            current: Some(current),
+            current,
+            exhausted,
+            reverse,
+            comparator,
         }
     }
 }
 
-impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> StorageIterator
-    for MergeIterator<I>
-{
-    type KeyType<'a> = KeySlice<'a>;
-
-    fn key(&self) -> KeySlice<'_> {
-        self.current.as_ref().unwrap().1.key()
-    }
-
-    fn value(&self) -> &[u8] {
-        self.current.as_ref().unwrap().1.value()
-    }
-
-    fn is_valid(&self) -> bool {
-        self.current
-            .as_ref()
-            .map(|x| x.1.is_valid())
-            .unwrap_or(false)
-    }
-
-    fn next(&mut self) -> Result<()> {
+impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> MergeIterator<I> {
+    /// Move the iterator of the current key, and the others at the same key, in the direction of
+    /// the merge iterator.
+    fn advance(&mut self) -> Result<()> {
+        let reverse = self.reverse;
+        let step = |iter: &mut I| if reverse { iter.prev() } else { iter.next() };
         let current = self.current.as_mut().unwrap();
         // Pop the item out of the heap if they have the same value.
         while let Some(mut inner_iter) = self.iters.peek_mut() {
-            debug_assert!(
-                inner_iter.1.key() >= current.1.key(),
-                "heap invariant violated"
-            );
+            debug_assert!(*inner_iter <= *current, "heap invariant violated");
             if inner_iter.1.key() == current.1.key() {
-                // Case 1: an error occurred when calling `next`.
-                if let e @ Err(_) = inner_iter.1.next() {
+                // Case 1: an error occurred when moving the iterator.
+                if let e @ Err(_) = step(&mut inner_iter.1) {
                     PeekMut::pop(inner_iter);
                     return e;
                 }
 
                 // Case 2: iter is no longer valid.
                 if !inner_iter.1.is_valid() {
-                    PeekMut::pop(inner_iter);
+                    self.exhausted.push(PeekMut::pop(inner_iter));
                 }
             } else {
                 break;
             }
         }
 
-        current.1.next()?;
+        step(&mut current.1)?;
 
         // If the current iterator is invalid, pop it out of the heap and select the next one.
         if !current.1.is_valid() {
             if let Some(iter) = self.iters.pop() {
-                *current = iter;
+                self.exhausted.push(std::mem::replace(current, iter));
             }
             return Ok(());
         }
@@ -152,6 +161,47 @@ impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> StorageIt
 
         Ok(())
     }
+}
+
+impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> StorageIterator
+    for MergeIterator<I>
+{
+    type KeyType<'a> = KeySlice<'a>;
+
+    fn key(&self) -> KeySlice<'_> {
+        self.current.as_ref().unwrap().1.key()
+    }
+
+    fn value(&self) -> &[u8] {
+        self.current.as_ref().unwrap().1.value()
+    }
+
+    fn value_kind(&self) -> ValueKind {
+        self.current.as_ref().unwrap().1.value_kind()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.current
+            .as_ref()
+            .map(|x| x.1.is_valid())
+            .unwrap_or(false)
+    }
+
+    fn next(&mut self) -> Result<()> {
+        ensure!(
+            !self.reverse,
+            "the merge iterator is created for reverse iteration"
+        );
+        self.advance()
+    }
+
+    fn prev(&mut self) -> Result<()> {
+        ensure!(
+            self.reverse,
+            "the merge iterator is not created for reverse iteration"
+        );
+        self.advance()
+    }
 
     fn num_active_iterators(&self) -> usize {
         self.iters
@@ -165,3 +215,39 @@ impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> StorageIt
                 .unwrap_or(0)
     }
 }
+
+impl<I: SeekableIterator> MergeIterator<I> {
+    /// Move every iterator with `seek`, including the exhausted ones, and merge them again.
+    fn seek_with(&mut self, seek: impl Fn(&mut I) -> Result<()>) -> Result<()> {
+        let mut iters = std::mem::take(&mut self.iters).into_vec();
+        iters.extend(self.current.take());
+        iters.append(&mut self.exhausted);
+        // Keep the order of the iterators, which decides the entry to prefer for the same key.
+        iters.sort_by_key(|iter| iter.0);
+        let mut seeked = Vec::with_capacity(iters.len());
+        for mut iter in iters {
+            seek(&mut iter.1)?;
+            seeked.push(iter.1);
+        }
+        *self = Self::create_with_direction(seeked, self.reverse, self.comparator);
+        Ok(())
+    }
+}
+
+impl<I: SeekableIterator> SeekableIterator for MergeIterator<I> {
+    fn seek_to_key(&mut self, key: KeySlice) -> Result<()> {
+        ensure!(
+            !self.reverse,
+            "the merge iterator is created for reverse iteration"
+        );
+        self.seek_with(|iter| iter.seek_to_key(key))
+    }
+
+    fn seek_for_prev(&mut self, key: KeySlice) -> Result<()> {
+        ensure!(
+            self.reverse,
+            "the merge iterator is not created for reverse iteration"
+        );
+        self.seek_with(|iter| iter.seek_for_prev(key))
+    }
+}
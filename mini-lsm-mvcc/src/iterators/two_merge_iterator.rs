@@ -12,60 +12,115 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::Result;
+use anyhow::{Result, ensure};
 
-use super::StorageIterator;
+use crate::comparator::{BYTEWISE_COMPARATOR, Comparator};
+use crate::key::KeySlice;
+
+use super::{SeekableIterator, StorageIterator, ValueKind};
 
 /// Merges two iterators of different types into one. If the two iterators have the same key, only
-/// produce the key once and prefer the entry from A.
+/// produce the key once and prefer the entry from A. A two-merge iterator created for reverse
+/// iteration moves with `prev` over the iterators positioned at their last keys.
 pub struct TwoMergeIterator<A: StorageIterator, B: StorageIterator> {
     a: A,
     b: B,
     choose_a: bool,
+    reverse: bool,
+    /// Orders the keys of the two iterators.
+    comparator: &'static dyn Comparator,
 }
 
 impl<
-    A: 'static + StorageIterator,
-    B: 'static + for<'a> StorageIterator<KeyType<'a> = A::KeyType<'a>>,
+    A: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
+    B: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
 > TwoMergeIterator<A, B>
 {
-    fn choose_a(a: &A, b: &B) -> bool {
+    fn choose_a(a: &A, b: &B, reverse: bool, comparator: &dyn Comparator) -> bool {
         if !a.is_valid() {
             return false;
         }
         if !b.is_valid() {
             return true;
         }
-        a.key() < b.key()
+        if reverse {
+            comparator.compare_keys(a.key(), b.key()).is_gt()
+        } else {
+            comparator.compare_keys(a.key(), b.key()).is_lt()
+        }
     }
 
     fn skip_b(&mut self) -> Result<()> {
-        if self.a.is_valid() && self.b.is_valid() && self.b.key() == self.a.key() {
-            self.b.next()?;
+        if self.a.is_valid()
+            && self.b.is_valid()
+            && self
+                .comparator
+                .compare_keys(self.b.key(), self.a.key())
+                .is_eq()
+        {
+            if self.reverse {
+                self.b.prev()?;
+            } else {
+                self.b.next()?;
+            }
         }
         Ok(())
     }
 
+    /// Get the second iterator, which must be left at the same key.
+    pub(crate) fn b_mut(&mut self) -> &mut B {
+        &mut self.b
+    }
+
+    /// Create a two-merge iterator over iterators with the keys ordered bytewise.
     pub fn create(a: A, b: B) -> Result<Self> {
+        Self::create_with_direction(a, b, false, &BYTEWISE_COMPARATOR)
+    }
+
+    /// Create a two-merge iterator for reverse iteration over the iterators, which are positioned
+    /// at the last keys to merge, with the keys ordered bytewise.
+    pub fn create_rev(a: A, b: B) -> Result<Self> {
+        Self::create_with_direction(a, b, true, &BYTEWISE_COMPARATOR)
+    }
+
+    /// Create a two-merge iterator over iterators with the keys ordered by `comparator`.
+    pub(crate) fn create_with_comparator(
+        a: A,
+        b: B,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self> {
+        Self::create_with_direction(a, b, false, comparator)
+    }
+
+    /// Create a two-merge iterator in the given direction over iterators with the keys ordered
+    /// by `comparator`.
+    pub(crate) fn create_with_direction(
+        a: A,
+        b: B,
+        reverse: bool,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self> {
         let mut iter = Self {
             choose_a: false,
             a,
             b,
+            reverse,
+            comparator,
         };
         iter.skip_b()?;
-        iter.choose_a = Self::choose_a(&iter.a, &iter.b);
+        iter.choose_a = Self::choose_a(&iter.a, &iter.b, reverse, comparator);
         Ok(iter)
     }
 }
 
 impl<
-    A: 'static + StorageIterator,
-    B: 'static + for<'a> StorageIterator<KeyType<'a> = A::KeyType<'a>>,
+    A: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
+    B: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
 > StorageIterator for TwoMergeIterator<A, B>
 {
-    type KeyType<'a> = A::KeyType<'a>;
+    type KeyType<'a> = KeySlice<'a>;
 
-    fn key(&self) -> A::KeyType<'_> {
+    fn key(&self) -> KeySlice<'_> {
         if self.choose_a {
             debug_assert!(self.a.is_valid());
             self.a.key()
@@ -83,6 +138,14 @@ impl<
         }
     }
 
+    fn value_kind(&self) -> ValueKind {
+        if self.choose_a {
+            self.a.value_kind()
+        } else {
+            self.b.value_kind()
+        }
+    }
+
     fn is_valid(&self) -> bool {
         if self.choose_a {
             self.a.is_valid()
@@ -92,13 +155,32 @@ impl<
     }
 
     fn next(&mut self) -> Result<()> {
+        ensure!(
+            !self.reverse,
+            "the merge iterator is created for reverse iteration"
+        );
         if self.choose_a {
             self.a.next()?;
         } else {
             self.b.next()?;
         }
         self.skip_b()?;
-        self.choose_a = Self::choose_a(&self.a, &self.b);
+        self.choose_a = Self::choose_a(&self.a, &self.b, false, self.comparator);
+        Ok(())
+    }
+
+    fn prev(&mut self) -> Result<()> {
+        ensure!(
+            self.reverse,
+            "the merge iterator is not created for reverse iteration"
+        );
+        if self.choose_a {
+            self.a.prev()?;
+        } else {
+            self.b.prev()?;
+        }
+        self.skip_b()?;
+        self.choose_a = Self::choose_a(&self.a, &self.b, true, self.comparator);
         Ok(())
     }
 
@@ -106,3 +188,29 @@ impl<
         self.a.num_active_iterators() + self.b.num_active_iterators()
     }
 }
+
+impl<A: SeekableIterator, B: SeekableIterator> SeekableIterator for TwoMergeIterator<A, B> {
+    fn seek_to_key(&mut self, key: KeySlice) -> Result<()> {
+        ensure!(
+            !self.reverse,
+            "the merge iterator is created for reverse iteration"
+        );
+        self.a.seek_to_key(key)?;
+        self.b.seek_to_key(key)?;
+        self.skip_b()?;
+        self.choose_a = Self::choose_a(&self.a, &self.b, false, self.comparator);
+        Ok(())
+    }
+
+    fn seek_for_prev(&mut self, key: KeySlice) -> Result<()> {
+        ensure!(
+            self.reverse,
+            "the merge iterator is not created for reverse iteration"
+        );
+        self.a.seek_for_prev(key)?;
+        self.b.seek_for_prev(key)?;
+        self.skip_b()?;
+        self.choose_a = Self::choose_a(&self.a, &self.b, true, self.comparator);
+        Ok(())
+    }
+}
@@ -16,6 +16,8 @@ use std::{cmp::Reverse, fmt::Debug};
 
 use bytes::Bytes;
 
+/// A user key and its timestamp. Keys are ordered bytewise; the storage orders them with the
+/// comparator of the database instead, through `Comparator::compare_keys`.
 pub struct Key<T: AsRef<[u8]>>(T, u64);
 
 pub type KeySlice<'a> = Key<&'a [u8]>;
@@ -12,17 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod backup;
 pub mod block;
+pub mod block_cache;
+pub mod checkpoint;
 pub mod compact;
+pub mod comparator;
 pub mod debug;
+pub mod ingest;
 pub mod iterators;
 pub mod key;
 pub mod lsm_iterator;
 pub mod lsm_storage;
 pub mod manifest;
 pub mod mem_table;
+pub mod merge_operator;
 pub mod mvcc;
+pub mod prefix;
+pub mod range_tombstone;
+pub mod repair;
+pub mod salvage;
 pub mod table;
+pub mod ttl;
+pub mod value_log;
+pub mod varint;
 pub mod wal;
 
 #[cfg(test)]
@@ -14,56 +14,132 @@
 
 use std::ops::Bound;
 
-use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail, ensure};
 use bytes::Bytes;
 
-use crate::iterators::StorageIterator;
+use crate::comparator::Comparator;
 use crate::iterators::concat_iterator::SstConcatIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
+use crate::iterators::{SeekableIterator, StorageIterator, ValueKind};
+use crate::key::{KeySlice, TS_RANGE_BEGIN, TS_RANGE_END};
 use crate::mem_table::MemTableIterator;
+use crate::merge_operator::{MergeOperator, merge_operands};
+use crate::range_tombstone::{RangeTombstone, is_covered};
 use crate::table::SsTableIterator;
+use crate::ttl;
+use crate::value_log::{ValueLogFile, resolve_value};
 
 /// Represents the internal type for an LSM iterator. This type will be changed across the course for multiple times.
-type LsmIteratorInner = TwoMergeIterator<
+pub(crate) type LsmIteratorInner = TwoMergeIterator<
     TwoMergeIterator<MergeIterator<MemTableIterator>, MergeIterator<SsTableIterator>>,
     MergeIterator<SstConcatIterator>,
 >;
 
 pub struct LsmIterator {
     inner: LsmIteratorInner,
+    /// The bound that the iterator starts from, which the seeks stay within.
+    start_bound: Bound<Bytes>,
     end_bound: Bound<Bytes>,
+    /// Orders the user keys of the storage.
+    comparator: &'static dyn Comparator,
     is_valid: bool,
     read_ts: u64,
     prev_key: Vec<u8>,
+    /// The value log files that the pointers in `inner` may refer to.
+    value_log: Arc<BTreeMap<usize, Arc<ValueLogFile>>>,
+    /// The value of the current entry if it is stored in the value log.
+    resolved_value: Bytes,
+    /// The range tombstones visible at `read_ts` that may cover the keys of the iterator.
+    range_tombstones: Vec<RangeTombstone>,
+    /// Combines the merge operands with the earlier versions of their keys.
+    merge_operator: Option<Arc<dyn MergeOperator>>,
+    /// The current value was merged from merge operands into `resolved_value`, and `inner` may
+    /// have moved past the current key.
+    merged: bool,
+    /// The time that the expiring values are checked against, fixed when the iterator is created.
+    now: u64,
+    /// The iterator moves from the last key with `prev`, and `end_bound` is the lower bound. The
+    /// current key and value are always kept in `prev_key` and `resolved_value`.
+    reverse: bool,
 }
 
 impl LsmIterator {
+    /// Create an iterator over `iter`, which is positioned at the first key within `bounds`.
     pub(crate) fn new(
         iter: LsmIteratorInner,
-        end_bound: Bound<Bytes>,
+        (lower, upper): (Bound<Bytes>, Bound<Bytes>),
+        comparator: &'static dyn Comparator,
         read_ts: u64,
+        value_log: Arc<BTreeMap<usize, Arc<ValueLogFile>>>,
+        range_tombstones: Vec<RangeTombstone>,
+        merge_operator: Option<Arc<dyn MergeOperator>>,
     ) -> Result<Self> {
         let mut iter = Self {
             is_valid: iter.is_valid(),
             inner: iter,
-            end_bound,
+            start_bound: lower,
+            end_bound: upper,
+            comparator,
             read_ts,
             prev_key: Vec::new(),
+            value_log,
+            resolved_value: Bytes::new(),
+            range_tombstones,
+            merge_operator,
+            merged: false,
+            now: ttl::now_millis(),
+            reverse: false,
         };
         iter.check_end_bound();
         iter.move_to_key()?;
         Ok(iter)
     }
 
+    /// Create an iterator for reverse iteration over `iter`, which is positioned at the last key
+    /// within `bounds`. The iterator stops at the lower bound.
+    pub(crate) fn new_rev(
+        iter: LsmIteratorInner,
+        (lower, upper): (Bound<Bytes>, Bound<Bytes>),
+        comparator: &'static dyn Comparator,
+        read_ts: u64,
+        value_log: Arc<BTreeMap<usize, Arc<ValueLogFile>>>,
+        range_tombstones: Vec<RangeTombstone>,
+        merge_operator: Option<Arc<dyn MergeOperator>>,
+    ) -> Result<Self> {
+        let mut iter = Self {
+            is_valid: false,
+            inner: iter,
+            start_bound: upper,
+            end_bound: lower,
+            comparator,
+            read_ts,
+            prev_key: Vec::new(),
+            value_log,
+            resolved_value: Bytes::new(),
+            range_tombstones,
+            merge_operator,
+            merged: false,
+            now: ttl::now_millis(),
+            reverse: true,
+        };
+        iter.move_to_key_rev()?;
+        Ok(iter)
+    }
+
     fn check_end_bound(&mut self) {
         if !self.is_valid {
             return;
         }
+        let key = self.inner.key();
+        let comparator = self.comparator;
         match self.end_bound.as_ref() {
             Bound::Unbounded => {}
-            Bound::Included(key) => self.is_valid = self.inner.key().key_ref() <= key.as_ref(),
-            Bound::Excluded(key) => self.is_valid = self.inner.key().key_ref() < key.as_ref(),
+            Bound::Included(end) => self.is_valid = comparator.compare(key.key_ref(), end).is_le(),
+            Bound::Excluded(end) => self.is_valid = comparator.compare(key.key_ref(), end).is_lt(),
         }
     }
 
@@ -99,12 +175,264 @@ impl LsmIterator {
             if self.inner.key().key_ref() != self.prev_key {
                 continue;
             }
-            if !self.inner.value().is_empty() {
-                break;
+            if !self.inner.value().is_empty()
+                && !is_covered(
+                    &self.range_tombstones,
+                    self.inner.key().key_ref(),
+                    self.inner.key().ts(),
+                    self.comparator,
+                )
+            {
+                match self.inner.value_kind() {
+                    ValueKind::MergeOperand => {
+                        if self.merge_operands()? {
+                            break;
+                        }
+                        // The merged value is empty, so the key is deleted.
+                        self.is_valid = self.inner.is_valid();
+                        self.check_end_bound();
+                    }
+                    ValueKind::ExpiringValue => {
+                        if ttl::unexpired_value(self.inner.value(), self.now)?.is_some() {
+                            break;
+                        }
+                    }
+                    ValueKind::Value | ValueKind::ValuePointer => break,
+                }
+            }
+        }
+        if self.is_valid && !self.merged && self.inner.value_kind() == ValueKind::ValuePointer {
+            self.resolved_value = resolve_value(
+                &self.value_log,
+                self.inner.key().key_ref(),
+                self.inner.value(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl LsmIterator {
+    /// Move to the first key that >= `key`, or to the last key that <= `key` if the iterator is
+    /// created for reverse iteration. The seek stays within the range of the iterator, and the
+    /// iterator keeps reading at the same timestamp.
+    pub fn seek(&mut self, key: &[u8]) -> Result<()> {
+        let (key, inclusive) = match &self.start_bound {
+            Bound::Included(start) if self.comes_before(key, start) => (start.clone(), true),
+            Bound::Excluded(start) if !self.comes_before(start, key) => (start.clone(), false),
+            _ => (Bytes::copy_from_slice(key), true),
+        };
+        self.seek_inner(&key, inclusive)?;
+        self.prev_key.clear();
+        self.merged = false;
+        if self.reverse {
+            self.move_to_key_rev()
+        } else {
+            self.is_valid = self.inner.is_valid();
+            self.check_end_bound();
+            self.move_to_key()
+        }
+    }
+
+    /// Whether `key` comes before `other` in the direction of the iterator.
+    fn comes_before(&self, key: &[u8], other: &[u8]) -> bool {
+        let ordering = self.comparator.compare(key, other);
+        if self.reverse {
+            ordering.is_gt()
+        } else {
+            ordering.is_lt()
+        }
+    }
+
+    /// Position `inner` at the first version of `key`, or of the key after it if the key is not
+    /// `inclusive`, in the direction of the iterator.
+    fn seek_inner(&mut self, key: &[u8], inclusive: bool) -> Result<()> {
+        let key_with_ts = |ts| KeySlice::from_slice_with_ts(key, ts);
+        if self.reverse {
+            if inclusive {
+                return self.inner.seek_for_prev(key_with_ts(TS_RANGE_END));
+            }
+            self.inner.seek_for_prev(key_with_ts(TS_RANGE_BEGIN))?;
+            while self.inner.is_valid() && self.inner.key().key_ref() == key {
+                self.inner.prev()?;
+            }
+        } else {
+            self.inner.seek_to_key(key_with_ts(TS_RANGE_BEGIN))?;
+            if !inclusive {
+                while self.inner.is_valid() && self.inner.key().key_ref() == key {
+                    self.inner.next()?;
+                }
             }
         }
         Ok(())
     }
+
+    /// Whether the inner iterator is at a key above the lower bound of a reverse iterator.
+    fn inner_above_end_bound(&self) -> bool {
+        if !self.inner.is_valid() {
+            return false;
+        }
+        let key = self.inner.key();
+        let comparator = self.comparator;
+        match self.end_bound.as_ref() {
+            Bound::Unbounded => true,
+            Bound::Included(end) => comparator.compare(key.key_ref(), end).is_ge(),
+            Bound::Excluded(end) => comparator.compare(key.key_ref(), end).is_gt(),
+        }
+    }
+
+    /// Move to the previous key with a visible value. The versions of a key are visited from the
+    /// oldest to the newest, so they are collected before the newest visible one is resolved, and
+    /// `inner` ends up at the next user key to visit.
+    fn move_to_key_rev(&mut self) -> Result<()> {
+        let mut versions = Vec::new();
+        loop {
+            if !self.inner_above_end_bound() {
+                self.is_valid = false;
+                return Ok(());
+            }
+            self.prev_key.clear();
+            self.prev_key.extend(self.inner.key().key_ref());
+            versions.clear();
+            while self.inner_above_end_bound() && self.inner.key().key_ref() == self.prev_key {
+                let key = self.inner.key();
+                if key.ts() <= self.read_ts {
+                    let covered = is_covered(
+                        &self.range_tombstones,
+                        key.key_ref(),
+                        key.ts(),
+                        self.comparator,
+                    );
+                    let kind = self.inner.value_kind();
+                    // Only the merge operands need the earlier versions of the key.
+                    if covered || kind != ValueKind::MergeOperand {
+                        versions.clear();
+                    }
+                    versions.push((kind, Bytes::copy_from_slice(self.inner.value()), covered));
+                }
+                self.inner.prev()?;
+            }
+            if let Some(value) = self.resolve_versions(&versions)? {
+                self.resolved_value = value;
+                self.is_valid = true;
+                return Ok(());
+            }
+        }
+    }
+
+    /// Resolve the value of the current key from its visible versions, ordered from the oldest to
+    /// the newest. Returns `None` if the key is deleted.
+    fn resolve_versions(&self, versions: &[(ValueKind, Bytes, bool)]) -> Result<Option<Bytes>> {
+        let Some((kind, value, covered)) = versions.last() else {
+            return Ok(None);
+        };
+        if value.is_empty() || *covered {
+            return Ok(None);
+        }
+        match kind {
+            ValueKind::Value => Ok(Some(value.clone())),
+            ValueKind::ValuePointer => {
+                Ok(Some(resolve_value(&self.value_log, &self.prev_key, value)?))
+            }
+            ValueKind::ExpiringValue => {
+                Ok(ttl::unexpired_value(value, self.now)?.map(Bytes::copy_from_slice))
+            }
+            ValueKind::MergeOperand => {
+                let merge_operator = self
+                    .merge_operator
+                    .as_ref()
+                    .context("found a merge operand, but no merge operator is configured")?;
+                let mut operands = Vec::new();
+                let mut existing = None;
+                for (kind, value, covered) in versions.iter().rev() {
+                    if *covered {
+                        break;
+                    }
+                    match kind {
+                        ValueKind::MergeOperand => operands.push(value.clone()),
+                        ValueKind::Value => {
+                            if !value.is_empty() {
+                                existing = Some(value.clone());
+                            }
+                        }
+                        ValueKind::ValuePointer => {
+                            existing = Some(resolve_value(&self.value_log, &self.prev_key, value)?);
+                        }
+                        ValueKind::ExpiringValue => {
+                            existing =
+                                ttl::unexpired_value(value, self.now)?.map(Bytes::copy_from_slice);
+                        }
+                    }
+                }
+                let value = merge_operands(
+                    merge_operator.as_ref(),
+                    &self.prev_key,
+                    existing.as_deref(),
+                    &operands,
+                )?;
+                Ok((!value.is_empty()).then_some(value))
+            }
+        }
+    }
+
+    /// Combine the merge operands from the current entry with the earlier versions of the key,
+    /// moving `inner` past the operands. Returns false if the merged value is empty.
+    fn merge_operands(&mut self) -> Result<bool> {
+        let merge_operator = self
+            .merge_operator
+            .clone()
+            .context("found a merge operand, but no merge operator is configured")?;
+        let mut operands = Vec::new();
+        let mut existing = None;
+        while self.inner.is_valid() && self.inner.key().key_ref() == self.prev_key {
+            let key = self.inner.key();
+            if is_covered(
+                &self.range_tombstones,
+                key.key_ref(),
+                key.ts(),
+                self.comparator,
+            ) {
+                break;
+            }
+            match self.inner.value_kind() {
+                ValueKind::MergeOperand => {
+                    operands.push(Bytes::copy_from_slice(self.inner.value()));
+                }
+                ValueKind::Value => {
+                    if !self.inner.value().is_empty() {
+                        existing = Some(Bytes::copy_from_slice(self.inner.value()));
+                    }
+                    break;
+                }
+                ValueKind::ValuePointer => {
+                    existing = Some(resolve_value(
+                        &self.value_log,
+                        &self.prev_key,
+                        self.inner.value(),
+                    )?);
+                    break;
+                }
+                ValueKind::ExpiringValue => {
+                    existing = ttl::unexpired_value(self.inner.value(), self.now)?
+                        .map(Bytes::copy_from_slice);
+                    break;
+                }
+            }
+            self.inner.next()?;
+        }
+        let value = merge_operands(
+            merge_operator.as_ref(),
+            &self.prev_key,
+            existing.as_deref(),
+            &operands,
+        )?;
+        if value.is_empty() {
+            return Ok(false);
+        }
+        self.resolved_value = value;
+        self.merged = true;
+        Ok(true)
+    }
 }
 
 impl StorageIterator for LsmIterator {
@@ -115,19 +443,48 @@ impl StorageIterator for LsmIterator {
     }
 
     fn key(&self) -> &[u8] {
+        if self.merged || self.reverse {
+            return &self.prev_key;
+        }
         self.inner.key().key_ref()
     }
 
     fn value(&self) -> &[u8] {
-        self.inner.value()
+        if self.merged || self.reverse {
+            return &self.resolved_value;
+        }
+        match self.inner.value_kind() {
+            ValueKind::Value | ValueKind::MergeOperand => self.inner.value(),
+            ValueKind::ValuePointer => &self.resolved_value,
+            // The expiry time was checked when the iterator moved to the entry.
+            ValueKind::ExpiringValue => ttl::decode_expiring_value(self.inner.value()).unwrap().1,
+        }
     }
 
     fn next(&mut self) -> Result<()> {
-        self.next_inner()?;
+        ensure!(
+            !self.reverse,
+            "the iterator is created for reverse iteration"
+        );
+        if std::mem::take(&mut self.merged) {
+            // `inner` is already past the merge operands of the current key.
+            self.is_valid = self.inner.is_valid();
+            self.check_end_bound();
+        } else {
+            self.next_inner()?;
+        }
         self.move_to_key()?;
         Ok(())
     }
 
+    fn prev(&mut self) -> Result<()> {
+        ensure!(
+            self.reverse,
+            "the iterator is not created for reverse iteration"
+        );
+        self.move_to_key_rev()
+    }
+
     fn num_active_iterators(&self) -> usize {
         self.inner.num_active_iterators()
     }
@@ -188,7 +545,34 @@ impl<I: StorageIterator> StorageIterator for FusedIterator<I> {
         Ok(())
     }
 
+    fn prev(&mut self) -> Result<()> {
+        if self.has_errored {
+            bail!("the iterator is tainted");
+        }
+        if self.iter.is_valid()
+            && let Err(e) = self.iter.prev()
+        {
+            self.has_errored = true;
+            return Err(e);
+        }
+        Ok(())
+    }
+
     fn num_active_iterators(&self) -> usize {
         self.iter.num_active_iterators()
     }
 }
+
+impl FusedIterator<LsmIterator> {
+    /// Seek the underlying iterator with `LsmIterator::seek`.
+    pub fn seek(&mut self, key: &[u8]) -> Result<()> {
+        if self.has_errored {
+            bail!("the iterator is tainted");
+        }
+        if let Err(e) = self.iter.seek(key) {
+            self.has_errored = true;
+            return Err(e);
+        }
+        Ok(())
+    }
+}
@@ -12,35 +12,46 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{BTreeSet, HashMap};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Debug;
 use std::fs::File;
 use std::ops::Bound;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
 
-use anyhow::{Context, Result, ensure};
+use anyhow::{Context, Result, bail, ensure};
 use bytes::Bytes;
 use parking_lot::{Mutex, MutexGuard, RwLock};
 
-use crate::block::Block;
 use crate::compact::{
-    CompactionController, CompactionOptions, LeveledCompactionController, LeveledCompactionOptions,
-    SimpleLeveledCompactionController, SimpleLeveledCompactionOptions, TieredCompactionController,
+    CompactionController, CompactionOptions, LeveledCompactionOptions,
+    SimpleLeveledCompactionOptions,
 };
-use crate::iterators::StorageIterator;
+use crate::comparator::{BYTEWISE_COMPARATOR, Comparator, is_bytewise};
+use crate::ingest::apply_ingest_placements;
 use crate::iterators::concat_iterator::SstConcatIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
+use crate::iterators::{StorageIterator, ValueKind};
 use crate::key::{self, KeySlice};
-use crate::lsm_iterator::{FusedIterator, LsmIterator};
-use crate::manifest::{Manifest, ManifestRecord};
+use crate::lsm_iterator::{FusedIterator, LsmIterator, LsmIteratorInner};
+use crate::manifest::{self, Manifest, ManifestRecord};
 use crate::mem_table::{MemTable, map_bound, map_key_bound_plus_ts};
+use crate::merge_operator::MergeOperator;
 use crate::mvcc::LsmMvccInner;
 use crate::mvcc::txn::{Transaction, TxnIterator};
-use crate::table::{FileObject, SsTable, SsTableBuilder, SsTableIterator};
+use crate::prefix::{PrefixExtractor, prefix_upper_bound};
+use crate::range_tombstone::RangeTombstone;
+use crate::salvage::{self, SalvageReport, UnavailableRange};
+use crate::table::{CompressionType, FileObject, SsTable, SsTableBuilder, SsTableIterator};
+use crate::ttl;
+use crate::value_log::ValueLog;
+use crate::varint::uvarint_len;
 
-pub type BlockCache = moka::sync::Cache<(usize, usize), Arc<Block>>;
+pub use crate::block_cache::BlockCache;
 
 /// Represents the state of the storage engine.
 #[derive(Clone)]
@@ -56,33 +67,99 @@ pub struct LsmStorageState {
     pub levels: Vec<(usize, Vec<usize>)>,
     /// SST objects.
     pub sstables: HashMap<usize, Arc<SsTable>>,
+    /// The column families other than the default one, whose state is the rest of this state. The
+    /// state of a column family has none.
+    pub column_families: BTreeMap<String, ColumnFamily>,
+}
+
+/// The name of the column family that the writes without a column family go to.
+pub const DEFAULT_COLUMN_FAMILY: &str = "default";
+
+/// A key space with its own mem-tables, SSTs and compaction options. The column families share the
+/// WAL, the manifest and the timestamps of the storage.
+#[derive(Clone)]
+pub struct ColumnFamily {
+    /// The id of the column family in the WAL and the manifest. The default column family is 0.
+    pub id: u32,
+    pub compaction_options: CompactionOptions,
+    /// The mem-tables and the SSTs of the column family.
+    pub state: Arc<LsmStorageState>,
 }
 
 pub enum WriteBatchRecord<T: AsRef<[u8]>> {
     Put(T, T),
     Del(T),
+    /// Delete the keys in `[start, end)`. Only the versions written before the batch are deleted.
+    DelRange(T, T),
+    /// Write a merge operand, which the merge operator combines with the value of the key.
+    Merge(T, T),
+    /// Put a value that expires after the duration.
+    PutWithTtl(T, T, Duration),
+    /// Write the records to the column family with the name. The records cannot be column family
+    /// records themselves.
+    ColumnFamily(String, Vec<WriteBatchRecord<T>>),
 }
 
-fn validate_write_batch<T: AsRef<[u8]>>(batch: &[WriteBatchRecord<T>]) -> Result<()> {
+fn validate_write_batch<T: AsRef<[u8]>>(
+    batch: &[WriteBatchRecord<T>],
+    options: &LsmStorageOptions,
+) -> Result<()> {
     let mut encoded_batch_len = 0usize;
+    let mut records = Vec::with_capacity(batch.len());
     for record in batch {
+        if let WriteBatchRecord::ColumnFamily(_, family_batch) = record {
+            for record in family_batch {
+                ensure!(
+                    !matches!(record, WriteBatchRecord::ColumnFamily(..)),
+                    "column family records cannot be nested"
+                );
+                // The keys of a column family start with its id in the WAL.
+                encoded_batch_len = encoded_batch_len
+                    .checked_add(uvarint_len(u32::MAX as u64))
+                    .context("write batch size overflow")?;
+                records.push(record);
+            }
+        } else {
+            records.push(record);
+        }
+    }
+    for record in records {
         let (key, value) = match record {
             WriteBatchRecord::Put(key, value) => (key.as_ref(), value.as_ref()),
+            WriteBatchRecord::PutWithTtl(key, value, _) => {
+                // The expiry time is stored before the value.
+                encoded_batch_len = encoded_batch_len
+                    .checked_add(std::mem::size_of::<u64>())
+                    .context("write batch size overflow")?;
+                (key.as_ref(), value.as_ref())
+            }
             WriteBatchRecord::Del(key) => (key.as_ref(), &[][..]),
+            WriteBatchRecord::DelRange(start, end) => {
+                let (start, end) = (start.as_ref(), end.as_ref());
+                ensure!(!start.is_empty(), "range deletion start cannot be empty");
+                ensure!(
+                    options.comparator.compare(start, end).is_lt(),
+                    "range deletion start must be before its end"
+                );
+                (start, end)
+            }
+            WriteBatchRecord::Merge(key, operand) => {
+                let (key, operand) = (key.as_ref(), operand.as_ref());
+                ensure!(
+                    options.merge_operator.is_some(),
+                    "cannot merge without a merge operator"
+                );
+                ensure!(!key.is_empty(), "key cannot be empty");
+                ensure!(!operand.is_empty(), "merge operand cannot be empty");
+                (key, operand)
+            }
+            WriteBatchRecord::ColumnFamily(..) => unreachable!(),
         };
-        ensure!(
-            u16::try_from(key.len()).is_ok(),
-            "key is too large for the on-disk format"
-        );
-        ensure!(
-            u16::try_from(value.len()).is_ok(),
-            "value is too large for the on-disk format"
-        );
         encoded_batch_len = encoded_batch_len
-            .checked_add(std::mem::size_of::<u16>())
+            .checked_add(uvarint_len(key.len() as u64))
             .and_then(|len| len.checked_add(key.len()))
             .and_then(|len| len.checked_add(std::mem::size_of::<u64>()))
-            .and_then(|len| len.checked_add(std::mem::size_of::<u16>()))
+            .and_then(|len| len.checked_add(uvarint_len(value.len() as u64)))
             .and_then(|len| len.checked_add(value.len()))
             .context("write batch size overflow")?;
     }
@@ -94,8 +171,24 @@ fn validate_write_batch<T: AsRef<[u8]>>(batch: &[WriteBatchRecord<T>]) -> Result
 }
 
 impl LsmStorageState {
-    fn create(options: &LsmStorageOptions) -> Self {
-        let levels = match &options.compaction_options {
+    /// A state without any levels, for the compaction simulator to lay out.
+    pub fn new_empty() -> Self {
+        Self {
+            memtable: Arc::new(MemTable::create(0)),
+            imm_memtables: Vec::new(),
+            l0_sstables: Vec::new(),
+            levels: Vec::new(),
+            sstables: Default::default(),
+            column_families: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn create(
+        compaction_options: &CompactionOptions,
+        memtable_id: usize,
+        comparator: &'static dyn Comparator,
+    ) -> Self {
+        let levels = match compaction_options {
             CompactionOptions::Leveled(LeveledCompactionOptions { max_levels, .. })
             | CompactionOptions::Simple(SimpleLeveledCompactionOptions { max_levels, .. }) => (1
                 ..=*max_levels)
@@ -105,12 +198,26 @@ impl LsmStorageState {
             CompactionOptions::NoCompaction => vec![(1, Vec::new())],
         };
         Self {
-            memtable: Arc::new(MemTable::create(0)),
+            memtable: Arc::new(MemTable::create_with_comparator(memtable_id, comparator)),
             imm_memtables: Vec::new(),
             l0_sstables: Vec::new(),
             levels,
             sstables: Default::default(),
+            column_families: BTreeMap::new(),
+        }
+    }
+
+    /// The id and the state of the column family with the name, where this is the state of the
+    /// default column family.
+    pub(crate) fn column_family(&self, name: &str) -> Result<(u32, &LsmStorageState)> {
+        if name == DEFAULT_COLUMN_FAMILY {
+            return Ok((0, self));
         }
+        let column_family = self
+            .column_families
+            .get(name)
+            .with_context(|| format!("column family {name:?} does not exist"))?;
+        Ok((column_family.id, &column_family.state))
     }
 }
 
@@ -118,6 +225,14 @@ impl LsmStorageState {
 pub struct LsmStorageOptions {
     // Block size in bytes
     pub block_size: usize,
+    // Number of entries between the restart points of a data block, which store the whole key
+    pub block_restart_interval: usize,
+    // Append a hash index of the user keys to data blocks to speed up point lookups
+    pub data_block_hash_index: bool,
+    // Adds the prefixes of the keys to the bloom filters of the SSTs, used by prefix scans
+    pub prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    // Combines the merge operands of a key with its value, required to write merge operands
+    pub merge_operator: Option<Arc<dyn MergeOperator>>,
     // SST size in bytes, also the approximate memtable capacity limit
     pub target_sst_size: usize,
     // Maximum number of memtables in memory, flush to L0 when exceeding this limit
@@ -125,63 +240,332 @@ pub struct LsmStorageOptions {
     pub compaction_options: CompactionOptions,
     pub enable_wal: bool,
     pub serializable: bool,
+    // Codec used to compress the data blocks of newly-written SSTs
+    pub compression: CompressionType,
+    // Capacity of the data block cache in bytes
+    pub block_cache_capacity: u64,
+    // Capacity of the index and bloom filter cache in bytes, separate from the data block cache
+    pub meta_cache_capacity: u64,
+    // Values at least this large are moved to value log files when flushed, disabled if None
+    pub value_separation_threshold: Option<usize>,
+    // Fraction of a value log file that must be discarded before it is garbage collected
+    pub value_log_gc_discard_ratio: f64,
+    // Order of the user keys, recorded in the manifest and cannot be changed after creation
+    pub comparator: &'static dyn Comparator,
+    // Rewrite the manifest as a snapshot of the state once it grows beyond this size in bytes
+    pub max_manifest_size: u64,
+    // Number of threads that open the SSTs when the database is opened
+    pub recovery_threads: usize,
+    // Refresh the iterators onto the latest state of the storage after they move over this many
+    // keys, so that a long scan does not keep the flushed memtables and compacted SSTs alive
+    pub iterator_refresh_interval: Option<usize>,
 }
 
 impl LsmStorageOptions {
     pub fn default_for_week1_test() -> Self {
         Self {
             block_size: 4096,
+            block_restart_interval: 16,
+            data_block_hash_index: false,
+            prefix_extractor: None,
+            merge_operator: None,
             target_sst_size: 2 << 20,
             compaction_options: CompactionOptions::NoCompaction,
             enable_wal: false,
             num_memtable_limit: 50,
             serializable: false,
+            compression: CompressionType::None,
+            block_cache_capacity: 4 << 30,
+            meta_cache_capacity: 256 << 20,
+            value_separation_threshold: None,
+            value_log_gc_discard_ratio: 0.5,
+            comparator: &BYTEWISE_COMPARATOR,
+            max_manifest_size: 1 << 20,
+            recovery_threads: 4,
+            iterator_refresh_interval: None,
         }
     }
 
     pub fn default_for_week1_day6_test() -> Self {
         Self {
             block_size: 4096,
+            block_restart_interval: 16,
+            data_block_hash_index: false,
+            prefix_extractor: None,
+            merge_operator: None,
             target_sst_size: 2 << 20,
             compaction_options: CompactionOptions::NoCompaction,
             enable_wal: false,
             num_memtable_limit: 2,
             serializable: false,
+            compression: CompressionType::None,
+            block_cache_capacity: 4 << 30,
+            meta_cache_capacity: 256 << 20,
+            value_separation_threshold: None,
+            value_log_gc_discard_ratio: 0.5,
+            comparator: &BYTEWISE_COMPARATOR,
+            max_manifest_size: 1 << 20,
+            recovery_threads: 4,
+            iterator_refresh_interval: None,
         }
     }
 
     pub fn default_for_week2_test(compaction_options: CompactionOptions) -> Self {
         Self {
             block_size: 4096,
+            block_restart_interval: 16,
+            data_block_hash_index: false,
+            prefix_extractor: None,
+            merge_operator: None,
             target_sst_size: 1 << 20, // 1MB
             compaction_options,
             enable_wal: false,
             num_memtable_limit: 2,
             serializable: false,
+            compression: CompressionType::None,
+            block_cache_capacity: 4 << 30,
+            meta_cache_capacity: 256 << 20,
+            value_separation_threshold: None,
+            value_log_gc_discard_ratio: 0.5,
+            comparator: &BYTEWISE_COMPARATOR,
+            max_manifest_size: 1 << 20,
+            recovery_threads: 4,
+            iterator_refresh_interval: None,
         }
     }
 }
 
+impl Default for LsmStorageOptions {
+    /// Options for a database that is used outside of the tests: leveled compaction, with the WAL
+    /// enabled.
+    fn default() -> Self {
+        Self {
+            block_size: 4096,
+            block_restart_interval: 16,
+            data_block_hash_index: false,
+            prefix_extractor: None,
+            merge_operator: None,
+            target_sst_size: 2 << 20, // 2MB
+            compaction_options: CompactionOptions::Leveled(LeveledCompactionOptions {
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 4,
+                base_level_size_mb: 128,
+                level_size_multiplier: 2,
+            }),
+            enable_wal: true,
+            num_memtable_limit: 3,
+            serializable: false,
+            compression: CompressionType::None,
+            block_cache_capacity: 256 << 20,
+            meta_cache_capacity: 64 << 20,
+            value_separation_threshold: None,
+            value_log_gc_discard_ratio: 0.5,
+            comparator: &BYTEWISE_COMPARATOR,
+            max_manifest_size: 1 << 20,
+            recovery_threads: 4,
+            iterator_refresh_interval: None,
+        }
+    }
+}
+
+/// Add an SST flushed from a mem-table to the state of a column family.
+pub(crate) fn add_flushed_sst(
+    state: &mut LsmStorageState,
+    compaction_controller: &CompactionController,
+    sst_id: usize,
+) {
+    if compaction_controller.flush_to_l0() {
+        // In leveled compaction or no compaction, simply flush to L0
+        state.l0_sstables.insert(0, sst_id);
+    } else {
+        // In tiered compaction, create a new tier
+        state.levels.insert(0, (sst_id, vec![sst_id]));
+    }
+}
+
+fn column_family_by_id(
+    column_families: &mut BTreeMap<String, ColumnFamily>,
+    id: u32,
+) -> Result<&mut ColumnFamily> {
+    column_families
+        .values_mut()
+        .find(|column_family| column_family.id == id)
+        .with_context(|| format!("column family {id} does not exist"))
+}
+
+/// Report the progress of opening the SSTs after every this many tables.
+const OPEN_PROGRESS_INTERVAL: usize = 1024;
+
+/// Open the SST with the id in the database directory `path`, which belongs to the column family
+/// with the id `column_family`.
+fn open_table(
+    path: &Path,
+    table_id: usize,
+    column_family: u32,
+    block_cache: &Arc<BlockCache>,
+    options: &LsmStorageOptions,
+) -> Result<SsTable> {
+    let file = FileObject::open(&LsmStorageInner::path_of_sst_static(path, table_id))
+        .context("failed to open SST")?;
+    let table = SsTable::open_with_comparator(
+        table_id,
+        Some(block_cache.clone()),
+        file,
+        options.comparator,
+    )?;
+    // The tables written before the column family was recorded are trusted to the manifest.
+    if let Some(id) = table.column_family() {
+        ensure!(
+            id == column_family,
+            "SST belongs to column family {id}, not {column_family}"
+        );
+    }
+    Ok(table)
+}
+
+/// Open the SSTs with the ids on a bounded number of threads with `open`. If some SSTs cannot be
+/// opened, the error of the first one in `table_ids` is returned, as if they were opened one by
+/// one.
+fn open_tables<T: Send>(
+    table_ids: &[usize],
+    options: &LsmStorageOptions,
+    open: impl Fn(usize) -> Result<T> + Sync,
+) -> Result<Vec<T>> {
+    let next = AtomicUsize::new(0);
+    let opened = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results = table_ids
+        .iter()
+        .map(|_| Mutex::new(None))
+        .collect::<Vec<Mutex<Option<Result<T>>>>>();
+    let threads = options.recovery_threads.clamp(1, table_ids.len().max(1));
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                // The tables are taken in order, so every table before a failed one is opened.
+                while !failed.load(Ordering::SeqCst) {
+                    let idx = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(&table_id) = table_ids.get(idx) else {
+                        break;
+                    };
+                    let result = open(table_id);
+                    failed.fetch_or(result.is_err(), Ordering::SeqCst);
+                    *results[idx].lock() = Some(result);
+                    let opened = opened.fetch_add(1, Ordering::SeqCst) + 1;
+                    if opened.is_multiple_of(OPEN_PROGRESS_INTERVAL) {
+                        println!("{}/{} SSTs opened", opened, table_ids.len());
+                    }
+                }
+            });
+        }
+    });
+    let mut tables = Vec::with_capacity(table_ids.len());
+    for result in results {
+        match result.into_inner() {
+            Some(result) => tables.push(result?),
+            None => unreachable!("a table is skipped only after an earlier table failed"),
+        }
+    }
+    Ok(tables)
+}
+
+/// Open the SSTs of the state of a column family, returning the number of SSTs opened. The column
+/// family is given by its name and id, or is `None` for the default column family. With `salvage`,
+/// every block of the SSTs is verified, and the SSTs that are corrupted are removed from the state
+/// and reported as unavailable in the column family.
+fn open_sstables(
+    path: &Path,
+    state: &mut LsmStorageState,
+    compaction_controller: &CompactionController,
+    block_cache: &Arc<BlockCache>,
+    options: &LsmStorageOptions,
+    salvage: Option<&mut SalvageReport>,
+    column_family: Option<(&str, u32)>,
+) -> Result<usize> {
+    let (name, id) = match column_family {
+        Some((name, id)) => (Some(name), id),
+        None => (None, 0),
+    };
+    let table_ids = state
+        .l0_sstables
+        .iter()
+        .chain(state.levels.iter().flat_map(|(_, files)| files))
+        .copied()
+        .collect::<Vec<_>>();
+    let tables = match salvage {
+        None => open_tables(&table_ids, options, |table_id| {
+            open_table(path, table_id, id, block_cache, options)
+        })?,
+        Some(report) => {
+            let results = open_tables(&table_ids, options, |table_id| {
+                Ok(match open_table(path, table_id, id, block_cache, options) {
+                    Ok(table) => match salvage::verify_table(&table) {
+                        Ok(()) => Ok(table),
+                        Err(e) => Err(UnavailableRange::of_table(table_id, Some(&table), name, &e)),
+                    },
+                    Err(e) => Err(UnavailableRange::of_table(table_id, None, name, &e)),
+                })
+            })?;
+            let mut tables = Vec::with_capacity(results.len());
+            // The corrupted SSTs are only left out of the state. The database is read-only, so
+            // the files stay where they are.
+            let mut corrupted = HashSet::new();
+            for (table_id, result) in table_ids.iter().zip(results) {
+                match result {
+                    Ok(table) => tables.push(table),
+                    Err(range) => {
+                        report.unavailable.push(range);
+                        corrupted.insert(*table_id);
+                    }
+                }
+            }
+            state.l0_sstables.retain(|id| !corrupted.contains(id));
+            for (_, files) in &mut state.levels {
+                files.retain(|id| !corrupted.contains(id));
+            }
+            tables
+        }
+    };
+    let opened = tables.len();
+    for sst in tables {
+        state.sstables.insert(sst.sst_id(), Arc::new(sst));
+    }
+
+    // Sort SSTs on each level (only for leveled compaction)
+    if let CompactionController::Leveled(_) = compaction_controller {
+        for (_id, ssts) in &mut state.levels {
+            ssts.sort_by(|x, y| {
+                let x = state.sstables.get(x).unwrap();
+                let y = state.sstables.get(y).unwrap();
+                x.comparator()
+                    .compare_keys(x.first_key().as_key_slice(), y.first_key().as_key_slice())
+            })
+        }
+    }
+    Ok(opened)
+}
+
 fn range_overlap(
     user_begin: Bound<&[u8]>,
     user_end: Bound<&[u8]>,
     table_begin: KeySlice,
     table_end: KeySlice,
+    comparator: &dyn Comparator,
 ) -> bool {
     match user_end {
-        Bound::Excluded(key) if key <= table_begin.key_ref() => {
+        Bound::Excluded(key) if comparator.compare(key, table_begin.key_ref()).is_le() => {
             return false;
         }
-        Bound::Included(key) if key < table_begin.key_ref() => {
+        Bound::Included(key) if comparator.compare(key, table_begin.key_ref()).is_lt() => {
             return false;
         }
         _ => {}
     }
     match user_begin {
-        Bound::Excluded(key) if key >= table_end.key_ref() => {
+        Bound::Excluded(key) if comparator.compare(key, table_end.key_ref()).is_ge() => {
             return false;
         }
-        Bound::Included(key) if key > table_end.key_ref() => {
+        Bound::Included(key) if comparator.compare(key, table_end.key_ref()).is_gt() => {
             return false;
         }
         _ => {}
@@ -189,13 +573,62 @@ fn range_overlap(
     true
 }
 
-fn key_within(user_key: &[u8], table_begin: KeySlice, table_end: KeySlice) -> bool {
-    table_begin.key_ref() <= user_key && user_key <= table_end.key_ref()
+fn key_within(
+    user_key: &[u8],
+    table_begin: KeySlice,
+    table_end: KeySlice,
+    comparator: &dyn Comparator,
+) -> bool {
+    comparator.compare(table_begin.key_ref(), user_key).is_le()
+        && comparator.compare(user_key, table_end.key_ref()).is_le()
 }
 
+/// What a compaction filter does with a version of a key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompactionDecision {
+    Keep,
+    /// Removes the version, and with it the earlier versions of the key.
+    Remove,
+    /// Replaces the value of the version. An empty value removes it.
+    ChangeValue(Bytes),
+}
+
+/// Decides which versions of the keys compaction keeps. Compaction only filters the latest version
+/// of a key that every reader sees, after it folds the merge operands into a value. Deletions and
+/// expired values are not filtered.
+pub trait CompactionFilter: Debug + Send + Sync {
+    /// Identifies the filter in error messages.
+    fn name(&self) -> &str;
+
+    /// Decides what to do with the version of `key` at `ts`, which compaction writes to `level`.
+    /// With tiered compaction, `level` is the position of the output tier, the latest tier being
+    /// level 1.
+    fn filter(&self, level: usize, key: &[u8], ts: u64, value: &[u8])
+    -> Result<CompactionDecision>;
+}
+
+/// Removes the keys that start with a prefix.
 #[derive(Clone, Debug)]
-pub enum CompactionFilter {
-    Prefix(Bytes),
+pub struct PrefixCompactionFilter(pub Bytes);
+
+impl CompactionFilter for PrefixCompactionFilter {
+    fn name(&self) -> &str {
+        "prefix"
+    }
+
+    fn filter(
+        &self,
+        _level: usize,
+        key: &[u8],
+        _ts: u64,
+        _value: &[u8],
+    ) -> Result<CompactionDecision> {
+        Ok(if key.starts_with(&self.0) {
+            CompactionDecision::Remove
+        } else {
+            CompactionDecision::Keep
+        })
+    }
 }
 
 /// The storage interface of the LSM tree.
@@ -209,7 +642,10 @@ pub(crate) struct LsmStorageInner {
     pub(crate) compaction_controller: CompactionController,
     pub(crate) manifest: Option<Manifest>,
     pub(crate) mvcc: Option<LsmMvccInner>,
-    pub(crate) compaction_filters: Arc<Mutex<Vec<CompactionFilter>>>,
+    pub(crate) compaction_filters: Arc<Mutex<Vec<Arc<dyn CompactionFilter>>>>,
+    pub(crate) value_log: ValueLog,
+    /// The database was opened read-only, without a manifest or a WAL to write to.
+    pub(crate) read_only: bool,
 }
 
 /// A thin wrapper for `LsmStorageInner` and the user interface for MiniLSM.
@@ -234,6 +670,9 @@ impl Drop for MiniLsm {
 
 impl MiniLsm {
     pub fn close(&self) -> Result<()> {
+        if self.inner.read_only {
+            return Ok(());
+        }
         self.inner.sync_dir()?;
         self.compaction_notifier.send(()).ok();
         self.flush_notifier.send(()).ok();
@@ -258,11 +697,13 @@ impl MiniLsm {
         }
 
         // create memtable and skip updating manifest
-        if !self.inner.state.read().memtable.is_empty() {
-            self.inner
-                .freeze_memtable_with_memtable(Arc::new(MemTable::create(
+        if !self.inner.is_memtable_empty() {
+            self.inner.freeze_memtable_with_memtable(Arc::new(
+                MemTable::create_with_comparator(
                     self.inner.next_sst_id(),
-                )))?;
+                    self.inner.options.comparator,
+                ),
+            ))?;
         }
 
         while {
@@ -293,7 +734,28 @@ impl MiniLsm {
         }))
     }
 
-    pub fn add_compaction_filter(&self, compaction_filter: CompactionFilter) {
+    /// Open an existing database read-only, without the flush and compaction threads. The SSTs,
+    /// WAL frames and manifest records that fail their checksums are left out instead of failing
+    /// the open, and the key ranges whose latest versions may be lost are reported. The reads may
+    /// return older versions of the keys in these ranges, and every write fails.
+    pub fn open_read_only(
+        path: impl AsRef<Path>,
+        options: LsmStorageOptions,
+    ) -> Result<(Arc<Self>, SalvageReport)> {
+        let (inner, report) = LsmStorageInner::open_read_only(path, options)?;
+        let (flush_notifier, _) = crossbeam_channel::unbounded();
+        let (compaction_notifier, _) = crossbeam_channel::unbounded();
+        let lsm = Arc::new(Self {
+            inner: Arc::new(inner),
+            flush_notifier,
+            flush_thread: Mutex::new(None),
+            compaction_notifier,
+            compaction_thread: Mutex::new(None),
+        });
+        Ok((lsm, report))
+    }
+
+    pub fn add_compaction_filter(&self, compaction_filter: Arc<dyn CompactionFilter>) {
         self.inner.add_compaction_filter(compaction_filter)
     }
 
@@ -309,10 +771,25 @@ impl MiniLsm {
         self.inner.put(key, value)
     }
 
+    /// Put a key-value pair that is deleted once `ttl` has passed.
+    pub fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        self.inner.put_with_ttl(key, value, ttl)
+    }
+
     pub fn delete(&self, key: &[u8]) -> Result<()> {
         self.inner.delete(key)
     }
 
+    /// Delete the keys in `[lower, upper)` with a single range tombstone.
+    pub fn delete_range(&self, lower: &[u8], upper: &[u8]) -> Result<()> {
+        self.inner.delete_range(lower, upper)
+    }
+
+    /// Write a merge operand of `key`, which the merge operator combines with its value.
+    pub fn merge(&self, key: &[u8], operand: &[u8]) -> Result<()> {
+        self.inner.merge(key, operand)
+    }
+
     pub fn sync(&self) -> Result<()> {
         self.inner.sync()
     }
@@ -325,9 +802,72 @@ impl MiniLsm {
         self.inner.scan(lower, upper)
     }
 
+    /// Scan a range of keys in descending order. The iterator starts at the last key of the range,
+    /// and `prev` moves it to the previous key.
+    pub fn scan_rev(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Result<TxnIterator> {
+        self.inner.scan_rev(lower, upper)
+    }
+
+    /// Scan the keys that start with `prefix`. With a prefix extractor, the SSTs whose bloom
+    /// filters exclude the prefix are skipped.
+    pub fn prefix_scan(&self, prefix: &[u8]) -> Result<TxnIterator> {
+        self.inner.prefix_scan(prefix)
+    }
+
+    /// Create a column family, which is compacted with `compaction_options`.
+    pub fn create_column_family(
+        &self,
+        name: &str,
+        compaction_options: CompactionOptions,
+    ) -> Result<()> {
+        self.inner.create_column_family(name, compaction_options)
+    }
+
+    pub fn get_cf(&self, column_family: &str, key: &[u8]) -> Result<Option<Bytes>> {
+        self.inner.get_cf(column_family, key)
+    }
+
+    pub fn put_cf(&self, column_family: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.inner.put_cf(column_family, key, value)
+    }
+
+    pub fn delete_cf(&self, column_family: &str, key: &[u8]) -> Result<()> {
+        self.inner.delete_cf(column_family, key)
+    }
+
+    /// Put a key-value pair to a column family that is deleted once `ttl` has passed.
+    pub fn put_with_ttl_cf(
+        &self,
+        column_family: &str,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Result<()> {
+        self.inner.put_with_ttl_cf(column_family, key, value, ttl)
+    }
+
+    /// Delete the keys of a column family in `[lower, upper)` with a single range tombstone.
+    pub fn delete_range_cf(&self, column_family: &str, lower: &[u8], upper: &[u8]) -> Result<()> {
+        self.inner.delete_range_cf(column_family, lower, upper)
+    }
+
+    /// Write a merge operand of `key` in a column family.
+    pub fn merge_cf(&self, column_family: &str, key: &[u8], operand: &[u8]) -> Result<()> {
+        self.inner.merge_cf(column_family, key, operand)
+    }
+
+    pub fn scan_cf(
+        &self,
+        column_family: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<TxnIterator> {
+        self.inner.scan_cf(column_family, lower, upper)
+    }
+
     /// Only call this in test cases due to race conditions
     pub fn force_flush(&self) -> Result<()> {
-        if !self.inner.state.read().memtable.is_empty() {
+        if !self.inner.is_memtable_empty() {
             self.inner
                 .force_freeze_memtable(&self.inner.state_lock.lock())?;
         }
@@ -340,6 +880,32 @@ impl MiniLsm {
     pub fn force_full_compaction(&self) -> Result<()> {
         self.inner.force_full_compaction()
     }
+
+    /// Garbage collect the value log files that have reached the discard ratio.
+    pub fn force_value_log_gc(&self) -> Result<()> {
+        self.inner.trigger_value_log_gc()
+    }
+
+    /// Ingest SSTs written by [`SstFileWriter`](crate::ingest::SstFileWriter), returning the
+    /// commit timestamp of their keys.
+    pub fn ingest_external_files(&self, paths: &[impl AsRef<Path>]) -> Result<u64> {
+        self.inner.ingest_external_files(paths)
+    }
+
+    /// Ingest SSTs written by [`SstFileWriter`](crate::ingest::SstFileWriter) into a column
+    /// family, returning the commit timestamp of their keys.
+    pub fn ingest_external_files_cf(
+        &self,
+        column_family: &str,
+        paths: &[impl AsRef<Path>],
+    ) -> Result<u64> {
+        self.inner.ingest_external_files_cf(column_family, paths)
+    }
+
+    /// Create a consistent copy of the database in `dir`, which opens as an independent database.
+    pub fn create_checkpoint(&self, dir: impl AsRef<Path>) -> Result<()> {
+        self.inner.create_checkpoint(dir)
+    }
 }
 
 impl LsmStorageInner {
@@ -359,54 +925,120 @@ impl LsmStorageInner {
     /// Start the storage engine by either loading an existing directory or creating a new one if the directory does
     /// not exist.
     pub(crate) fn open(path: impl AsRef<Path>, options: LsmStorageOptions) -> Result<Self> {
-        let mut state = LsmStorageState::create(&options);
-        let path = path.as_ref();
+        Self::open_with_salvage(path.as_ref(), options, None)
+    }
+
+    /// Open an existing database read-only, leaving out the files that fail their checksums.
+    pub(crate) fn open_read_only(
+        path: impl AsRef<Path>,
+        options: LsmStorageOptions,
+    ) -> Result<(Self, SalvageReport)> {
+        let mut report = SalvageReport::default();
+        let storage = Self::open_with_salvage(path.as_ref(), options, Some(&mut report))?;
+        Ok((storage, report))
+    }
+
+    /// Open the database in `path`. With `salvage`, the database is opened read-only, and the
+    /// corrupted files are reported there instead of failing the open.
+    fn open_with_salvage(
+        path: &Path,
+        options: LsmStorageOptions,
+        mut salvage: Option<&mut SalvageReport>,
+    ) -> Result<Self> {
+        let read_only = salvage.is_some();
+        let mut state = LsmStorageState::create(&options.compaction_options, 0, options.comparator);
+        let mut column_families = BTreeMap::new();
         let mut next_sst_id = 1;
-        let block_cache = Arc::new(BlockCache::new(1 << 20)); // 4GB block cache,
-        let manifest;
+        let block_cache = Arc::new(BlockCache::new(
+            options.block_cache_capacity,
+            options.meta_cache_capacity,
+        ));
+        let mut manifest = None;
 
-        let compaction_controller = match &options.compaction_options {
-            CompactionOptions::Leveled(options) => {
-                CompactionController::Leveled(LeveledCompactionController::new(options.clone()))
-            }
-            CompactionOptions::Tiered(options) => {
-                CompactionController::Tiered(TieredCompactionController::new(options.clone()))
-            }
-            CompactionOptions::Simple(options) => CompactionController::Simple(
-                SimpleLeveledCompactionController::new(options.clone()),
-            ),
-            CompactionOptions::NoCompaction => CompactionController::NoCompaction,
-        };
+        let compaction_controller = CompactionController::new(&options.compaction_options);
 
         if !path.exists() {
+            ensure!(!read_only, "{} does not exist", path.display());
             std::fs::create_dir_all(path).context("failed to create DB dir")?;
         }
-        let manifest_path = path.join("MANIFEST");
+        let manifest_path = manifest::current_manifest_path(path)?;
+        ensure!(
+            !read_only || manifest_path.exists(),
+            "{} has no manifest to open read-only",
+            path.display()
+        );
         let mut last_commit_ts = 0;
+        let mut known_ssts = HashSet::new();
+        let mut collected_value_logs = HashSet::new();
+        let mut discarded_value_logs = BTreeMap::new();
         if !manifest_path.exists() {
             if options.enable_wal {
                 state.memtable = Arc::new(MemTable::create_with_wal(
                     state.memtable.id(),
                     Self::path_of_wal_static(path, state.memtable.id()),
+                    options.comparator,
                 )?);
             }
-            manifest = Manifest::create(&manifest_path).context("failed to create manifest")?;
-            manifest.add_record_when_init(ManifestRecord::NewMemtable(state.memtable.id()))?;
+            let m = Manifest::create(&manifest_path).context("failed to create manifest")?;
+            m.add_record_when_init(ManifestRecord::Comparator(
+                options.comparator.name().to_string(),
+            ))?;
+            m.add_record_when_init(ManifestRecord::NewMemtable(state.memtable.id()))?;
+            manifest = Some(m);
         } else {
-            let (m, records) = Manifest::recover(&manifest_path)?;
+            let records = if let Some(report) = salvage.as_deref_mut() {
+                let (records, corruption) = Manifest::recover_read_only(&manifest_path)?;
+                if let Some(e) = corruption {
+                    // The records after the corrupted one may have changed any column family.
+                    let file = manifest_path.file_name().unwrap().to_string_lossy();
+                    report
+                        .unavailable
+                        .push(UnavailableRange::unbounded(file, None, &e));
+                }
+                records
+            } else {
+                manifest::remove_obsolete_manifests(path)?;
+                let (m, records) = Manifest::recover(&manifest_path)?;
+                manifest = Some(m);
+                records
+            };
             let mut memtables = BTreeSet::new();
+            // the databases created before the comparator was recorded are ordered bytewise
+            let mut comparator = BYTEWISE_COMPARATOR.name().to_string();
             for record in records {
                 match record {
                     ManifestRecord::Flush(sst_id) => {
                         let res = memtables.remove(&sst_id);
                         assert!(res, "memtable not exist?");
-                        if compaction_controller.flush_to_l0() {
-                            state.l0_sstables.insert(0, sst_id);
-                        } else {
-                            state.levels.insert(0, (sst_id, vec![sst_id]));
-                        }
+                        known_ssts.insert(sst_id);
+                        add_flushed_sst(&mut state, &compaction_controller, sst_id);
                         next_sst_id = next_sst_id.max(sst_id);
                     }
+                    ManifestRecord::FlushColumnFamilies {
+                        memtable,
+                        flush_default,
+                        ssts,
+                    } => {
+                        let res = memtables.remove(&memtable);
+                        assert!(res, "memtable not exist?");
+                        if flush_default {
+                            known_ssts.insert(memtable);
+                            add_flushed_sst(&mut state, &compaction_controller, memtable);
+                        }
+                        next_sst_id = next_sst_id.max(memtable);
+                        for (id, sst_id) in ssts {
+                            let column_family = column_family_by_id(&mut column_families, id)?;
+                            let controller =
+                                CompactionController::new(&column_family.compaction_options);
+                            add_flushed_sst(
+                                Arc::make_mut(&mut column_family.state),
+                                &controller,
+                                sst_id,
+                            );
+                            known_ssts.insert(sst_id);
+                            next_sst_id = next_sst_id.max(sst_id);
+                        }
+                    }
                     ManifestRecord::NewMemtable(x) => {
                         next_sst_id = next_sst_id.max(x);
                         memtables.insert(x);
@@ -416,78 +1048,258 @@ impl LsmStorageInner {
                             .apply_compaction_result(&state, &task, &output, true);
                         // TODO: apply remove again
                         state = new_state;
+                        known_ssts.extend(output.iter().copied());
+                        next_sst_id =
+                            next_sst_id.max(output.iter().max().copied().unwrap_or_default());
+                    }
+                    ManifestRecord::ValueLogGc(id) => {
+                        collected_value_logs.insert(id);
+                    }
+                    ManifestRecord::ValueLogDiscarded(discarded) => {
+                        discarded_value_logs.extend(discarded);
+                    }
+                    ManifestRecord::Comparator(name) => {
+                        comparator = name;
+                    }
+                    ManifestRecord::CreateColumnFamily(name, id, compaction_options) => {
+                        let family_state =
+                            LsmStorageState::create(&compaction_options, 0, options.comparator);
+                        column_families.insert(
+                            name,
+                            ColumnFamily {
+                                id,
+                                compaction_options,
+                                state: Arc::new(family_state),
+                            },
+                        );
+                    }
+                    ManifestRecord::ColumnFamilyCompaction(id, task, output) => {
+                        let column_family = column_family_by_id(&mut column_families, id)?;
+                        let controller =
+                            CompactionController::new(&column_family.compaction_options);
+                        let (new_state, _) = controller.apply_compaction_result(
+                            &column_family.state,
+                            &task,
+                            &output,
+                            true,
+                        );
+                        column_family.state = Arc::new(new_state);
+                        known_ssts.extend(output.iter().copied());
                         next_sst_id =
                             next_sst_id.max(output.iter().max().copied().unwrap_or_default());
                     }
+                    ManifestRecord::IngestExternalFiles(ssts) => {
+                        apply_ingest_placements(&mut state, &compaction_controller, &ssts);
+                        for (_, _, sst_id) in ssts {
+                            known_ssts.insert(sst_id);
+                            next_sst_id = next_sst_id.max(sst_id);
+                        }
+                    }
+                    ManifestRecord::ColumnFamilyIngestExternalFiles(id, ssts) => {
+                        let column_family = column_family_by_id(&mut column_families, id)?;
+                        let controller =
+                            CompactionController::new(&column_family.compaction_options);
+                        apply_ingest_placements(
+                            Arc::make_mut(&mut column_family.state),
+                            &controller,
+                            &ssts,
+                        );
+                        for (_, _, sst_id) in ssts {
+                            known_ssts.insert(sst_id);
+                            next_sst_id = next_sst_id.max(sst_id);
+                        }
+                    }
+                    ManifestRecord::ValueLogFiles(ids) => {
+                        // The value log files are named after the SSTs they were written with.
+                        next_sst_id =
+                            next_sst_id.max(ids.iter().max().copied().unwrap_or_default());
+                        known_ssts.extend(ids);
+                    }
+                    ManifestRecord::Snapshot {
+                        column_family: id,
+                        l0_sstables,
+                        levels,
+                    } => {
+                        let ssts = l0_sstables
+                            .iter()
+                            .chain(levels.iter().flat_map(|(_, files)| files))
+                            .copied()
+                            .collect::<Vec<_>>();
+                        next_sst_id =
+                            next_sst_id.max(ssts.iter().max().copied().unwrap_or_default());
+                        known_ssts.extend(ssts);
+                        let state = if id == 0 {
+                            &mut state
+                        } else {
+                            Arc::make_mut(&mut column_family_by_id(&mut column_families, id)?.state)
+                        };
+                        state.l0_sstables = l0_sstables;
+                        state.levels = levels;
+                    }
                 }
             }
+            ensure!(
+                comparator == options.comparator.name(),
+                "the database was created with the {comparator:?} comparator, cannot open it with {:?}",
+                options.comparator.name()
+            );
 
-            let mut sst_cnt = 0;
             // recover SSTs
-            for table_id in state
-                .l0_sstables
-                .iter()
-                .chain(state.levels.iter().flat_map(|(_, files)| files))
-            {
-                let table_id = *table_id;
-                let sst = SsTable::open(
-                    table_id,
-                    Some(block_cache.clone()),
-                    FileObject::open(&Self::path_of_sst_static(path, table_id))
-                        .context("failed to open SST")?,
+            let mut sst_cnt = open_sstables(
+                path,
+                &mut state,
+                &compaction_controller,
+                &block_cache,
+                &options,
+                salvage.as_deref_mut(),
+                None,
+            )?;
+            for (name, column_family) in column_families.iter_mut() {
+                let controller = CompactionController::new(&column_family.compaction_options);
+                sst_cnt += open_sstables(
+                    path,
+                    Arc::make_mut(&mut column_family.state),
+                    &controller,
+                    &block_cache,
+                    &options,
+                    salvage.as_deref_mut(),
+                    Some((name, column_family.id)),
                 )?;
-                last_commit_ts = last_commit_ts.max(sst.max_ts());
-                state.sstables.insert(table_id, Arc::new(sst));
-                sst_cnt += 1;
             }
+            last_commit_ts = last_commit_ts.max(
+                std::iter::once(&state)
+                    .chain(column_families.values().map(|x| x.state.as_ref()))
+                    .flat_map(|state| state.sstables.values())
+                    .map(|sst| sst.max_ts())
+                    .max()
+                    .unwrap_or_default(),
+            );
             println!("{} SSTs opened", sst_cnt);
 
             next_sst_id += 1;
 
-            // Sort SSTs on each level (only for leveled compaction)
-            if let CompactionController::Leveled(_) = &compaction_controller {
-                for (_id, ssts) in &mut state.levels {
-                    ssts.sort_by(|x, y| {
-                        state
-                            .sstables
-                            .get(x)
-                            .unwrap()
-                            .first_key()
-                            .cmp(state.sstables.get(y).unwrap().first_key())
-                    })
-                }
-            }
-
             // recover memtables
             if options.enable_wal {
                 let mut wal_cnt = 0;
                 for id in memtables.iter() {
-                    let memtable =
-                        MemTable::recover_from_wal(*id, Self::path_of_wal_static(path, *id))?;
-                    let max_ts = memtable
-                        .map
-                        .iter()
-                        .map(|x| x.key().ts())
+                    let wal_path = Self::path_of_wal_static(path, *id);
+                    let (memtable, mut family_memtables) = match salvage.as_deref_mut() {
+                        None => MemTable::recover_column_families_from_wal(
+                            *id,
+                            &wal_path,
+                            options.comparator,
+                        )?,
+                        Some(report) => {
+                            // The keys of a corrupted frame are unknown, so every key of every
+                            // column family may be lost.
+                            let file = format!("{:05}.wal", id);
+                            let mut corrupted_frames = Vec::new();
+                            let memtables = MemTable::salvage_column_families_from_wal(
+                                *id,
+                                &wal_path,
+                                options.comparator,
+                                &mut corrupted_frames,
+                            );
+                            for (offset, e) in corrupted_frames {
+                                let e = e.context(format!(
+                                    "corrupted WAL frame at byte offset {offset}"
+                                ));
+                                report.unavailable.push(UnavailableRange::unbounded(
+                                    file.as_str(),
+                                    None,
+                                    &e,
+                                ));
+                            }
+                            match memtables {
+                                Ok(memtables) => memtables,
+                                Err(e) => {
+                                    report
+                                        .unavailable
+                                        .push(UnavailableRange::unbounded(file, None, &e));
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+                    let max_ts = std::iter::once(&memtable)
+                        .chain(family_memtables.values())
+                        .flat_map(|memtable| {
+                            memtable
+                                .map
+                                .iter()
+                                .map(|x| x.key().key().ts())
+                                .chain(memtable.range_tombstones_max_ts())
+                        })
                         .max()
                         .unwrap_or_default();
                     last_commit_ts = last_commit_ts.max(max_ts);
-                    if !memtable.is_empty() {
+                    // The mem-tables of the column families are flushed with the mem-table of the
+                    // default column family that owns the WAL.
+                    let mut is_empty = memtable.is_empty();
+                    for column_family in column_families.values_mut() {
+                        if let Some(family_memtable) = family_memtables.remove(&column_family.id)
+                            && !family_memtable.is_empty()
+                        {
+                            Arc::make_mut(&mut column_family.state)
+                                .imm_memtables
+                                .insert(0, Arc::new(family_memtable));
+                            is_empty = false;
+                        }
+                    }
+                    if !family_memtables
+                        .values()
+                        .all(|memtable| memtable.is_empty())
+                    {
+                        let e = anyhow::anyhow!(
+                            "{:05}.wal has entries of a column family that does not exist",
+                            id
+                        );
+                        // The column family may have been created by a corrupted manifest record.
+                        match salvage.as_deref_mut() {
+                            Some(report) => report.unavailable.push(UnavailableRange::unbounded(
+                                format!("{:05}.wal", id),
+                                None,
+                                &e,
+                            )),
+                            None => return Err(e),
+                        }
+                    }
+                    if !is_empty {
                         state.imm_memtables.insert(0, Arc::new(memtable));
                         wal_cnt += 1;
                     }
                 }
                 println!("{} WALs recovered", wal_cnt);
+            }
+            if options.enable_wal && !read_only {
                 state.memtable = Arc::new(MemTable::create_with_wal(
                     next_sst_id,
                     Self::path_of_wal_static(path, next_sst_id),
+                    options.comparator,
                 )?);
             } else {
-                state.memtable = Arc::new(MemTable::create(next_sst_id));
+                state.memtable = Arc::new(MemTable::create_with_comparator(
+                    next_sst_id,
+                    options.comparator,
+                ));
+            }
+            for column_family in column_families.values_mut() {
+                Arc::make_mut(&mut column_family.state).memtable = Arc::new(
+                    MemTable::create_with_comparator(next_sst_id, options.comparator),
+                );
+            }
+            if let Some(m) = &manifest {
+                m.add_record_when_init(ManifestRecord::NewMemtable(state.memtable.id()))?;
             }
-            m.add_record_when_init(ManifestRecord::NewMemtable(state.memtable.id()))?;
             next_sst_id += 1;
-            manifest = m;
         };
+        state.column_families = column_families;
+        let value_log = if read_only {
+            ValueLog::open_read_only(path, &known_ssts, &collected_value_logs)?
+        } else {
+            ValueLog::open(path, &known_ssts, &collected_value_logs)?
+        };
+        value_log.restore_discarded(&discarded_value_logs);
 
         let storage = Self {
             state: Arc::new(RwLock::new(Arc::new(state))),
@@ -496,17 +1308,22 @@ impl LsmStorageInner {
             block_cache,
             next_sst_id: AtomicUsize::new(next_sst_id),
             compaction_controller,
-            manifest: Some(manifest),
+            manifest,
             options: options.into(),
             mvcc: Some(LsmMvccInner::new(last_commit_ts)),
             compaction_filters: Arc::new(Mutex::new(Vec::new())),
+            value_log,
+            read_only,
         };
-        storage.sync_dir()?;
+        if !read_only {
+            storage.sync_dir()?;
+            storage.trigger_manifest_rotation()?;
+        }
 
         Ok(storage)
     }
 
-    pub fn add_compaction_filter(&self, compaction_filter: CompactionFilter) {
+    pub fn add_compaction_filter(&self, compaction_filter: Arc<dyn CompactionFilter>) {
         let mut compaction_filters = self.compaction_filters.lock();
         compaction_filters.push(compaction_filter);
     }
@@ -516,18 +1333,202 @@ impl LsmStorageInner {
         self.state.read().memtable.sync_wal()
     }
 
+    pub fn create_column_family(
+        &self,
+        name: &str,
+        compaction_options: CompactionOptions,
+    ) -> Result<()> {
+        self.ensure_writable()?;
+        let state_lock = self.state_lock.lock();
+        let mut snapshot = self.state.read().as_ref().clone();
+        ensure!(
+            name != DEFAULT_COLUMN_FAMILY && !snapshot.column_families.contains_key(name),
+            "column family {name:?} already exists"
+        );
+        let id = snapshot
+            .column_families
+            .values()
+            .map(|column_family| column_family.id)
+            .max()
+            .unwrap_or_default()
+            .checked_add(1)
+            .context("too many column families")?;
+        // The mem-table shares its id with the mem-table of the default column family, so that
+        // they are frozen and flushed together.
+        let state = LsmStorageState::create(
+            &compaction_options,
+            snapshot.memtable.id(),
+            self.options.comparator,
+        );
+        self.manifest().add_record(
+            &state_lock,
+            ManifestRecord::CreateColumnFamily(name.to_string(), id, compaction_options.clone()),
+        )?;
+        snapshot.column_families.insert(
+            name.to_string(),
+            ColumnFamily {
+                id,
+                compaction_options,
+                state: Arc::new(state),
+            },
+        );
+        *self.state.write() = Arc::new(snapshot);
+        Ok(())
+    }
+
+    /// Take a snapshot of the state of a column family.
+    pub(crate) fn column_family_snapshot(&self, name: &str) -> Result<Arc<LsmStorageState>> {
+        let guard = self.state.read();
+        if name == DEFAULT_COLUMN_FAMILY {
+            return Ok(Arc::clone(&guard));
+        }
+        Ok(guard
+            .column_families
+            .get(name)
+            .with_context(|| format!("column family {name:?} does not exist"))?
+            .state
+            .clone())
+    }
+
+    /// The size of the largest mem-table of the column families.
+    fn max_memtable_size(&self) -> usize {
+        let guard = self.state.read();
+        guard
+            .column_families
+            .values()
+            .map(|column_family| column_family.state.memtable.approximate_size())
+            .fold(guard.memtable.approximate_size(), usize::max)
+    }
+
+    /// Whether the mem-tables of every column family are empty.
+    pub(crate) fn is_memtable_empty(&self) -> bool {
+        let guard = self.state.read();
+        guard.memtable.is_empty()
+            && guard
+                .column_families
+                .values()
+                .all(|column_family| column_family.state.memtable.is_empty())
+    }
+
     /// Get a key from the storage. In day 7, this can be further optimized by using a bloom filter.
     pub fn get(self: &Arc<Self>, key: &[u8]) -> Result<Option<Bytes>> {
         let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
         txn.get(key)
     }
 
-    pub(crate) fn get_with_ts(&self, key: &[u8], read_ts: u64) -> Result<Option<Bytes>> {
-        let snapshot = {
-            let guard = self.state.read();
-            Arc::clone(&guard)
-        }; // drop global lock here
+    pub fn get_cf(self: &Arc<Self>, column_family: &str, key: &[u8]) -> Result<Option<Bytes>> {
+        let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+        txn.get_cf(column_family, key)
+    }
 
+    pub(crate) fn get_cf_with_ts(
+        &self,
+        column_family: &str,
+        key: &[u8],
+        read_ts: u64,
+    ) -> Result<Option<Bytes>> {
+        // take the value log snapshot first, so that it covers every file the state refers to
+        let value_log = self.value_log.snapshot();
+        let snapshot = self.column_family_snapshot(column_family)?; // drop global lock here
+
+        // The SSTs that cannot contain the key are skipped, so the iterator must not move past it.
+        let iter = LsmIterator::new(
+            self.point_lookup_iter(&snapshot, key)?,
+            (
+                Bound::Included(Bytes::copy_from_slice(key)),
+                Bound::Included(Bytes::copy_from_slice(key)),
+            ),
+            self.options.comparator,
+            read_ts,
+            value_log,
+            self.range_tombstones(
+                &snapshot,
+                Bound::Included(key),
+                Bound::Included(key),
+                read_ts,
+            ),
+            self.options.merge_operator.clone(),
+        )?;
+
+        if iter.is_valid() && iter.key() == key && !iter.value().is_empty() {
+            return Ok(Some(Bytes::copy_from_slice(iter.value())));
+        }
+        Ok(None)
+    }
+
+    /// Collect the range tombstones that are visible at `read_ts` and may have a key within the
+    /// bounds. The key range of an SST covers its tombstones, so only the SSTs that overlap the
+    /// bounds are checked.
+    pub(crate) fn range_tombstones(
+        &self,
+        snapshot: &LsmStorageState,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+    ) -> Vec<RangeTombstone> {
+        let comparator = self.options.comparator;
+        let mut range_tombstones = snapshot
+            .memtable
+            .range_tombstones(lower, upper, read_ts, comparator);
+        for memtable in snapshot.imm_memtables.iter() {
+            range_tombstones.extend(memtable.range_tombstones(lower, upper, read_ts, comparator));
+        }
+        let overlaps = |table: &SsTable| {
+            range_overlap(
+                lower,
+                upper,
+                table.first_key().as_key_slice(),
+                table.last_key().as_key_slice(),
+                table.comparator(),
+            )
+        };
+        let ends_before_lower = |table: &SsTable| {
+            let last_key = table.last_key().key_ref();
+            match lower {
+                Bound::Included(key) => comparator.compare(key, last_key).is_gt(),
+                Bound::Excluded(key) => comparator.compare(key, last_key).is_ge(),
+                Bound::Unbounded => false,
+            }
+        };
+        let mut tables = Vec::new();
+        for table_id in snapshot.l0_sstables.iter() {
+            let table = &snapshot.sstables[table_id];
+            if overlaps(table) {
+                tables.push(table);
+            }
+        }
+        for (_, level_sst_ids) in &snapshot.levels {
+            // The tables of a level are sorted and do not overlap, so the ones that overlap the
+            // bounds are a run of them.
+            let start =
+                level_sst_ids.partition_point(|id| ends_before_lower(&snapshot.sstables[id]));
+            tables.extend(
+                level_sst_ids[start..]
+                    .iter()
+                    .map(|id| &snapshot.sstables[id])
+                    .take_while(|table| overlaps(table)),
+            );
+        }
+        for table in tables {
+            range_tombstones.extend(
+                table
+                    .range_tombstones()
+                    .iter()
+                    .filter(|tombstone| {
+                        tombstone.ts <= read_ts && tombstone.overlaps(lower, upper, comparator)
+                    })
+                    .cloned(),
+            );
+        }
+        range_tombstones
+    }
+
+    /// Create an iterator over every version of `key`, skipping the SSTs that cannot contain it.
+    pub(crate) fn point_lookup_iter(
+        &self,
+        snapshot: &LsmStorageState,
+        key: &[u8],
+    ) -> Result<LsmIteratorInner> {
         let mut memtable_iters = Vec::with_capacity(snapshot.imm_memtables.len() + 1);
         memtable_iters.push(Box::new(snapshot.memtable.scan(
             Bound::Included(KeySlice::from_slice_with_ts(key, key::TS_RANGE_BEGIN)),
@@ -539,101 +1540,225 @@ impl LsmStorageInner {
                 Bound::Included(KeySlice::from_slice_with_ts(key, key::TS_RANGE_END)),
             )));
         }
-        let memtable_iter = MergeIterator::create(memtable_iters);
+        let comparator = self.options.comparator;
+        let memtable_iter = MergeIterator::create_with_comparator(memtable_iters, comparator);
 
         let mut l0_iters = Vec::with_capacity(snapshot.l0_sstables.len());
 
-        let keep_table = |key: &[u8], table: &SsTable| {
+        let keep_table = |key: &[u8], table: &SsTable| -> Result<bool> {
             if key_within(
                 key,
                 table.first_key().as_key_slice(),
                 table.last_key().as_key_slice(),
+                table.comparator(),
             ) {
-                if let Some(bloom) = &table.bloom {
+                if let Some(bloom) = table.bloom()? {
                     if bloom.may_contain(farmhash::fingerprint32(key)) {
-                        return true;
+                        return Ok(true);
                     }
                 } else {
-                    return true;
+                    return Ok(true);
                 }
             }
-            false
+            Ok(false)
         };
 
         for table in snapshot.l0_sstables.iter() {
             let table = snapshot.sstables[table].clone();
-            if keep_table(key, &table) {
-                l0_iters.push(Box::new(SsTableIterator::create_and_seek_to_key(
+            if keep_table(key, &table)? {
+                l0_iters.push(Box::new(SsTableIterator::create_and_seek_for_get(
                     table,
                     KeySlice::from_slice_with_ts(key, key::TS_RANGE_BEGIN),
                 )?));
             }
         }
-        let l0_iter = MergeIterator::create(l0_iters);
+        let l0_iter = MergeIterator::create_with_comparator(l0_iters, comparator);
         let mut level_iters = Vec::with_capacity(snapshot.levels.len());
         for (_, level_sst_ids) in &snapshot.levels {
             let mut level_ssts = Vec::with_capacity(level_sst_ids.len());
             for table in level_sst_ids {
                 let table = snapshot.sstables[table].clone();
-                if keep_table(key, &table) {
+                if keep_table(key, &table)? {
                     level_ssts.push(table);
                 }
             }
-            let level_iter = SstConcatIterator::create_and_seek_to_key(
+            let level_iter = SstConcatIterator::create_and_seek_for_get(
                 level_ssts,
                 KeySlice::from_slice_with_ts(key, key::TS_RANGE_BEGIN),
             )?;
             level_iters.push(Box::new(level_iter));
         }
 
-        let iter = LsmIterator::new(
-            TwoMergeIterator::create(
-                TwoMergeIterator::create(memtable_iter, l0_iter)?,
-                MergeIterator::create(level_iters),
-            )?,
-            Bound::Unbounded,
-            read_ts,
-        )?;
-
-        if iter.is_valid() && iter.key() == key && !iter.value().is_empty() {
-            return Ok(Some(Bytes::copy_from_slice(iter.value())));
-        }
-        Ok(None)
+        TwoMergeIterator::create_with_comparator(
+            TwoMergeIterator::create_with_comparator(memtable_iter, l0_iter, comparator)?,
+            MergeIterator::create_with_comparator(level_iters, comparator),
+            comparator,
+        )
     }
 
     pub fn write_batch_inner<T: AsRef<[u8]>>(&self, batch: &[WriteBatchRecord<T>]) -> Result<u64> {
+        self.ensure_writable()?;
         if batch.is_empty() {
             return Ok(self.mvcc().latest_commit_ts());
         }
-        validate_write_batch(batch)?;
+        validate_write_batch(batch, &self.options)?;
         let _lck = self.mvcc().write_lock.lock();
         let ts = self.mvcc().latest_commit_ts() + 1;
-        let mut batch_datas: Vec<(key::Key<&[u8]>, &[u8])> = vec![];
-        let size;
+        let mut records = Vec::with_capacity(batch.len());
         for record in batch {
+            if let WriteBatchRecord::ColumnFamily(name, family_batch) = record {
+                records.extend(family_batch.iter().map(|record| (name.as_str(), record)));
+            } else {
+                records.push((DEFAULT_COLUMN_FAMILY, record));
+            }
+        }
+        let mut batch_datas: Vec<(&str, KeySlice, ValueKind, Cow<[u8]>)> = vec![];
+        // The latest entry of each key in `batch_datas`. A write replaces the latest entry of its
+        // key, and a merge is combined with it. Merge operands that cannot be combined are written
+        // one timestamp after another, and the batch commits at the latest of them.
+        let mut latest_entries: HashMap<(&str, &[u8]), usize> = HashMap::new();
+        let mut commit_ts = ts;
+        let mut range_tombstones = vec![];
+        let size;
+        for (family, record) in records {
             match record {
                 WriteBatchRecord::Del(key) => {
                     let key = key.as_ref();
                     assert!(!key.is_empty(), "key cannot be empty");
-                    batch_datas.push((KeySlice::from_slice_with_ts(key, ts), b""));
+                    let entry_ts = latest_entries
+                        .get(&(family, key))
+                        .map_or(ts, |&idx| batch_datas[idx].1.ts());
+                    latest_entries.insert((family, key), batch_datas.len());
+                    batch_datas.push((
+                        family,
+                        KeySlice::from_slice_with_ts(key, entry_ts),
+                        ValueKind::Value,
+                        Cow::Borrowed(b""),
+                    ));
                 }
                 WriteBatchRecord::Put(key, value) => {
                     let key = key.as_ref();
                     let value = value.as_ref();
                     assert!(!key.is_empty(), "key cannot be empty");
                     assert!(!value.is_empty(), "value cannot be empty");
-                    batch_datas.push((KeySlice::from_slice_with_ts(key, ts), value));
+                    let entry_ts = latest_entries
+                        .get(&(family, key))
+                        .map_or(ts, |&idx| batch_datas[idx].1.ts());
+                    latest_entries.insert((family, key), batch_datas.len());
+                    batch_datas.push((
+                        family,
+                        KeySlice::from_slice_with_ts(key, entry_ts),
+                        ValueKind::Value,
+                        Cow::Borrowed(value),
+                    ));
+                }
+                WriteBatchRecord::PutWithTtl(key, value, ttl) => {
+                    let key = key.as_ref();
+                    let value = value.as_ref();
+                    assert!(!key.is_empty(), "key cannot be empty");
+                    assert!(!value.is_empty(), "value cannot be empty");
+                    let entry_ts = latest_entries
+                        .get(&(family, key))
+                        .map_or(ts, |&idx| batch_datas[idx].1.ts());
+                    latest_entries.insert((family, key), batch_datas.len());
+                    batch_datas.push((
+                        family,
+                        KeySlice::from_slice_with_ts(key, entry_ts),
+                        ValueKind::ExpiringValue,
+                        Cow::Owned(ttl::encode_expiring_value(ttl::expiry_after(*ttl), value)),
+                    ));
+                }
+                WriteBatchRecord::Merge(key, operand) => {
+                    let key = key.as_ref();
+                    let operand = operand.as_ref();
+                    let Some(&idx) = latest_entries.get(&(family, key)) else {
+                        latest_entries.insert((family, key), batch_datas.len());
+                        batch_datas.push((
+                            family,
+                            KeySlice::from_slice_with_ts(key, ts),
+                            ValueKind::MergeOperand,
+                            Cow::Borrowed(operand),
+                        ));
+                        continue;
+                    };
+                    let merge_operator = self.options.merge_operator.as_ref().unwrap();
+                    let (_, entry_key, kind, value) = &mut batch_datas[idx];
+                    let merged = if *kind == ValueKind::Value {
+                        let existing = (!value.is_empty()).then_some(value.as_ref());
+                        merge_operator.full_merge(key, existing, &[operand])?
+                    } else if *kind == ValueKind::ExpiringValue {
+                        // The merged value does not expire.
+                        let existing = ttl::unexpired_value(value, ttl::now_millis())?;
+                        merge_operator.full_merge(key, existing, &[operand])?
+                    } else if let Some(merged) =
+                        merge_operator.partial_merge(key, &[value.as_ref(), operand])?
+                    {
+                        merged
+                    } else {
+                        // Keep the operand as a record of its own, after the earlier operand.
+                        let entry_ts = entry_key.ts() + 1;
+                        commit_ts = commit_ts.max(entry_ts);
+                        latest_entries.insert((family, key), batch_datas.len());
+                        batch_datas.push((
+                            family,
+                            KeySlice::from_slice_with_ts(key, entry_ts),
+                            ValueKind::MergeOperand,
+                            Cow::Borrowed(operand),
+                        ));
+                        continue;
+                    };
+                    if *kind == ValueKind::ExpiringValue {
+                        *kind = ValueKind::Value;
+                    }
+                    *value = Cow::Owned(merged);
                 }
+                WriteBatchRecord::DelRange(start, end) => {
+                    range_tombstones.push((
+                        family,
+                        RangeTombstone::new(
+                            Bytes::copy_from_slice(start.as_ref()),
+                            Bytes::copy_from_slice(end.as_ref()),
+                            ts,
+                        ),
+                    ));
+                }
+                WriteBatchRecord::ColumnFamily(..) => unreachable!(),
             }
         }
         {
             let guard = self.state.read();
-            guard.memtable.put_batch(&batch_datas)?;
-            size = guard.memtable.approximate_size();
+            // Group the batch by column family, keeping the default column family first.
+            let mut family_batches = BTreeMap::new();
+            family_batches.insert(0, (&**guard, vec![], vec![]));
+            for (family, key, kind, value) in &batch_datas {
+                let (id, state) = guard.column_family(family)?;
+                let (_, entries, _) = family_batches.entry(id).or_insert((state, vec![], vec![]));
+                entries.push((*key, *kind, value.as_ref()));
+            }
+            for (family, tombstone) in &range_tombstones {
+                let (id, state) = guard.column_family(family)?;
+                let (_, _, tombstones) =
+                    family_batches.entry(id).or_insert((state, vec![], vec![]));
+                tombstones.push(tombstone.clone());
+            }
+            let batches = family_batches
+                .iter()
+                .map(|(id, (_, entries, tombstones))| (*id, &entries[..], &tombstones[..]))
+                .collect::<Vec<_>>();
+            // The batches to every column family are logged in one WAL frame.
+            guard.memtable.log_column_family_batches(&batches)?;
+            for (family_state, entries, tombstones) in family_batches.values() {
+                family_state.memtable.insert_batch(entries, tombstones);
+            }
+            size = family_batches
+                .values()
+                .map(|(state, _, _)| state.memtable.approximate_size())
+                .max()
+                .unwrap_or_default();
         }
-        self.mvcc().update_commit_ts(ts);
+        self.mvcc().update_commit_ts(commit_ts);
         self.try_freeze(size)?;
-        Ok(ts)
+        Ok(commit_ts)
     }
 
     pub fn write_batch<T: AsRef<[u8]>>(
@@ -644,13 +1769,33 @@ impl LsmStorageInner {
             self.write_batch_inner(batch)?;
         } else {
             let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+            let mut records = Vec::with_capacity(batch.len());
             for record in batch {
+                if let WriteBatchRecord::ColumnFamily(name, family_batch) = record {
+                    records.extend(family_batch.iter().map(|record| (name.as_str(), record)));
+                } else {
+                    records.push((DEFAULT_COLUMN_FAMILY, record));
+                }
+            }
+            for (family, record) in records {
                 match record {
                     WriteBatchRecord::Del(key) => {
-                        txn.delete(key.as_ref());
+                        txn.delete_cf(family, key.as_ref())?;
                     }
                     WriteBatchRecord::Put(key, value) => {
-                        txn.put(key.as_ref(), value.as_ref());
+                        txn.put_cf(family, key.as_ref(), value.as_ref())?;
+                    }
+                    WriteBatchRecord::PutWithTtl(key, value, ttl) => {
+                        txn.put_with_ttl_cf(family, key.as_ref(), value.as_ref(), *ttl)?;
+                    }
+                    WriteBatchRecord::DelRange(start, end) => {
+                        txn.delete_range_cf(family, start.as_ref(), end.as_ref())?;
+                    }
+                    WriteBatchRecord::Merge(key, operand) => {
+                        txn.merge_cf(family, key.as_ref(), operand.as_ref())?;
+                    }
+                    WriteBatchRecord::ColumnFamily(..) => {
+                        bail!("column family records cannot be nested")
                     }
                 }
             }
@@ -671,6 +1816,18 @@ impl LsmStorageInner {
         Ok(())
     }
 
+    /// Put a key-value pair that expires after `ttl`.
+    pub fn put_with_ttl(self: &Arc<Self>, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        if !self.options.serializable {
+            self.write_batch_inner(&[WriteBatchRecord::PutWithTtl(key, value, ttl)])?;
+        } else {
+            let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+            txn.put_with_ttl(key, value, ttl);
+            txn.commit()?;
+        }
+        Ok(())
+    }
+
     /// Remove a key from the storage by writing an empty value.
     pub fn delete(self: &Arc<Self>, key: &[u8]) -> Result<()> {
         if !self.options.serializable {
@@ -683,13 +1840,87 @@ impl LsmStorageInner {
         Ok(())
     }
 
+    /// Remove the keys in `[lower, upper)` from the storage by writing a range tombstone.
+    pub fn delete_range(self: &Arc<Self>, lower: &[u8], upper: &[u8]) -> Result<()> {
+        if !self.options.serializable {
+            self.write_batch_inner(&[WriteBatchRecord::DelRange(lower, upper)])?;
+        } else {
+            let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+            txn.delete_range(lower, upper);
+            txn.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Write a merge operand of `key`. The key is not read, so concurrent merges of a key do not
+    /// conflict with each other.
+    pub fn merge(self: &Arc<Self>, key: &[u8], operand: &[u8]) -> Result<()> {
+        if !self.options.serializable {
+            self.write_batch_inner(&[WriteBatchRecord::Merge(key, operand)])?;
+        } else {
+            let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+            txn.merge(key, operand)?;
+            txn.commit()?;
+        }
+        Ok(())
+    }
+
+    pub fn put_cf(self: &Arc<Self>, column_family: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.write_batch(&[WriteBatchRecord::ColumnFamily(
+            column_family.to_string(),
+            vec![WriteBatchRecord::Put(key, value)],
+        )])
+    }
+
+    pub fn delete_cf(self: &Arc<Self>, column_family: &str, key: &[u8]) -> Result<()> {
+        self.write_batch(&[WriteBatchRecord::ColumnFamily(
+            column_family.to_string(),
+            vec![WriteBatchRecord::Del(key)],
+        )])
+    }
+
+    pub fn put_with_ttl_cf(
+        self: &Arc<Self>,
+        column_family: &str,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Result<()> {
+        self.write_batch(&[WriteBatchRecord::ColumnFamily(
+            column_family.to_string(),
+            vec![WriteBatchRecord::PutWithTtl(key, value, ttl)],
+        )])
+    }
+
+    pub fn delete_range_cf(
+        self: &Arc<Self>,
+        column_family: &str,
+        lower: &[u8],
+        upper: &[u8],
+    ) -> Result<()> {
+        self.write_batch(&[WriteBatchRecord::ColumnFamily(
+            column_family.to_string(),
+            vec![WriteBatchRecord::DelRange(lower, upper)],
+        )])
+    }
+
+    pub fn merge_cf(
+        self: &Arc<Self>,
+        column_family: &str,
+        key: &[u8],
+        operand: &[u8],
+    ) -> Result<()> {
+        self.write_batch(&[WriteBatchRecord::ColumnFamily(
+            column_family.to_string(),
+            vec![WriteBatchRecord::Merge(key, operand)],
+        )])
+    }
+
     fn try_freeze(&self, estimated_size: usize) -> Result<()> {
         if estimated_size >= self.options.target_sst_size {
             let state_lock = self.state_lock.lock();
-            let guard = self.state.read();
             // the memtable could have already been frozen, check again to ensure we really need to freeze
-            if guard.memtable.approximate_size() >= self.options.target_sst_size {
-                drop(guard);
+            if self.max_memtable_size() >= self.options.target_sst_size {
                 self.force_freeze_memtable(&state_lock)?;
             }
         }
@@ -712,18 +1943,73 @@ impl LsmStorageInner {
         Self::path_of_wal_static(&self.path, id)
     }
 
+    /// Fail the changes to a database that is opened read-only.
+    pub(crate) fn ensure_writable(&self) -> Result<()> {
+        ensure!(!self.read_only, "the database is opened read-only");
+        Ok(())
+    }
+
     pub(super) fn sync_dir(&self) -> Result<()> {
         File::open(&self.path)?.sync_all()?;
         Ok(())
     }
 
+    /// Rewrite the manifest as a snapshot of the state if it has grown beyond the configured size.
+    pub(crate) fn trigger_manifest_rotation(&self) -> Result<()> {
+        if self.manifest().size()? < self.options.max_manifest_size {
+            return Ok(());
+        }
+        self.force_manifest_rotation()
+    }
+
+    /// Rewrite the manifest as a snapshot of the state.
+    pub(crate) fn force_manifest_rotation(&self) -> Result<()> {
+        self.ensure_writable()?;
+        // Value log garbage collection writes its records after the files are rewritten, so it
+        // must not run while the referenced value log files are collected.
+        let _gc_lock = self.value_log.gc_lock.lock();
+        let state_lock = self.state_lock.lock();
+        let snapshot = self.state.read().clone();
+        let value_logs = self
+            .value_log
+            .snapshot()
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        // Every mem-table is kept, as it is flushed later with a record that refers to it.
+        let memtables = snapshot
+            .imm_memtables
+            .iter()
+            .rev()
+            .chain(std::iter::once(&snapshot.memtable))
+            .map(|memtable| memtable.id())
+            .collect::<Vec<_>>();
+        let records = self.snapshot_manifest_records(&snapshot, &memtables, &value_logs);
+        self.manifest().rotate(&state_lock, &self.path, &records)?;
+        println!("manifest rotated with {} records", records.len());
+        Ok(())
+    }
+
     fn freeze_memtable_with_memtable(&self, memtable: Arc<MemTable>) -> Result<()> {
         let mut guard = self.state.write();
         // Swap the current memtable with a new one.
         let mut snapshot = guard.as_ref().clone();
+        let memtable_id = memtable.id();
         let old_memtable = std::mem::replace(&mut snapshot.memtable, memtable);
         // Add the memtable to the immutable memtables.
         snapshot.imm_memtables.insert(0, old_memtable.clone());
+        // The column families are frozen with the default column family, as they share its WAL.
+        for column_family in snapshot.column_families.values_mut() {
+            let state = Arc::make_mut(&mut column_family.state);
+            let old_memtable = std::mem::replace(
+                &mut state.memtable,
+                Arc::new(MemTable::create_with_comparator(
+                    memtable_id,
+                    self.options.comparator,
+                )),
+            );
+            state.imm_memtables.insert(0, old_memtable);
+        }
         // Update the snapshot.
         *guard = Arc::new(snapshot);
 
@@ -735,14 +2021,19 @@ impl LsmStorageInner {
 
     /// Force freeze the current memtable to an immutable memtable
     pub fn force_freeze_memtable(&self, state_lock_observer: &MutexGuard<'_, ()>) -> Result<()> {
+        self.ensure_writable()?;
         let memtable_id = self.next_sst_id();
         let memtable = if self.options.enable_wal {
             Arc::new(MemTable::create_with_wal(
                 memtable_id,
                 self.path_of_wal(memtable_id),
+                self.options.comparator,
             )?)
         } else {
-            Arc::new(MemTable::create(memtable_id))
+            Arc::new(MemTable::create_with_comparator(
+                memtable_id,
+                self.options.comparator,
+            ))
         };
 
         if self.options.enable_wal {
@@ -760,25 +2051,58 @@ impl LsmStorageInner {
 
     /// Force flush the earliest-created immutable memtable to disk
     pub fn force_flush_next_imm_memtable(&self) -> Result<()> {
+        self.ensure_writable()?;
         let state_lock = self.state_lock.lock();
 
-        let flush_memtable = {
+        let (flush_memtable, family_memtables) = {
             let guard = self.state.read();
             let Some(flush_memtable) = guard.imm_memtables.last() else {
                 return Ok(());
             };
-            flush_memtable.clone()
+            // The column families created after the freeze have no mem-table to flush.
+            let family_memtables = guard
+                .column_families
+                .iter()
+                .filter_map(|(name, column_family)| {
+                    let memtable = column_family.state.imm_memtables.last()?;
+                    (memtable.id() == flush_memtable.id())
+                        .then(|| (name.clone(), column_family.id, memtable.clone()))
+                })
+                .collect::<Vec<_>>();
+            (flush_memtable.clone(), family_memtables)
         };
 
-        let mut builder = SsTableBuilder::new(self.options.block_size);
-        flush_memtable.flush(&mut builder)?;
         let sst_id = flush_memtable.id();
-        let sst = Arc::new(builder.build(
-            sst_id,
-            Some(self.block_cache.clone()),
-            self.path_of_sst(sst_id),
-        )?);
+        // The column families are flushed to new SSTs the same way as the default column family.
+        let mut family_ssts = Vec::new();
+        let mut value_logs = Vec::new();
+        for (name, id, memtable) in &family_memtables {
+            if memtable.is_empty() {
+                continue;
+            }
+            let family_sst_id = self.next_sst_id();
+            let (sst, has_value_log) = self.flush_memtable_to_sst(memtable, family_sst_id, *id)?;
+            if has_value_log {
+                value_logs.push(family_sst_id);
+            }
+            family_ssts.push((name, *id, sst));
+        }
+        // An empty mem-table of the default column family is not flushed, as an SST cannot be
+        // empty. A mem-table is frozen empty when a freeze races with another one.
+        let flush_default = !flush_memtable.is_empty();
+        let mut default_sst = None;
+        if flush_default {
+            let (sst, has_value_log) = self.flush_memtable_to_sst(&flush_memtable, sst_id, 0)?;
+            if has_value_log {
+                value_logs.push(sst_id);
+            }
+            default_sst = Some(sst);
+        }
         self.sync_dir()?;
+        // The value log files must be readable before the SSTs become visible.
+        for id in value_logs {
+            self.value_log.add_file(id)?;
+        }
 
         // Add the flushed L0 table to the list.
         {
@@ -788,21 +2112,48 @@ impl LsmStorageInner {
             let mem = snapshot.imm_memtables.pop().unwrap();
             assert_eq!(mem.id(), sst_id);
             // Add L0 table
-            if self.compaction_controller.flush_to_l0() {
-                // In leveled compaction or no compaction, simply flush to L0
-                snapshot.l0_sstables.insert(0, sst_id);
-            } else {
-                // In tiered compaction, create a new tier
-                snapshot.levels.insert(0, (sst_id, vec![sst_id]));
+            if let Some(sst) = default_sst {
+                add_flushed_sst(&mut snapshot, &self.compaction_controller, sst_id);
+                println!("flushed {}.sst with size={}", sst_id, sst.table_size());
+                snapshot.sstables.insert(sst_id, sst);
+            }
+            let column_families = &mut snapshot.column_families;
+            for (name, _, _) in &family_memtables {
+                let column_family = column_families.get_mut(name).unwrap();
+                let state = Arc::make_mut(&mut column_family.state);
+                let mem = state.imm_memtables.pop().unwrap();
+                assert_eq!(mem.id(), sst_id);
+            }
+            for (name, _, sst) in &family_ssts {
+                let column_family = column_families.get_mut(*name).unwrap();
+                let controller = CompactionController::new(&column_family.compaction_options);
+                let state = Arc::make_mut(&mut column_family.state);
+                add_flushed_sst(state, &controller, sst.sst_id());
+                println!(
+                    "flushed {}.sst of column family {:?} with size={}",
+                    sst.sst_id(),
+                    name,
+                    sst.table_size()
+                );
+                state.sstables.insert(sst.sst_id(), sst.clone());
             }
-            println!("flushed {}.sst with size={}", sst_id, sst.table_size());
-            snapshot.sstables.insert(sst_id, sst);
             // Update the snapshot.
             *guard = Arc::new(snapshot);
         }
 
-        self.manifest()
-            .add_record(&state_lock, ManifestRecord::Flush(sst_id))?;
+        let record = if family_ssts.is_empty() && flush_default {
+            ManifestRecord::Flush(sst_id)
+        } else {
+            ManifestRecord::FlushColumnFamilies {
+                memtable: sst_id,
+                flush_default,
+                ssts: family_ssts
+                    .iter()
+                    .map(|(_, id, sst)| (*id, sst.sst_id()))
+                    .collect(),
+            }
+        };
+        self.manifest().add_record(&state_lock, record)?;
 
         if self.options.enable_wal {
             std::fs::remove_file(self.path_of_wal(sst_id))?;
@@ -813,6 +2164,27 @@ impl LsmStorageInner {
         Ok(())
     }
 
+    /// Flush a mem-table to a new SST of the column family with the id, separating the large values
+    /// into a value log file with the id of the SST. Returns the SST and whether the value log file
+    /// is written, which is not tracked until the SST is synced to the directory.
+    fn flush_memtable_to_sst(
+        &self,
+        memtable: &MemTable,
+        sst_id: usize,
+        column_family: u32,
+    ) -> Result<(Arc<SsTable>, bool)> {
+        let mut builder = SsTableBuilder::new_with_value_log(&self.options, sst_id)
+            .with_column_family(column_family);
+        memtable.flush(&mut builder)?;
+        let has_value_log = builder.has_separated_values();
+        let sst = builder.build(
+            sst_id,
+            Some(self.block_cache.clone()),
+            self.path_of_sst(sst_id),
+        )?;
+        Ok((Arc::new(sst), has_value_log))
+    }
+
     pub fn new_txn(self: &Arc<Self>) -> Result<Arc<Transaction>> {
         Ok(self.mvcc().new_txn(self.clone(), self.options.serializable))
     }
@@ -823,24 +2195,132 @@ impl LsmStorageInner {
         txn.scan(lower, upper)
     }
 
+    /// Create an iterator over a range of keys that moves from the last key to the first with
+    /// `prev`.
+    pub fn scan_rev(
+        self: &Arc<Self>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<TxnIterator> {
+        let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+        txn.scan_rev(lower, upper)
+    }
+
+    pub fn prefix_scan(self: &Arc<Self>, prefix: &[u8]) -> Result<TxnIterator> {
+        let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+        txn.prefix_scan(prefix)
+    }
+
+    pub fn scan_cf(
+        self: &Arc<Self>,
+        column_family: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<TxnIterator> {
+        let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+        txn.scan_cf(column_family, lower, upper)
+    }
+
     pub(crate) fn scan_with_ts(
         &self,
         lower: Bound<&[u8]>,
         upper: Bound<&[u8]>,
         read_ts: u64,
     ) -> Result<FusedIterator<LsmIterator>> {
-        let snapshot = {
-            let guard = self.state.read();
-            Arc::clone(&guard)
-        }; // drop global lock here
+        self.scan_cf_with_ts(DEFAULT_COLUMN_FAMILY, lower, upper, read_ts)
+    }
+
+    pub(crate) fn scan_cf_with_ts(
+        &self,
+        column_family: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        self.scan_with_ts_filtered(column_family, lower, upper, read_ts, false, |_| Ok(true))
+    }
+
+    /// Create an iterator that moves from the last key of the range to the first with `prev`.
+    pub(crate) fn scan_cf_rev_with_ts(
+        &self,
+        column_family: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        self.scan_with_ts_filtered(column_family, lower, upper, read_ts, true, |_| Ok(true))
+    }
+
+    /// Scan the keys that start with `prefix`, skipping the SSTs whose bloom filters show that they
+    /// have no such key.
+    pub(crate) fn prefix_scan_with_ts(
+        &self,
+        prefix: &[u8],
+        read_ts: u64,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        ensure!(
+            is_bytewise(self.options.comparator),
+            "prefix scans need the keys with a common prefix to be adjacent, which the {:?} comparator does not guarantee",
+            self.options.comparator.name()
+        );
+        let upper = prefix_upper_bound(prefix);
+        let upper = upper.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        let extractor = self
+            .options
+            .prefix_extractor
+            .as_ref()
+            .and_then(|extractor| Some((extractor.name(), extractor.prefix(prefix)?)));
+        let prefix_bound = Bound::Included(prefix);
+        self.scan_with_ts_filtered(
+            DEFAULT_COLUMN_FAMILY,
+            prefix_bound,
+            upper,
+            read_ts,
+            false,
+            |table| {
+                let Some((name, extracted)) = &extractor else {
+                    return Ok(true);
+                };
+                if table.prefix_extractor() != Some(name.as_str()) {
+                    return Ok(true);
+                }
+                Ok(table
+                    .bloom()?
+                    .is_none_or(|bloom| bloom.may_contain(farmhash::fingerprint32(extracted))))
+            },
+        )
+    }
+
+    /// Scan a range of a column family, skipping the SSTs for which `keep_table` returns false. A
+    /// `reverse` iterator starts at the upper bound and moves with `prev`.
+    fn scan_with_ts_filtered(
+        &self,
+        column_family: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+        reverse: bool,
+        keep_table: impl Fn(&SsTable) -> Result<bool>,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let value_log = self.value_log.snapshot();
+        let snapshot = self.column_family_snapshot(column_family)?; // drop global lock here
 
         let mut memtable_iters = Vec::with_capacity(snapshot.imm_memtables.len() + 1);
         let (begin, end) = map_key_bound_plus_ts(lower, upper, read_ts);
-        memtable_iters.push(Box::new(snapshot.memtable.scan(begin, end)));
+        let scan_memtable = |memtable: &MemTable| {
+            if reverse {
+                memtable.scan_rev(begin, end)
+            } else {
+                memtable.scan(begin, end)
+            }
+        };
+        memtable_iters.push(Box::new(scan_memtable(&snapshot.memtable)));
         for memtable in snapshot.imm_memtables.iter() {
-            memtable_iters.push(Box::new(memtable.scan(begin, end)));
+            memtable_iters.push(Box::new(scan_memtable(memtable)));
         }
-        let memtable_iter = MergeIterator::create(memtable_iters);
+        let comparator = self.options.comparator;
+        let memtable_iter =
+            MergeIterator::create_with_direction(memtable_iters, reverse, comparator);
 
         let mut table_iters = Vec::with_capacity(snapshot.l0_sstables.len());
         for table_id in snapshot.l0_sstables.iter() {
@@ -850,32 +2330,54 @@ impl LsmStorageInner {
                 upper,
                 table.first_key().as_key_slice(),
                 table.last_key().as_key_slice(),
-            ) {
-                let iter = match lower {
-                    Bound::Included(key) => SsTableIterator::create_and_seek_to_key(
-                        table,
-                        KeySlice::from_slice_with_ts(key, key::TS_RANGE_BEGIN),
-                    )?,
-                    Bound::Excluded(key) => {
-                        let mut iter = SsTableIterator::create_and_seek_to_key(
+                table.comparator(),
+            ) && keep_table(&table)?
+            {
+                let iter = if reverse {
+                    match upper {
+                        Bound::Included(key) => SsTableIterator::create_and_seek_for_prev(
+                            table,
+                            KeySlice::from_slice_with_ts(key, key::TS_RANGE_END),
+                        )?,
+                        Bound::Excluded(key) => {
+                            let mut iter = SsTableIterator::create_and_seek_for_prev(
+                                table,
+                                KeySlice::from_slice_with_ts(key, key::TS_RANGE_BEGIN),
+                            )?;
+                            while iter.is_valid() && iter.key().key_ref() == key {
+                                iter.prev()?;
+                            }
+                            iter
+                        }
+                        Bound::Unbounded => SsTableIterator::create_and_seek_to_last(table)?,
+                    }
+                } else {
+                    match lower {
+                        Bound::Included(key) => SsTableIterator::create_and_seek_to_key(
                             table,
                             KeySlice::from_slice_with_ts(key, key::TS_RANGE_BEGIN),
-                        )?;
-                        // TODO: we can implement `key.next()` so that we can directly seek to the
-                        // right place in the previous line.
-                        while iter.is_valid() && iter.key().key_ref() == key {
-                            iter.next()?;
+                        )?,
+                        Bound::Excluded(key) => {
+                            let mut iter = SsTableIterator::create_and_seek_to_key(
+                                table,
+                                KeySlice::from_slice_with_ts(key, key::TS_RANGE_BEGIN),
+                            )?;
+                            // TODO: we can implement `key.next()` so that we can directly seek to the
+                            // right place in the previous line.
+                            while iter.is_valid() && iter.key().key_ref() == key {
+                                iter.next()?;
+                            }
+                            iter
                         }
-                        iter
+                        Bound::Unbounded => SsTableIterator::create_and_seek_to_first(table)?,
                     }
-                    Bound::Unbounded => SsTableIterator::create_and_seek_to_first(table)?,
                 };
 
                 table_iters.push(Box::new(iter));
             }
         }
 
-        let l0_iter = MergeIterator::create(table_iters);
+        let l0_iter = MergeIterator::create_with_direction(table_iters, reverse, comparator);
         let mut level_iters = Vec::with_capacity(snapshot.levels.len());
         for (_, level_sst_ids) in &snapshot.levels {
             let mut level_ssts = Vec::with_capacity(level_sst_ids.len());
@@ -886,38 +2388,83 @@ impl LsmStorageInner {
                     upper,
                     table.first_key().as_key_slice(),
                     table.last_key().as_key_slice(),
-                ) {
+                    table.comparator(),
+                ) && keep_table(&table)?
+                {
                     level_ssts.push(table);
                 }
             }
 
-            let level_iter = match lower {
-                Bound::Included(key) => SstConcatIterator::create_and_seek_to_key(
-                    level_ssts,
-                    KeySlice::from_slice_with_ts(key, key::TS_RANGE_BEGIN),
-                )?,
-                Bound::Excluded(key) => {
-                    let mut iter = SstConcatIterator::create_and_seek_to_key(
+            let level_iter = if reverse {
+                match upper {
+                    Bound::Included(key) => SstConcatIterator::create_and_seek_for_prev(
+                        level_ssts,
+                        KeySlice::from_slice_with_ts(key, key::TS_RANGE_END),
+                    )?,
+                    Bound::Excluded(key) => {
+                        let mut iter = SstConcatIterator::create_and_seek_for_prev(
+                            level_ssts,
+                            KeySlice::from_slice_with_ts(key, key::TS_RANGE_BEGIN),
+                        )?;
+                        while iter.is_valid() && iter.key().key_ref() == key {
+                            iter.prev()?;
+                        }
+                        iter
+                    }
+                    Bound::Unbounded => SstConcatIterator::create_and_seek_to_last(level_ssts)?,
+                }
+            } else {
+                match lower {
+                    Bound::Included(key) => SstConcatIterator::create_and_seek_to_key(
                         level_ssts,
                         KeySlice::from_slice_with_ts(key, key::TS_RANGE_BEGIN),
-                    )?;
-                    while iter.is_valid() && iter.key().key_ref() == key {
-                        iter.next()?;
+                    )?,
+                    Bound::Excluded(key) => {
+                        let mut iter = SstConcatIterator::create_and_seek_to_key(
+                            level_ssts,
+                            KeySlice::from_slice_with_ts(key, key::TS_RANGE_BEGIN),
+                        )?;
+                        while iter.is_valid() && iter.key().key_ref() == key {
+                            iter.next()?;
+                        }
+                        iter
                     }
-                    iter
+                    Bound::Unbounded => SstConcatIterator::create_and_seek_to_first(level_ssts)?,
                 }
-                Bound::Unbounded => SstConcatIterator::create_and_seek_to_first(level_ssts)?,
             };
             level_iters.push(Box::new(level_iter));
         }
 
-        let iter = TwoMergeIterator::create(memtable_iter, l0_iter)?;
-        let iter = TwoMergeIterator::create(iter, MergeIterator::create(level_iters))?;
+        let range_tombstones = self.range_tombstones(&snapshot, lower, upper, read_ts);
+        let merge_operator = self.options.merge_operator.clone();
+        let iter =
+            TwoMergeIterator::create_with_direction(memtable_iter, l0_iter, reverse, comparator)?;
+        let iter = TwoMergeIterator::create_with_direction(
+            iter,
+            MergeIterator::create_with_direction(level_iters, reverse, comparator),
+            reverse,
+            comparator,
+        )?;
+        if reverse {
+            return Ok(FusedIterator::new(LsmIterator::new_rev(
+                iter,
+                (map_bound(lower), map_bound(upper)),
+                self.options.comparator,
+                read_ts,
+                value_log,
+                range_tombstones,
+                merge_operator,
+            )?));
+        }
 
         Ok(FusedIterator::new(LsmIterator::new(
             iter,
-            map_bound(upper),
+            (map_bound(lower), map_bound(upper)),
+            self.options.comparator,
             read_ts,
+            value_log,
+            range_tombstones,
+            merge_operator,
         )?))
     }
 }
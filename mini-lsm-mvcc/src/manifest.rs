@@ -14,18 +14,196 @@
 
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result, anyhow, ensure};
 use bytes::BufMut;
 use parking_lot::{Mutex, MutexGuard};
 use serde::{Deserialize, Serialize};
 
-use crate::compact::CompactionTask;
+use crate::compact::{CompactionOptions, CompactionTask};
+
+/// The manifest of a database whose manifest has never been rotated. Once it is rotated, the
+/// manifest is a `MANIFEST-<number>` file, whose name is stored in the `CURRENT` file.
+const LEGACY_MANIFEST: &str = "MANIFEST";
+const CURRENT: &str = "CURRENT";
 
 pub struct Manifest {
     file: Arc<Mutex<File>>,
+    /// The number of the manifest file, 0 for the legacy `MANIFEST` file. Only changes with the
+    /// file lock held.
+    number: AtomicU64,
+}
+
+fn manifest_file_name(number: u64) -> String {
+    if number == 0 {
+        LEGACY_MANIFEST.to_string()
+    } else {
+        format!("{LEGACY_MANIFEST}-{number:06}")
+    }
+}
+
+/// The number of the manifest file at `path`, or 0 if it is not a rotated manifest.
+fn manifest_number(path: &Path) -> u64 {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix("MANIFEST-"))
+        .and_then(|number| number.parse().ok())
+        .unwrap_or(0)
+}
+
+/// The path of the current manifest of the database in `dir`, which may not exist yet.
+pub fn current_manifest_path(dir: impl AsRef<Path>) -> Result<PathBuf> {
+    let dir = dir.as_ref();
+    let current = dir.join(CURRENT);
+    if !current.exists() {
+        return Ok(dir.join(LEGACY_MANIFEST));
+    }
+    let name = std::fs::read_to_string(&current).context("failed to read CURRENT")?;
+    let name = name.trim_end();
+    ensure!(
+        name.starts_with("MANIFEST-") && manifest_number(Path::new(name)) != 0,
+        "CURRENT points to an invalid manifest {name:?}"
+    );
+    let path = dir.join(name);
+    ensure!(path.exists(), "the current manifest {name} is missing");
+    Ok(path)
+}
+
+/// Remove the manifest files in `dir` other than the current one, which are left behind by a
+/// rotation that did not finish.
+pub fn remove_obsolete_manifests(dir: impl AsRef<Path>) -> Result<()> {
+    let dir = dir.as_ref();
+    let current = current_manifest_path(dir)?;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(name) = file_name.to_str() else {
+            continue;
+        };
+        let is_manifest = name == LEGACY_MANIFEST || manifest_number(Path::new(name)) != 0;
+        if (is_manifest && entry.path() != current) || name == "CURRENT.tmp" {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Decode the frames of a manifest, returning the records, the length of the complete frames and
+/// the error of the first corrupted frame, where the decoding stops.
+fn decode_records(buf: &[u8]) -> (Vec<ManifestRecord>, usize, Option<anyhow::Error>) {
+    let mut records = Vec::new();
+    let mut valid_len = 0usize;
+    while valid_len < buf.len() {
+        let remaining = &buf[valid_len..];
+        if remaining.len() < std::mem::size_of::<u64>() {
+            break;
+        }
+
+        let len = u64::from_be_bytes([
+            remaining[0],
+            remaining[1],
+            remaining[2],
+            remaining[3],
+            remaining[4],
+            remaining[5],
+            remaining[6],
+            remaining[7],
+        ]);
+        let Ok(len) = usize::try_from(len) else {
+            break;
+        };
+        let Some(frame_len) = std::mem::size_of::<u64>()
+            .checked_add(len)
+            .and_then(|len| len.checked_add(std::mem::size_of::<u32>()))
+        else {
+            break;
+        };
+        if remaining.len() < frame_len {
+            break;
+        }
+
+        let body_start = std::mem::size_of::<u64>();
+        let body_end = body_start + len;
+        let body = &remaining[body_start..body_end];
+        let checksum = u32::from_be_bytes([
+            remaining[body_end],
+            remaining[body_end + 1],
+            remaining[body_end + 2],
+            remaining[body_end + 3],
+        ]);
+        if checksum != crc32fast::hash(body) {
+            let error = anyhow!("manifest checksum mismatched at byte offset {valid_len}");
+            return (records, valid_len, Some(error));
+        }
+        match serde_json::from_slice::<ManifestRecord>(body) {
+            Ok(record) => records.push(record),
+            Err(e) => {
+                let error = anyhow::Error::from(e).context(format!(
+                    "invalid manifest record at byte offset {valid_len}"
+                ));
+                return (records, valid_len, Some(error));
+            }
+        }
+        valid_len += frame_len;
+    }
+    (records, valid_len, None)
+}
+
+/// Write the encoded records to a new manifest `name` in `dir`, and switch CURRENT to it.
+fn write_current_manifest(dir: &Path, name: &str, buf: &[u8]) -> Result<File> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .create_new(true)
+        .write(true)
+        .open(dir.join(name))
+        .context("failed to create manifest")?;
+    file.write_all(buf)?;
+    file.sync_all()?;
+
+    let tmp_current = dir.join("CURRENT.tmp");
+    std::fs::write(&tmp_current, format!("{name}\n")).context("failed to write CURRENT")?;
+    File::open(&tmp_current)?.sync_all()?;
+    std::fs::rename(&tmp_current, dir.join(CURRENT)).context("failed to switch CURRENT")?;
+    File::open(dir)?.sync_all()?;
+    Ok(file)
+}
+
+/// Write the records to a new manifest in `dir` and make it the current one, returning its name.
+/// The other manifests in `dir` are left in place, even if they cannot be read.
+pub fn replace_manifest(dir: impl AsRef<Path>, records: &[ManifestRecord]) -> Result<String> {
+    let dir = dir.as_ref();
+    let mut number = 0;
+    for entry in std::fs::read_dir(dir)? {
+        number = number.max(manifest_number(&entry?.path()));
+    }
+    let name = manifest_file_name(number + 1);
+    let mut buf = Vec::new();
+    for record in records {
+        encode_record(record, &mut buf)?;
+    }
+    write_current_manifest(dir, &name, &buf)?;
+    Ok(name)
+}
+
+/// Whether `name` is the name of a manifest file or of CURRENT, which points to the manifest.
+pub fn is_manifest_file(name: &str) -> bool {
+    name == LEGACY_MANIFEST
+        || name == CURRENT
+        || name == "CURRENT.tmp"
+        || manifest_number(Path::new(name)) != 0
+}
+
+/// Encode a record into a frame of the manifest.
+fn encode_record(record: &ManifestRecord, buf: &mut Vec<u8>) -> Result<()> {
+    let body = serde_json::to_vec(record)?;
+    let len = u64::try_from(body.len()).context("manifest record is too large")?;
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(&body);
+    buf.put_u32(crc32fast::hash(&body));
+    Ok(())
 }
 
 #[derive(Serialize, Deserialize)]
@@ -33,6 +211,40 @@ pub enum ManifestRecord {
     Flush(usize),
     NewMemtable(usize),
     Compaction(CompactionTask, Vec<usize>),
+    ValueLogGc(usize),
+    /// The name of the comparator of the database, written when it is created.
+    Comparator(String),
+    /// A column family was created with its name, id and compaction options.
+    CreateColumnFamily(String, u32, CompactionOptions),
+    /// The mem-tables with the id were flushed in every column family. The default column family
+    /// was flushed to the SST with the same id unless its mem-table was empty, and the other
+    /// column families to the SSTs with the ids in `ssts`.
+    FlushColumnFamilies {
+        memtable: usize,
+        flush_default: bool,
+        ssts: Vec<(u32, usize)>,
+    },
+    /// A compaction of the column family with the id.
+    ColumnFamilyCompaction(u32, CompactionTask, Vec<usize>),
+    /// The SSTs of the column family with the id (0 for the default column family), replacing the
+    /// SSTs recorded before. Written to a compacted manifest, such as the one of a checkpoint.
+    Snapshot {
+        column_family: u32,
+        l0_sstables: Vec<usize>,
+        levels: Vec<(usize, Vec<usize>)>,
+    },
+    /// External SSTs ingested into the default column family, as `(level, index, sst_id)`. Each
+    /// SST was inserted at the index of the level, or flushed to L0 if the level is 0.
+    IngestExternalFiles(Vec<(usize, usize, usize)>),
+    /// External SSTs ingested into the column family with the id, placed like
+    /// `IngestExternalFiles`.
+    ColumnFamilyIngestExternalFiles(u32, Vec<(usize, usize, usize)>),
+    /// The value log files that are still referenced. Written to a compacted manifest, as the SSTs
+    /// the files were written with may have been compacted away.
+    ValueLogFiles(Vec<usize>),
+    /// The bytes discarded from the value log files with the ids, replacing the counts recorded
+    /// before. Written after the compactions that discard values.
+    ValueLogDiscarded(Vec<(usize, u64)>),
 }
 
 impl Manifest {
@@ -43,13 +255,15 @@ impl Manifest {
                     .read(true)
                     .create_new(true)
                     .write(true)
-                    .open(path)
+                    .open(path.as_ref())
                     .context("failed to create manifest")?,
             )),
+            number: AtomicU64::new(manifest_number(path.as_ref())),
         })
     }
 
     pub fn recover(path: impl AsRef<Path>) -> Result<(Self, Vec<ManifestRecord>)> {
+        let number = manifest_number(path.as_ref());
         let mut file = OpenOptions::new()
             .read(true)
             .append(true)
@@ -57,55 +271,9 @@ impl Manifest {
             .context("failed to recover manifest")?;
         let mut buf = Vec::new();
         file.read_to_end(&mut buf)?;
-        let mut records = Vec::new();
-        let mut valid_len = 0usize;
-        while valid_len < buf.len() {
-            let remaining = &buf[valid_len..];
-            if remaining.len() < std::mem::size_of::<u64>() {
-                break;
-            }
-
-            let len = u64::from_be_bytes([
-                remaining[0],
-                remaining[1],
-                remaining[2],
-                remaining[3],
-                remaining[4],
-                remaining[5],
-                remaining[6],
-                remaining[7],
-            ]);
-            let Ok(len) = usize::try_from(len) else {
-                break;
-            };
-            let Some(frame_len) = std::mem::size_of::<u64>()
-                .checked_add(len)
-                .and_then(|len| len.checked_add(std::mem::size_of::<u32>()))
-            else {
-                break;
-            };
-            if remaining.len() < frame_len {
-                break;
-            }
-
-            let body_start = std::mem::size_of::<u64>();
-            let body_end = body_start + len;
-            let body = &remaining[body_start..body_end];
-            let checksum = u32::from_be_bytes([
-                remaining[body_end],
-                remaining[body_end + 1],
-                remaining[body_end + 2],
-                remaining[body_end + 3],
-            ]);
-            if checksum != crc32fast::hash(body) {
-                bail!("manifest checksum mismatched at byte offset {valid_len}");
-            }
-            records.push(
-                serde_json::from_slice::<ManifestRecord>(body).with_context(|| {
-                    format!("invalid manifest record at byte offset {valid_len}")
-                })?,
-            );
-            valid_len += frame_len;
+        let (records, valid_len, corruption) = decode_records(&buf);
+        if let Some(corruption) = corruption {
+            return Err(corruption);
         }
 
         if valid_len < buf.len() {
@@ -118,11 +286,22 @@ impl Manifest {
         Ok((
             Self {
                 file: Arc::new(Mutex::new(file)),
+                number: AtomicU64::new(number),
             },
             records,
         ))
     }
 
+    /// Read the records of a manifest without modifying it. The records before the first
+    /// corrupted frame are returned with the error of the frame.
+    pub fn recover_read_only(
+        path: impl AsRef<Path>,
+    ) -> Result<(Vec<ManifestRecord>, Option<anyhow::Error>)> {
+        let buf = std::fs::read(path).context("failed to recover manifest")?;
+        let (records, _, corruption) = decode_records(&buf);
+        Ok((records, corruption))
+    }
+
     pub fn add_record(
         &self,
         _state_lock_observer: &MutexGuard<()>,
@@ -133,13 +312,39 @@ impl Manifest {
 
     pub fn add_record_when_init(&self, record: ManifestRecord) -> Result<()> {
         let mut file = self.file.lock();
-        let mut buf = serde_json::to_vec(&record)?;
-        let hash = crc32fast::hash(&buf);
-        let len = u64::try_from(buf.len()).context("manifest record is too large")?;
-        file.write_all(&len.to_be_bytes())?;
-        buf.put_u32(hash);
+        let mut buf = Vec::new();
+        encode_record(&record, &mut buf)?;
         file.write_all(&buf)?;
         file.sync_all()?;
         Ok(())
     }
+
+    /// The size of the manifest file in bytes.
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.file.lock().metadata()?.len())
+    }
+
+    /// Replace the manifest of the database in `dir` with a new manifest file that holds `records`.
+    /// `CURRENT` is switched to the new file atomically, so the database opens with either the old
+    /// or the new manifest if it crashes in the meantime.
+    pub fn rotate(
+        &self,
+        _state_lock_observer: &MutexGuard<()>,
+        dir: impl AsRef<Path>,
+        records: &[ManifestRecord],
+    ) -> Result<()> {
+        let dir = dir.as_ref();
+        let mut file = self.file.lock();
+        let old_number = self.number.load(Ordering::SeqCst);
+        let number = old_number + 1;
+        let name = manifest_file_name(number);
+        let mut buf = Vec::new();
+        for record in records {
+            encode_record(record, &mut buf)?;
+        }
+        *file = write_current_manifest(dir, &name, &buf)?;
+        self.number.store(number, Ordering::SeqCst);
+        std::fs::remove_file(dir.join(manifest_file_name(old_number)))?;
+        Ok(())
+    }
 }
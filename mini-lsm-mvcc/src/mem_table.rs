@@ -12,31 +12,40 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::ops::Bound;
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::AtomicUsize;
 
-use anyhow::Result;
+use anyhow::{Result, ensure};
 use bytes::Bytes;
 use crossbeam_skiplist::SkipMap;
 use crossbeam_skiplist::map::Entry;
 use ouroboros::self_referencing;
+use parking_lot::RwLock;
 
-use crate::iterators::StorageIterator;
+use crate::comparator::{BYTEWISE_COMPARATOR, Comparator, OrderedKey};
+use crate::iterators::{SeekableIterator, StorageIterator, ValueKind};
 use crate::key::{KeyBytes, KeySlice, TS_DEFAULT, TS_RANGE_BEGIN, TS_RANGE_END};
+use crate::range_tombstone::RangeTombstone;
 use crate::table::SsTableBuilder;
-use crate::wal::Wal;
+use crate::wal::{ColumnFamilyBatch, Wal, WalColumnFamily};
 
 /// A basic mem-table based on crossbeam-skiplist.
 ///
 /// An initial implementation of memtable is part of week 1, day 1. It will be incrementally implemented in other
 /// chapters of week 1 and week 2.
 pub struct MemTable {
-    pub(crate) map: Arc<SkipMap<KeyBytes, Bytes>>,
+    /// The entries, with how their values are stored.
+    pub(crate) map: Arc<SkipMap<OrderedKey, (ValueKind, Bytes)>>,
+    /// The range tombstones written to this memtable, in the order of their timestamps.
+    range_tombstones: RwLock<Vec<RangeTombstone>>,
     wal: Option<Wal>,
     id: usize,
     approximate_size: Arc<AtomicUsize>,
+    /// Orders the keys of the mem-table.
+    comparator: &'static dyn Comparator,
 }
 
 /// Create a bound of `Bytes` from a bound of `&[u8]`.
@@ -48,18 +57,39 @@ pub(crate) fn map_bound(bound: Bound<&[u8]>) -> Bound<Bytes> {
     }
 }
 
-/// Create a bound of `Bytes` from a bound of `KeySlice`.
-pub(crate) fn map_key_bound(bound: Bound<KeySlice>) -> Bound<KeyBytes> {
-    match bound {
-        Bound::Included(x) => Bound::Included(KeyBytes::from_bytes_with_ts(
-            Bytes::copy_from_slice(x.key_ref()),
-            x.ts(),
-        )),
-        Bound::Excluded(x) => Bound::Excluded(KeyBytes::from_bytes_with_ts(
-            Bytes::copy_from_slice(x.key_ref()),
-            x.ts(),
-        )),
-        Bound::Unbounded => Bound::Unbounded,
+/// Create a bound of a skip list ordered by `comparator` from a bound of `KeySlice`.
+pub(crate) fn map_key_bound(
+    bound: Bound<KeySlice>,
+    comparator: &'static dyn Comparator,
+) -> Bound<OrderedKey> {
+    bound.map(|x| OrderedKey::new(x.to_key_vec().into_key_bytes(), comparator))
+}
+
+/// The tighter of the lower bound of a range of a skip list and a key to seek to.
+pub(crate) fn seek_lower_bound(
+    lower: &Bound<OrderedKey>,
+    key: KeySlice,
+    comparator: &'static dyn Comparator,
+) -> Bound<OrderedKey> {
+    let ordering = |bound: &OrderedKey| comparator.compare_keys(key, bound.key().as_key_slice());
+    match lower {
+        Bound::Included(bound) if ordering(bound).is_lt() => lower.clone(),
+        Bound::Excluded(bound) if ordering(bound).is_le() => lower.clone(),
+        _ => map_key_bound(Bound::Included(key), comparator),
+    }
+}
+
+/// The tighter of the upper bound of a range of a skip list and a key to seek to in reverse.
+pub(crate) fn seek_upper_bound(
+    upper: &Bound<OrderedKey>,
+    key: KeySlice,
+    comparator: &'static dyn Comparator,
+) -> Bound<OrderedKey> {
+    let ordering = |bound: &OrderedKey| comparator.compare_keys(key, bound.key().as_key_slice());
+    match upper {
+        Bound::Included(bound) if ordering(bound).is_gt() => upper.clone(),
+        Bound::Excluded(bound) if ordering(bound).is_ge() => upper.clone(),
+        _ => map_key_bound(Bound::Included(key), comparator),
     }
 }
 
@@ -89,44 +119,135 @@ pub(crate) fn map_key_bound_plus_ts<'a>(
 }
 
 impl MemTable {
-    /// Create a new mem-table.
+    /// Create a new mem-table, with the keys ordered bytewise.
     pub fn create(id: usize) -> Self {
+        Self::create_with_comparator(id, &BYTEWISE_COMPARATOR)
+    }
+
+    /// Create a new mem-table, with the keys ordered by `comparator`.
+    pub fn create_with_comparator(id: usize, comparator: &'static dyn Comparator) -> Self {
         Self {
             id,
             map: Arc::new(SkipMap::new()),
+            range_tombstones: RwLock::new(Vec::new()),
             wal: None,
             approximate_size: Arc::new(AtomicUsize::new(0)),
+            comparator,
         }
     }
 
-    /// Create a new mem-table with WAL
-    pub fn create_with_wal(id: usize, path: impl AsRef<Path>) -> Result<Self> {
+    /// Create a new mem-table with WAL, with the keys ordered by `comparator`.
+    pub fn create_with_wal(
+        id: usize,
+        path: impl AsRef<Path>,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self> {
         Ok(Self {
-            id,
-            map: Arc::new(SkipMap::new()),
             wal: Some(Wal::create(path.as_ref())?),
-            approximate_size: Arc::new(AtomicUsize::new(0)),
+            ..Self::create_with_comparator(id, comparator)
         })
     }
 
-    /// Create a memtable from WAL
-    pub fn recover_from_wal(id: usize, path: impl AsRef<Path>) -> Result<Self> {
-        let map = Arc::new(SkipMap::new());
+    /// Create a memtable from WAL, with the keys ordered by `comparator`.
+    pub fn recover_from_wal(
+        id: usize,
+        path: impl AsRef<Path>,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut range_tombstones = Vec::new();
+        let wal =
+            Wal::recover_with_range_tombstones(path.as_ref(), &mut entries, &mut range_tombstones)?;
         Ok(Self {
-            id,
-            wal: Some(Wal::recover(path.as_ref(), &map)?),
-            map,
-            approximate_size: Arc::new(AtomicUsize::new(0)),
+            wal: Some(wal),
+            ..Self::from_wal_entries(id, entries, range_tombstones, comparator)
         })
     }
 
+    /// Recover the mem-table of the default column family from a WAL, together with the mem-tables
+    /// of the other column families by their ids. Only the mem-table of the default column family
+    /// owns the WAL.
+    pub(crate) fn recover_column_families_from_wal(
+        id: usize,
+        path: impl AsRef<Path>,
+        comparator: &'static dyn Comparator,
+    ) -> Result<(Self, HashMap<u32, Self>)> {
+        let mut column_families = HashMap::new();
+        let wal = Wal::recover_column_families(path.as_ref(), &mut column_families)?;
+        let (mut memtable, memtables) = Self::from_column_families(id, column_families, comparator);
+        memtable.wal = Some(wal);
+        Ok((memtable, memtables))
+    }
+
+    /// Recover the mem-tables of the column families from a WAL like
+    /// [`MemTable::recover_column_families_from_wal`], without modifying the WAL or keeping it open.
+    /// The corrupted frames are skipped, and their byte offsets and errors are collected into
+    /// `corrupted_frames`.
+    pub(crate) fn salvage_column_families_from_wal(
+        id: usize,
+        path: impl AsRef<Path>,
+        comparator: &'static dyn Comparator,
+        corrupted_frames: &mut Vec<(usize, anyhow::Error)>,
+    ) -> Result<(Self, HashMap<u32, Self>)> {
+        let mut column_families = HashMap::new();
+        corrupted_frames.extend(Wal::salvage_column_families(
+            path.as_ref(),
+            &mut column_families,
+        )?);
+        Ok(Self::from_column_families(id, column_families, comparator))
+    }
+
+    /// Create a mem-table of the entries and the range tombstones recovered from a WAL.
+    fn from_wal_entries(
+        id: usize,
+        entries: Vec<(KeyBytes, ValueKind, Bytes)>,
+        range_tombstones: Vec<RangeTombstone>,
+        comparator: &'static dyn Comparator,
+    ) -> Self {
+        let memtable = Self::create_with_comparator(id, comparator);
+        for (key, kind, value) in entries {
+            memtable
+                .map
+                .insert(OrderedKey::new(key, comparator), (kind, value));
+        }
+        *memtable.range_tombstones.write() = range_tombstones;
+        memtable
+    }
+
+    /// Create the mem-tables of the column families recovered from a WAL, returning the mem-table
+    /// of the default column family and the others by their ids.
+    fn from_column_families(
+        id: usize,
+        column_families: HashMap<u32, WalColumnFamily>,
+        comparator: &'static dyn Comparator,
+    ) -> (Self, HashMap<u32, Self>) {
+        let mut memtables = column_families
+            .into_iter()
+            .map(|(family_id, column_family)| {
+                let memtable = Self::from_wal_entries(
+                    id,
+                    column_family.entries,
+                    column_family.range_tombstones,
+                    comparator,
+                );
+                (family_id, memtable)
+            })
+            .collect::<HashMap<_, _>>();
+        let memtable = memtables
+            .remove(&0)
+            .unwrap_or_else(|| Self::create_with_comparator(id, comparator));
+        (memtable, memtables)
+    }
+
     /// Get a value by key. Should not be used in week 3.
     pub fn get(&self, key: KeySlice) -> Option<Bytes> {
         let key_bytes = KeyBytes::from_bytes_with_ts(
             Bytes::from_static(unsafe { std::mem::transmute::<&[u8], &[u8]>(key.key_ref()) }),
             key.ts(),
         );
-        self.map.get(&key_bytes).map(|e| e.value().clone())
+        self.map
+            .get(&OrderedKey::new(key_bytes, self.comparator))
+            .map(|e| e.value().1.clone())
     }
 
     pub fn for_testing_put_slice(&self, key: &[u8], value: &[u8]) -> Result<()> {
@@ -159,21 +280,61 @@ impl MemTable {
 
     /// Implement this in week 3, day 5.
     pub fn put_batch(&self, data: &[(KeySlice, &[u8])]) -> Result<()> {
+        let data = data
+            .iter()
+            .map(|(key, value)| (*key, ValueKind::Value, *value))
+            .collect::<Vec<_>>();
+        self.put_batch_with_range_tombstones(&data, &[])
+    }
+
+    /// Put the entries and the range tombstones of a batch into the mem-table. The entries are
+    /// values or merge operands.
+    pub fn put_batch_with_range_tombstones(
+        &self,
+        data: &[(KeySlice, ValueKind, &[u8])],
+        range_tombstones: &[RangeTombstone],
+    ) -> Result<()> {
         if let Some(ref wal) = self.wal {
-            wal.put_batch(data)?;
+            wal.put_batch_with_range_tombstones(data, range_tombstones)?;
         }
+        self.insert_batch(data, range_tombstones);
+        Ok(())
+    }
 
+    /// Log the batches to several column families in the WAL of the mem-table, without inserting
+    /// them. The mem-tables of the column families insert their batches after this.
+    pub(crate) fn log_column_family_batches(&self, batches: &[ColumnFamilyBatch]) -> Result<()> {
+        if let Some(ref wal) = self.wal {
+            wal.put_column_family_batches(batches)?;
+        }
+        Ok(())
+    }
+
+    /// Insert the entries and the range tombstones of a batch that has been logged.
+    pub(crate) fn insert_batch(
+        &self,
+        data: &[(KeySlice, ValueKind, &[u8])],
+        range_tombstones: &[RangeTombstone],
+    ) {
         let mut estimated_size = 0;
-        for (key, value) in data {
+        for (key, kind, value) in data {
             estimated_size += key.raw_len() + value.len();
             self.map.insert(
-                key.to_key_vec().into_key_bytes(),
-                Bytes::copy_from_slice(value),
+                OrderedKey::new(key.to_key_vec().into_key_bytes(), self.comparator),
+                (*kind, Bytes::copy_from_slice(value)),
             );
         }
+        if !range_tombstones.is_empty() {
+            for tombstone in range_tombstones {
+                estimated_size +=
+                    tombstone.start.len() + tombstone.end.len() + std::mem::size_of::<u64>();
+            }
+            self.range_tombstones
+                .write()
+                .extend_from_slice(range_tombstones);
+        }
         self.approximate_size
             .fetch_add(estimated_size, std::sync::atomic::Ordering::Relaxed);
-        Ok(())
     }
 
     pub fn sync_wal(&self) -> Result<()> {
@@ -185,21 +346,65 @@ impl MemTable {
 
     /// Get an iterator over a range of keys.
     pub fn scan(&self, lower: Bound<KeySlice>, upper: Bound<KeySlice>) -> MemTableIterator {
-        let (lower, upper) = (map_key_bound(lower), map_key_bound(upper));
-        let mut iter = MemTableIteratorBuilder {
-            map: self.map.clone(),
-            iter_builder: |map| map.range((lower, upper)),
-            item: (KeyBytes::new(), Bytes::new()),
-        }
-        .build();
-        iter.next().unwrap();
-        iter
+        let range = (
+            map_key_bound(lower, self.comparator),
+            map_key_bound(upper, self.comparator),
+        );
+        MemTableIterator::create(
+            self.map.clone(),
+            self.comparator,
+            range.clone(),
+            range,
+            false,
+        )
+    }
+
+    /// Get an iterator over a range of keys that starts at the last key and moves with `prev`.
+    pub fn scan_rev(&self, lower: Bound<KeySlice>, upper: Bound<KeySlice>) -> MemTableIterator {
+        let range = (
+            map_key_bound(lower, self.comparator),
+            map_key_bound(upper, self.comparator),
+        );
+        MemTableIterator::create(
+            self.map.clone(),
+            self.comparator,
+            range.clone(),
+            range,
+            true,
+        )
+    }
+
+    /// The range tombstones that are visible at `read_ts` and may have a key within the bounds.
+    pub fn range_tombstones(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+        comparator: &dyn Comparator,
+    ) -> Vec<RangeTombstone> {
+        self.range_tombstones
+            .read()
+            .iter()
+            .filter(|tombstone| {
+                tombstone.ts <= read_ts && tombstone.overlaps(lower, upper, comparator)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The largest timestamp of the range tombstones in the mem-table.
+    pub fn range_tombstones_max_ts(&self) -> Option<u64> {
+        self.range_tombstones.read().iter().map(|x| x.ts).max()
     }
 
     /// Flush the mem-table to SSTable. Implement in week 1 day 6.
     pub fn flush(&self, builder: &mut SsTableBuilder) -> Result<()> {
         for entry in self.map.iter() {
-            builder.add(entry.key().as_key_slice(), &entry.value()[..]);
+            let (kind, value) = entry.value();
+            builder.add_with_kind(entry.key().key().as_key_slice(), *kind, value);
+        }
+        for tombstone in self.range_tombstones.read().iter() {
+            builder.add_range_tombstone(tombstone.clone());
         }
         Ok(())
     }
@@ -215,16 +420,16 @@ impl MemTable {
 
     /// Only use this function when closing the database
     pub fn is_empty(&self) -> bool {
-        self.map.is_empty()
+        self.map.is_empty() && self.range_tombstones.read().is_empty()
     }
 }
 
 type SkipMapRangeIter<'a> = crossbeam_skiplist::map::Range<
     'a,
-    KeyBytes,
-    (Bound<KeyBytes>, Bound<KeyBytes>),
-    KeyBytes,
-    Bytes,
+    OrderedKey,
+    (Bound<OrderedKey>, Bound<OrderedKey>),
+    OrderedKey,
+    (ValueKind, Bytes),
 >;
 
 /// An iterator over a range of `SkipMap`. This is a self-referential structure and please refer to week 1, day 2
@@ -234,20 +439,54 @@ type SkipMapRangeIter<'a> = crossbeam_skiplist::map::Range<
 #[self_referencing]
 pub struct MemTableIterator {
     /// Stores a reference to the skipmap.
-    map: Arc<SkipMap<KeyBytes, Bytes>>,
+    map: Arc<SkipMap<OrderedKey, (ValueKind, Bytes)>>,
+    /// Orders the keys of the skipmap.
+    comparator: &'static dyn Comparator,
     /// Stores a skipmap iterator that refers to the lifetime of `MemTableIterator` itself.
     #[borrows(map)]
     #[not_covariant]
     iter: SkipMapRangeIter<'this>,
-    /// Stores the current key-value pair.
-    item: (KeyBytes, Bytes),
+    /// Stores the current key, value kind and value.
+    item: (KeyBytes, ValueKind, Bytes),
+    /// The iterator moves from the end of the range with `prev`.
+    reverse: bool,
+    /// The range of the scan, which the seeks stay within.
+    range: (Bound<OrderedKey>, Bound<OrderedKey>),
 }
 
 impl MemTableIterator {
-    fn entry_to_item(entry: Option<Entry<'_, KeyBytes, Bytes>>) -> (KeyBytes, Bytes) {
+    /// Create an iterator over `range` of `map`, positioned at the first key of `seek_range` in
+    /// the direction of the iterator.
+    fn create(
+        map: Arc<SkipMap<OrderedKey, (ValueKind, Bytes)>>,
+        comparator: &'static dyn Comparator,
+        range: (Bound<OrderedKey>, Bound<OrderedKey>),
+        seek_range: (Bound<OrderedKey>, Bound<OrderedKey>),
+        reverse: bool,
+    ) -> Self {
+        let mut iter = MemTableIteratorBuilder {
+            map,
+            comparator,
+            iter_builder: |map| map.range(seek_range),
+            item: (KeyBytes::new(), ValueKind::Value, Bytes::new()),
+            reverse,
+            range,
+        }
+        .build();
+        if reverse {
+            iter.prev().unwrap();
+        } else {
+            iter.next().unwrap();
+        }
+        iter
+    }
+
+    fn entry_to_item(
+        entry: Option<Entry<'_, OrderedKey, (ValueKind, Bytes)>>,
+    ) -> (KeyBytes, ValueKind, Bytes) {
         entry
-            .map(|x| (x.key().clone(), x.value().clone()))
-            .unwrap_or_else(|| (KeyBytes::new(), Bytes::new()))
+            .map(|x| (x.key().key().clone(), x.value().0, x.value().1.clone()))
+            .unwrap_or_else(|| (KeyBytes::new(), ValueKind::Value, Bytes::new()))
     }
 }
 
@@ -255,7 +494,11 @@ impl StorageIterator for MemTableIterator {
     type KeyType<'a> = KeySlice<'a>;
 
     fn value(&self) -> &[u8] {
-        &self.borrow_item().1[..]
+        &self.borrow_item().2[..]
+    }
+
+    fn value_kind(&self) -> ValueKind {
+        self.borrow_item().1
     }
 
     fn key(&self) -> KeySlice<'_> {
@@ -267,8 +510,60 @@ impl StorageIterator for MemTableIterator {
     }
 
     fn next(&mut self) -> Result<()> {
+        ensure!(
+            !self.borrow_reverse(),
+            "the mem-table iterator is created for reverse iteration"
+        );
         let entry = self.with_iter_mut(|iter| MemTableIterator::entry_to_item(iter.next()));
         self.with_mut(|x| *x.item = entry);
         Ok(())
     }
+
+    fn prev(&mut self) -> Result<()> {
+        ensure!(
+            *self.borrow_reverse(),
+            "the mem-table iterator is not created for reverse iteration"
+        );
+        let entry = self.with_iter_mut(|iter| MemTableIterator::entry_to_item(iter.next_back()));
+        self.with_mut(|x| *x.item = entry);
+        Ok(())
+    }
+}
+
+impl SeekableIterator for MemTableIterator {
+    fn seek_to_key(&mut self, key: KeySlice) -> Result<()> {
+        ensure!(
+            !self.borrow_reverse(),
+            "the mem-table iterator is created for reverse iteration"
+        );
+        let range = self.borrow_range().clone();
+        let comparator = *self.borrow_comparator();
+        let seek_range = (seek_lower_bound(&range.0, key, comparator), range.1.clone());
+        *self = Self::create(
+            self.borrow_map().clone(),
+            comparator,
+            range,
+            seek_range,
+            false,
+        );
+        Ok(())
+    }
+
+    fn seek_for_prev(&mut self, key: KeySlice) -> Result<()> {
+        ensure!(
+            *self.borrow_reverse(),
+            "the mem-table iterator is not created for reverse iteration"
+        );
+        let range = self.borrow_range().clone();
+        let comparator = *self.borrow_comparator();
+        let seek_range = (range.0.clone(), seek_upper_bound(&range.1, key, comparator));
+        *self = Self::create(
+            self.borrow_map().clone(),
+            comparator,
+            range,
+            seek_range,
+            true,
+        );
+        Ok(())
+    }
 }
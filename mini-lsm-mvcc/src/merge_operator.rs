@@ -0,0 +1,126 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Merge operators. A merge writes an operand instead of a value, so that a read-modify-write does
+//! not need to read the key. The operands of a key are combined with its value when it is read, and
+//! folded into a value by compaction once every reader sees them.
+
+use std::fmt::Debug;
+
+use anyhow::{Result, ensure};
+use bytes::Bytes;
+
+/// Combines the merge operands of a key with its value.
+pub trait MergeOperator: Debug + Send + Sync {
+    /// Identifies the operator in error messages.
+    fn name(&self) -> &str;
+
+    /// Applies `operands`, from the earliest to the latest, to the value of `key`, which is `None`
+    /// if the key does not exist. An empty result deletes the key.
+    fn full_merge(
+        &self,
+        key: &[u8],
+        existing: Option<&[u8]>,
+        operands: &[&[u8]],
+    ) -> Result<Vec<u8>>;
+
+    /// Combines `operands`, from the earliest to the latest, into a single operand with the same
+    /// effect, or returns `None` if they cannot be combined. A write batch that merges a key
+    /// several times combines its operands, or otherwise keeps them for `full_merge`.
+    fn partial_merge(&self, _key: &[u8], _operands: &[&[u8]]) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+}
+
+/// Appends the operands to the value, separated by a delimiter.
+#[derive(Debug, Clone, Copy)]
+pub struct StringAppendOperator(pub u8);
+
+impl MergeOperator for StringAppendOperator {
+    fn name(&self) -> &str {
+        "string-append"
+    }
+
+    fn full_merge(
+        &self,
+        _key: &[u8],
+        existing: Option<&[u8]>,
+        operands: &[&[u8]],
+    ) -> Result<Vec<u8>> {
+        let mut value = existing.map(<[u8]>::to_vec).unwrap_or_default();
+        for operand in operands {
+            if !value.is_empty() {
+                value.push(self.0);
+            }
+            value.extend_from_slice(operand);
+        }
+        Ok(value)
+    }
+
+    fn partial_merge(&self, key: &[u8], operands: &[&[u8]]) -> Result<Option<Vec<u8>>> {
+        self.full_merge(key, None, operands).map(Some)
+    }
+}
+
+/// Adds the operands to the value. The value and the operands are big-endian `u64`s, and the sum
+/// wraps around on overflow.
+#[derive(Debug, Clone, Copy)]
+pub struct U64AddOperator;
+
+fn decode_u64(value: &[u8]) -> Result<u64> {
+    ensure!(
+        value.len() == std::mem::size_of::<u64>(),
+        "u64 merge value must be 8 bytes, got {}",
+        value.len()
+    );
+    Ok(u64::from_be_bytes(value.try_into().unwrap()))
+}
+
+impl MergeOperator for U64AddOperator {
+    fn name(&self) -> &str {
+        "u64-add"
+    }
+
+    fn full_merge(
+        &self,
+        _key: &[u8],
+        existing: Option<&[u8]>,
+        operands: &[&[u8]],
+    ) -> Result<Vec<u8>> {
+        let mut sum = existing.map(decode_u64).transpose()?.unwrap_or_default();
+        for operand in operands {
+            sum = sum.wrapping_add(decode_u64(operand)?);
+        }
+        Ok(sum.to_be_bytes().to_vec())
+    }
+
+    fn partial_merge(&self, key: &[u8], operands: &[&[u8]]) -> Result<Option<Vec<u8>>> {
+        self.full_merge(key, None, operands).map(Some)
+    }
+}
+
+/// Merge the operands of `key`, collected from the latest to the earliest, into `existing`.
+pub(crate) fn merge_operands(
+    operator: &dyn MergeOperator,
+    key: &[u8],
+    existing: Option<&[u8]>,
+    latest_first: &[Bytes],
+) -> Result<Bytes> {
+    let operands = latest_first
+        .iter()
+        .rev()
+        .map(|operand| operand.as_ref())
+        .collect::<Vec<_>>();
+    Ok(operator.full_merge(key, existing, &operands)?.into())
+}
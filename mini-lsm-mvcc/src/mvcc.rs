@@ -19,19 +19,23 @@ pub mod txn;
 pub mod watermark;
 
 use std::{
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     sync::{Arc, atomic::AtomicBool},
 };
 
-use crossbeam_skiplist::SkipMap;
 use parking_lot::Mutex;
 
-use crate::lsm_storage::LsmStorageInner;
+use crate::lsm_storage::{DEFAULT_COLUMN_FAMILY, LsmStorageInner};
 
-use self::{txn::Transaction, watermark::Watermark};
+use self::{
+    txn::{LocalWrites, Transaction},
+    watermark::Watermark,
+};
 
 pub(crate) struct CommittedTxnData {
     pub(crate) key_hashes: HashSet<u32>,
+    /// Whether the transaction deleted a range of keys.
+    pub(crate) deleted_ranges: bool,
     #[allow(dead_code)]
     pub(crate) read_ts: u64,
     #[allow(dead_code)]
@@ -76,7 +80,10 @@ impl LsmMvccInner {
         Arc::new(Transaction {
             inner,
             read_ts,
-            local_storage: Arc::new(SkipMap::new()),
+            local_writes: Mutex::new(HashMap::from([(
+                DEFAULT_COLUMN_FAMILY.to_string(),
+                LocalWrites::default(),
+            )])),
             committed: Arc::new(AtomicBool::new(false)),
             key_hashes: if serializable {
                 Some(Mutex::new((HashSet::new(), HashSet::new())))
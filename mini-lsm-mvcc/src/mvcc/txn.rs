@@ -13,102 +13,456 @@
 // limitations under the License.
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     ops::Bound,
     sync::{
         Arc,
         atomic::{AtomicBool, Ordering},
     },
+    time::Duration,
 };
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail, ensure};
 use bytes::Bytes;
 use crossbeam_skiplist::{SkipMap, map::Entry};
 use ouroboros::self_referencing;
 use parking_lot::Mutex;
 
 use crate::{
-    iterators::{StorageIterator, two_merge_iterator::TwoMergeIterator},
+    comparator::{Comparator, OrderedKey},
+    iterators::{SeekableIterator, StorageIterator, two_merge_iterator::TwoMergeIterator},
+    key::{KeyBytes, KeySlice, TS_DEFAULT},
     lsm_iterator::{FusedIterator, LsmIterator},
-    lsm_storage::{LsmStorageInner, WriteBatchRecord},
-    mem_table::map_bound,
+    lsm_storage::{DEFAULT_COLUMN_FAMILY, LsmStorageInner, WriteBatchRecord},
+    mem_table::{map_bound, map_key_bound, seek_lower_bound, seek_upper_bound},
+    merge_operator::merge_operands,
     mvcc::CommittedTxnData,
+    prefix::prefix_upper_bound,
 };
 
 pub struct Transaction {
     pub(crate) read_ts: u64,
     pub(crate) inner: Arc<LsmStorageInner>,
-    pub(crate) local_storage: Arc<SkipMap<Bytes, Bytes>>,
+    /// The writes of the transaction to every column family it wrote or read, by name. The
+    /// default column family is always there.
+    pub(crate) local_writes: Mutex<HashMap<String, LocalWrites>>,
     pub(crate) committed: Arc<AtomicBool>,
     /// Write set and read set
     pub(crate) key_hashes: Option<Mutex<(HashSet<u32>, HashSet<u32>)>>,
 }
 
+/// The writes of a transaction to a column family.
+#[derive(Default)]
+pub(crate) struct LocalWrites {
+    /// The keys and values written. The keys are at the default timestamp, so that they are
+    /// ordered by the comparator of the storage.
+    pub(crate) storage: Arc<SkipMap<OrderedKey, Bytes>>,
+    /// The ranges `[start, end)` deleted by the transaction.
+    pub(crate) deleted_ranges: Vec<(Bytes, Bytes)>,
+    /// The merge operands written by the transaction, in order.
+    pub(crate) merge_operands: Vec<(Bytes, Bytes)>,
+    /// The TTLs of the values in the storage that expire. They expire after their TTL from the
+    /// commit.
+    pub(crate) ttls: HashMap<Bytes, Duration>,
+}
+
+impl LocalWrites {
+    /// Drop the pending merge operands and TTLs of the keys that are overwritten by a later write.
+    fn overwrite(&mut self, overwritten: impl Fn(&[u8]) -> bool) {
+        if !self.merge_operands.is_empty() {
+            self.merge_operands.retain(|(key, _)| !overwritten(key));
+        }
+        self.ttls.retain(|key, _| !overwritten(key));
+    }
+
+    /// The records that write the changes to the storage, with the deletions of ranges first, as
+    /// the other writes of the transaction are not deleted by them.
+    fn into_batch(self) -> Vec<WriteBatchRecord<Bytes>> {
+        let ttls = self.ttls;
+        self.deleted_ranges
+            .into_iter()
+            .map(|(start, end)| WriteBatchRecord::DelRange(start, end))
+            .chain(
+                self.merge_operands
+                    .into_iter()
+                    .map(|(key, operand)| WriteBatchRecord::Merge(key, operand)),
+            )
+            .chain(self.storage.iter().map(|entry| {
+                let key = entry.key().key().clone().into_inner();
+                if entry.value().is_empty() {
+                    WriteBatchRecord::Del(key)
+                } else if let Some(ttl) = ttls.get(&key) {
+                    WriteBatchRecord::PutWithTtl(key, entry.value().clone(), *ttl)
+                } else {
+                    WriteBatchRecord::Put(key, entry.value().clone())
+                }
+            }))
+            .collect()
+    }
+}
+
+/// The hash of a key in the read and write sets. The keys of the column families are hashed with
+/// their names, so that the same key in different column families does not conflict.
+fn key_hash(column_family: &str, key: &[u8]) -> u32 {
+    if column_family == DEFAULT_COLUMN_FAMILY {
+        farmhash::hash32(key)
+    } else {
+        farmhash::hash32_with_seed(key, farmhash::hash32(column_family.as_bytes()))
+    }
+}
+
 impl Transaction {
-    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
+    fn comparator(&self) -> &'static dyn Comparator {
+        self.inner.options.comparator
+    }
+
+    /// Make a key of the local storage.
+    fn local_key<'a>(&self, key: &'a [u8]) -> KeySlice<'a> {
+        KeySlice::from_slice_with_ts(key, TS_DEFAULT)
+    }
+
+    fn local_key_bytes(&self, key: &[u8]) -> OrderedKey {
+        OrderedKey::new(
+            self.local_key(key).to_key_vec().into_key_bytes(),
+            self.comparator(),
+        )
+    }
+
+    /// Run `f` on the writes of the transaction to a column family, which fails if the column
+    /// family does not exist.
+    fn with_local_writes<R>(
+        &self,
+        column_family: &str,
+        f: impl FnOnce(&mut LocalWrites) -> R,
+    ) -> Result<R> {
         if self.committed.load(Ordering::SeqCst) {
             panic!("cannot operate on committed txn!");
         }
+        let mut local_writes = self.local_writes.lock();
+        if let Some(writes) = local_writes.get_mut(column_family) {
+            return Ok(f(writes));
+        }
+        // Check that the column family exists before it is written.
+        self.inner.column_family_snapshot(column_family)?;
+        let writes = local_writes.entry(column_family.to_string()).or_default();
+        Ok(f(writes))
+    }
+
+    /// The local storage of a column family.
+    fn local_storage(&self, column_family: &str) -> Result<Arc<SkipMap<OrderedKey, Bytes>>> {
+        self.with_local_writes(column_family, |writes| writes.storage.clone())
+    }
+
+    fn add_to_write_set(&self, column_family: &str, key: &[u8]) {
+        if let Some(key_hashes) = &self.key_hashes {
+            let mut key_hashes = key_hashes.lock();
+            let (write_hashes, _) = &mut *key_hashes;
+            write_hashes.insert(key_hash(column_family, key));
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        self.get_cf(DEFAULT_COLUMN_FAMILY, key)
+    }
+
+    /// Returns true if the committed value of `key` in a column family is hidden by a range
+    /// deleted in this transaction. The keys written after the deletion are in the local storage
+    /// instead.
+    fn is_range_deleted(&self, column_family: &str, key: &[u8]) -> bool {
+        let local_writes = self.local_writes.lock();
+        let Some(writes) = local_writes.get(column_family) else {
+            return false;
+        };
+        let comparator = self.comparator();
+        !writes.deleted_ranges.is_empty()
+            && writes.deleted_ranges.iter().any(|(start, end)| {
+                comparator.compare(start, key).is_le() && comparator.compare(key, end).is_lt()
+            })
+            && !writes.storage.contains_key(&self.local_key_bytes(key))
+    }
+
+    pub fn scan(self: &Arc<Self>, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Result<TxnIterator> {
+        let storage_scan = self.storage_scan(lower, upper, LsmStorageInner::scan_with_ts);
+        self.scan_inner(lower, upper, storage_scan)
+    }
+
+    /// Scan the keys that start with `prefix`.
+    pub fn prefix_scan(self: &Arc<Self>, prefix: &[u8]) -> Result<TxnIterator> {
+        let upper = prefix_upper_bound(prefix);
+        let lower = Bound::Included(prefix);
+        let upper = upper.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        let prefix_bytes = Bytes::copy_from_slice(prefix);
+        let storage_scan = self.storage_scan(lower, upper, move |inner, _, _, read_ts| {
+            inner.prefix_scan_with_ts(&prefix_bytes, read_ts)
+        });
+        self.scan_inner(lower, upper, storage_scan)
+    }
+
+    /// Wrap `scan` of the storage in the bounds, so that the scan can be created again on the
+    /// latest state of the storage at the read timestamp of the transaction.
+    fn storage_scan(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        scan: impl Fn(
+            &LsmStorageInner,
+            Bound<&[u8]>,
+            Bound<&[u8]>,
+            u64,
+        ) -> Result<FusedIterator<LsmIterator>>
+        + Send
+        + Sync
+        + 'static,
+    ) -> StorageScan {
+        let inner = self.inner.clone();
+        let read_ts = self.read_ts;
+        let (lower, upper) = (map_bound(lower), map_bound(upper));
+        Box::new(move || {
+            let lower = lower.as_ref().map(|key| &key[..]);
+            let upper = upper.as_ref().map(|key| &key[..]);
+            scan(&inner, lower, upper, read_ts)
+        })
+    }
+
+    fn scan_inner(
+        self: &Arc<Self>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        storage_scan: StorageScan,
+    ) -> Result<TxnIterator> {
+        self.scan_local_storage(
+            DEFAULT_COLUMN_FAMILY,
+            self.local_storage(DEFAULT_COLUMN_FAMILY)?,
+            lower,
+            upper,
+            storage_scan,
+            false,
+        )
+    }
+
+    /// Scan a range of keys from the last key to the first. Call `prev` to advance the iterator.
+    pub fn scan_rev(
+        self: &Arc<Self>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<TxnIterator> {
+        self.scan_cf_rev(DEFAULT_COLUMN_FAMILY, lower, upper)
+    }
+
+    /// Scan a range of keys of a column family from the last key to the first.
+    pub fn scan_cf_rev(
+        self: &Arc<Self>,
+        column_family: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<TxnIterator> {
+        let local_storage = self.local_storage(column_family)?;
+        let name = column_family.to_string();
+        let storage_scan = self.storage_scan(lower, upper, move |inner, lower, upper, read_ts| {
+            inner.scan_cf_rev_with_ts(&name, lower, upper, read_ts)
+        });
+        self.scan_local_storage(
+            column_family,
+            local_storage,
+            lower,
+            upper,
+            storage_scan,
+            true,
+        )
+    }
+
+    /// Merge the writes of the transaction to a column family with a scan of the storage, which
+    /// moves with `prev` if it is `reverse`.
+    fn scan_local_storage(
+        self: &Arc<Self>,
+        column_family: &str,
+        local_storage: Arc<SkipMap<OrderedKey, Bytes>>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        storage_scan: StorageScan,
+        reverse: bool,
+    ) -> Result<TxnIterator> {
+        let comparator = self.comparator();
+        let range = (
+            map_key_bound(lower.map(|key| self.local_key(key)), comparator),
+            map_key_bound(upper.map(|key| self.local_key(key)), comparator),
+        );
+        let local_iter =
+            TxnLocalIterator::create(local_storage, comparator, range.clone(), range, reverse);
+        let storage_iter = TxnStorageIterator {
+            iter: Some(storage_scan()?),
+            storage_scan,
+        };
+        let iter =
+            TwoMergeIterator::create_with_direction(local_iter, storage_iter, reverse, comparator)?;
+
+        if reverse {
+            return TxnIterator::create_rev(self.clone(), column_family.to_string(), iter);
+        }
+        TxnIterator::create(self.clone(), column_family.to_string(), iter)
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) {
+        self.put_cf(DEFAULT_COLUMN_FAMILY, key, value)
+            .expect("the default column family always exists");
+    }
+
+    /// Put a value that expires after `ttl` from the commit of the transaction.
+    pub fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) {
+        self.put_with_ttl_cf(DEFAULT_COLUMN_FAMILY, key, value, ttl)
+            .expect("the default column family always exists");
+    }
+
+    pub fn delete(&self, key: &[u8]) {
+        self.delete_cf(DEFAULT_COLUMN_FAMILY, key)
+            .expect("the default column family always exists");
+    }
+
+    /// Delete the keys in `[lower, upper)`.
+    pub fn delete_range(&self, lower: &[u8], upper: &[u8]) {
+        self.delete_range_cf(DEFAULT_COLUMN_FAMILY, lower, upper)
+            .expect("the default column family always exists");
+    }
+
+    pub fn get_cf(&self, column_family: &str, key: &[u8]) -> Result<Option<Bytes>> {
+        let local_storage = self.local_storage(column_family)?;
         if let Some(guard) = &self.key_hashes {
             let mut guard = guard.lock();
             let (_, read_set) = &mut *guard;
-            read_set.insert(farmhash::hash32(key));
+            read_set.insert(key_hash(column_family, key));
         }
-        if let Some(entry) = self.local_storage.get(key) {
+        if let Some(entry) = local_storage.get(&self.local_key_bytes(key)) {
             if entry.value().is_empty() {
                 return Ok(None);
             } else {
                 return Ok(Some(entry.value().clone()));
             }
         }
-        self.inner.get_with_ts(key, self.read_ts)
+        if self.is_range_deleted(column_family, key) {
+            return Ok(None);
+        }
+        self.inner.get_cf_with_ts(column_family, key, self.read_ts)
     }
 
-    pub fn scan(self: &Arc<Self>, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Result<TxnIterator> {
-        if self.committed.load(Ordering::SeqCst) {
-            panic!("cannot operate on committed txn!");
-        }
-        let mut local_iter = TxnLocalIteratorBuilder {
-            map: self.local_storage.clone(),
-            iter_builder: |map| map.range((map_bound(lower), map_bound(upper))),
-            item: (Bytes::new(), Bytes::new()),
+    pub fn put_cf(&self, column_family: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.with_local_writes(column_family, |writes| {
+            writes
+                .storage
+                .insert(self.local_key_bytes(key), Bytes::copy_from_slice(value));
+            writes.overwrite(|written_key| written_key == key);
+        })?;
+        self.add_to_write_set(column_family, key);
+        Ok(())
+    }
+
+    /// Put a value to a column family that expires after `ttl` from the commit of the
+    /// transaction.
+    pub fn put_with_ttl_cf(
+        &self,
+        column_family: &str,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Result<()> {
+        self.put_cf(column_family, key, value)?;
+        self.with_local_writes(column_family, |writes| {
+            writes.ttls.insert(Bytes::copy_from_slice(key), ttl);
+        })
+    }
+
+    pub fn delete_cf(&self, column_family: &str, key: &[u8]) -> Result<()> {
+        self.with_local_writes(column_family, |writes| {
+            writes
+                .storage
+                .insert(self.local_key_bytes(key), Bytes::new());
+            writes.overwrite(|written_key| written_key == key);
+        })?;
+        self.add_to_write_set(column_family, key);
+        Ok(())
+    }
+
+    /// Delete the keys of a column family in `[lower, upper)`.
+    pub fn delete_range_cf(&self, column_family: &str, lower: &[u8], upper: &[u8]) -> Result<()> {
+        let comparator = self.comparator();
+        let in_range = |key: &[u8]| {
+            comparator.compare(lower, key).is_le() && comparator.compare(key, upper).is_lt()
+        };
+        self.with_local_writes(column_family, |writes| {
+            // The local writes in the range happened before the deletion, so they are deleted too.
+            for entry in writes.storage.range((
+                Bound::Included(self.local_key_bytes(lower)),
+                Bound::Excluded(self.local_key_bytes(upper)),
+            )) {
+                writes.storage.insert(entry.key().clone(), Bytes::new());
+            }
+            writes.overwrite(in_range);
+            writes
+                .deleted_ranges
+                .push((Bytes::copy_from_slice(lower), Bytes::copy_from_slice(upper)));
+        })
+    }
+
+    pub fn scan_cf(
+        self: &Arc<Self>,
+        column_family: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<TxnIterator> {
+        if column_family == DEFAULT_COLUMN_FAMILY {
+            return self.scan(lower, upper);
         }
-        .build();
-        local_iter.next()?;
-
-        TxnIterator::create(
-            self.clone(),
-            TwoMergeIterator::create(
-                local_iter,
-                self.inner.scan_with_ts(lower, upper, self.read_ts)?,
-            )?,
+        let local_storage = self.local_storage(column_family)?;
+        let name = column_family.to_string();
+        let storage_scan = self.storage_scan(lower, upper, move |inner, lower, upper, read_ts| {
+            inner.scan_cf_with_ts(&name, lower, upper, read_ts)
+        });
+        self.scan_local_storage(
+            column_family,
+            local_storage,
+            lower,
+            upper,
+            storage_scan,
+            false,
         )
     }
 
-    pub fn put(&self, key: &[u8], value: &[u8]) {
-        if self.committed.load(Ordering::SeqCst) {
-            panic!("cannot operate on committed txn!");
-        }
-        self.local_storage
-            .insert(Bytes::copy_from_slice(key), Bytes::copy_from_slice(value));
-        if let Some(key_hashes) = &self.key_hashes {
-            let mut key_hashes = key_hashes.lock();
-            let (write_hashes, _) = &mut *key_hashes;
-            write_hashes.insert(farmhash::hash32(key));
-        }
+    /// Write a merge operand of `key`. An operand of a key written by the transaction is merged
+    /// into its value right away. Otherwise, the operand is not visible to the reads of the
+    /// transaction, so this is only used by write batches.
+    pub(crate) fn merge(&self, key: &[u8], operand: &[u8]) -> Result<()> {
+        self.merge_cf(DEFAULT_COLUMN_FAMILY, key, operand)
     }
 
-    pub fn delete(&self, key: &[u8]) {
-        if self.committed.load(Ordering::SeqCst) {
-            panic!("cannot operate on committed txn!");
-        }
-        self.local_storage
-            .insert(Bytes::copy_from_slice(key), Bytes::new());
-        if let Some(key_hashes) = &self.key_hashes {
-            let mut key_hashes = key_hashes.lock();
-            let (write_hashes, _) = &mut *key_hashes;
-            write_hashes.insert(farmhash::hash32(key));
-        }
+    /// Write a merge operand of `key` in a column family, like [`Transaction::merge`].
+    pub(crate) fn merge_cf(&self, column_family: &str, key: &[u8], operand: &[u8]) -> Result<()> {
+        let merge_operator = self
+            .inner
+            .options
+            .merge_operator
+            .as_ref()
+            .context("cannot merge without a merge operator")?;
+        let local_key = self.local_key_bytes(key);
+        self.with_local_writes(column_family, |writes| {
+            if let Some(entry) = writes.storage.get(&local_key) {
+                let existing = (!entry.value().is_empty()).then(|| entry.value().as_ref());
+                let value = merge_operands(
+                    merge_operator.as_ref(),
+                    key,
+                    existing,
+                    &[Bytes::copy_from_slice(operand)],
+                )?;
+                writes.storage.insert(local_key, value);
+                // Like a merge in the storage, the merged value does not expire.
+                writes.ttls.remove(key);
+            } else {
+                writes
+                    .merge_operands
+                    .push((Bytes::copy_from_slice(key), Bytes::copy_from_slice(operand)));
+            }
+            Ok::<_, anyhow::Error>(())
+        })??;
+        self.add_to_write_set(column_family, key);
+        Ok(())
     }
 
     pub fn commit(&self) -> Result<()> {
@@ -117,6 +471,10 @@ impl Transaction {
             .expect("cannot operate on committed txn!");
         let _commit_lock = self.inner.mvcc().commit_lock.lock();
         let serializability_check;
+        let mut local_writes = std::mem::take(&mut *self.local_writes.lock());
+        let has_deleted_ranges = local_writes
+            .values()
+            .any(|writes| !writes.deleted_ranges.is_empty());
         if let Some(guard) = &self.key_hashes {
             let guard = guard.lock();
             let (write_set, read_set) = &*guard;
@@ -124,9 +482,14 @@ impl Transaction {
                 "commit txn: write_set: {:?}, read_set: {:?}",
                 write_set, read_set
             );
-            if !write_set.is_empty() {
+            if !write_set.is_empty() || has_deleted_ranges {
                 let committed_txns = self.inner.mvcc().committed_txns.lock();
                 for (_, txn_data) in committed_txns.range((self.read_ts + 1)..) {
+                    // The keys in a deleted range are not known, so a range deletion conflicts
+                    // with every read.
+                    if txn_data.deleted_ranges && !read_set.is_empty() {
+                        bail!("serializable check failed");
+                    }
                     for key_hash in read_set {
                         if txn_data.key_hashes.contains(key_hash) {
                             bail!("serializable check failed");
@@ -138,17 +501,16 @@ impl Transaction {
         } else {
             serializability_check = false;
         }
-        let batch = self
-            .local_storage
-            .iter()
-            .map(|entry| {
-                if entry.value().is_empty() {
-                    WriteBatchRecord::Del(entry.key().clone())
-                } else {
-                    WriteBatchRecord::Put(entry.key().clone(), entry.value().clone())
-                }
-            })
-            .collect::<Vec<_>>();
+        let mut batch = local_writes
+            .remove(DEFAULT_COLUMN_FAMILY)
+            .map(LocalWrites::into_batch)
+            .unwrap_or_default();
+        for (name, writes) in local_writes {
+            let family_batch = writes.into_batch();
+            if !family_batch.is_empty() {
+                batch.push(WriteBatchRecord::ColumnFamily(name, family_batch));
+            }
+        }
         if batch.is_empty() {
             return Ok(());
         }
@@ -162,6 +524,7 @@ impl Transaction {
                 ts,
                 CommittedTxnData {
                     key_hashes: std::mem::take(write_set),
+                    deleted_ranges: has_deleted_ranges,
                     read_ts: self.read_ts,
                     commit_ts: ts,
                 },
@@ -188,38 +551,75 @@ impl Drop for Transaction {
     }
 }
 
-type SkipMapRangeIter<'a> =
-    crossbeam_skiplist::map::Range<'a, Bytes, (Bound<Bytes>, Bound<Bytes>), Bytes, Bytes>;
+type SkipMapRangeIter<'a> = crossbeam_skiplist::map::Range<
+    'a,
+    OrderedKey,
+    (Bound<OrderedKey>, Bound<OrderedKey>),
+    OrderedKey,
+    Bytes,
+>;
 
 #[self_referencing]
 pub struct TxnLocalIterator {
     /// Stores a reference to the skipmap.
-    map: Arc<SkipMap<Bytes, Bytes>>,
+    map: Arc<SkipMap<OrderedKey, Bytes>>,
+    /// The comparator of the keys of the skipmap.
+    comparator: &'static dyn Comparator,
     /// Stores a skipmap iterator that refers to the lifetime of `TxnLocalIterator` itself.
     #[borrows(map)]
     #[not_covariant]
     iter: SkipMapRangeIter<'this>,
     /// Stores the current key-value pair.
-    item: (Bytes, Bytes),
+    item: (KeyBytes, Bytes),
+    /// The iterator moves from the end of the range with `prev`.
+    reverse: bool,
+    /// The range of the scan, which the seeks stay within.
+    range: (Bound<OrderedKey>, Bound<OrderedKey>),
 }
 
 impl TxnLocalIterator {
-    fn entry_to_item(entry: Option<Entry<'_, Bytes, Bytes>>) -> (Bytes, Bytes) {
+    /// Create an iterator over `range` of `map`, positioned at the first key of `seek_range` in
+    /// the direction of the iterator.
+    fn create(
+        map: Arc<SkipMap<OrderedKey, Bytes>>,
+        comparator: &'static dyn Comparator,
+        range: (Bound<OrderedKey>, Bound<OrderedKey>),
+        seek_range: (Bound<OrderedKey>, Bound<OrderedKey>),
+        reverse: bool,
+    ) -> Self {
+        let mut iter = TxnLocalIteratorBuilder {
+            map,
+            comparator,
+            iter_builder: |map| map.range(seek_range),
+            item: (KeyBytes::new(), Bytes::new()),
+            reverse,
+            range,
+        }
+        .build();
+        if reverse {
+            iter.prev().unwrap();
+        } else {
+            iter.next().unwrap();
+        }
+        iter
+    }
+
+    fn entry_to_item(entry: Option<Entry<'_, OrderedKey, Bytes>>) -> (KeyBytes, Bytes) {
         entry
-            .map(|x| (x.key().clone(), x.value().clone()))
-            .unwrap_or_else(|| (Bytes::new(), Bytes::new()))
+            .map(|x| (x.key().key().clone(), x.value().clone()))
+            .unwrap_or_else(|| (KeyBytes::new(), Bytes::new()))
     }
 }
 
 impl StorageIterator for TxnLocalIterator {
-    type KeyType<'a> = &'a [u8];
+    type KeyType<'a> = KeySlice<'a>;
 
     fn value(&self) -> &[u8] {
         &self.borrow_item().1[..]
     }
 
-    fn key(&self) -> &[u8] {
-        &self.borrow_item().0[..]
+    fn key(&self) -> KeySlice<'_> {
+        self.borrow_item().0.as_key_slice()
     }
 
     fn is_valid(&self) -> bool {
@@ -227,23 +627,189 @@ impl StorageIterator for TxnLocalIterator {
     }
 
     fn next(&mut self) -> Result<()> {
+        ensure!(
+            !self.borrow_reverse(),
+            "the local iterator is created for reverse iteration"
+        );
         let entry = self.with_iter_mut(|iter| TxnLocalIterator::entry_to_item(iter.next()));
         self.with_mut(|x| *x.item = entry);
         Ok(())
     }
+
+    fn prev(&mut self) -> Result<()> {
+        ensure!(
+            *self.borrow_reverse(),
+            "the local iterator is not created for reverse iteration"
+        );
+        let entry = self.with_iter_mut(|iter| TxnLocalIterator::entry_to_item(iter.next_back()));
+        self.with_mut(|x| *x.item = entry);
+        Ok(())
+    }
+}
+
+impl SeekableIterator for TxnLocalIterator {
+    fn seek_to_key(&mut self, key: KeySlice) -> Result<()> {
+        ensure!(
+            !self.borrow_reverse(),
+            "the local iterator is created for reverse iteration"
+        );
+        let comparator = *self.borrow_comparator();
+        let range = self.borrow_range().clone();
+        let seek_range = (seek_lower_bound(&range.0, key, comparator), range.1.clone());
+        *self = Self::create(
+            self.borrow_map().clone(),
+            comparator,
+            range,
+            seek_range,
+            false,
+        );
+        Ok(())
+    }
+
+    fn seek_for_prev(&mut self, key: KeySlice) -> Result<()> {
+        ensure!(
+            *self.borrow_reverse(),
+            "the local iterator is not created for reverse iteration"
+        );
+        let comparator = *self.borrow_comparator();
+        let range = self.borrow_range().clone();
+        let seek_range = (range.0.clone(), seek_upper_bound(&range.1, key, comparator));
+        *self = Self::create(
+            self.borrow_map().clone(),
+            comparator,
+            range,
+            seek_range,
+            true,
+        );
+        Ok(())
+    }
+}
+
+/// Creates the iterator over the storage for a scan of a transaction.
+type StorageScan = Box<dyn Fn() -> Result<FusedIterator<LsmIterator>> + Send + Sync>;
+
+/// Exposes the keys of the storage at the default timestamp, so that they are merged with the keys
+/// of the local storage in the order of the comparator.
+pub struct TxnStorageIterator {
+    /// The iterator over the storage, or `None` if it is exhausted and released by a refresh.
+    iter: Option<FusedIterator<LsmIterator>>,
+    /// Creates the iterator again on the latest state of the storage.
+    storage_scan: StorageScan,
+}
+
+impl TxnStorageIterator {
+    /// Create the iterator again on the latest state of the storage and move it back to its key,
+    /// releasing the memtables and SSTs of the old state. An exhausted iterator is released until
+    /// the next seek.
+    fn refresh(&mut self) -> Result<()> {
+        let key = self
+            .iter
+            .take()
+            .filter(|iter| iter.is_valid())
+            .map(|iter| Bytes::copy_from_slice(iter.key()));
+        if let Some(key) = key {
+            self.seek(&key)?;
+        }
+        Ok(())
+    }
+
+    fn seek(&mut self, key: &[u8]) -> Result<()> {
+        let iter = match &mut self.iter {
+            Some(iter) => iter,
+            None => self.iter.insert((self.storage_scan)()?),
+        };
+        iter.seek(key)
+    }
+}
+
+impl StorageIterator for TxnStorageIterator {
+    type KeyType<'a> = KeySlice<'a>;
+
+    fn value(&self) -> &[u8] {
+        self.iter.as_ref().unwrap().value()
+    }
+
+    fn key(&self) -> KeySlice<'_> {
+        KeySlice::from_slice_with_ts(self.iter.as_ref().unwrap().key(), TS_DEFAULT)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.iter.as_ref().is_some_and(|iter| iter.is_valid())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        match &mut self.iter {
+            Some(iter) => iter.next(),
+            None => Ok(()),
+        }
+    }
+
+    fn prev(&mut self) -> Result<()> {
+        match &mut self.iter {
+            Some(iter) => iter.prev(),
+            None => Ok(()),
+        }
+    }
+
+    fn num_active_iterators(&self) -> usize {
+        self.iter
+            .as_ref()
+            .map_or(0, |iter| iter.num_active_iterators())
+    }
+}
+
+impl SeekableIterator for TxnStorageIterator {
+    fn seek_to_key(&mut self, key: KeySlice) -> Result<()> {
+        self.seek(key.key_ref())
+    }
+
+    fn seek_for_prev(&mut self, key: KeySlice) -> Result<()> {
+        self.seek(key.key_ref())
+    }
 }
 
 pub struct TxnIterator {
     txn: Arc<Transaction>,
-    iter: TwoMergeIterator<TxnLocalIterator, FusedIterator<LsmIterator>>,
+    /// The column family that the keys are in.
+    column_family: String,
+    iter: TwoMergeIterator<TxnLocalIterator, TxnStorageIterator>,
+    /// The iterator moves from the last key with `prev`.
+    reverse: bool,
+    /// The number of keys the iterator moved over since it was created or refreshed.
+    keys_since_refresh: usize,
 }
 
 impl TxnIterator {
     pub fn create(
         txn: Arc<Transaction>,
-        iter: TwoMergeIterator<TxnLocalIterator, FusedIterator<LsmIterator>>,
+        column_family: String,
+        iter: TwoMergeIterator<TxnLocalIterator, TxnStorageIterator>,
+    ) -> Result<Self> {
+        Self::create_with_direction(txn, column_family, iter, false)
+    }
+
+    /// Create an iterator for reverse iteration over `iter`, which is created with `create_rev`.
+    pub fn create_rev(
+        txn: Arc<Transaction>,
+        column_family: String,
+        iter: TwoMergeIterator<TxnLocalIterator, TxnStorageIterator>,
+    ) -> Result<Self> {
+        Self::create_with_direction(txn, column_family, iter, true)
+    }
+
+    fn create_with_direction(
+        txn: Arc<Transaction>,
+        column_family: String,
+        iter: TwoMergeIterator<TxnLocalIterator, TxnStorageIterator>,
+        reverse: bool,
     ) -> Result<Self> {
-        let mut iter = Self { txn, iter };
+        let mut iter = Self {
+            txn,
+            column_family,
+            iter,
+            reverse,
+            keys_since_refresh: 0,
+        };
         iter.skip_deletes()?;
         if iter.is_valid() {
             iter.add_to_read_set(iter.key());
@@ -252,8 +818,53 @@ impl TxnIterator {
     }
 
     fn skip_deletes(&mut self) -> Result<()> {
-        while self.iter.is_valid() && self.iter.value().is_empty() {
-            self.iter.next()?;
+        while self.iter.is_valid()
+            && (self.iter.value().is_empty()
+                || self
+                    .txn
+                    .is_range_deleted(&self.column_family, self.iter.key().key_ref()))
+        {
+            if self.reverse {
+                self.iter.prev()?;
+            } else {
+                self.iter.next()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Move to the first key that >= `key`, or to the last key that <= `key` if the iterator is
+    /// created with `scan_rev`. The seek stays within the range of the scan, and the iterator keeps
+    /// reading the snapshot of the transaction.
+    pub fn seek(&mut self, key: &[u8]) -> Result<()> {
+        let key = self.txn.local_key(key);
+        if self.reverse {
+            self.iter.seek_for_prev(key)?;
+        } else {
+            self.iter.seek_to_key(key)?;
+        }
+        self.skip_deletes()?;
+        if self.is_valid() {
+            self.add_to_read_set(self.key());
+        }
+        Ok(())
+    }
+
+    /// Create the iterator over the storage again on its latest state, at the same read timestamp
+    /// and position, so that the memtables and SSTs of the old state can be released. This is
+    /// done automatically every `iterator_refresh_interval` keys.
+    pub fn refresh(&mut self) -> Result<()> {
+        self.keys_since_refresh = 0;
+        self.iter.b_mut().refresh()
+    }
+
+    /// Count a move of the iterator, and refresh it once it moved over the refresh interval.
+    fn maybe_refresh(&mut self) -> Result<()> {
+        self.keys_since_refresh += 1;
+        if let Some(interval) = self.txn.inner.options.iterator_refresh_interval
+            && self.keys_since_refresh >= interval
+        {
+            self.refresh()?;
         }
         Ok(())
     }
@@ -262,7 +873,7 @@ impl TxnIterator {
         if let Some(guard) = &self.txn.key_hashes {
             let mut guard = guard.lock();
             let (_, read_set) = &mut *guard;
-            read_set.insert(farmhash::hash32(key));
+            read_set.insert(key_hash(&self.column_family, key));
         }
     }
 }
@@ -278,7 +889,7 @@ impl StorageIterator for TxnIterator {
     }
 
     fn key(&self) -> Self::KeyType<'_> {
-        self.iter.key()
+        self.iter.key().key_ref()
     }
 
     fn is_valid(&self) -> bool {
@@ -291,7 +902,16 @@ impl StorageIterator for TxnIterator {
         if self.is_valid() {
             self.add_to_read_set(self.key());
         }
-        Ok(())
+        self.maybe_refresh()
+    }
+
+    fn prev(&mut self) -> Result<()> {
+        self.iter.prev()?;
+        self.skip_deletes()?;
+        if self.is_valid() {
+            self.add_to_read_set(self.key());
+        }
+        self.maybe_refresh()
     }
 
     fn num_active_iterators(&self) -> usize {
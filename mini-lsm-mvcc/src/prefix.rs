@@ -0,0 +1,54 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prefix extractors. When one is configured, the SSTs also add the prefix of every key to their
+//! bloom filters, so that a prefix scan can skip the SSTs that have no key with the prefix.
+
+use std::fmt::Debug;
+
+/// Extracts the prefix of a key.
+///
+/// The prefix of a key must only depend on the first bytes of the key: if `prefix(a)` is
+/// `Some(p)`, then `prefix(b)` must be `Some(p)` for every key `b` that starts with `a`.
+pub trait PrefixExtractor: Debug + Send + Sync {
+    /// Identifies the extractor. It is recorded in the SSTs, whose prefix bloom filters are only
+    /// used if it matches the configured extractor.
+    fn name(&self) -> String;
+
+    /// Returns the prefix of `key`, or `None` if the key has no prefix.
+    fn prefix<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]>;
+}
+
+/// Uses the first `len` bytes of a key as its prefix. Shorter keys have no prefix.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedPrefix(pub usize);
+
+impl PrefixExtractor for FixedPrefix {
+    fn name(&self) -> String {
+        format!("fixed:{}", self.0)
+    }
+
+    fn prefix<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        key.get(..self.0)
+    }
+}
+
+/// The smallest key that is greater than every key starting with `prefix`, or `None` if there is
+/// no such key.
+pub fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|byte| *byte != u8::MAX)?;
+    let mut upper = prefix[..=last].to_vec();
+    upper[last] += 1;
+    Some(upper)
+}
@@ -0,0 +1,90 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Range tombstones. A range tombstone deletes every version of the keys in its range that was
+//! written before it, so that a range of keys can be deleted without writing a tombstone for each
+//! key.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+
+use crate::comparator::Comparator;
+
+/// Deletes the versions of the keys in `[start, end)` whose timestamps are below `ts`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeTombstone {
+    /// The first key of the range.
+    pub start: Bytes,
+    /// The end of the range, which is not part of it.
+    pub end: Bytes,
+    /// The commit timestamp of the deletion.
+    pub ts: u64,
+}
+
+impl RangeTombstone {
+    pub fn new(start: Bytes, end: Bytes, ts: u64) -> Self {
+        Self { start, end, ts }
+    }
+
+    /// Returns true if `key` is in the range, ordered by `comparator`.
+    pub fn contains(&self, key: &[u8], comparator: &dyn Comparator) -> bool {
+        comparator.compare(&self.start, key).is_le() && comparator.compare(key, &self.end).is_lt()
+    }
+
+    /// Returns true if the tombstone deletes the version of `key` at `ts`. The writes in the same
+    /// batch as the deletion share its timestamp and are not deleted.
+    pub fn covers(&self, key: &[u8], ts: u64, comparator: &dyn Comparator) -> bool {
+        ts < self.ts && self.contains(key, comparator)
+    }
+
+    /// Returns true if the range may have a key within the bounds.
+    pub fn overlaps(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        comparator: &dyn Comparator,
+    ) -> bool {
+        let after_lower = match lower {
+            Bound::Included(key) | Bound::Excluded(key) => {
+                comparator.compare(key, &self.end).is_lt()
+            }
+            Bound::Unbounded => true,
+        };
+        let before_upper = match upper {
+            Bound::Included(key) => comparator.compare(&self.start, key).is_le(),
+            Bound::Excluded(key) => comparator.compare(&self.start, key).is_lt(),
+            Bound::Unbounded => true,
+        };
+        after_lower && before_upper
+    }
+
+    /// Returns true if the range has a key in `[first, last]`.
+    pub fn overlaps_keys(&self, first: &[u8], last: &[u8], comparator: &dyn Comparator) -> bool {
+        comparator.compare(&self.start, last).is_le()
+            && comparator.compare(first, &self.end).is_lt()
+    }
+}
+
+/// Returns true if one of the tombstones deletes the version of `key` at `ts`.
+pub fn is_covered(
+    tombstones: &[RangeTombstone],
+    key: &[u8],
+    ts: u64,
+    comparator: &dyn Comparator,
+) -> bool {
+    tombstones
+        .iter()
+        .any(|tombstone| tombstone.covers(key, ts, comparator))
+}
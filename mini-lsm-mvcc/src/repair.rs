@@ -0,0 +1,430 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rebuilds the manifest of a database from the files in its directory, for when the manifest is
+//! lost or corrupted. Every SST carries its key range and the latest timestamp of its keys, so the
+//! tables are placed into a valid level layout from the files alone, and the surviving WALs are
+//! flushed into new SSTs.
+//!
+//! The column families are only known from what is left of the old manifest. An SST is routed to
+//! the column family recorded in it, or for a table written before SSTs recorded it, to the one
+//! that the old manifest recorded. The SSTs of a column family that the manifest no longer records
+//! are moved into [`LOST_DIR`], and the WAL entries of such a column family are dropped. If the
+//! repair is interrupted, run it again.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result, ensure};
+
+use crate::compact::{CompactionController, CompactionOptions};
+use crate::comparator::Comparator;
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions, LsmStorageState, add_flushed_sst};
+use crate::manifest::{self, Manifest, ManifestRecord};
+use crate::mem_table::MemTable;
+use crate::salvage;
+use crate::table::{FileObject, SsTable, SsTableBuilder};
+
+/// The directory in the database directory that [`repair`] moves the files it replaces into.
+pub const LOST_DIR: &str = "lost";
+
+/// What [`repair`] did to a database.
+#[derive(Clone, Debug, Default)]
+pub struct RepairReport {
+    /// The number of SSTs in the new manifest, including the ones flushed from the WALs.
+    pub tables: usize,
+    /// The number of WALs flushed into SSTs.
+    pub wals: usize,
+    /// The files that could not be fully recovered, with the reasons.
+    pub corrupted: Vec<(String, String)>,
+    /// The files moved into [`LOST_DIR`]: the corrupted SSTs, the SSTs of unknown column families,
+    /// the flushed WALs and the old manifests.
+    pub archived: Vec<String>,
+}
+
+/// What the old manifest still tells about the column families.
+#[derive(Default)]
+struct OldManifest {
+    comparator: Option<String>,
+    column_families: BTreeMap<u32, (String, CompactionOptions)>,
+    /// The column family that each SST was last recorded in, if it is not the default one.
+    sst_column_families: HashMap<usize, u32>,
+}
+
+impl OldManifest {
+    /// Read the records of the current manifest up to the first corrupted one.
+    fn read(path: &Path, report: &mut RepairReport) -> Self {
+        let mut old = Self::default();
+        let manifest_path = match manifest::current_manifest_path(path) {
+            Ok(manifest_path) if manifest_path.exists() => manifest_path,
+            Ok(_) => return old,
+            Err(e) => {
+                report
+                    .corrupted
+                    .push(("CURRENT".to_string(), format!("{e:#}")));
+                return old;
+            }
+        };
+        let file = manifest_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let records = match Manifest::recover_read_only(&manifest_path) {
+            Ok((records, corruption)) => {
+                if let Some(e) = corruption {
+                    report.corrupted.push((file, format!("{e:#}")));
+                }
+                records
+            }
+            Err(e) => {
+                report.corrupted.push((file, format!("{e:#}")));
+                return old;
+            }
+        };
+        for record in records {
+            match record {
+                ManifestRecord::Comparator(name) => old.comparator = Some(name),
+                ManifestRecord::CreateColumnFamily(name, id, compaction_options) => {
+                    old.column_families.insert(id, (name, compaction_options));
+                }
+                ManifestRecord::FlushColumnFamilies { ssts, .. } => {
+                    for (id, sst_id) in ssts {
+                        old.sst_column_families.insert(sst_id, id);
+                    }
+                }
+                ManifestRecord::ColumnFamilyCompaction(id, _, output) => {
+                    for sst_id in output {
+                        old.sst_column_families.insert(sst_id, id);
+                    }
+                }
+                ManifestRecord::ColumnFamilyIngestExternalFiles(id, ssts) => {
+                    for (_, _, sst_id) in ssts {
+                        old.sst_column_families.insert(sst_id, id);
+                    }
+                }
+                ManifestRecord::Snapshot {
+                    column_family: id,
+                    l0_sstables,
+                    levels,
+                } if id != 0 => {
+                    for sst_id in l0_sstables
+                        .into_iter()
+                        .chain(levels.into_iter().flat_map(|(_, files)| files))
+                    {
+                        old.sst_column_families.insert(sst_id, id);
+                    }
+                }
+                _ => {}
+            }
+        }
+        old
+    }
+}
+
+/// The id of a file named `{id:05}.{extension}`.
+fn file_id(name: &str, extension: &str) -> Option<usize> {
+    name.strip_suffix(extension)?
+        .strip_suffix('.')?
+        .parse()
+        .ok()
+}
+
+/// Open an SST and read all of its blocks.
+fn open_and_verify(
+    path: &Path,
+    sst_id: usize,
+    comparator: &'static dyn Comparator,
+) -> Result<SsTable> {
+    let file = FileObject::open(&LsmStorageInner::path_of_sst_static(path, sst_id))
+        .context("failed to open SST")?;
+    let table = SsTable::open_with_comparator(sst_id, None, file, comparator)?;
+    salvage::verify_table(&table)?;
+    Ok(table)
+}
+
+/// Place the tables of a column family into a valid level layout, returning the snapshot of the
+/// column family. The tables that overlap no other table hold the only versions of their keys, so
+/// they are placed into the bottom level with leveled compaction. The others are placed as if
+/// they were flushed in the order of their latest timestamps.
+fn place_tables(
+    column_family: u32,
+    compaction_options: &CompactionOptions,
+    mut tables: Vec<Arc<SsTable>>,
+    comparator: &'static dyn Comparator,
+) -> ManifestRecord {
+    let controller = CompactionController::new(compaction_options);
+    let mut state = LsmStorageState::create(compaction_options, 0, comparator);
+    tables.sort_by_key(|table| (table.max_ts(), table.sst_id()));
+
+    let mut by_first_key = (0..tables.len()).collect::<Vec<_>>();
+    by_first_key.sort_by(|x, y| {
+        comparator.compare(
+            tables[*x].first_key().key_ref(),
+            tables[*y].first_key().key_ref(),
+        )
+    });
+    let mut isolated = vec![false; tables.len()];
+    if controller.flush_to_l0() && !state.levels.is_empty() {
+        let mut max_last_key: Option<&[u8]> = None;
+        for (pos, &idx) in by_first_key.iter().enumerate() {
+            let table = &tables[idx];
+            let after_previous = max_last_key.is_none_or(|last| {
+                comparator
+                    .compare(last, table.first_key().key_ref())
+                    .is_lt()
+            });
+            let before_next = by_first_key.get(pos + 1).is_none_or(|&next| {
+                comparator
+                    .compare(
+                        table.last_key().key_ref(),
+                        tables[next].first_key().key_ref(),
+                    )
+                    .is_lt()
+            });
+            isolated[idx] = after_previous && before_next;
+            if max_last_key
+                .is_none_or(|last| comparator.compare(last, table.last_key().key_ref()).is_lt())
+            {
+                max_last_key = Some(table.last_key().key_ref());
+            }
+        }
+        let bottom = &mut state.levels.last_mut().unwrap().1;
+        bottom.extend(
+            by_first_key
+                .iter()
+                .filter(|idx| isolated[**idx])
+                .map(|idx| tables[*idx].sst_id()),
+        );
+    }
+    for (idx, table) in tables.iter().enumerate() {
+        if !isolated[idx] {
+            add_flushed_sst(&mut state, &controller, table.sst_id());
+        }
+    }
+    ManifestRecord::Snapshot {
+        column_family,
+        l0_sstables: state.l0_sstables,
+        levels: state.levels,
+    }
+}
+
+/// Flush a mem-table of a column family recovered from a WAL to a new SST with the id.
+fn flush_memtable(
+    path: &Path,
+    memtable: &MemTable,
+    sst_id: usize,
+    options: &LsmStorageOptions,
+    column_family: u32,
+) -> Result<(SsTable, bool)> {
+    let mut builder =
+        SsTableBuilder::new_with_value_log(options, sst_id).with_column_family(column_family);
+    memtable.flush(&mut builder)?;
+    let has_value_log = builder.has_separated_values();
+    let table = builder.build(
+        sst_id,
+        None,
+        LsmStorageInner::path_of_sst_static(path, sst_id),
+    )?;
+    Ok((table, has_value_log))
+}
+
+/// Rebuild the manifest of the database in `path` from its SSTs and WALs. The SSTs that cannot
+/// be opened, fail their checksums or belong to an unknown column family are left out, the WALs
+/// are flushed into new SSTs, and the replaced files are moved into [`LOST_DIR`]. The default column family is laid out for the
+/// compaction options in `options`, and the database must be opened with them afterwards.
+pub fn repair(path: impl AsRef<Path>, options: &LsmStorageOptions) -> Result<RepairReport> {
+    let path = path.as_ref();
+    ensure!(
+        path.is_dir(),
+        "{} is not a database directory",
+        path.display()
+    );
+    let comparator = options.comparator;
+    let mut report = RepairReport::default();
+    let old = OldManifest::read(path, &mut report);
+    if let Some(name) = &old.comparator {
+        ensure!(
+            name == comparator.name(),
+            "the database was created with the {name:?} comparator, cannot repair it with {:?}",
+            comparator.name()
+        );
+    }
+
+    let mut sst_ids = Vec::new();
+    let mut wal_ids = Vec::new();
+    let mut value_log_ids = Vec::new();
+    let mut manifests = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let file_name = entry?.file_name();
+        let Some(name) = file_name.to_str() else {
+            continue;
+        };
+        if let Some(id) = file_id(name, "sst") {
+            sst_ids.push(id);
+        } else if let Some(id) = file_id(name, "wal") {
+            wal_ids.push(id);
+        } else if let Some(id) = file_id(name, "vlog") {
+            value_log_ids.push(id);
+        } else if manifest::is_manifest_file(name) && name != "CURRENT" {
+            manifests.push(name.to_string());
+        }
+    }
+    sst_ids.sort();
+    wal_ids.sort();
+    value_log_ids.sort();
+    manifests.sort();
+    let mut next_sst_id = sst_ids
+        .iter()
+        .chain(&wal_ids)
+        .chain(&value_log_ids)
+        .max()
+        .copied()
+        .unwrap_or_default()
+        + 1;
+
+    let mut tables: BTreeMap<u32, Vec<Arc<SsTable>>> = BTreeMap::new();
+    let mut archived = Vec::new();
+    for sst_id in sst_ids {
+        match open_and_verify(path, sst_id, comparator) {
+            Ok(table) => {
+                let column_family = table.column_family().unwrap_or_else(|| {
+                    old.sst_column_families
+                        .get(&sst_id)
+                        .copied()
+                        .unwrap_or_default()
+                });
+                if column_family != 0 && !old.column_families.contains_key(&column_family) {
+                    let name = format!("{sst_id:05}.sst");
+                    report.corrupted.push((
+                        name.clone(),
+                        format!("the SST belongs to column family {column_family}, which the manifest does not record"),
+                    ));
+                    archived.push(name);
+                    continue;
+                }
+                tables
+                    .entry(column_family)
+                    .or_default()
+                    .push(Arc::new(table));
+            }
+            Err(e) => {
+                let name = format!("{sst_id:05}.sst");
+                report.corrupted.push((name.clone(), format!("{e:#}")));
+                archived.push(name);
+            }
+        }
+    }
+
+    for wal_id in wal_ids {
+        let name = format!("{wal_id:05}.wal");
+        let mut corrupted_frames = Vec::new();
+        let memtables = MemTable::salvage_column_families_from_wal(
+            wal_id,
+            LsmStorageInner::path_of_wal_static(path, wal_id),
+            comparator,
+            &mut corrupted_frames,
+        );
+        for (offset, e) in corrupted_frames {
+            report.corrupted.push((
+                name.clone(),
+                format!("corrupted WAL frame at byte offset {offset}: {e:#}"),
+            ));
+        }
+        let (memtable, family_memtables) = match memtables {
+            Ok(memtables) => memtables,
+            Err(e) => {
+                report.corrupted.push((name.clone(), format!("{e:#}")));
+                archived.push(name);
+                continue;
+            }
+        };
+        let mut family_memtables = family_memtables.into_iter().collect::<Vec<_>>();
+        family_memtables.sort_by_key(|(id, _)| *id);
+        for (column_family, memtable) in std::iter::once((0, memtable)).chain(family_memtables) {
+            if memtable.is_empty() {
+                continue;
+            }
+            if column_family != 0 && !old.column_families.contains_key(&column_family) {
+                report.corrupted.push((
+                    name.clone(),
+                    format!("dropped the entries of column family {column_family}, which the manifest does not record"),
+                ));
+                continue;
+            }
+            let sst_id = next_sst_id;
+            next_sst_id += 1;
+            let (table, has_value_log) =
+                flush_memtable(path, &memtable, sst_id, options, column_family)?;
+            if has_value_log {
+                value_log_ids.push(sst_id);
+            }
+            tables
+                .entry(column_family)
+                .or_default()
+                .push(Arc::new(table));
+        }
+        report.wals += 1;
+        archived.push(name);
+    }
+    File::open(path)?.sync_all()?;
+
+    // The flushed WALs and the corrupted SSTs are moved away before the new manifest is written,
+    // so that running the repair again does not flush the WALs twice.
+    let lost_dir = path.join(LOST_DIR);
+    std::fs::create_dir_all(&lost_dir).context("failed to create the lost directory")?;
+    for name in &archived {
+        std::fs::rename(path.join(name), lost_dir.join(name))
+            .with_context(|| format!("failed to move {name} into {LOST_DIR}"))?;
+    }
+    File::open(path)?.sync_all()?;
+
+    let mut records = vec![ManifestRecord::Comparator(comparator.name().to_string())];
+    for (id, (name, compaction_options)) in &old.column_families {
+        records.push(ManifestRecord::CreateColumnFamily(
+            name.clone(),
+            *id,
+            compaction_options.clone(),
+        ));
+    }
+    report.tables = tables.values().map(|tables| tables.len()).sum();
+    records.push(place_tables(
+        0,
+        &options.compaction_options,
+        tables.remove(&0).unwrap_or_default(),
+        comparator,
+    ));
+    for (id, (_, compaction_options)) in &old.column_families {
+        records.push(place_tables(
+            *id,
+            compaction_options,
+            tables.remove(id).unwrap_or_default(),
+            comparator,
+        ));
+    }
+    value_log_ids.sort();
+    records.push(ManifestRecord::ValueLogFiles(value_log_ids));
+    manifest::replace_manifest(path, &records)?;
+
+    for name in &manifests {
+        std::fs::rename(path.join(name), lost_dir.join(name))
+            .with_context(|| format!("failed to move {name} into {LOST_DIR}"))?;
+    }
+    File::open(path)?.sync_all()?;
+    archived.extend(manifests);
+    report.archived = archived;
+    Ok(report)
+}
@@ -0,0 +1,110 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Opens a database whose files fail their checksums. The corrupted SSTs, WAL frames and manifest
+//! records are left out, and the database is opened read-only with a report of the key ranges
+//! that are unavailable.
+//!
+//! The reads are not filtered by the report: a key in an unavailable range may return an older
+//! version of it from the files that are still available, or nothing at all.
+
+use std::ops::Bound;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+
+use crate::table::SsTable;
+
+/// A range of keys whose latest versions may be lost, because `file` is corrupted. The bounds
+/// are ordered by the comparator of the database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnavailableRange {
+    /// The name of the corrupted file in the database directory.
+    pub file: String,
+    /// The column family of the keys, or `None` for the default column family. It is also `None`
+    /// if the keys of any column family may be lost.
+    pub column_family: Option<String>,
+    pub lower: Bound<Bytes>,
+    pub upper: Bound<Bytes>,
+    pub reason: String,
+}
+
+impl UnavailableRange {
+    /// All the keys of the column family, for a corrupted file whose keys are unknown.
+    pub(crate) fn unbounded(
+        file: impl Into<String>,
+        column_family: Option<&str>,
+        reason: &anyhow::Error,
+    ) -> Self {
+        Self {
+            file: file.into(),
+            column_family: column_family.map(str::to_string),
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+            reason: format!("{reason:#}"),
+        }
+    }
+
+    /// The keys of a corrupted SST, or all the keys if the SST cannot be opened. The range covers
+    /// the keys of the SST and the keys deleted by its range tombstones.
+    pub(crate) fn of_table(
+        sst_id: usize,
+        table: Option<&SsTable>,
+        column_family: Option<&str>,
+        reason: &anyhow::Error,
+    ) -> Self {
+        let mut range = Self::unbounded(format!("{sst_id:05}.sst"), column_family, reason);
+        if let Some(table) = table {
+            let comparator = table.comparator();
+            let mut lower = table.first_key().key_ref();
+            let mut upper = table.last_key().key_ref();
+            for tombstone in table.range_tombstones() {
+                if comparator.compare(&tombstone.start, lower).is_lt() {
+                    lower = &tombstone.start;
+                }
+                if comparator.compare(&tombstone.end, upper).is_gt() {
+                    upper = &tombstone.end;
+                }
+            }
+            range.lower = Bound::Included(Bytes::copy_from_slice(lower));
+            range.upper = Bound::Included(Bytes::copy_from_slice(upper));
+        }
+        range
+    }
+}
+
+/// The files left out when a database is opened read-only with
+/// [`MiniLsm::open_read_only`](crate::lsm_storage::MiniLsm::open_read_only).
+#[derive(Clone, Debug, Default)]
+pub struct SalvageReport {
+    pub unavailable: Vec<UnavailableRange>,
+}
+
+impl SalvageReport {
+    /// Whether every file of the database is available.
+    pub fn is_clean(&self) -> bool {
+        self.unavailable.is_empty()
+    }
+}
+
+/// Read every block of an SST, so that a corrupted block is found when the SST is opened rather
+/// than when it is read.
+pub(crate) fn verify_table(table: &SsTable) -> Result<()> {
+    for block_idx in 0..table.num_of_blocks() {
+        table
+            .read_block(block_idx)
+            .with_context(|| format!("block {block_idx} is corrupted"))?;
+    }
+    Ok(())
+}
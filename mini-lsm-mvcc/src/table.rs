@@ -14,23 +14,49 @@
 
 pub(crate) mod bloom;
 mod builder;
+mod compression;
+mod footer;
+mod index;
 mod iterator;
+mod range_tombstones;
 
 use std::fs::File;
 use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::{Context, Result, anyhow, bail, ensure};
+use anyhow::{Context, Result, bail, ensure};
 pub use builder::SsTableBuilder;
-use bytes::BufMut;
+use bytes::{BufMut, Bytes};
+pub use compression::CompressionType;
+pub use footer::{BlockHandle, Footer, SST_FORMAT_VERSION, SST_MAGIC};
+pub use index::{IndexPartitionMeta, TopLevelIndex};
 pub use iterator::SsTableIterator;
 
-use crate::block::Block;
-use crate::key::{KeyBytes, KeySlice};
+use crate::block::{Block, BlockFormat, BlockIterator};
+use crate::comparator::{BYTEWISE_COMPARATOR, Comparator, is_bytewise};
+use crate::key::{KeyBytes, KeySlice, TS_RANGE_BEGIN};
 use crate::lsm_storage::BlockCache;
+use crate::range_tombstone::RangeTombstone;
 
 use self::bloom::Bloom;
 
+/// The size of the trailer after every block: compression type (u8) and checksum (u32).
+const BLOCK_TRAILER_SIZE: usize = std::mem::size_of::<u8>() + std::mem::size_of::<u32>();
+
+/// The size of the trailer after every block of a format version 0 table, which has no
+/// compression type: checksum (u32).
+const LEGACY_BLOCK_TRAILER_SIZE: usize = std::mem::size_of::<u32>();
+
+/// The size of the trailer after every block of a table with the given format version. The
+/// compression type is part of the trailer since format version 1.
+fn block_trailer_size(format_version: u32) -> usize {
+    if format_version == 0 {
+        LEGACY_BLOCK_TRAILER_SIZE
+    } else {
+        BLOCK_TRAILER_SIZE
+    }
+}
+
 fn take_bytes<'a>(buf: &mut &'a [u8], len: usize, what: &str) -> Result<&'a [u8]> {
     ensure!(buf.len() >= len, "{what} is truncated");
     let (value, rest) = buf.split_at(len);
@@ -55,6 +81,36 @@ fn take_u64(buf: &mut &[u8], what: &str) -> Result<u64> {
     ]))
 }
 
+/// The key range of a table, widened to cover its range tombstones. `keys` is the range of the
+/// stored keys, or `None` if the table has none. A tombstone that starts before the first user key
+/// moves the first key to its start, and one that ends after the last user key moves the last key
+/// to its end. Both are taken at `TS_RANGE_BEGIN`, which sorts before every version of the key, so
+/// a table ending where the next one starts still ends before it.
+pub(crate) fn widen_key_range(
+    keys: Option<(KeyBytes, KeyBytes)>,
+    range_tombstones: &[RangeTombstone],
+    comparator: &'static dyn Comparator,
+) -> Option<(KeyBytes, KeyBytes)> {
+    let bound = |key: &Bytes| KeyBytes::from_bytes_with_ts(key.clone(), TS_RANGE_BEGIN);
+    range_tombstones
+        .iter()
+        .fold(keys, |range, tombstone| match range {
+            None => Some((bound(&tombstone.start), bound(&tombstone.end))),
+            Some((mut first, mut last)) => {
+                if comparator
+                    .compare(&tombstone.start, first.key_ref())
+                    .is_lt()
+                {
+                    first = bound(&tombstone.start);
+                }
+                if comparator.compare(&tombstone.end, last.key_ref()).is_gt() {
+                    last = bound(&tombstone.end);
+                }
+                Some((first, last))
+            }
+        })
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BlockMeta {
     /// Offset of this data block.
@@ -211,20 +267,40 @@ impl FileObject {
     }
 }
 
+/// The block index of an SST.
+enum SsTableIndex {
+    /// Format versions 0 and 1: the metadata of every block, kept in memory.
+    Flat(Vec<BlockMeta>),
+    /// Format version 2 and later: a top-level index in memory, and index partitions that are
+    /// read through the block cache on demand.
+    Partitioned(TopLevelIndex),
+}
+
 /// An SSTable.
 pub struct SsTable {
     /// The actual storage unit of SsTable, the format is as above.
     pub(crate) file: FileObject,
-    /// The meta blocks that hold info for data blocks.
-    pub(crate) block_meta: Vec<BlockMeta>,
+    /// The index that locates the data blocks.
+    index: SsTableIndex,
     /// The offset that indicates the start point of meta blocks in `file`.
     pub(crate) block_meta_offset: usize,
     id: usize,
     block_cache: Option<Arc<BlockCache>>,
     first_key: KeyBytes,
     last_key: KeyBytes,
-    pub(crate) bloom: Option<Bloom>,
+    /// The bloom filter, pinned in memory only when the table has no block cache. Otherwise it is
+    /// read through the metadata cache.
+    pub(crate) bloom: Option<Arc<Bloom>>,
+    /// Location of the bloom filter in `file`.
+    bloom_handle: Option<BlockHandle>,
+    /// The range tombstones of the table, which are always kept in memory.
+    range_tombstones: Vec<RangeTombstone>,
     max_ts: u64,
+    /// The timestamp of every key of the table, which replaces the timestamps stored with the keys.
+    global_ts: Option<u64>,
+    format_version: u32,
+    /// Orders the keys of the table.
+    comparator: &'static dyn Comparator,
 }
 impl SsTable {
     #[cfg(test)]
@@ -232,40 +308,43 @@ impl SsTable {
         Self::open(0, None, file)
     }
 
-    /// Open SSTable from a file.
+    /// Open SSTable from a file, with the keys ordered bytewise.
     pub fn open(id: usize, block_cache: Option<Arc<BlockCache>>, file: FileObject) -> Result<Self> {
-        let len = file.size();
-        let bloom_trailer_offset = len
-            .checked_sub(std::mem::size_of::<u32>() as u64)
-            .context("SST bloom-offset trailer is truncated")?;
-        let raw_bloom_offset = file.read(bloom_trailer_offset, 4)?;
-        let bloom_offset = u32::from_be_bytes([
-            raw_bloom_offset[0],
-            raw_bloom_offset[1],
-            raw_bloom_offset[2],
-            raw_bloom_offset[3],
-        ]) as u64;
-        ensure!(
-            bloom_offset <= bloom_trailer_offset,
-            "SST bloom offset is out of bounds"
-        );
-        let meta_trailer_offset = bloom_offset
-            .checked_sub(std::mem::size_of::<u32>() as u64)
-            .context("SST metadata-offset trailer is truncated")?;
-        let raw_bloom = file.read(bloom_offset, bloom_trailer_offset - bloom_offset)?;
-        let bloom_filter = Bloom::decode(&raw_bloom)?;
-        let raw_meta_offset = file.read(meta_trailer_offset, 4)?;
-        let block_meta_offset = u32::from_be_bytes([
-            raw_meta_offset[0],
-            raw_meta_offset[1],
-            raw_meta_offset[2],
-            raw_meta_offset[3],
-        ]) as u64;
-        ensure!(
-            block_meta_offset <= meta_trailer_offset,
-            "SST block-metadata offset is out of bounds"
-        );
-        let raw_meta = file.read(block_meta_offset, meta_trailer_offset - block_meta_offset)?;
+        Self::open_with_comparator(id, block_cache, file, &BYTEWISE_COMPARATOR)
+    }
+
+    /// Open SSTable from a file. The table must have been written with `comparator`.
+    pub fn open_with_comparator(
+        id: usize,
+        block_cache: Option<Arc<BlockCache>>,
+        file: FileObject,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self> {
+        let footer = Footer::read(&file)?;
+        match footer.version {
+            0 | 1 => {
+                ensure!(
+                    is_bytewise(comparator),
+                    "SST format version {} is ordered bytewise, not by {:?}",
+                    footer.version,
+                    comparator.name()
+                );
+                Self::open_v1(id, block_cache, file, footer)
+            }
+            2..=11 => Self::open_v2(id, block_cache, file, footer, comparator),
+            version => bail!("unsupported SST format version {version}"),
+        }
+    }
+
+    fn open_v1(
+        id: usize,
+        block_cache: Option<Arc<BlockCache>>,
+        file: FileObject,
+        footer: Footer,
+    ) -> Result<Self> {
+        let bloom = Self::load_bloom(id, block_cache.as_deref(), &file, footer.bloom)?;
+        let block_meta_offset = footer.index.offset;
+        let raw_meta = file.read(block_meta_offset, footer.index.len)?;
         let (block_meta, max_ts) = BlockMeta::decode_block_meta(&raw_meta[..])?;
         ensure!(!block_meta.is_empty(), "SST has no data blocks");
         ensure!(
@@ -283,25 +362,86 @@ impl SsTable {
         ensure!(
             block_meta.iter().all(|meta| {
                 meta.offset
-                    .checked_add(std::mem::size_of::<u32>())
+                    .checked_add(block_trailer_size(footer.version))
                     .is_some_and(|end| end < block_meta_offset_usize)
             }),
-            "SST block offset or checksum is out of bounds"
+            "SST block offset or trailer is out of bounds"
         );
         Ok(Self {
             file,
             first_key: block_meta[0].first_key.clone(),
             last_key: block_meta[block_meta.len() - 1].last_key.clone(),
-            block_meta,
+            index: SsTableIndex::Flat(block_meta),
             block_meta_offset: block_meta_offset_usize,
             id,
             block_cache,
-            bloom: Some(bloom_filter),
+            bloom,
+            bloom_handle: Some(footer.bloom),
+            range_tombstones: Vec::new(),
             max_ts,
+            global_ts: None,
+            format_version: footer.version,
+            comparator: &BYTEWISE_COMPARATOR,
         })
     }
 
-    /// Create a mock SST with only first key + last key metadata
+    fn open_v2(
+        id: usize,
+        block_cache: Option<Arc<BlockCache>>,
+        file: FileObject,
+        footer: Footer,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self> {
+        let bloom = Self::load_bloom(id, block_cache.as_deref(), &file, footer.bloom)?;
+        let raw_index = file.read(footer.index.offset, footer.index.len)?;
+        let index =
+            TopLevelIndex::decode(&raw_index, footer.index.offset, footer.version, comparator)?;
+        let range_tombstones = match footer.range_tombstones {
+            Some(handle) => {
+                range_tombstones::decode_range_tombstones(&file.read(handle.offset, handle.len)?)?
+            }
+            None => Vec::new(),
+        };
+        // The partitions are written right after the data blocks, so the first partition starts
+        // where the data ends. A table without data blocks has no partitions either.
+        let data_end = index.partitions.first().map_or(0, |p| p.handle.offset);
+        let keys = index
+            .partitions
+            .first()
+            .zip(index.partitions.last())
+            .map(|(first, last)| (first.first_key.clone(), last.last_key.clone()));
+        let (mut first_key, mut last_key) = widen_key_range(keys, &range_tombstones, comparator)
+            .context("SST has neither keys nor range tombstones")?;
+        let mut max_ts = index.max_ts;
+        if let Some(ts) = footer.global_ts {
+            ensure!(
+                range_tombstones.is_empty(),
+                "SST with a global timestamp has range tombstones"
+            );
+            first_key = Self::key_with_global_ts(first_key, ts);
+            last_key = Self::key_with_global_ts(last_key, ts);
+            max_ts = ts;
+        }
+        Ok(Self {
+            file,
+            first_key,
+            last_key,
+            max_ts,
+            global_ts: footer.global_ts,
+            index: SsTableIndex::Partitioned(index),
+            block_meta_offset: usize::try_from(data_end)
+                .context("SST metadata offset is too large")?,
+            id,
+            block_cache,
+            bloom,
+            bloom_handle: Some(footer.bloom),
+            range_tombstones,
+            format_version: footer.version,
+            comparator,
+        })
+    }
+
+    /// Create a mock SST with only first key + last key metadata, ordered bytewise.
     pub fn create_meta_only(
         id: usize,
         file_size: u64,
@@ -310,70 +450,264 @@ impl SsTable {
     ) -> Self {
         Self {
             file: FileObject(None, file_size),
-            block_meta: vec![],
+            index: SsTableIndex::Flat(vec![]),
             block_meta_offset: 0,
             id,
             block_cache: None,
             first_key,
             last_key,
             bloom: None,
+            bloom_handle: None,
+            range_tombstones: Vec::new(),
             max_ts: 0,
+            global_ts: None,
+            format_version: SST_FORMAT_VERSION,
+            comparator: &BYTEWISE_COMPARATOR,
         }
     }
 
-    /// Read a block from the disk.
-    pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
-        let offset = self
-            .block_meta
-            .get(block_idx)
-            .context("SST block index is out of bounds")?
-            .offset;
-        let offset_end = self
-            .block_meta
-            .get(block_idx + 1)
-            .map_or(self.block_meta_offset, |x| x.offset);
-        let range_len = offset_end
-            .checked_sub(offset)
-            .context("SST block offsets are reversed")?;
-        let block_len = range_len
-            .checked_sub(std::mem::size_of::<u32>())
-            .context("SST block checksum is truncated")?;
-        let block_data_with_chksum: Vec<u8> = self.file.read(offset as u64, range_len as u64)?;
-        let block_data = &block_data_with_chksum[..block_len];
+    fn key_with_global_ts(key: KeyBytes, ts: u64) -> KeyBytes {
+        KeyBytes::from_bytes_with_ts(key.into_inner(), ts)
+    }
+
+    /// Decode the bloom filter when opening a table. The filter is validated either way, but it is
+    /// only pinned in the table if there is no block cache to hold it.
+    fn load_bloom(
+        id: usize,
+        block_cache: Option<&BlockCache>,
+        file: &FileObject,
+        handle: BlockHandle,
+    ) -> Result<Option<Arc<Bloom>>> {
+        let bloom = Arc::new(Bloom::decode(&file.read(handle.offset, handle.len)?)?);
+        if let Some(block_cache) = block_cache {
+            block_cache.insert_bloom(id, bloom);
+            Ok(None)
+        } else {
+            Ok(Some(bloom))
+        }
+    }
+
+    /// Get the bloom filter of the table, if it has one.
+    pub fn bloom(&self) -> Result<Option<Arc<Bloom>>> {
+        if let Some(ref bloom) = self.bloom {
+            return Ok(Some(bloom.clone()));
+        }
+        let (Some(handle), Some(block_cache)) = (self.bloom_handle, &self.block_cache) else {
+            return Ok(None);
+        };
+        let bloom = block_cache.get_bloom(self.id, || {
+            Ok(Arc::new(Bloom::decode(
+                &self.file.read(handle.offset, handle.len)?,
+            )?))
+        })?;
+        Ok(Some(bloom))
+    }
+
+    /// Read a block (a data block or an index partition) at the given location, verifying its
+    /// checksum and decompressing it.
+    fn read_block_at(&self, handle: BlockHandle) -> Result<Block> {
+        let trailer_size = block_trailer_size(self.format_version);
+        let block_len = usize::try_from(handle.len)
+            .ok()
+            .and_then(|len| len.checked_sub(trailer_size))
+            .context("SST block trailer is truncated")?;
+        let block_data_with_chksum: Vec<u8> = self.file.read(handle.offset, handle.len)?;
+        let checksum_offset = block_data_with_chksum.len() - std::mem::size_of::<u32>();
         let checksum = u32::from_be_bytes([
-            block_data_with_chksum[block_len],
-            block_data_with_chksum[block_len + 1],
-            block_data_with_chksum[block_len + 2],
-            block_data_with_chksum[block_len + 3],
+            block_data_with_chksum[checksum_offset],
+            block_data_with_chksum[checksum_offset + 1],
+            block_data_with_chksum[checksum_offset + 2],
+            block_data_with_chksum[checksum_offset + 3],
         ]);
-        if checksum != crc32fast::hash(block_data) {
+        if self.format_version == 0 {
+            // Format version 0 checksums the block data only, and has no compression type.
+            if checksum != crc32fast::hash(&block_data_with_chksum[..block_len]) {
+                bail!("block checksum mismatched");
+            }
+            return Block::decode_checked(
+                &block_data_with_chksum[..block_len],
+                self.block_format(),
+                self.comparator,
+            );
+        }
+        if checksum != crc32fast::hash(&block_data_with_chksum[..checksum_offset]) {
             bail!("block checksum mismatched");
         }
-        Ok(Arc::new(Block::decode_checked(block_data)?))
+        let compression = CompressionType::from_u8(block_data_with_chksum[block_len])?;
+        let block_data = compression.decompress(&block_data_with_chksum[..block_len])?;
+        Block::decode_checked(&block_data, self.block_format(), self.comparator)
+    }
+
+    /// The encoding of the blocks in this table.
+    fn block_format(&self) -> BlockFormat {
+        match self.format_version {
+            0..=3 => BlockFormat::Fixed,
+            4 => BlockFormat::Varint,
+            5..=8 => BlockFormat::Restart,
+            _ => BlockFormat::RestartWithKind,
+        }
+    }
+
+    /// Read an index partition from the disk.
+    fn read_index_partition(&self, index: &TopLevelIndex, partition_idx: usize) -> Result<Block> {
+        let partition = index.partitions[partition_idx].handle;
+        let block = self.read_block_at(partition)?;
+        ensure!(
+            block.offsets.len() == index.partition_blocks(partition_idx).len(),
+            "SST index partition has a wrong number of entries"
+        );
+        Ok(block)
+    }
+
+    /// Read an index partition, with block cache.
+    fn read_index_partition_cached(
+        &self,
+        index: &TopLevelIndex,
+        partition_idx: usize,
+    ) -> Result<Arc<Block>> {
+        if let Some(ref block_cache) = self.block_cache {
+            block_cache.get_index_partition(self.id, partition_idx, || {
+                self.read_index_partition(index, partition_idx)
+                    .map(Arc::new)
+            })
+        } else {
+            Ok(Arc::new(self.read_index_partition(index, partition_idx)?))
+        }
+    }
+
+    /// Look up the location of a data block.
+    fn block_handle(&self, block_idx: usize) -> Result<BlockHandle> {
+        match &self.index {
+            SsTableIndex::Flat(block_meta) => {
+                let offset = block_meta
+                    .get(block_idx)
+                    .context("SST block index is out of bounds")?
+                    .offset;
+                let offset_end = block_meta
+                    .get(block_idx + 1)
+                    .map_or(self.block_meta_offset, |x| x.offset);
+                let len = offset_end
+                    .checked_sub(offset)
+                    .context("SST block offsets are reversed")?;
+                Ok(BlockHandle {
+                    offset: offset as u64,
+                    len: len as u64,
+                })
+            }
+            SsTableIndex::Partitioned(index) => {
+                ensure!(
+                    block_idx < index.num_blocks,
+                    "SST block index is out of bounds"
+                );
+                let partition_idx = index.partition_of_block(block_idx);
+                let partition = self.read_index_partition_cached(index, partition_idx)?;
+                let iter = BlockIterator::create_and_seek_to_idx(
+                    partition,
+                    block_idx - index.partitions[partition_idx].first_block_idx,
+                );
+                let handle = index::decode_index_entry(iter.value())?;
+                ensure!(
+                    handle.end()? <= self.block_meta_offset as u64,
+                    "SST block offset or trailer is out of bounds"
+                );
+                Ok(handle)
+            }
+        }
+    }
+
+    /// Get the metadata of a data block. Only the metadata of version 0 and 1 tables is kept in
+    /// memory;
+    /// for later versions this reads the data block.
+    pub fn block_meta(&self, block_idx: usize) -> Result<BlockMeta> {
+        if let SsTableIndex::Flat(block_meta) = &self.index {
+            return block_meta
+                .get(block_idx)
+                .cloned()
+                .context("SST block index is out of bounds");
+        }
+        let handle = self.block_handle(block_idx)?;
+        let mut iter = BlockIterator::create_and_seek_to_first(self.read_block_cached(block_idx)?);
+        let mut first_key = iter.key().to_key_vec().into_key_bytes();
+        let mut last_key = first_key.clone();
+        while iter.is_valid() {
+            last_key = iter.key().to_key_vec().into_key_bytes();
+            iter.next();
+        }
+        if let Some(ts) = self.global_ts {
+            first_key = Self::key_with_global_ts(first_key, ts);
+            last_key = Self::key_with_global_ts(last_key, ts);
+        }
+        Ok(BlockMeta {
+            offset: usize::try_from(handle.offset).context("SST block offset is too large")?,
+            first_key,
+            last_key,
+        })
+    }
+
+    /// Read a block from the disk.
+    pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
+        let handle = self.block_handle(block_idx)?;
+        Ok(Arc::new(self.read_block_at(handle)?))
     }
 
     /// Read a block from disk, with block cache.
     pub fn read_block_cached(&self, block_idx: usize) -> Result<Arc<Block>> {
         if let Some(ref block_cache) = self.block_cache {
-            let blk = block_cache
-                .try_get_with((self.id, block_idx), || self.read_block(block_idx))
-                .map_err(|e| anyhow!("{}", e))?;
-            Ok(blk)
+            block_cache.get_data_block(self.id, block_idx, || self.read_block(block_idx))
         } else {
             self.read_block(block_idx)
         }
     }
 
     /// Find the block that may contain `key`.
-    pub fn find_block_idx(&self, key: KeySlice) -> usize {
-        self.block_meta
-            .partition_point(|meta| meta.first_key.as_key_slice() <= key)
-            .saturating_sub(1)
+    pub fn find_block_idx(&self, key: KeySlice) -> Result<usize> {
+        match &self.index {
+            SsTableIndex::Flat(block_meta) => Ok(block_meta
+                .partition_point(|meta| {
+                    self.comparator
+                        .compare_keys(meta.first_key.as_key_slice(), key)
+                        .is_le()
+                })
+                .saturating_sub(1)),
+            SsTableIndex::Partitioned(index) => {
+                let partition_idx = index.partition_of_key(key, self.comparator);
+                let partition = self.read_index_partition_cached(index, partition_idx)?;
+                let num_entries = partition.offsets.len();
+                let iter = BlockIterator::create_and_seek_to_key(partition, key);
+                // Keys after the end of the table map to the last block.
+                let entry_idx = if iter.is_valid() {
+                    iter.entry_idx()
+                } else {
+                    num_entries - 1
+                };
+                Ok(index.partitions[partition_idx].first_block_idx + entry_idx)
+            }
+        }
     }
 
     /// Get number of data blocks.
     pub fn num_of_blocks(&self) -> usize {
-        self.block_meta.len()
+        match &self.index {
+            SsTableIndex::Flat(block_meta) => block_meta.len(),
+            SsTableIndex::Partitioned(index) => index.num_blocks,
+        }
+    }
+
+    /// Get the number of index partitions, or `None` if the index is not partitioned.
+    pub fn num_of_index_partitions(&self) -> Option<usize> {
+        match &self.index {
+            SsTableIndex::Flat(_) => None,
+            SsTableIndex::Partitioned(index) => Some(index.partitions.len()),
+        }
+    }
+
+    /// The id of the column family that the table belongs to, or `None` if the table does not
+    /// record it.
+    pub fn column_family(&self) -> Option<u32> {
+        match &self.index {
+            SsTableIndex::Flat(_) => None,
+            SsTableIndex::Partitioned(index) => index.column_family,
+        }
     }
 
     pub fn first_key(&self) -> &KeyBytes {
@@ -395,4 +729,32 @@ impl SsTable {
     pub fn max_ts(&self) -> u64 {
         self.max_ts
     }
+
+    /// The timestamp of every key of the table, if the table was ingested with one.
+    pub fn global_ts(&self) -> Option<u64> {
+        self.global_ts
+    }
+
+    /// The name of the prefix extractor whose prefixes are in the bloom filter of the table.
+    pub fn prefix_extractor(&self) -> Option<&str> {
+        match &self.index {
+            SsTableIndex::Flat(_) => None,
+            SsTableIndex::Partitioned(index) => index.prefix_extractor.as_deref(),
+        }
+    }
+
+    /// The range tombstones of the table. They may cover keys outside of the key range of the
+    /// table.
+    pub fn range_tombstones(&self) -> &[RangeTombstone] {
+        &self.range_tombstones
+    }
+
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    /// The comparator that orders the keys of the table.
+    pub fn comparator(&self) -> &'static dyn Comparator {
+        self.comparator
+    }
 }
@@ -15,14 +15,24 @@
 use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::{Result, ensure};
 use bytes::BufMut;
 
 use super::bloom::Bloom;
-use super::{BlockMeta, FileObject, SsTable};
-use crate::block::BlockBuilder;
+use super::index::{self, IndexPartitionMeta, TopLevelIndex};
+use super::range_tombstones;
+use super::{
+    BlockHandle, BlockMeta, CompressionType, FileObject, Footer, SST_FORMAT_VERSION, SsTable,
+    SsTableIndex, widen_key_range,
+};
+use crate::block::{BlockBuilder, DEFAULT_RESTART_INTERVAL};
+use crate::comparator::{BYTEWISE_COMPARATOR, Comparator};
+use crate::iterators::ValueKind;
 use crate::key::{KeySlice, KeyVec};
-use crate::lsm_storage::BlockCache;
+use crate::lsm_storage::{BlockCache, LsmStorageOptions};
+use crate::prefix::PrefixExtractor;
+use crate::range_tombstone::RangeTombstone;
+use crate::value_log::ValueLogWriter;
 
 /// Builds an SSTable from key-value pairs.
 pub struct SsTableBuilder {
@@ -32,8 +42,22 @@ pub struct SsTableBuilder {
     data: Vec<u8>,
     pub(crate) meta: Vec<BlockMeta>,
     block_size: usize,
+    restart_interval: usize,
+    hash_index: bool,
     key_hashes: Vec<u32>,
+    prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    /// The prefix of the last key that has a prefix.
+    last_prefix: Vec<u8>,
+    range_tombstones: Vec<RangeTombstone>,
     max_ts: u64,
+    compression: CompressionType,
+    value_log: Option<ValueLogWriter>,
+    /// Orders the keys of the table. The keys must be added in this order.
+    comparator: &'static dyn Comparator,
+    /// The first error while adding keys, returned by `build`. Keys added after it are dropped.
+    error: Option<anyhow::Error>,
+    /// The id of the column family that the table belongs to.
+    column_family: u32,
 }
 
 impl SsTableBuilder {
@@ -45,14 +69,97 @@ impl SsTableBuilder {
             first_key: KeyVec::new(),
             last_key: KeyVec::new(),
             block_size,
+            restart_interval: DEFAULT_RESTART_INTERVAL,
+            hash_index: false,
             builder: BlockBuilder::new(block_size),
             key_hashes: Vec::new(),
+            prefix_extractor: None,
+            last_prefix: Vec::new(),
+            range_tombstones: Vec::new(),
             max_ts: 0,
+            compression: CompressionType::None,
+            value_log: None,
+            comparator: &BYTEWISE_COMPARATOR,
+            error: None,
+            column_family: 0,
         }
     }
 
-    /// Adds a key-value pair to SSTable
+    /// Create a builder with the block size, restart interval, hash index, compression codec,
+    /// prefix extractor and comparator of the storage options.
+    pub fn new_with_options(options: &LsmStorageOptions) -> Self {
+        let mut builder = Self::new(options.block_size);
+        builder.comparator = options.comparator;
+        builder.prefix_extractor = options.prefix_extractor.clone();
+        builder.restart_interval = options.block_restart_interval;
+        builder.hash_index = options.data_block_hash_index;
+        builder.builder = builder.new_block_builder();
+        builder.compression = options.compression;
+        builder
+    }
+
+    /// Create a builder that also moves the values at least as large as the value separation
+    /// threshold to a value log file. The file shares its id with the SST, which must be built with
+    /// `id`.
+    pub fn new_with_value_log(options: &LsmStorageOptions, id: usize) -> Self {
+        let mut builder = Self::new_with_options(options);
+        builder.value_log = options
+            .value_separation_threshold
+            .map(|threshold| ValueLogWriter::new(id, threshold));
+        builder
+    }
+
+    /// Record that the table belongs to the column family with the id, instead of the default one.
+    pub fn with_column_family(mut self, column_family: u32) -> Self {
+        self.column_family = column_family;
+        self
+    }
+
+    /// Adds a key-value pair to SSTable. If the key cannot be added, the error is returned by
+    /// `build`.
     pub fn add(&mut self, key: KeySlice, value: &[u8]) {
+        if let Some(value_log) = self.value_log.as_mut()
+            && value_log.should_separate(key, value)
+        {
+            match value_log.add(key, value) {
+                Ok(pointer) => self.add_entry(key, ValueKind::ValuePointer, &pointer.encode()),
+                Err(e) => {
+                    self.error.get_or_insert(e);
+                }
+            }
+        } else {
+            self.add_entry(key, ValueKind::Value, value);
+        }
+    }
+
+    /// Adds an entry whose value is stored as `kind`. Value pointers, merge operands and expiring
+    /// values are copied as they are.
+    pub fn add_with_kind(&mut self, key: KeySlice, kind: ValueKind, value: &[u8]) {
+        match kind {
+            ValueKind::Value => self.add(key, value),
+            ValueKind::ValuePointer | ValueKind::MergeOperand | ValueKind::ExpiringValue => {
+                self.add_entry(key, kind, value)
+            }
+        }
+    }
+
+    /// Adds a range tombstone to the SSTable. The key range of the table is widened to cover it.
+    pub fn add_range_tombstone(&mut self, tombstone: RangeTombstone) {
+        self.max_ts = self.max_ts.max(tombstone.ts);
+        self.range_tombstones.push(tombstone);
+    }
+
+    /// Returns true if some values have been moved to the value log file.
+    pub fn has_separated_values(&self) -> bool {
+        self.value_log
+            .as_ref()
+            .is_some_and(|value_log| !value_log.is_empty())
+    }
+
+    fn add_entry(&mut self, key: KeySlice, kind: ValueKind, value: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
         if self.first_key.is_empty() {
             self.first_key.set_from_slice(key);
         }
@@ -61,17 +168,29 @@ impl SsTableBuilder {
             self.max_ts = key.ts();
         }
         self.key_hashes.push(farmhash::fingerprint32(key.key_ref()));
+        if let Some(prefix) = self
+            .prefix_extractor
+            .as_ref()
+            .and_then(|extractor| extractor.prefix(key.key_ref()))
+            && prefix != self.last_prefix
+        {
+            self.key_hashes.push(farmhash::fingerprint32(prefix));
+            self.last_prefix = prefix.to_vec();
+        }
 
-        if self.builder.add(key, value) {
+        if self.builder.add_with_kind(key, kind, value) {
             self.last_key.set_from_slice(key);
             return;
         }
 
         // create a new block builder and append block data
-        self.finish_block();
+        if let Err(e) = self.finish_block() {
+            self.error = Some(e);
+            return;
+        }
 
         // add the key-value pair to the next block
-        assert!(self.builder.add(key, value));
+        assert!(self.builder.add_with_kind(key, kind, value));
         self.first_key.set_from_slice(key);
         self.last_key.set_from_slice(key);
     }
@@ -81,49 +200,206 @@ impl SsTableBuilder {
         self.data.len()
     }
 
-    fn finish_block(&mut self) {
-        let builder = std::mem::replace(&mut self.builder, BlockBuilder::new(self.block_size));
+    /// Append an encoded block and its trailer to `buf`. The block is kept uncompressed if the
+    /// codec does not make it smaller.
+    fn write_block(
+        buf: &mut Vec<u8>,
+        compression: CompressionType,
+        encoded_block: &[u8],
+    ) -> Result<()> {
+        let block_start = buf.len();
+        match compression.compress(encoded_block)? {
+            Some(compressed) if compressed.len() < encoded_block.len() => {
+                buf.extend(compressed);
+                buf.put_u8(compression.to_u8());
+            }
+            _ => {
+                buf.extend(encoded_block);
+                buf.put_u8(CompressionType::None.to_u8());
+            }
+        }
+        let checksum = crc32fast::hash(&buf[block_start..]);
+        buf.put_u32(checksum);
+        Ok(())
+    }
+
+    fn new_block_builder(&self) -> BlockBuilder {
+        if self.hash_index {
+            BlockBuilder::new_with_hash_index(self.block_size, self.restart_interval)
+        } else {
+            BlockBuilder::new_with_restart_interval(self.block_size, self.restart_interval)
+        }
+    }
+
+    fn finish_block(&mut self) -> Result<()> {
+        let new_builder = self.new_block_builder();
+        let builder = std::mem::replace(&mut self.builder, new_builder);
         let encoded_block = builder.build().encode();
         self.meta.push(BlockMeta {
             offset: self.data.len(),
             first_key: std::mem::take(&mut self.first_key).into_key_bytes(),
             last_key: std::mem::take(&mut self.last_key).into_key_bytes(),
         });
-        let checksum = crc32fast::hash(&encoded_block);
-        self.data.extend(encoded_block);
-        self.data.put_u32(checksum);
+        Self::write_block(&mut self.data, self.compression, &encoded_block)
+    }
+
+    /// Write the index partitions after the data blocks, and return the top-level index. Each
+    /// partition is filled up to the block size. Every key in a partition is a restart point, so
+    /// looking up a block is a plain binary search.
+    fn write_index_partitions(&self, buf: &mut Vec<u8>) -> Result<TopLevelIndex> {
+        let data_end = buf.len();
+        let mut partitions = Vec::new();
+        let mut builder = BlockBuilder::new_with_restart_interval(self.block_size, 1);
+        let mut first_block_idx = 0;
+        for (block_idx, meta) in self.meta.iter().enumerate() {
+            let block_end = self.meta.get(block_idx + 1).map_or(data_end, |x| x.offset);
+            let handle = BlockHandle {
+                offset: meta.offset as u64,
+                len: (block_end - meta.offset) as u64,
+            };
+            let entry = index::encode_index_entry(handle);
+            if builder.add(meta.last_key.as_key_slice(), &entry) {
+                continue;
+            }
+            let full = std::mem::replace(
+                &mut builder,
+                BlockBuilder::new_with_restart_interval(self.block_size, 1),
+            );
+            partitions.push(self.finish_index_partition(buf, full, first_block_idx, block_idx)?);
+            first_block_idx = block_idx;
+            ensure!(
+                builder.add(meta.last_key.as_key_slice(), &entry),
+                "SST index entry is too large"
+            );
+        }
+        if !self.meta.is_empty() {
+            partitions.push(self.finish_index_partition(
+                buf,
+                builder,
+                first_block_idx,
+                self.meta.len(),
+            )?);
+        }
+        Ok(TopLevelIndex {
+            partitions,
+            num_blocks: self.meta.len(),
+            max_ts: self.max_ts,
+            prefix_extractor: self
+                .prefix_extractor
+                .as_ref()
+                .map(|extractor| extractor.name()),
+            comparator: self.comparator.name().to_string(),
+            column_family: Some(self.column_family),
+        })
+    }
+
+    fn finish_index_partition(
+        &self,
+        buf: &mut Vec<u8>,
+        builder: BlockBuilder,
+        first_block_idx: usize,
+        end_block_idx: usize,
+    ) -> Result<IndexPartitionMeta> {
+        let offset = buf.len();
+        Self::write_block(buf, self.compression, &builder.build().encode())?;
+        Ok(IndexPartitionMeta {
+            handle: BlockHandle {
+                offset: offset as u64,
+                len: (buf.len() - offset) as u64,
+            },
+            first_block_idx,
+            first_key: self.meta[first_block_idx].first_key.clone(),
+            last_key: self.meta[end_block_idx - 1].last_key.clone(),
+        })
     }
 
     /// Builds the SSTable and writes it to the given path. Use the `FileObject` structure to manipulate the disk objects.
+    /// The value log file, if any, is written next to the SST before the SST itself.
     pub fn build(
         mut self,
         id: usize,
         block_cache: Option<Arc<BlockCache>>,
         path: impl AsRef<Path>,
     ) -> Result<SsTable> {
-        self.finish_block();
-        let mut buf = self.data;
-        let meta_offset = buf.len();
-        BlockMeta::encode_block_meta(&self.meta, self.max_ts, &mut buf)?;
-        buf.put_u32(u32::try_from(meta_offset).context("SST metadata offset is too large")?);
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        if let Some(value_log) = self.value_log.take()
+            && !value_log.is_empty()
+        {
+            ensure!(
+                value_log.file_id() == id,
+                "value log file must share its id with the SST"
+            );
+            value_log.finish(&path.as_ref().with_extension("vlog"))?;
+        }
+        if !self.builder.is_empty() {
+            self.finish_block()?;
+        }
+        ensure!(
+            !self.meta.is_empty() || !self.range_tombstones.is_empty(),
+            "SST has neither keys nor range tombstones"
+        );
+        let mut buf = std::mem::take(&mut self.data);
+        let data_end = buf.len();
+        let index = self.write_index_partitions(&mut buf)?;
+        let index_offset = buf.len();
+        index.encode(&mut buf)?;
         let bloom = Bloom::build_from_key_hashes(
             &self.key_hashes,
             Bloom::bloom_bits_per_key(self.key_hashes.len(), 0.01),
         );
         let bloom_offset = buf.len();
         bloom.encode(&mut buf);
-        buf.put_u32(u32::try_from(bloom_offset).context("SST bloom offset is too large")?);
+        let range_tombstones_offset = buf.len();
+        range_tombstones::encode_range_tombstones(&self.range_tombstones, &mut buf)?;
+        let footer = Footer {
+            version: SST_FORMAT_VERSION,
+            index: BlockHandle {
+                offset: index_offset as u64,
+                len: (bloom_offset - index_offset) as u64,
+            },
+            bloom: BlockHandle {
+                offset: bloom_offset as u64,
+                len: (range_tombstones_offset - bloom_offset) as u64,
+            },
+            range_tombstones: Some(BlockHandle {
+                offset: range_tombstones_offset as u64,
+                len: (buf.len() - range_tombstones_offset) as u64,
+            }),
+            global_ts: None,
+        };
+        footer.encode(&mut buf);
         let file = FileObject::create(path.as_ref(), buf)?;
+        let bloom = Arc::new(bloom);
+        let pinned_bloom = if let Some(ref block_cache) = block_cache {
+            block_cache.insert_bloom(id, bloom);
+            None
+        } else {
+            Some(bloom)
+        };
+        let keys = self
+            .meta
+            .first()
+            .zip(self.meta.last())
+            .map(|(first, last)| (first.first_key.clone(), last.last_key.clone()));
+        let (first_key, last_key) = widen_key_range(keys, &self.range_tombstones, self.comparator)
+            .expect("the table has keys or range tombstones");
         Ok(SsTable {
             id,
             file,
-            first_key: self.meta.first().unwrap().first_key.clone(),
-            last_key: self.meta.last().unwrap().last_key.clone(),
-            block_meta: self.meta,
-            block_meta_offset: meta_offset,
+            first_key,
+            last_key,
+            index: SsTableIndex::Partitioned(index),
+            block_meta_offset: data_end,
             block_cache,
-            bloom: Some(bloom),
+            bloom: pinned_bloom,
+            bloom_handle: Some(footer.bloom),
+            range_tombstones: self.range_tombstones,
             max_ts: self.max_ts,
+            global_ts: None,
+            format_version: SST_FORMAT_VERSION,
+            comparator: self.comparator,
         })
     }
 
@@ -0,0 +1,80 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result, bail};
+
+/// The codec used to compress a data block. The codec is recorded as a single byte after every
+/// block, so tables written with different settings (or blocks that were stored uncompressed
+/// because they did not shrink) can always be read back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionType {
+    #[default]
+    None,
+    Lz4,
+    Snappy,
+    Zstd,
+}
+
+impl CompressionType {
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            CompressionType::None => 0,
+            CompressionType::Lz4 => 1,
+            CompressionType::Snappy => 2,
+            CompressionType::Zstd => 3,
+        }
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Result<Self> {
+        Ok(match value {
+            0 => CompressionType::None,
+            1 => CompressionType::Lz4,
+            2 => CompressionType::Snappy,
+            3 => CompressionType::Zstd,
+            _ => bail!("unknown block compression type {value}"),
+        })
+    }
+
+    /// Compress the encoded block. Returns `None` if this codec does not compress anything.
+    pub(crate) fn compress(self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(match self {
+            CompressionType::None => None,
+            CompressionType::Lz4 => Some(lz4_flex::compress_prepend_size(data)),
+            CompressionType::Snappy => Some(
+                snap::raw::Encoder::new()
+                    .compress_vec(data)
+                    .context("failed to compress block with snappy")?,
+            ),
+            CompressionType::Zstd => Some(
+                zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL)
+                    .context("failed to compress block with zstd")?,
+            ),
+        })
+    }
+
+    /// Decompress a block payload written with this codec.
+    pub(crate) fn decompress(self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            CompressionType::None => data.to_vec(),
+            CompressionType::Lz4 => lz4_flex::decompress_size_prepended(data)
+                .context("failed to decompress lz4 block")?,
+            CompressionType::Snappy => snap::raw::Decoder::new()
+                .decompress_vec(data)
+                .context("failed to decompress snappy block")?,
+            CompressionType::Zstd => {
+                zstd::stream::decode_all(data).context("failed to decompress zstd block")?
+            }
+        })
+    }
+}
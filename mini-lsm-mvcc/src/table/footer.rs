@@ -0,0 +1,264 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail, ensure};
+use bytes::BufMut;
+
+use super::{FileObject, take_u32, take_u64};
+
+/// "mini-lsm" in ASCII. Every SST ends with this magic number.
+pub const SST_MAGIC: u64 = 0x6d69_6e69_2d6c_736d;
+
+/// The format version written by this build. Version 3 has the same layout as version 2, and
+/// stores the value kind of every entry in the top byte of its timestamp. Version 4 encodes the
+/// lengths of keys and values in blocks and in the top-level index as varints. Version 5 adds
+/// restart points to the blocks, and version 6 records the prefix extractor in the top-level index.
+/// Version 7 adds the range tombstone block, and version 8 records the comparator in the top-level
+/// index. Version 9 stores the value kind of every entry in its own byte after the timestamp, and
+/// allows a table that only has range tombstones and no data blocks. Version 10 records the column
+/// family of the table in the top-level index, and version 11 adds the global timestamp to the
+/// footer.
+pub const SST_FORMAT_VERSION: u32 = 11;
+
+/// The size of the part of the footer that does not depend on the format version: format version,
+/// footer checksum and magic number.
+const FOOTER_TRAILER_SIZE: usize =
+    std::mem::size_of::<u32>() + std::mem::size_of::<u32>() + std::mem::size_of::<u64>();
+
+/// The location of a section in the SST file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockHandle {
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl BlockHandle {
+    const ENCODED_SIZE: usize = std::mem::size_of::<u64>() * 2;
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.put_u64(self.offset);
+        buf.put_u64(self.len);
+    }
+
+    fn decode(buf: &mut &[u8], what: &str) -> Result<Self> {
+        let offset = take_u64(buf, what)?;
+        let len = take_u64(buf, what)?;
+        Ok(Self { offset, len })
+    }
+
+    pub fn end(&self) -> Result<u64> {
+        self.offset
+            .checked_add(self.len)
+            .context("SST section range overflow")
+    }
+}
+
+/// The fixed-size footer at the end of every SST.
+///
+/// ```text
+/// | index handle | bloom handle | range tombstones handle | global ts (u64) | format version (u32) | checksum (u32) | magic (u64) |
+/// ```
+///
+/// The range tombstones handle is written since version 7, and the global timestamp since version
+/// 11, where 0 means that the table has none.
+///
+/// The magic number and the format version are always the last 16 bytes of the file, so a reader
+/// can identify the file and then decode the rest of the footer according to its version. The
+/// checksum covers everything in the footer before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Footer {
+    pub version: u32,
+    /// The block metadata in versions 0 and 1, or the top-level index in later versions.
+    pub index: BlockHandle,
+    pub bloom: BlockHandle,
+    /// The range tombstone block, since version 7.
+    pub range_tombstones: Option<BlockHandle>,
+    /// The timestamp of every key of the table, instead of the timestamps stored with the keys,
+    /// since version 11. It is set on an ingested table once the table has been written.
+    pub global_ts: Option<u64>,
+}
+
+impl Footer {
+    /// The size of the encoded footer of a given format version.
+    fn encoded_size(version: u32) -> Result<usize> {
+        match version {
+            1..=6 => Ok(BlockHandle::ENCODED_SIZE * 2 + FOOTER_TRAILER_SIZE),
+            7..=10 => Ok(BlockHandle::ENCODED_SIZE * 3 + FOOTER_TRAILER_SIZE),
+            11 => Ok(BlockHandle::ENCODED_SIZE * 3
+                + std::mem::size_of::<u64>()
+                + FOOTER_TRAILER_SIZE),
+            _ => bail!("unsupported SST format version {version}"),
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let offset = buf.len();
+        self.index.encode(buf);
+        self.bloom.encode(buf);
+        if let Some(range_tombstones) = self.range_tombstones {
+            range_tombstones.encode(buf);
+        }
+        if self.version >= 11 {
+            buf.put_u64(self.global_ts.unwrap_or_default());
+        }
+        buf.put_u32(self.version);
+        let checksum = crc32fast::hash(&buf[offset..]);
+        buf.put_u32(checksum);
+        buf.put_u64(SST_MAGIC);
+    }
+
+    /// Read and validate the footer of an SST file.
+    pub fn read(file: &FileObject) -> Result<Self> {
+        let len = file.size();
+        let trailer_offset = len
+            .checked_sub(FOOTER_TRAILER_SIZE as u64)
+            .context("SST footer is truncated")?;
+        let trailer = file.read(trailer_offset, FOOTER_TRAILER_SIZE as u64)?;
+        let mut cursor = &trailer[..];
+        let version = take_u32(&mut cursor, "SST format version")?;
+        let _ = take_u32(&mut cursor, "SST footer checksum")?;
+        let magic = take_u64(&mut cursor, "SST magic number")?;
+        if magic != SST_MAGIC {
+            return Self::read_legacy(file).context("not a mini-lsm SST: bad magic number");
+        }
+
+        let footer_size = Self::encoded_size(version)?;
+        let footer_offset = len
+            .checked_sub(footer_size as u64)
+            .context("SST footer is truncated")?;
+        let raw_footer = file.read(footer_offset, footer_size as u64)?;
+        let checksum_offset = footer_size - std::mem::size_of::<u32>() - std::mem::size_of::<u64>();
+        let mut cursor = &raw_footer[checksum_offset..];
+        let checksum = take_u32(&mut cursor, "SST footer checksum")?;
+        ensure!(
+            checksum == crc32fast::hash(&raw_footer[..checksum_offset]),
+            "SST footer checksum mismatched"
+        );
+
+        let mut cursor = &raw_footer[..];
+        let footer = match version {
+            1..=6 => Self {
+                version,
+                index: BlockHandle::decode(&mut cursor, "SST index handle")?,
+                bloom: BlockHandle::decode(&mut cursor, "SST bloom handle")?,
+                range_tombstones: None,
+                global_ts: None,
+            },
+            7..=11 => Self {
+                version,
+                index: BlockHandle::decode(&mut cursor, "SST index handle")?,
+                bloom: BlockHandle::decode(&mut cursor, "SST bloom handle")?,
+                range_tombstones: Some(BlockHandle::decode(
+                    &mut cursor,
+                    "SST range tombstones handle",
+                )?),
+                global_ts: if version >= 11 {
+                    Some(take_u64(&mut cursor, "SST global timestamp")?).filter(|ts| *ts != 0)
+                } else {
+                    None
+                },
+            },
+            _ => unreachable!(),
+        };
+        ensure!(
+            footer.index.end()? <= footer.bloom.offset,
+            "SST index overlaps the bloom filter"
+        );
+        let bloom_end = footer.bloom.end()?;
+        if let Some(range_tombstones) = footer.range_tombstones {
+            ensure!(
+                bloom_end <= range_tombstones.offset,
+                "SST bloom filter overlaps the range tombstones"
+            );
+            ensure!(
+                range_tombstones.end()? <= footer_offset,
+                "SST range tombstones overlap the footer"
+            );
+        } else {
+            ensure!(
+                bloom_end <= footer_offset,
+                "SST bloom filter overlaps the footer"
+            );
+        }
+        Ok(footer)
+    }
+
+    /// Read the trailer of a format version 0 table, which has no magic number.
+    fn read_legacy(file: &FileObject) -> Result<Self> {
+        let len = file.size();
+        let bloom_trailer_offset = len
+            .checked_sub(std::mem::size_of::<u32>() as u64)
+            .context("SST bloom-offset trailer is truncated")?;
+        let bloom_offset = take_u32(
+            &mut &file.read(bloom_trailer_offset, 4)?[..],
+            "SST bloom offset",
+        )? as u64;
+        ensure!(
+            bloom_offset <= bloom_trailer_offset,
+            "SST bloom offset is out of bounds"
+        );
+        let meta_trailer_offset = bloom_offset
+            .checked_sub(std::mem::size_of::<u32>() as u64)
+            .context("SST metadata-offset trailer is truncated")?;
+        let meta_offset = take_u32(
+            &mut &file.read(meta_trailer_offset, 4)?[..],
+            "SST metadata offset",
+        )? as u64;
+        ensure!(
+            meta_offset <= meta_trailer_offset,
+            "SST block-metadata offset is out of bounds"
+        );
+        Ok(Self {
+            version: 0,
+            index: BlockHandle {
+                offset: meta_offset,
+                len: meta_trailer_offset - meta_offset,
+            },
+            bloom: BlockHandle {
+                offset: bloom_offset,
+                len: bloom_trailer_offset - bloom_offset,
+            },
+            range_tombstones: None,
+            global_ts: None,
+        })
+    }
+
+    /// Set the global timestamp of the SST at `path` by rewriting its footer in place, which is
+    /// synced before this returns.
+    pub fn write_global_ts(path: &Path, ts: u64) -> Result<()> {
+        use std::os::unix::fs::FileExt;
+        ensure!(ts != 0, "the global timestamp cannot be 0");
+        let mut footer = Self::read(&FileObject::open(path)?)?;
+        ensure!(
+            footer.version >= 11,
+            "SST format version {} has no global timestamp",
+            footer.version
+        );
+        footer.global_ts = Some(ts);
+        let mut buf = Vec::new();
+        footer.encode(&mut buf);
+        let file = File::options().write(true).open(path)?;
+        let offset = file
+            .metadata()?
+            .len()
+            .checked_sub(buf.len() as u64)
+            .context("SST footer is truncated")?;
+        file.write_all_at(&buf, offset)?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The partitioned block index.
+//!
+//! The index of every data block is stored in index partitions, which are encoded as ordinary
+//! blocks and go through the block cache. Each entry of a partition is keyed by the last key of a
+//! data block, and its value is the location of the block:
+//!
+//! ```text
+//! | block offset (u64) | block length (u64) |
+//! ```
+//!
+//! so seeking a partition to a key lands on the only data block that may contain the key. The
+//! top-level index, which describes every partition, is the only part of the index that stays in
+//! memory:
+//!
+//! ```text
+//! | partition count (u32) | partition metadata ... | block count (u32) | max ts (u64) | prefix extractor | comparator | column family (varint) | checksum (u32) |
+//! ```
+//!
+//! The keys in the partition metadata are prefixed with their length, which is a u16 up to format
+//! version 3 and a varint since version 4. The name of the prefix extractor, which is empty if the
+//! bloom filter has no prefixes, is prefixed with its varint length and written since version 6.
+//! The name of the comparator that orders the keys is written the same way since version 8; the
+//! keys of older tables are ordered bytewise. The id of the column family that the table belongs to
+//! is written since version 10.
+
+use anyhow::{Context, Result, ensure};
+use bytes::BufMut;
+
+use super::{BlockHandle, take_bytes, take_u16, take_u32, take_u64};
+use crate::comparator::{BYTEWISE_COMPARATOR, Comparator};
+use crate::key::{KeyBytes, KeySlice};
+use crate::varint::{get_uvarint, get_uvarint_len, put_uvarint};
+
+fn put_key(buf: &mut Vec<u8>, key: &KeyBytes) {
+    put_uvarint(buf, key.key_len() as u64);
+    buf.put_slice(key.key_ref());
+    buf.put_u64(key.ts());
+}
+
+fn take_key(buf: &mut &[u8], version: u32, what: &str) -> Result<KeyBytes> {
+    let key_len = if version >= 4 {
+        get_uvarint_len(buf, what)?
+    } else {
+        take_u16(buf, what)? as usize
+    };
+    ensure!(key_len > 0, "{what} is empty");
+    let key = take_bytes(buf, key_len, what)?;
+    let ts = take_u64(buf, what)?;
+    Ok(KeyBytes::from_bytes_with_ts(key.to_vec().into(), ts))
+}
+
+/// Encode the value of the index entry of a data block.
+pub(crate) fn encode_index_entry(handle: BlockHandle) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(std::mem::size_of::<u64>() * 2);
+    buf.put_u64(handle.offset);
+    buf.put_u64(handle.len);
+    buf
+}
+
+/// Decode the value of an index entry.
+pub(crate) fn decode_index_entry(mut value: &[u8]) -> Result<BlockHandle> {
+    let offset = take_u64(&mut value, "SST block offset")?;
+    let len = take_u64(&mut value, "SST block length")?;
+    ensure!(value.is_empty(), "SST index entry has trailing bytes");
+    Ok(BlockHandle { offset, len })
+}
+
+/// Describes one partition of the block index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexPartitionMeta {
+    /// Location of the partition in the file.
+    pub handle: BlockHandle,
+    /// Index of the first data block covered by this partition.
+    pub first_block_idx: usize,
+    /// The first key of the first data block in this partition.
+    pub first_key: KeyBytes,
+    /// The last key of the last data block in this partition.
+    pub last_key: KeyBytes,
+}
+
+/// The resident part of a partitioned block index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopLevelIndex {
+    pub partitions: Vec<IndexPartitionMeta>,
+    pub num_blocks: usize,
+    pub max_ts: u64,
+    /// The name of the prefix extractor whose prefixes are in the bloom filter.
+    pub prefix_extractor: Option<String>,
+    /// The name of the comparator that orders the keys of the table.
+    pub comparator: String,
+    /// The id of the column family that the table belongs to, or `None` if the table is older
+    /// than format version 10 and does not record it.
+    pub column_family: Option<u32>,
+}
+
+impl TopLevelIndex {
+    /// Encode the top-level index to a buffer.
+    pub fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let original_len = buf.len();
+        buf.put_u32(u32::try_from(self.partitions.len()).context("too many SST index partitions")?);
+        for partition in &self.partitions {
+            buf.put_u64(partition.handle.offset);
+            buf.put_u64(partition.handle.len);
+            buf.put_u32(u32::try_from(partition.first_block_idx).context("too many SST blocks")?);
+            put_key(buf, &partition.first_key);
+            put_key(buf, &partition.last_key);
+        }
+        buf.put_u32(u32::try_from(self.num_blocks).context("too many SST blocks")?);
+        buf.put_u64(self.max_ts);
+        let prefix_extractor = self.prefix_extractor.as_deref().unwrap_or_default();
+        put_uvarint(buf, prefix_extractor.len() as u64);
+        buf.put_slice(prefix_extractor.as_bytes());
+        put_uvarint(buf, self.comparator.len() as u64);
+        buf.put_slice(self.comparator.as_bytes());
+        put_uvarint(buf, self.column_family.unwrap_or_default() as u64);
+        buf.put_u32(crc32fast::hash(&buf[original_len..]));
+        Ok(())
+    }
+
+    /// Decode the top-level index of a table of the given format version from a buffer.
+    /// `index_offset` is where the top-level index starts, which is also where the last partition
+    /// must end. The table must have been written with `comparator`, which orders the decoded keys.
+    pub fn decode(
+        buf: &[u8],
+        index_offset: u64,
+        version: u32,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self> {
+        let checksum_offset = buf
+            .len()
+            .checked_sub(std::mem::size_of::<u32>())
+            .context("SST index is truncated")?;
+        let mut cursor = &buf[checksum_offset..];
+        let checksum = take_u32(&mut cursor, "SST index checksum")?;
+        ensure!(
+            checksum == crc32fast::hash(&buf[..checksum_offset]),
+            "SST index checksum mismatched"
+        );
+
+        let mut cursor = &buf[..checksum_offset];
+        let num_partitions = take_u32(&mut cursor, "SST index partition count")? as usize;
+        let minimum_entry_size = std::mem::size_of::<u64>() * 4
+            + std::mem::size_of::<u32>()
+            + std::mem::size_of::<u8>() * 2;
+        ensure!(
+            num_partitions <= cursor.len() / minimum_entry_size,
+            "SST index partition count exceeds the index length"
+        );
+        let mut partitions = Vec::with_capacity(num_partitions);
+        for _ in 0..num_partitions {
+            let offset = take_u64(&mut cursor, "SST index partition offset")?;
+            let len = take_u64(&mut cursor, "SST index partition length")?;
+            let first_block_idx = take_u32(&mut cursor, "SST index partition block index")?;
+            let first_key = take_key(&mut cursor, version, "SST first key")?;
+            let last_key = take_key(&mut cursor, version, "SST last key")?;
+            partitions.push(IndexPartitionMeta {
+                handle: BlockHandle { offset, len },
+                first_block_idx: first_block_idx as usize,
+                first_key,
+                last_key,
+            });
+        }
+        let num_blocks = take_u32(&mut cursor, "SST block count")? as usize;
+        let max_ts = take_u64(&mut cursor, "SST maximum timestamp")?;
+        let mut prefix_extractor = None;
+        if version >= 6 {
+            let len = get_uvarint_len(&mut cursor, "SST prefix extractor length")?;
+            let name = take_bytes(&mut cursor, len, "SST prefix extractor")?;
+            let name = std::str::from_utf8(name).context("SST prefix extractor is not UTF-8")?;
+            prefix_extractor = (!name.is_empty()).then(|| name.to_string());
+        }
+        let mut comparator_name = BYTEWISE_COMPARATOR.name();
+        if version >= 8 {
+            let len = get_uvarint_len(&mut cursor, "SST comparator length")?;
+            let name = take_bytes(&mut cursor, len, "SST comparator")?;
+            comparator_name = std::str::from_utf8(name).context("SST comparator is not UTF-8")?;
+        }
+        let mut column_family = None;
+        if version >= 10 {
+            let id = get_uvarint(&mut cursor, "SST column family")?;
+            column_family = Some(u32::try_from(id).context("SST column family id is too large")?);
+        }
+        ensure!(cursor.is_empty(), "SST index has trailing bytes");
+        ensure!(
+            comparator_name == comparator.name(),
+            "SST keys are ordered by the {comparator_name:?} comparator, not {:?}",
+            comparator.name()
+        );
+        let index = Self {
+            partitions,
+            num_blocks,
+            max_ts,
+            prefix_extractor,
+            comparator: comparator_name.to_string(),
+            column_family,
+        };
+        if index.partitions.is_empty() {
+            // Since format version 9, a table may hold only range tombstones.
+            ensure!(version >= 9, "SST has no data blocks");
+            ensure!(
+                num_blocks == 0,
+                "SST index has no partitions for its blocks"
+            );
+            return Ok(index);
+        }
+        let partitions = &index.partitions;
+        ensure!(
+            partitions[0].first_block_idx == 0,
+            "first SST index partition must start at block zero"
+        );
+        ensure!(
+            partitions[0].handle.offset > 0,
+            "SST index partitions must follow the data blocks"
+        );
+        for pair in partitions.windows(2) {
+            ensure!(
+                pair[0].first_block_idx < pair[1].first_block_idx,
+                "SST index partitions are not strictly increasing"
+            );
+            ensure!(
+                pair[0].handle.end()? == pair[1].handle.offset,
+                "SST index partitions are not contiguous"
+            );
+            ensure!(
+                comparator
+                    .compare_keys(
+                        pair[0].last_key.as_key_slice(),
+                        pair[1].first_key.as_key_slice()
+                    )
+                    .is_lt(),
+                "SST index partitions overlap"
+            );
+        }
+        let last = partitions.last().unwrap();
+        ensure!(
+            last.first_block_idx < num_blocks,
+            "SST index partition covers no blocks"
+        );
+        ensure!(
+            last.handle.end()? == index_offset,
+            "SST index partitions must end at the top-level index"
+        );
+        ensure!(
+            partitions.iter().all(|p| comparator
+                .compare_keys(p.first_key.as_key_slice(), p.last_key.as_key_slice())
+                .is_le()),
+            "SST index partition first key is after its last key"
+        );
+        Ok(index)
+    }
+
+    /// The range of data blocks covered by a partition.
+    pub fn partition_blocks(&self, partition_idx: usize) -> std::ops::Range<usize> {
+        let start = self.partitions[partition_idx].first_block_idx;
+        let end = self
+            .partitions
+            .get(partition_idx + 1)
+            .map_or(self.num_blocks, |p| p.first_block_idx);
+        start..end
+    }
+
+    /// Find the partition that covers a data block.
+    pub fn partition_of_block(&self, block_idx: usize) -> usize {
+        self.partitions
+            .partition_point(|p| p.first_block_idx <= block_idx)
+            .saturating_sub(1)
+    }
+
+    /// Find the partition whose blocks may contain `key`, with the keys ordered by `comparator`.
+    /// Keys after the end of the table map to the last partition.
+    pub fn partition_of_key(&self, key: KeySlice, comparator: &dyn Comparator) -> usize {
+        self.partitions
+            .partition_point(|p| {
+                comparator
+                    .compare_keys(p.last_key.as_key_slice(), key)
+                    .is_lt()
+            })
+            .min(self.partitions.len() - 1)
+    }
+}
@@ -18,17 +18,77 @@ use anyhow::Result;
 
 use super::SsTable;
 use crate::block::BlockIterator;
-use crate::iterators::StorageIterator;
-use crate::key::KeySlice;
+use crate::iterators::{SeekableIterator, StorageIterator, ValueKind};
+use crate::key::{KeySlice, TS_DEFAULT};
 
 /// An iterator over the contents of an SSTable.
 pub struct SsTableIterator {
     table: Arc<SsTable>,
-    blk_iter: BlockIterator,
+    /// `None` if the table has no data blocks, which is the case for a table that only holds
+    /// range tombstones.
+    blk_iter: Option<BlockIterator>,
     blk_idx: usize,
 }
 
 impl SsTableIterator {
+    /// Run a seek on a table with data blocks, or leave the iterator invalid on a table without.
+    fn seek_with(
+        table: &Arc<SsTable>,
+        seek: impl FnOnce(&Arc<SsTable>) -> Result<(usize, BlockIterator)>,
+    ) -> Result<(usize, Option<BlockIterator>)> {
+        if table.num_of_blocks() == 0 {
+            return Ok((0, None));
+        }
+        let (blk_idx, blk_iter) = seek(table)?;
+        Ok((blk_idx, Some(blk_iter)))
+    }
+
+    /// The key to look up in the blocks of `table` for a seek to `key`. Every key of a table with a
+    /// global timestamp is stored with the default timestamp.
+    fn stored_key<'a>(table: &SsTable, key: KeySlice<'a>) -> KeySlice<'a> {
+        match table.global_ts() {
+            Some(_) => KeySlice::from_slice_with_ts(key.key_ref(), TS_DEFAULT),
+            None => key,
+        }
+    }
+
+    /// Whether the iterator is at the user key of `key`, with a global timestamp that orders the
+    /// entry on the wrong side of `key` for a seek in the given direction.
+    fn at_global_ts_past(&self, key: KeySlice, forward: bool) -> bool {
+        let Some(ts) = self.table.global_ts() else {
+            return false;
+        };
+        let past = if forward {
+            ts > key.ts()
+        } else {
+            ts < key.ts()
+        };
+        past && self.is_valid()
+            && self
+                .table
+                .comparator()
+                .compare(self.key().key_ref(), key.key_ref())
+                .is_eq()
+    }
+
+    /// Finish a seek for the first entry >= `key`. The seek found the stored entry of the user key
+    /// of `key`, which is before `key` if its global timestamp is newer.
+    fn fix_seek_forward(mut self, key: KeySlice) -> Result<Self> {
+        if self.at_global_ts_past(key, true) {
+            self.next()?;
+        }
+        Ok(self)
+    }
+
+    /// Finish a seek for the last entry <= `key`. The seek found the stored entry of the user key
+    /// of `key`, which is after `key` if its global timestamp is older.
+    fn fix_seek_backward(mut self, key: KeySlice) -> Result<Self> {
+        if self.at_global_ts_past(key, false) {
+            self.prev()?;
+        }
+        Ok(self)
+    }
+
     fn seek_to_first_inner(table: &Arc<SsTable>) -> Result<(usize, BlockIterator)> {
         Ok((
             0,
@@ -38,7 +98,7 @@ impl SsTableIterator {
 
     /// Create a new iterator and seek to the first key-value pair.
     pub fn create_and_seek_to_first(table: Arc<SsTable>) -> Result<Self> {
-        let (blk_idx, blk_iter) = Self::seek_to_first_inner(&table)?;
+        let (blk_idx, blk_iter) = Self::seek_with(&table, Self::seek_to_first_inner)?;
         let iter = Self {
             blk_iter,
             table,
@@ -49,14 +109,14 @@ impl SsTableIterator {
 
     /// Seek to the first key-value pair.
     pub fn seek_to_first(&mut self) -> Result<()> {
-        let (blk_idx, blk_iter) = Self::seek_to_first_inner(&self.table)?;
+        let (blk_idx, blk_iter) = Self::seek_with(&self.table, Self::seek_to_first_inner)?;
         self.blk_idx = blk_idx;
         self.blk_iter = blk_iter;
         Ok(())
     }
 
     fn seek_to_key_inner(table: &Arc<SsTable>, key: KeySlice) -> Result<(usize, BlockIterator)> {
-        let mut blk_idx = table.find_block_idx(key);
+        let mut blk_idx = table.find_block_idx(key)?;
         let mut blk_iter =
             BlockIterator::create_and_seek_to_key(table.read_block_cached(blk_idx)?, key);
         if !blk_iter.is_valid() {
@@ -71,20 +131,113 @@ impl SsTableIterator {
 
     /// Create a new iterator and seek to the first key-value pair which >= `key`.
     pub fn create_and_seek_to_key(table: Arc<SsTable>, key: KeySlice) -> Result<Self> {
-        let (blk_idx, blk_iter) = Self::seek_to_key_inner(&table, key)?;
+        let stored_key = Self::stored_key(&table, key);
+        let (blk_idx, blk_iter) =
+            Self::seek_with(&table, |table| Self::seek_to_key_inner(table, stored_key))?;
         let iter = Self {
             blk_iter,
             table,
             blk_idx,
         };
-        Ok(iter)
+        iter.fix_seek_forward(key)
+    }
+
+    fn seek_to_last_inner(table: &Arc<SsTable>) -> Result<(usize, BlockIterator)> {
+        let blk_idx = table.num_of_blocks() - 1;
+        Ok((
+            blk_idx,
+            BlockIterator::create_and_seek_to_last(table.read_block_cached(blk_idx)?),
+        ))
+    }
+
+    /// Create a new iterator and seek to the last key-value pair.
+    pub fn create_and_seek_to_last(table: Arc<SsTable>) -> Result<Self> {
+        let (blk_idx, blk_iter) = Self::seek_with(&table, Self::seek_to_last_inner)?;
+        Ok(Self {
+            blk_iter,
+            table,
+            blk_idx,
+        })
+    }
+
+    /// Seek to the last key-value pair.
+    pub fn seek_to_last(&mut self) -> Result<()> {
+        let (blk_idx, blk_iter) = Self::seek_with(&self.table, Self::seek_to_last_inner)?;
+        self.blk_idx = blk_idx;
+        self.blk_iter = blk_iter;
+        Ok(())
+    }
+
+    fn seek_for_prev_inner(table: &Arc<SsTable>, key: KeySlice) -> Result<(usize, BlockIterator)> {
+        let mut blk_idx = table.find_block_idx(key)?;
+        let mut blk_iter =
+            BlockIterator::create_and_seek_for_prev(table.read_block_cached(blk_idx)?, key);
+        if !blk_iter.is_valid() && blk_idx > 0 {
+            blk_idx -= 1;
+            blk_iter = BlockIterator::create_and_seek_to_last(table.read_block_cached(blk_idx)?);
+        }
+        Ok((blk_idx, blk_iter))
+    }
+
+    /// Create a new iterator and seek to the last key-value pair which <= `key`.
+    pub fn create_and_seek_for_prev(table: Arc<SsTable>, key: KeySlice) -> Result<Self> {
+        let stored_key = Self::stored_key(&table, key);
+        let (blk_idx, blk_iter) =
+            Self::seek_with(&table, |table| Self::seek_for_prev_inner(table, stored_key))?;
+        Self {
+            blk_iter,
+            table,
+            blk_idx,
+        }
+        .fix_seek_backward(key)
+    }
+
+    /// Seek to the last key-value pair which <= `key`.
+    pub fn seek_for_prev(&mut self, key: KeySlice) -> Result<()> {
+        *self = Self::create_and_seek_for_prev(self.table.clone(), key)?;
+        Ok(())
+    }
+
+    /// Create a new iterator for a point lookup and seek to the first key-value pair which >=
+    /// `key`. The hash indices of the data blocks are used when present, and the iterator is
+    /// invalid if they show that the table has no entry with the user key of `key`.
+    pub fn create_and_seek_for_get(table: Arc<SsTable>, key: KeySlice) -> Result<Self> {
+        let stored_key = Self::stored_key(&table, key);
+        let (blk_idx, blk_iter) =
+            Self::seek_with(&table, |table| Self::seek_for_get_inner(table, stored_key))?;
+        Self {
+            blk_iter,
+            table,
+            blk_idx,
+        }
+        .fix_seek_forward(key)
+    }
+
+    fn seek_for_get_inner(table: &Arc<SsTable>, key: KeySlice) -> Result<(usize, BlockIterator)> {
+        let mut blk_idx = table.find_block_idx(key)?;
+        let block = table.read_block_cached(blk_idx)?;
+        let num_entries = block.offsets.len();
+        let mut blk_iter = match BlockIterator::create_and_seek_for_get(block.clone(), key) {
+            Some(blk_iter) => blk_iter,
+            None => {
+                blk_idx = table.num_of_blocks();
+                BlockIterator::create_and_seek_to_idx(block, num_entries)
+            }
+        };
+        if !blk_iter.is_valid() && blk_idx < table.num_of_blocks() {
+            // Every entry of the block is less than `key`.
+            blk_idx += 1;
+            if blk_idx < table.num_of_blocks() {
+                blk_iter =
+                    BlockIterator::create_and_seek_to_first(table.read_block_cached(blk_idx)?);
+            }
+        }
+        Ok((blk_idx, blk_iter))
     }
 
     /// Seek to the first key-value pair which >= `key`.
     pub fn seek_to_key(&mut self, key: KeySlice) -> Result<()> {
-        let (blk_idx, blk_iter) = Self::seek_to_key_inner(&self.table, key)?;
-        self.blk_iter = blk_iter;
-        self.blk_idx = blk_idx;
+        *self = Self::create_and_seek_to_key(self.table.clone(), key)?;
         Ok(())
     }
 }
@@ -93,27 +246,64 @@ impl StorageIterator for SsTableIterator {
     type KeyType<'a> = KeySlice<'a>;
 
     fn value(&self) -> &[u8] {
-        self.blk_iter.value()
+        self.blk_iter.as_ref().unwrap().value()
+    }
+
+    fn value_kind(&self) -> ValueKind {
+        self.blk_iter.as_ref().unwrap().value_kind()
     }
 
     fn key(&self) -> KeySlice<'_> {
-        self.blk_iter.key()
+        let key = self.blk_iter.as_ref().unwrap().key();
+        match self.table.global_ts() {
+            Some(ts) => KeySlice::from_slice_with_ts(key.key_ref(), ts),
+            None => key,
+        }
     }
 
     fn is_valid(&self) -> bool {
-        self.blk_iter.is_valid()
+        self.blk_iter.as_ref().is_some_and(BlockIterator::is_valid)
     }
 
     fn next(&mut self) -> Result<()> {
-        self.blk_iter.next();
-        if !self.blk_iter.is_valid() {
+        let Some(blk_iter) = &mut self.blk_iter else {
+            return Ok(());
+        };
+        blk_iter.next();
+        if !blk_iter.is_valid() {
             self.blk_idx += 1;
             if self.blk_idx < self.table.num_of_blocks() {
-                self.blk_iter = BlockIterator::create_and_seek_to_first(
+                *blk_iter = BlockIterator::create_and_seek_to_first(
                     self.table.read_block_cached(self.blk_idx)?,
                 );
             }
         }
         Ok(())
     }
+
+    fn prev(&mut self) -> Result<()> {
+        let Some(blk_iter) = &mut self.blk_iter else {
+            return Ok(());
+        };
+        if !blk_iter.is_valid() {
+            return Ok(());
+        }
+        blk_iter.prev();
+        if !blk_iter.is_valid() && self.blk_idx > 0 {
+            self.blk_idx -= 1;
+            *blk_iter =
+                BlockIterator::create_and_seek_to_last(self.table.read_block_cached(self.blk_idx)?);
+        }
+        Ok(())
+    }
+}
+
+impl SeekableIterator for SsTableIterator {
+    fn seek_to_key(&mut self, key: KeySlice) -> Result<()> {
+        SsTableIterator::seek_to_key(self, key)
+    }
+
+    fn seek_for_prev(&mut self, key: KeySlice) -> Result<()> {
+        SsTableIterator::seek_for_prev(self, key)
+    }
 }
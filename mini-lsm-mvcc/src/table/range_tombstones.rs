@@ -0,0 +1,85 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The range tombstone block, which holds the range tombstones of a table:
+//!
+//! ```text
+//! | tombstone count (u32) | start len (varint) | start | end len (varint) | end | ts (u64) | ... | checksum (u32) |
+//! ```
+
+use anyhow::{Context, Result, ensure};
+use bytes::{BufMut, Bytes};
+
+use super::{take_bytes, take_u32, take_u64};
+use crate::range_tombstone::RangeTombstone;
+use crate::varint::{get_uvarint_len, put_uvarint};
+
+/// Encode the range tombstones to a buffer.
+pub(crate) fn encode_range_tombstones(
+    range_tombstones: &[RangeTombstone],
+    buf: &mut Vec<u8>,
+) -> Result<()> {
+    let original_len = buf.len();
+    buf.put_u32(u32::try_from(range_tombstones.len()).context("too many SST range tombstones")?);
+    for tombstone in range_tombstones {
+        put_uvarint(buf, tombstone.start.len() as u64);
+        buf.put_slice(&tombstone.start);
+        put_uvarint(buf, tombstone.end.len() as u64);
+        buf.put_slice(&tombstone.end);
+        buf.put_u64(tombstone.ts);
+    }
+    buf.put_u32(crc32fast::hash(&buf[original_len..]));
+    Ok(())
+}
+
+/// Decode the range tombstones from a buffer.
+pub(crate) fn decode_range_tombstones(buf: &[u8]) -> Result<Vec<RangeTombstone>> {
+    let checksum_offset = buf
+        .len()
+        .checked_sub(std::mem::size_of::<u32>())
+        .context("SST range tombstones are truncated")?;
+    let mut cursor = &buf[checksum_offset..];
+    let checksum = take_u32(&mut cursor, "SST range tombstones checksum")?;
+    ensure!(
+        checksum == crc32fast::hash(&buf[..checksum_offset]),
+        "SST range tombstones checksum mismatched"
+    );
+
+    let mut cursor = &buf[..checksum_offset];
+    let count = take_u32(&mut cursor, "SST range tombstone count")? as usize;
+    let minimum_entry_size = std::mem::size_of::<u8>() * 2 + std::mem::size_of::<u64>();
+    ensure!(
+        count <= cursor.len() / minimum_entry_size,
+        "SST range tombstone count exceeds the block length"
+    );
+    let mut range_tombstones = Vec::with_capacity(count);
+    for _ in 0..count {
+        let start_len = get_uvarint_len(&mut cursor, "SST range tombstone start length")?;
+        let start = take_bytes(&mut cursor, start_len, "SST range tombstone start")?;
+        let end_len = get_uvarint_len(&mut cursor, "SST range tombstone end length")?;
+        let end = take_bytes(&mut cursor, end_len, "SST range tombstone end")?;
+        let ts = take_u64(&mut cursor, "SST range tombstone timestamp")?;
+        ensure!(start < end, "SST range tombstone is empty");
+        range_tombstones.push(RangeTombstone::new(
+            Bytes::copy_from_slice(start),
+            Bytes::copy_from_slice(end),
+            ts,
+        ));
+    }
+    ensure!(
+        cursor.is_empty(),
+        "SST range tombstones have trailing bytes"
+    );
+    Ok(range_tombstones)
+}
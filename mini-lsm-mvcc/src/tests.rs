@@ -12,8 +12,32 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod backup;
+mod block_cache;
+mod block_hash_index;
+mod block_restart;
+mod checkpoint;
+mod column_family;
+mod compaction_filter;
+mod comparator;
 mod harness;
+mod ingest;
+mod iterator_refresh;
+mod iterator_seek;
+mod manifest_rotation;
+mod merge_operator;
+mod parallel_open;
+mod prefix_scan;
+mod range_delete;
 mod release_regressions;
+mod repair;
+mod reverse_scan;
+mod salvage;
+mod sst_compression;
+mod sst_format;
+mod sst_index;
+mod ttl;
+mod value_log;
 mod week1_day1;
 mod week1_day2;
 mod week1_day3;
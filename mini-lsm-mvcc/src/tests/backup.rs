@@ -0,0 +1,175 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    backup::BackupEngine,
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+fn value_of(idx: usize, version: usize) -> Vec<u8> {
+    format!("value_{:05}_{}", idx, version).into_bytes()
+}
+
+fn shared_files(path: &Path) -> usize {
+    std::fs::read_dir(path.join("shared")).unwrap().count()
+}
+
+fn check(storage: &MiniLsm, version: usize, num_keys: usize) {
+    for idx in 0..num_keys {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(Bytes::from(value_of(idx, version))),
+            "key {idx}"
+        );
+    }
+    assert_eq!(storage.get(&key_of(num_keys)).unwrap(), None);
+}
+
+#[test]
+fn test_incremental_backups_share_ssts() {
+    let dir = tempdir().unwrap();
+    let backup_dir = tempdir().unwrap();
+    let restore_dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let engine = BackupEngine::open(&backup_dir).unwrap();
+
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    assert_eq!(engine.create_backup(&storage).unwrap(), 1);
+    assert_eq!(shared_files(backup_dir.path()), 1);
+
+    for idx in 0..20 {
+        storage.put(&key_of(idx), &value_of(idx, 2)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    // Left in the mem-table, and only backed up in the WAL.
+    storage.put(&key_of(20), &value_of(20, 2)).unwrap();
+    assert_eq!(engine.create_backup(&storage).unwrap(), 2);
+    // The SST of the first backup is not copied again.
+    assert_eq!(shared_files(backup_dir.path()), 2);
+
+    let backups = engine.backups().unwrap();
+    assert_eq!(
+        backups.iter().map(|backup| backup.id).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    assert!(backups[0].num_files < backups[1].num_files);
+    engine.verify_backup(1).unwrap();
+    engine.verify_backup(2).unwrap();
+
+    storage.put(&key_of(0), &value_of(0, 3)).unwrap();
+    storage.close().unwrap();
+
+    let first = restore_dir.path().join("first");
+    engine.restore(1, &first).unwrap();
+    let restored = MiniLsm::open(&first, options()).unwrap();
+    check(&restored, 1, 10);
+    restored.close().unwrap();
+
+    // Deleting the first backup keeps the SST that the second backup shares.
+    engine.delete_backup(1).unwrap();
+    assert!(engine.delete_backup(1).is_err());
+    assert_eq!(shared_files(backup_dir.path()), 2);
+    let engine = BackupEngine::open(&backup_dir).unwrap();
+    let second = restore_dir.path().join("second");
+    engine.restore(2, &second).unwrap();
+    let restored = MiniLsm::open(&second, options()).unwrap();
+    check(&restored, 2, 21);
+}
+
+#[test]
+fn test_backup_verification_detects_corruption() {
+    let dir = tempdir().unwrap();
+    let backup_dir = tempdir().unwrap();
+    let restore_dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let engine = BackupEngine::open(&backup_dir).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    let id = engine.create_backup(&storage).unwrap();
+    assert!(engine.verify_backup(id + 1).is_err());
+    assert!(engine.restore(id, restore_dir.path()).is_err());
+
+    let sst = std::fs::read_dir(backup_dir.path().join("shared"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let mut data = std::fs::read(&sst).unwrap();
+    data[0] ^= 1;
+    std::fs::write(&sst, &data).unwrap();
+    assert!(engine.verify_backup(id).is_err());
+    assert!(
+        engine
+            .restore(id, restore_dir.path().join("restored"))
+            .is_err()
+    );
+
+    // The files of a backup that did not finish are removed when the engine is opened.
+    std::fs::create_dir(backup_dir.path().join("private").join("9")).unwrap();
+    std::fs::write(backup_dir.path().join("shared").join("00009_0_0.sst"), b"").unwrap();
+    BackupEngine::open(&backup_dir).unwrap();
+    assert!(!backup_dir.path().join("private").join("9").exists());
+    assert_eq!(shared_files(backup_dir.path()), 1);
+}
+
+#[test]
+fn test_backup_ids_are_not_reused() {
+    let dir = tempdir().unwrap();
+    let backup_dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let engine = BackupEngine::open(&backup_dir).unwrap();
+    storage.put(&key_of(0), &value_of(0, 1)).unwrap();
+    storage.force_flush().unwrap();
+    assert_eq!(engine.create_backup(&storage).unwrap(), 1);
+    assert_eq!(engine.create_backup(&storage).unwrap(), 2);
+    // Both backups refer to the same copy of the SST.
+    assert_eq!(shared_files(backup_dir.path()), 1);
+
+    // The id of the deleted latest backup is not given to the next one.
+    engine.delete_backup(2).unwrap();
+    let engine = BackupEngine::open(&backup_dir).unwrap();
+    assert_eq!(engine.create_backup(&storage).unwrap(), 3);
+    assert_eq!(
+        engine
+            .backups()
+            .unwrap()
+            .iter()
+            .map(|backup| backup.id)
+            .collect::<Vec<_>>(),
+        vec![1, 3]
+    );
+    engine.verify_backup(3).unwrap();
+}
@@ -0,0 +1,143 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    block_cache::MetaCacheKey,
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    key::KeySlice,
+    lsm_storage::{BlockCache, LsmStorageOptions, MiniLsm},
+    table::{FileObject, SsTable, SsTableBuilder, SsTableIterator},
+};
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+fn value_of(idx: usize) -> Vec<u8> {
+    format!("value_{:05}_", idx).repeat(4).into_bytes()
+}
+
+const NUM_KEYS: usize = 2000;
+
+fn build_sst(path: &std::path::Path) {
+    let mut builder = SsTableBuilder::new(256);
+    for idx in 0..NUM_KEYS {
+        builder.add(
+            KeySlice::for_testing_from_slice_with_ts(&key_of(idx), 1),
+            &value_of(idx),
+        );
+    }
+    drop(builder.build_for_test(path).unwrap());
+}
+
+#[test]
+fn test_block_cache_scan_does_not_evict_metadata() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    build_sst(&path);
+    // The data cache only has room for a few blocks, while the metadata cache has plenty.
+    let block_cache = Arc::new(BlockCache::new(1024, 1 << 20));
+    let sst = Arc::new(
+        SsTable::open(
+            1,
+            Some(block_cache.clone()),
+            FileObject::open(&path).unwrap(),
+        )
+        .unwrap(),
+    );
+
+    let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, NUM_KEYS);
+    // Seek to every block so that every index partition is read.
+    for idx in (0..NUM_KEYS).step_by(10) {
+        let iter = SsTableIterator::create_and_seek_to_key(
+            sst.clone(),
+            KeySlice::for_testing_from_slice_with_ts(&key_of(idx), 1),
+        )
+        .unwrap();
+        assert_eq!(iter.key().for_testing_key_ref(), key_of(idx).as_slice());
+    }
+
+    block_cache.data.run_pending_tasks();
+    block_cache.meta.run_pending_tasks();
+    assert!(block_cache.data.weighted_size() <= 1024);
+    assert!(
+        (0..sst.num_of_blocks()).any(|idx| !block_cache.contains_data_block(1, idx)),
+        "the data cache should have evicted blocks"
+    );
+    assert!(block_cache.contains_meta(MetaCacheKey::Bloom(1)));
+    for idx in 0..sst.num_of_index_partitions().unwrap() {
+        assert!(block_cache.contains_meta(MetaCacheKey::Index(1, idx)));
+    }
+}
+
+#[test]
+fn test_block_cache_reloads_evicted_bloom() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    build_sst(&path);
+    // Nothing fits in the metadata cache, so the bloom filter is read from the disk on demand.
+    let block_cache = Arc::new(BlockCache::new(1 << 20, 0));
+    let sst = SsTable::open(
+        1,
+        Some(block_cache.clone()),
+        FileObject::open(&path).unwrap(),
+    )
+    .unwrap();
+    block_cache.meta.run_pending_tasks();
+    assert!(!block_cache.contains_meta(MetaCacheKey::Bloom(1)));
+
+    let bloom = sst.bloom().unwrap().unwrap();
+    for idx in 0..NUM_KEYS {
+        assert!(bloom.may_contain(farmhash::fingerprint32(&key_of(idx))));
+    }
+    let uncached = SsTable::open_for_test(FileObject::open(&path).unwrap()).unwrap();
+    let pinned = uncached.bloom().unwrap().unwrap();
+    assert_eq!(bloom.filter, pinned.filter);
+    assert_eq!(bloom.k, pinned.k);
+}
+
+#[test]
+fn test_block_cache_storage_with_small_caches() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.block_size = 256;
+    options.block_cache_capacity = 4096;
+    options.meta_cache_capacity = 4096;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..NUM_KEYS {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+        if idx % 500 == 499 {
+            storage.force_flush().unwrap();
+        }
+    }
+    for idx in 0..NUM_KEYS {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(Bytes::from(value_of(idx)))
+        );
+    }
+    assert_eq!(storage.get(b"key_99999").unwrap(), None);
+}
@@ -0,0 +1,159 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    block::{Block, BlockBuilder, BlockFormat, BlockIterator},
+    compact::CompactionOptions,
+    comparator::BYTEWISE_COMPARATOR,
+    key::KeySlice,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+const NUM_KEYS: usize = 100;
+const NUM_VERSIONS: u64 = 3;
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx * 2).into_bytes()
+}
+
+fn missing_key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx * 2 + 1).into_bytes()
+}
+
+fn value_of(idx: usize, ts: u64) -> Vec<u8> {
+    format!("value_{idx}@{ts}").into_bytes()
+}
+
+fn build_block() -> Arc<Block> {
+    let mut builder = BlockBuilder::new_with_hash_index(1 << 20, 4);
+    for idx in 0..NUM_KEYS {
+        for ts in (1..=NUM_VERSIONS).rev() {
+            assert!(builder.add(
+                KeySlice::for_testing_from_slice_with_ts(&key_of(idx), ts),
+                &value_of(idx, ts)
+            ));
+        }
+    }
+    let block = builder.build();
+    assert!(!block.hash_buckets.is_empty());
+    let decoded = Block::decode_checked(
+        &block.encode(),
+        BlockFormat::RestartWithKind,
+        &BYTEWISE_COMPARATOR,
+    )
+    .unwrap();
+    assert_eq!(decoded.hash_buckets, block.hash_buckets);
+    Arc::new(decoded)
+}
+
+#[test]
+fn test_block_hash_index_seek_for_get() {
+    let block = build_block();
+    for idx in 0..NUM_KEYS {
+        let key = key_of(idx);
+        for read_ts in 0..=NUM_VERSIONS + 1 {
+            let seek_key = KeySlice::for_testing_from_slice_with_ts(&key, read_ts);
+            let iter = BlockIterator::create_and_seek_for_get(block.clone(), seek_key).unwrap();
+            let expected = BlockIterator::create_and_seek_to_key(block.clone(), seek_key);
+            assert_eq!(iter.is_valid(), expected.is_valid());
+            if expected.is_valid() {
+                assert_eq!(iter.key(), expected.key());
+                assert_eq!(iter.value(), expected.value());
+            }
+        }
+
+        // A user key that is not in the block is either ruled out, or found in the same position
+        // as a binary search.
+        let missing = missing_key_of(idx);
+        let seek_key = KeySlice::for_testing_from_slice_with_ts(&missing, NUM_VERSIONS);
+        if let Some(iter) = BlockIterator::create_and_seek_for_get(block.clone(), seek_key) {
+            let expected = BlockIterator::create_and_seek_to_key(block.clone(), seek_key);
+            assert_eq!(iter.is_valid(), expected.is_valid());
+            if expected.is_valid() {
+                assert_eq!(iter.key(), expected.key());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_block_hash_index_rejects_bad_buckets() {
+    let block = build_block();
+    let mut encoded = block.encode().to_vec();
+    // The buckets are followed by the number of buckets and the number of restart points.
+    let first_bucket = encoded.len() - 4 - block.hash_buckets.len() * 2;
+    encoded[first_bucket..first_bucket + 2].copy_from_slice(&0x7000u16.to_be_bytes());
+    assert!(
+        Block::decode_checked(&encoded, BlockFormat::RestartWithKind, &BYTEWISE_COMPARATOR)
+            .is_err()
+    );
+}
+
+#[test]
+fn test_block_hash_index_point_lookups() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.block_size = 256;
+    options.data_block_hash_index = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    let mut snapshots = Vec::new();
+    for ts in 1..=NUM_VERSIONS {
+        for idx in 0..NUM_KEYS {
+            storage.put(&key_of(idx), &value_of(idx, ts)).unwrap();
+        }
+        snapshots.push(storage.new_txn().unwrap());
+        storage.force_flush().unwrap();
+    }
+    for idx in (0..NUM_KEYS).step_by(3) {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+
+    let check = |storage: &MiniLsm| {
+        for idx in 0..NUM_KEYS {
+            let latest = (idx % 3 != 0).then(|| Bytes::from(value_of(idx, NUM_VERSIONS)));
+            assert_eq!(storage.get(&key_of(idx)).unwrap(), latest);
+            assert_eq!(storage.get(&missing_key_of(idx)).unwrap(), None);
+        }
+    };
+    check(&storage);
+    for (version, snapshot) in snapshots.iter().enumerate() {
+        for idx in 0..NUM_KEYS {
+            assert_eq!(
+                snapshot.get(&key_of(idx)).unwrap(),
+                Some(Bytes::from(value_of(idx, version as u64 + 1)))
+            );
+        }
+    }
+    drop(snapshots);
+
+    storage.force_full_compaction().unwrap();
+    check(&storage);
+    let sst = {
+        let state = storage.inner.state.read();
+        let (_, level) = &state.levels[0];
+        state.sstables[&level[0]].clone()
+    };
+    assert!(!sst.read_block(0).unwrap().hash_buckets.is_empty());
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    check(&storage);
+}
@@ -0,0 +1,170 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::BufMut;
+
+use crate::{
+    block::{Block, BlockBuilder, BlockFormat, BlockIterator},
+    comparator::BYTEWISE_COMPARATOR,
+    key::KeySlice,
+    varint::put_uvarint,
+};
+
+const NUM_KEYS: usize = 300;
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("user/{:03}/item/{:05}", idx / 10, idx * 3).into_bytes()
+}
+
+fn value_of(idx: usize) -> Vec<u8> {
+    format!("value_{idx}").into_bytes()
+}
+
+fn build_block(restart_interval: usize) -> Block {
+    let mut builder = BlockBuilder::new_with_restart_interval(1 << 20, restart_interval);
+    for idx in 0..NUM_KEYS {
+        assert!(builder.add(
+            KeySlice::for_testing_from_slice_with_ts(&key_of(idx), 1),
+            &value_of(idx)
+        ));
+    }
+    builder.build()
+}
+
+fn check_block(block: Arc<Block>) {
+    let mut iter = BlockIterator::create_and_seek_to_first(block.clone());
+    for idx in 0..NUM_KEYS {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key().for_testing_key_ref(), key_of(idx).as_slice());
+        assert_eq!(iter.value(), value_of(idx).as_slice());
+        iter.next();
+    }
+    assert!(!iter.is_valid());
+
+    for idx in 0..NUM_KEYS {
+        let key = key_of(idx);
+        let iter = BlockIterator::create_and_seek_to_key(
+            block.clone(),
+            KeySlice::for_testing_from_slice_with_ts(&key, 1),
+        );
+        assert!(iter.is_valid());
+        assert_eq!(iter.key().for_testing_key_ref(), key.as_slice());
+        assert_eq!(iter.value(), value_of(idx).as_slice());
+
+        // A key between `idx` and `idx + 1` seeks to `idx + 1`.
+        let mut between = key.clone();
+        between.push(0);
+        let iter = BlockIterator::create_and_seek_to_key(
+            block.clone(),
+            KeySlice::for_testing_from_slice_with_ts(&between, 1),
+        );
+        if idx + 1 < NUM_KEYS {
+            assert_eq!(iter.key().for_testing_key_ref(), key_of(idx + 1).as_slice());
+        } else {
+            assert!(!iter.is_valid());
+        }
+    }
+
+    let iter = BlockIterator::create_and_seek_to_key(
+        block,
+        KeySlice::for_testing_from_slice_with_ts(b"a", 1),
+    );
+    assert_eq!(iter.key().for_testing_key_ref(), key_of(0).as_slice());
+}
+
+#[test]
+fn test_block_restart_points_seek() {
+    for restart_interval in [1, 2, 7, 16, NUM_KEYS + 1] {
+        let block = build_block(restart_interval);
+        assert_eq!(block.format, BlockFormat::RestartWithKind);
+        assert_eq!(block.restarts.len(), NUM_KEYS.div_ceil(restart_interval));
+        let encoded = block.encode();
+        let decoded = Block::decode(&encoded);
+        assert_eq!(decoded.offsets, block.offsets);
+        assert_eq!(decoded.restarts, block.restarts);
+        check_block(Arc::new(decoded));
+    }
+}
+
+#[test]
+fn test_block_restart_points_compress_keys() {
+    let every_key = build_block(1).encode().len();
+    let restarts = build_block(16).encode().len();
+    assert!(
+        restarts * 3 < every_key * 2,
+        "{restarts} bytes with restart points, {every_key} bytes without"
+    );
+}
+
+#[test]
+fn test_block_reads_varint_blocks() {
+    // A block of SST format version 4 lists every entry in its footer, and keys share their
+    // prefixes with the first key of the block.
+    let first_key = key_of(0);
+    let mut data = Vec::new();
+    let mut offsets = Vec::new();
+    for idx in 0..NUM_KEYS {
+        let key = key_of(idx);
+        let overlap = if idx == 0 {
+            0
+        } else {
+            first_key
+                .iter()
+                .zip(&key)
+                .take_while(|(a, b)| a == b)
+                .count()
+        };
+        offsets.push(data.len() as u16);
+        put_uvarint(&mut data, overlap as u64);
+        put_uvarint(&mut data, (key.len() - overlap) as u64);
+        data.put_slice(&key[overlap..]);
+        data.put_u64(1);
+        put_uvarint(&mut data, value_of(idx).len() as u64);
+        data.put_slice(&value_of(idx));
+    }
+    for offset in &offsets {
+        data.put_u16(*offset);
+    }
+    data.put_u16(offsets.len() as u16);
+
+    let block = Block::decode_checked(&data, BlockFormat::Varint, &BYTEWISE_COMPARATOR).unwrap();
+    assert!(block.restarts.is_empty());
+    check_block(Arc::new(block));
+}
+
+#[test]
+fn test_block_restart_rejects_bad_restart_points() {
+    let block = build_block(4);
+    let encoded = block.encode();
+    let restarts_offset = encoded.len() - 2 - block.restarts.len() * 2;
+
+    // A restart point in the middle of an entry.
+    let mut corrupt = encoded.to_vec();
+    corrupt[restarts_offset + 3] ^= 1;
+    assert!(
+        Block::decode_checked(&corrupt, BlockFormat::RestartWithKind, &BYTEWISE_COMPARATOR)
+            .is_err()
+    );
+
+    // A restart point at an entry that shares its prefix with the previous key.
+    let second_entry = block.offsets[1];
+    let mut corrupt = encoded.to_vec();
+    corrupt[restarts_offset + 2..restarts_offset + 4].copy_from_slice(&second_entry.to_be_bytes());
+    assert!(
+        Block::decode_checked(&corrupt, BlockFormat::RestartWithKind, &BYTEWISE_COMPARATOR)
+            .is_err()
+    );
+}
@@ -0,0 +1,174 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions},
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn options(enable_wal: bool) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = enable_wal;
+    options.value_separation_threshold = Some(32);
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+/// Even keys get values that are stored in the value log.
+fn value_of(idx: usize, version: usize) -> Vec<u8> {
+    let value = format!("value_{:05}_{}", idx, version);
+    if idx.is_multiple_of(2) {
+        value.repeat(4).into_bytes()
+    } else {
+        value.into_bytes()
+    }
+}
+
+fn scan_cf(storage: &MiniLsm, column_family: &str) -> Vec<(Bytes, Bytes)> {
+    let mut iter = storage
+        .scan_cf(column_family, Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    result
+}
+
+#[test]
+fn test_checkpoint_is_independent_of_live_database() {
+    let dir = tempdir().unwrap();
+    let checkpoint_dir = tempdir().unwrap();
+    let checkpoint = checkpoint_dir.path().join("checkpoint");
+    let storage = MiniLsm::open(&dir, options(true)).unwrap();
+    storage
+        .create_column_family(
+            "users",
+            CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+                size_ratio_percent: 200,
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 2,
+            }),
+        )
+        .unwrap();
+    for idx in 0..40 {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+        storage.put_cf("users", &key_of(idx), b"user").unwrap();
+        if idx % 10 == 9 {
+            storage.force_flush().unwrap();
+        }
+    }
+    storage.delete(&key_of(3)).unwrap();
+    // Left in the mem-table, and only recovered from the copied WAL.
+    storage.put(&key_of(100), &value_of(100, 1)).unwrap();
+    storage.put_cf("users", &key_of(100), b"user").unwrap();
+    let expected = scan_cf(&storage, "default");
+    let expected_users = scan_cf(&storage, "users");
+
+    storage.create_checkpoint(&checkpoint).unwrap();
+    assert!(storage.create_checkpoint(&checkpoint).is_err());
+
+    // Compacting the live database deletes the SSTs that the checkpoint links.
+    for idx in 0..40 {
+        storage.put(&key_of(idx), &value_of(idx, 2)).unwrap();
+        storage.delete_cf("users", &key_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let restored = MiniLsm::open(&checkpoint, options(true)).unwrap();
+    assert_eq!(scan_cf(&restored, "default"), expected);
+    assert_eq!(scan_cf(&restored, "users"), expected_users);
+    assert_eq!(restored.get(&key_of(3)).unwrap(), None);
+    assert_eq!(
+        restored.get(&key_of(100)).unwrap(),
+        Some(Bytes::from(value_of(100, 1)))
+    );
+
+    // The checkpoint accepts writes of its own, and keeps them across a reopen.
+    restored.put(&key_of(3), &value_of(3, 3)).unwrap();
+    restored.force_flush().unwrap();
+    restored.close().unwrap();
+    drop(restored);
+    let restored = MiniLsm::open(&checkpoint, options(true)).unwrap();
+    assert_eq!(
+        restored.get(&key_of(3)).unwrap(),
+        Some(Bytes::from(value_of(3, 3)))
+    );
+    assert_eq!(
+        restored.get(&key_of(0)).unwrap(),
+        Some(Bytes::from(value_of(0, 1)))
+    );
+
+    let storage = MiniLsm::open(&dir, options(true)).unwrap();
+    assert_eq!(
+        storage.get(&key_of(3)).unwrap(),
+        Some(Bytes::from(value_of(3, 2)))
+    );
+    assert_eq!(
+        scan_cf(&storage, "users"),
+        vec![(Bytes::from(key_of(100)), Bytes::from("user"))]
+    );
+}
+
+#[test]
+fn test_checkpoint_without_wal_flushes_memtables() {
+    let dir = tempdir().unwrap();
+    let checkpoint_dir = tempdir().unwrap();
+    let checkpoint = checkpoint_dir.path().join("checkpoint");
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    storage
+        .create_column_family("users", CompactionOptions::NoCompaction)
+        .unwrap();
+    for idx in 0..20 {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.put_cf("users", b"a", b"1").unwrap();
+    storage.create_checkpoint(&checkpoint).unwrap();
+    assert!(std::fs::read_dir(&checkpoint).unwrap().all(|entry| {
+        !entry
+            .unwrap()
+            .file_name()
+            .to_str()
+            .unwrap()
+            .ends_with(".wal")
+    }));
+
+    let restored = MiniLsm::open(&checkpoint, options(false)).unwrap();
+    for idx in 0..20 {
+        assert_eq!(
+            restored.get(&key_of(idx)).unwrap(),
+            Some(Bytes::from(value_of(idx, 1)))
+        );
+    }
+    assert_eq!(
+        restored.get_cf("users", b"a").unwrap(),
+        Some(Bytes::from("1"))
+    );
+}
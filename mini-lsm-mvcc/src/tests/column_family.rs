@@ -0,0 +1,391 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions},
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+    merge_operator::StringAppendOperator,
+};
+
+fn options(enable_wal: bool) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = enable_wal;
+    options
+}
+
+fn collect(mut iter: impl for<'a> StorageIterator<KeyType<'a> = &'a [u8]>) -> Vec<(Bytes, Bytes)> {
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    result
+}
+
+fn scan_cf(storage: &MiniLsm, column_family: &str) -> Vec<(Bytes, Bytes)> {
+    collect(
+        storage
+            .scan_cf(column_family, Bound::Unbounded, Bound::Unbounded)
+            .unwrap(),
+    )
+}
+
+fn pairs(pairs: &[(&'static str, &'static str)]) -> Vec<(Bytes, Bytes)> {
+    pairs
+        .iter()
+        .map(|(key, value)| (Bytes::from(*key), Bytes::from(*value)))
+        .collect()
+}
+
+#[test]
+fn test_column_families_are_separate_key_spaces() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    storage
+        .create_column_family("users", CompactionOptions::NoCompaction)
+        .unwrap();
+    assert!(
+        storage
+            .create_column_family("users", CompactionOptions::NoCompaction)
+            .is_err()
+    );
+    assert!(
+        storage
+            .create_column_family("default", CompactionOptions::NoCompaction)
+            .is_err()
+    );
+    assert!(storage.put_cf("missing", b"a", b"1").is_err());
+    assert!(storage.get_cf("missing", b"a").is_err());
+
+    storage.put(b"a", b"default").unwrap();
+    storage.put_cf("users", b"a", b"users").unwrap();
+    storage.put_cf("users", b"b", b"users").unwrap();
+    storage.force_flush().unwrap();
+    storage.delete_cf("users", b"b").unwrap();
+    storage.put_cf("users", b"c", b"users").unwrap();
+
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("default")));
+    assert_eq!(
+        storage.get_cf("default", b"a").unwrap(),
+        Some(Bytes::from("default"))
+    );
+    assert_eq!(
+        storage.get_cf("users", b"a").unwrap(),
+        Some(Bytes::from("users"))
+    );
+    assert_eq!(storage.get_cf("users", b"b").unwrap(), None);
+    assert_eq!(scan_cf(&storage, "default"), pairs(&[("a", "default")]));
+    assert_eq!(
+        scan_cf(&storage, "users"),
+        pairs(&[("a", "users"), ("c", "users")])
+    );
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    assert_eq!(scan_cf(&storage, "default"), pairs(&[("a", "default")]));
+    assert_eq!(
+        scan_cf(&storage, "users"),
+        pairs(&[("a", "users"), ("c", "users")])
+    );
+}
+
+#[test]
+fn test_write_batch_across_column_families_is_recovered_from_wal() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(true)).unwrap();
+    storage
+        .create_column_family("users", CompactionOptions::NoCompaction)
+        .unwrap();
+    storage
+        .create_column_family("orders", CompactionOptions::NoCompaction)
+        .unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::Put(b"k".to_vec(), b"default".to_vec()),
+            WriteBatchRecord::ColumnFamily(
+                "users".to_string(),
+                vec![
+                    WriteBatchRecord::Put(b"k".to_vec(), b"users".to_vec()),
+                    WriteBatchRecord::Put(b"l".to_vec(), b"users".to_vec()),
+                ],
+            ),
+            WriteBatchRecord::ColumnFamily(
+                "orders".to_string(),
+                vec![WriteBatchRecord::Put(b"k".to_vec(), b"orders".to_vec())],
+            ),
+        ])
+        .unwrap();
+    // Only the column families are written to the flushed mem-tables.
+    storage.force_flush().unwrap();
+    storage
+        .write_batch(&[WriteBatchRecord::ColumnFamily(
+            "users".to_string(),
+            vec![
+                WriteBatchRecord::DelRange(b"k".to_vec(), b"l".to_vec()),
+                WriteBatchRecord::Put(b"m".to_vec(), b"users".to_vec()),
+            ],
+        )])
+        .unwrap();
+    storage.force_flush().unwrap();
+    storage
+        .write_batch(&[WriteBatchRecord::ColumnFamily(
+            "orders".to_string(),
+            vec![WriteBatchRecord::Del(b"k".to_vec())],
+        )])
+        .unwrap();
+    // A batch to a missing column family is rejected as a whole.
+    assert!(
+        storage
+            .write_batch(&[
+                WriteBatchRecord::Put(b"x".to_vec(), b"default".to_vec()),
+                WriteBatchRecord::ColumnFamily(
+                    "missing".to_string(),
+                    vec![WriteBatchRecord::Put(b"x".to_vec(), b"missing".to_vec())],
+                ),
+            ])
+            .is_err()
+    );
+
+    let check = |storage: &MiniLsm| {
+        assert_eq!(scan_cf(storage, "default"), pairs(&[("k", "default")]));
+        assert_eq!(
+            scan_cf(storage, "users"),
+            pairs(&[("l", "users"), ("m", "users")])
+        );
+        assert_eq!(scan_cf(storage, "orders"), vec![]);
+    };
+    check(&storage);
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options(true)).unwrap();
+    check(&storage);
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options(true)).unwrap();
+    check(&storage);
+}
+
+#[test]
+fn test_column_family_compaction_options() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    storage
+        .create_column_family(
+            "compacted",
+            CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 2,
+                size_ratio_percent: 200,
+            }),
+        )
+        .unwrap();
+    for round in 0..4 {
+        for idx in 0..10 {
+            let key = format!("key_{idx:02}");
+            let value = format!("value_{round}");
+            storage.put(key.as_bytes(), value.as_bytes()).unwrap();
+            storage
+                .put_cf("compacted", key.as_bytes(), value.as_bytes())
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    for _ in 0..100 {
+        std::thread::sleep(Duration::from_millis(50));
+        let state = storage.inner.state.read();
+        if state.column_families["compacted"]
+            .state
+            .l0_sstables
+            .is_empty()
+        {
+            break;
+        }
+    }
+    // The default column family is not compacted.
+    assert_eq!(storage.inner.state.read().l0_sstables.len(), 4);
+    assert!(
+        storage.inner.state.read().column_families["compacted"]
+            .state
+            .l0_sstables
+            .is_empty()
+    );
+
+    let expected = (0..10)
+        .map(|idx| {
+            (
+                Bytes::from(format!("key_{idx:02}")),
+                Bytes::from_static(b"value_3"),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(scan_cf(&storage, "default"), expected);
+    assert_eq!(scan_cf(&storage, "compacted"), expected);
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    assert_eq!(scan_cf(&storage, "default"), expected);
+    assert_eq!(scan_cf(&storage, "compacted"), expected);
+}
+
+#[test]
+fn test_transaction_across_column_families() {
+    let dir = tempdir().unwrap();
+    let mut options = options(false);
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage
+        .create_column_family("users", CompactionOptions::NoCompaction)
+        .unwrap();
+    storage.put_cf("users", b"a", b"1").unwrap();
+
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"a", b"default");
+    txn.put_cf("users", b"a", b"2").unwrap();
+    txn.delete_cf("users", b"b").unwrap();
+    assert!(txn.put_cf("missing", b"a", b"1").is_err());
+    assert_eq!(
+        txn.get_cf("users", b"a").unwrap(),
+        Some(Bytes::from_static(b"2"))
+    );
+    assert_eq!(
+        collect(
+            txn.scan_cf("users", Bound::Unbounded, Bound::Unbounded)
+                .unwrap()
+        ),
+        pairs(&[("a", "2")])
+    );
+    // The writes are not visible before the commit.
+    assert_eq!(
+        storage.get_cf("users", b"a").unwrap(),
+        Some(Bytes::from_static(b"1"))
+    );
+    txn.commit().unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("default")));
+    assert_eq!(
+        storage.get_cf("users", b"a").unwrap(),
+        Some(Bytes::from_static(b"2"))
+    );
+
+    // The same key in another column family does not conflict.
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.get_cf("users", b"a").unwrap();
+    txn1.put(b"b", b"1");
+    txn2.put(b"a", b"2");
+    txn2.commit().unwrap();
+    txn1.commit().unwrap();
+
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.get_cf("users", b"a").unwrap();
+    txn1.put(b"b", b"2");
+    txn2.put_cf("users", b"a", b"3").unwrap();
+    txn2.commit().unwrap();
+    assert!(txn1.commit().is_err());
+}
+
+#[test]
+fn test_column_family_flush_separates_values() {
+    let dir = tempdir().unwrap();
+    let mut options = options(true);
+    options.value_separation_threshold = Some(64);
+    let large = "large_value_".repeat(8);
+    {
+        let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+        storage
+            .create_column_family("blobs", CompactionOptions::NoCompaction)
+            .unwrap();
+        storage.put_cf("blobs", b"large", large.as_bytes()).unwrap();
+        storage.put_cf("blobs", b"small", b"1").unwrap();
+        storage.force_flush().unwrap();
+
+        let state = storage.inner.state.read();
+        let family = &state.column_families["blobs"];
+        assert_eq!(family.state.l0_sstables.len(), 1);
+        let sst = family.state.sstables.values().next().unwrap();
+        assert_eq!(sst.column_family(), Some(family.id));
+        // The large value is in the value log file written with the SST of the column family.
+        assert!(
+            dir.path()
+                .join(format!("{:05}.vlog", sst.sst_id()))
+                .exists()
+        );
+        assert!(state.l0_sstables.is_empty());
+    }
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(
+        scan_cf(&storage, "blobs"),
+        vec![
+            (Bytes::from("large"), Bytes::from(large)),
+            (Bytes::from("small"), Bytes::from("1")),
+        ]
+    );
+}
+
+#[test]
+fn test_column_family_merge_delete_range_and_ttl() {
+    for serializable in [false, true] {
+        let dir = tempdir().unwrap();
+        let mut options = options(true);
+        options.serializable = serializable;
+        options.merge_operator = Some(Arc::new(StringAppendOperator(b',')));
+        let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+        storage
+            .create_column_family("users", CompactionOptions::NoCompaction)
+            .unwrap();
+        for key in ["a", "b", "c", "d"] {
+            storage.put_cf("users", key.as_bytes(), b"1").unwrap();
+            storage.put(key.as_bytes(), b"1").unwrap();
+        }
+        storage.merge_cf("users", b"a", b"2").unwrap();
+        storage.delete_range_cf("users", b"b", b"d").unwrap();
+        storage
+            .put_with_ttl_cf("users", b"e", b"1", Duration::ZERO)
+            .unwrap();
+        storage
+            .put_with_ttl_cf("users", b"f", b"1", Duration::from_secs(3600))
+            .unwrap();
+        assert!(storage.delete_range_cf("missing", b"a", b"b").is_err());
+
+        let expected = pairs(&[("a", "1,2"), ("d", "1"), ("f", "1")]);
+        assert_eq!(scan_cf(&storage, "users"), expected);
+        // The default column family is not changed.
+        assert_eq!(
+            collect(storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap()),
+            pairs(&[("a", "1"), ("b", "1"), ("c", "1"), ("d", "1")])
+        );
+        storage.force_flush().unwrap();
+        assert_eq!(scan_cf(&storage, "users"), expected);
+        storage.close().unwrap();
+
+        let storage = MiniLsm::open(&dir, options).unwrap();
+        assert_eq!(scan_cf(&storage, "users"), expected);
+        assert_eq!(storage.get_cf("users", b"b").unwrap(), None);
+    }
+}
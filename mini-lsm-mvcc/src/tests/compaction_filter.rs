@@ -0,0 +1,213 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions},
+    lsm_storage::{
+        CompactionDecision, CompactionFilter, LsmStorageOptions, MiniLsm, PrefixCompactionFilter,
+    },
+    merge_operator::StringAppendOperator,
+};
+
+/// Upgrades the values from the `v1:` schema to `v2:`, and records the levels it runs at.
+#[derive(Debug, Default)]
+struct MigrationFilter {
+    levels: Mutex<Vec<usize>>,
+}
+
+impl CompactionFilter for MigrationFilter {
+    fn name(&self) -> &str {
+        "migration"
+    }
+
+    fn filter(
+        &self,
+        level: usize,
+        _key: &[u8],
+        _ts: u64,
+        value: &[u8],
+    ) -> Result<CompactionDecision> {
+        self.levels.lock().push(level);
+        Ok(match value.strip_prefix(b"v1:") {
+            Some(value) => CompactionDecision::ChangeValue(Bytes::from([b"v2:", value].concat())),
+            None => CompactionDecision::Keep,
+        })
+    }
+}
+
+/// Removes the values equal to `value`.
+#[derive(Debug)]
+struct ValueFilter {
+    value: &'static [u8],
+}
+
+impl CompactionFilter for ValueFilter {
+    fn name(&self) -> &str {
+        "value"
+    }
+
+    fn filter(
+        &self,
+        _level: usize,
+        _key: &[u8],
+        _ts: u64,
+        value: &[u8],
+    ) -> Result<CompactionDecision> {
+        Ok(if value == self.value {
+            CompactionDecision::Remove
+        } else {
+            CompactionDecision::Keep
+        })
+    }
+}
+
+#[derive(Debug)]
+struct FailingFilter;
+
+impl CompactionFilter for FailingFilter {
+    fn name(&self) -> &str {
+        "failing"
+    }
+
+    fn filter(&self, _: usize, _: &[u8], _: u64, _: &[u8]) -> Result<CompactionDecision> {
+        bail!("cannot decode the value")
+    }
+}
+
+#[test]
+fn test_compaction_filters_remove_and_change_values() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.merge_operator = Some(Arc::new(StringAppendOperator(b',')));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"tenant1/a", b"v1:a").unwrap();
+    storage.put(b"tenant1/b", b"v1:b").unwrap();
+    storage.put(b"tenant2/a", b"v1:a").unwrap();
+    storage.put(b"tenant2/b", b"v2:b").unwrap();
+    storage.put(b"tenant2/c", b"v1:c").unwrap();
+    storage.force_flush().unwrap();
+    storage.merge(b"tenant2/c", b"d").unwrap();
+    // A reader that started before the update keeps the version it sees.
+    let snapshot = storage.new_txn().unwrap();
+    storage.put(b"tenant2/a", b"v1:updated").unwrap();
+    storage.force_flush().unwrap();
+
+    let migration = Arc::new(MigrationFilter::default());
+    storage.add_compaction_filter(Arc::new(PrefixCompactionFilter(Bytes::from("tenant1/"))));
+    storage.add_compaction_filter(migration.clone());
+    storage.force_full_compaction().unwrap();
+
+    assert_eq!(storage.get(b"tenant1/a").unwrap(), None);
+    assert_eq!(storage.get(b"tenant1/b").unwrap(), None);
+    assert_eq!(
+        storage.get(b"tenant2/a").unwrap(),
+        Some(Bytes::from_static(b"v1:updated"))
+    );
+    assert_eq!(
+        snapshot.get(b"tenant2/a").unwrap(),
+        Some(Bytes::from_static(b"v2:a"))
+    );
+    assert_eq!(
+        storage.get(b"tenant2/b").unwrap(),
+        Some(Bytes::from_static(b"v2:b"))
+    );
+    // The merge operands are filtered once they are folded into the value.
+    assert_eq!(
+        storage.get(b"tenant2/c").unwrap(),
+        Some(Bytes::from_static(b"v2:c,d"))
+    );
+    // The filters only run after the removal by the prefix filter.
+    let levels = std::mem::take(&mut *migration.levels.lock());
+    assert_eq!(levels, vec![1; 3]);
+
+    drop(snapshot);
+    storage.force_full_compaction().unwrap();
+    assert_eq!(
+        storage.get(b"tenant2/a").unwrap(),
+        Some(Bytes::from_static(b"v2:updated"))
+    );
+}
+
+#[test]
+fn test_compaction_filter_removal_hides_lower_levels() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        &dir,
+        LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+            SimpleLeveledCompactionOptions {
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 3,
+                size_ratio_percent: 200,
+            },
+        )),
+    )
+    .unwrap();
+    let wait_for_compaction = || {
+        for _ in 0..100 {
+            std::thread::sleep(Duration::from_millis(50));
+            if storage.inner.state.read().l0_sstables.is_empty() {
+                break;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    };
+
+    storage.put(b"key", b"old").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"other", b"old").unwrap();
+    storage.force_flush().unwrap();
+    wait_for_compaction();
+    assert!(
+        !storage.inner.state.read().levels[2].1.is_empty(),
+        "the old versions are in the bottom level"
+    );
+
+    storage.add_compaction_filter(Arc::new(ValueFilter { value: b"removed" }));
+    storage.put(b"key", b"removed").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"other", b"new").unwrap();
+    storage.force_flush().unwrap();
+    wait_for_compaction();
+    // The removed version is replaced by a deletion until the compaction reaches the old version.
+    assert_eq!(storage.get(b"key").unwrap(), None);
+    assert_eq!(
+        storage.get(b"other").unwrap(),
+        Some(Bytes::from_static(b"new"))
+    );
+}
+
+#[test]
+fn test_compaction_filter_error_fails_compaction() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        &dir,
+        LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction),
+    )
+    .unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.force_flush().unwrap();
+    storage.add_compaction_filter(Arc::new(FailingFilter));
+    assert!(storage.force_full_compaction().is_err());
+    assert_eq!(
+        storage.get(b"key").unwrap(),
+        Some(Bytes::from_static(b"value"))
+    );
+}
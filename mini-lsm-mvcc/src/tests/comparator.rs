@@ -0,0 +1,211 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    comparator::Comparator,
+    iterators::{StorageIterator, merge_iterator::MergeIterator},
+    key::KeySlice,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    mem_table::MemTable,
+    table::{FileObject, SsTable},
+};
+
+/// Orders the keys from the largest to the smallest.
+struct ReverseComparator;
+
+impl Comparator for ReverseComparator {
+    fn name(&self) -> &str {
+        "reverse-bytewise"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        b.cmp(a)
+    }
+}
+
+static REVERSE_COMPARATOR: ReverseComparator = ReverseComparator;
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    options.comparator = &REVERSE_COMPARATOR;
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{idx:04}").into_bytes()
+}
+
+fn value_of(idx: usize) -> Vec<u8> {
+    format!("value_{idx}").into_bytes()
+}
+
+fn collect(mut iter: impl for<'a> StorageIterator<KeyType<'a> = &'a [u8]>) -> Vec<Bytes> {
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push(Bytes::copy_from_slice(iter.key()));
+        iter.next().unwrap();
+    }
+    result
+}
+
+fn keys(indices: impl Iterator<Item = usize>) -> Vec<Bytes> {
+    indices.map(|idx| Bytes::from(key_of(idx))).collect()
+}
+
+/// Check that the keys `0..100` are all there and in descending order.
+fn check(storage: &MiniLsm) {
+    for idx in 0..100 {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap().as_deref(),
+            Some(&value_of(idx)[..]),
+            "key {idx}"
+        );
+    }
+    assert_eq!(
+        collect(storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap()),
+        keys((0..100).rev())
+    );
+    // The lower bound comes first in the order of the comparator.
+    assert_eq!(
+        collect(
+            storage
+                .scan(Bound::Included(&key_of(80)), Bound::Excluded(&key_of(20)))
+                .unwrap()
+        ),
+        keys((21..=80).rev())
+    );
+}
+
+#[test]
+fn test_comparator_orders_memtables_ssts_and_compaction() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    for idx in (0..100).step_by(2) {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (1..100).step_by(2) {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    check(&storage);
+
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"key_0050a", b"local");
+    txn.delete(&key_of(50));
+    let mut expected = keys((51..100).rev());
+    expected.push(Bytes::from_static(b"key_0050a"));
+    expected.extend(keys((0..50).rev()));
+    assert_eq!(
+        collect(txn.scan(Bound::Unbounded, Bound::Unbounded).unwrap()),
+        expected
+    );
+    drop(txn);
+
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    check(&storage);
+    storage.close().unwrap();
+
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    check(&storage);
+}
+
+#[test]
+fn test_open_with_another_comparator_is_rejected() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    storage.close().unwrap();
+
+    let mut bytewise = options();
+    bytewise.comparator = LsmStorageOptions::default_for_week1_test().comparator;
+    assert!(MiniLsm::open(&dir, bytewise).is_err());
+
+    let file = FileObject::open(&storage.inner.path_of_sst(sst_id)).unwrap();
+    assert!(SsTable::open(sst_id, None, file).is_err());
+    let file = FileObject::open(&storage.inner.path_of_sst(sst_id)).unwrap();
+    let table = SsTable::open_with_comparator(sst_id, None, file, &REVERSE_COMPARATOR).unwrap();
+    assert_eq!(table.first_key().key_ref(), key_of(9));
+    assert_eq!(table.last_key().key_ref(), key_of(0));
+}
+
+#[test]
+fn test_delete_range_with_comparator() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    // The range starts at its first key in the order of the comparator.
+    storage.delete_range(&key_of(70), &key_of(30)).unwrap();
+    let expected = keys((71..100).rev().chain((0..=30).rev()));
+    assert_eq!(
+        collect(storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap()),
+        expected
+    );
+    assert_eq!(storage.get(&key_of(50)).unwrap(), None);
+    assert_eq!(
+        storage.get(&key_of(30)).unwrap().as_deref(),
+        Some(&value_of(30)[..])
+    );
+
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert_eq!(
+        collect(storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap()),
+        expected
+    );
+
+    // Keys with a common prefix are not adjacent in every order.
+    assert!(storage.prefix_scan(b"key_00").is_err());
+}
+
+#[test]
+fn test_memtables_merge_in_comparator_order() {
+    let first = MemTable::create_with_comparator(0, &REVERSE_COMPARATOR);
+    let second = MemTable::create_with_comparator(1, &REVERSE_COMPARATOR);
+    for (memtable, keys) in [(&first, [b"a", b"c"]), (&second, [b"b", b"d"])] {
+        for key in keys {
+            memtable
+                .put(KeySlice::from_slice_with_ts(key, 1), b"value")
+                .unwrap();
+        }
+    }
+    let mut iter = MergeIterator::create_with_comparator(
+        vec![
+            Box::new(first.scan(Bound::Unbounded, Bound::Unbounded)),
+            Box::new(second.scan(Bound::Unbounded, Bound::Unbounded)),
+        ],
+        &REVERSE_COMPARATOR,
+    );
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(iter.key().key_ref().to_vec());
+        iter.next().unwrap();
+    }
+    assert_eq!(keys, [b"d", b"c", b"b", b"a"]);
+}
@@ -0,0 +1,277 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions},
+    ingest::SstFileWriter,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn options(compaction_options: CompactionOptions) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(compaction_options);
+    options.enable_wal = true;
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+fn value_of(idx: usize, version: usize) -> Vec<u8> {
+    format!("value_{:05}_{}", idx, version).into_bytes()
+}
+
+/// Write an external SST with the keys in the range, and deletions of the keys in `deleted`.
+fn write_sst(
+    dir: &Path,
+    name: &str,
+    keys: std::ops::Range<usize>,
+    deleted: &[usize],
+    version: usize,
+    options: &LsmStorageOptions,
+) -> PathBuf {
+    let path = dir.join(name);
+    let mut writer = SstFileWriter::create(&path, options);
+    for idx in keys {
+        if deleted.contains(&idx) {
+            writer.delete(&key_of(idx)).unwrap();
+        } else {
+            writer.put(&key_of(idx), &value_of(idx, version)).unwrap();
+        }
+    }
+    writer.finish().unwrap();
+    path
+}
+
+#[test]
+fn test_sst_file_writer_requires_sorted_keys() {
+    let dir = tempdir().unwrap();
+    let options = options(CompactionOptions::NoCompaction);
+    let mut writer = SstFileWriter::create(dir.path().join("1.sst"), &options);
+    writer.put(b"b", b"1").unwrap();
+    assert!(writer.put(b"a", b"1").is_err());
+    assert!(writer.put(b"b", b"2").is_err());
+    assert!(writer.put(b"c", b"").is_err());
+    writer.put(b"c", b"1").unwrap();
+    writer.finish().unwrap();
+
+    let writer = SstFileWriter::create(dir.path().join("2.sst"), &options);
+    assert!(writer.finish().is_err());
+}
+
+#[test]
+fn test_ingest_into_lowest_level() {
+    let dir = tempdir().unwrap();
+    let files = tempdir().unwrap();
+    let options = options(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+
+    // Ingested keys are newer than the keys written before.
+    storage.put(&key_of(1), &value_of(1, 0)).unwrap();
+    let first = write_sst(files.path(), "first.sst", 0..10, &[5], 1, &options);
+    storage.ingest_external_files(&[first]).unwrap();
+    assert_eq!(storage.inner.state.read().levels[0].1.len(), 1);
+    assert_eq!(
+        storage.get(&key_of(1)).unwrap(),
+        Some(Bytes::from(value_of(1, 1)))
+    );
+    assert_eq!(storage.get(&key_of(5)).unwrap(), None);
+    storage.put(&key_of(2), &value_of(2, 2)).unwrap();
+    assert_eq!(
+        storage.get(&key_of(2)).unwrap(),
+        Some(Bytes::from(value_of(2, 2)))
+    );
+
+    storage.put(&key_of(20), &value_of(20, 2)).unwrap();
+    storage.force_flush().unwrap();
+    // The first SST overlaps L0 and goes there, the second is placed after the SST in level 1.
+    let overlapping = write_sst(files.path(), "overlapping.sst", 15..25, &[], 3, &options);
+    let last = write_sst(files.path(), "last.sst", 30..40, &[], 3, &options);
+    let both = write_sst(files.path(), "both.sst", 35..45, &[], 3, &options);
+    assert!(
+        storage
+            .ingest_external_files(&[last.clone(), both])
+            .is_err()
+    );
+    storage.ingest_external_files(&[last, overlapping]).unwrap();
+    let check = |storage: &MiniLsm| {
+        {
+            let state = storage.inner.state.read();
+            assert_eq!(state.l0_sstables.len(), 2);
+            assert_eq!(state.levels[0].1.len(), 2);
+            let first_keys = state.levels[0]
+                .1
+                .iter()
+                .map(|id| state.sstables[id].first_key().key_ref().to_vec())
+                .collect::<Vec<_>>();
+            assert_eq!(first_keys, vec![key_of(0), key_of(30)]);
+        }
+        for idx in (0..10).chain(15..25).chain(30..40) {
+            let version = match idx {
+                2 => 2,
+                0..10 => 1,
+                _ => 3,
+            };
+            let expected = (idx != 5).then(|| Bytes::from(value_of(idx, version)));
+            assert_eq!(storage.get(&key_of(idx)).unwrap(), expected, "key {idx}");
+        }
+    };
+    check(&storage);
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    check(&storage);
+    // The timestamps of the ingested keys are recovered.
+    storage.put(&key_of(30), &value_of(30, 4)).unwrap();
+    assert_eq!(
+        storage.get(&key_of(30)).unwrap(),
+        Some(Bytes::from(value_of(30, 4)))
+    );
+}
+
+#[test]
+fn test_ingest_with_compaction() {
+    let dir = tempdir().unwrap();
+    let files = tempdir().unwrap();
+    let options = options(CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+        level0_file_num_compaction_trigger: 2,
+        max_levels: 3,
+        size_ratio_percent: 200,
+    }));
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for round in 0..3 {
+        for idx in 0..100 {
+            storage.put(&key_of(idx), &value_of(idx, round)).unwrap();
+        }
+        storage.force_flush().unwrap();
+        let path = write_sst(
+            files.path(),
+            &format!("{round}.sst"),
+            (round + 1) * 100..(round + 2) * 100,
+            &[],
+            round,
+            &options,
+        );
+        storage.ingest_external_files(&[path]).unwrap();
+    }
+    for _ in 0..100 {
+        std::thread::sleep(Duration::from_millis(50));
+        if storage.inner.state.read().l0_sstables.len() < 2 {
+            break;
+        }
+    }
+    let check = |storage: &MiniLsm| {
+        for idx in 0..100 {
+            assert_eq!(
+                storage.get(&key_of(idx)).unwrap(),
+                Some(Bytes::from(value_of(idx, 2)))
+            );
+        }
+        for idx in 100..400 {
+            assert_eq!(
+                storage.get(&key_of(idx)).unwrap(),
+                Some(Bytes::from(value_of(idx, idx / 100 - 1)))
+            );
+        }
+    };
+    check(&storage);
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    check(&storage);
+}
+
+#[test]
+fn test_ingest_into_column_family() {
+    let dir = tempdir().unwrap();
+    let files = tempdir().unwrap();
+    let mut options = options(CompactionOptions::NoCompaction);
+    options.value_separation_threshold = Some(8);
+    options.value_log_gc_discard_ratio = 0.0;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage
+        .create_column_family("users", CompactionOptions::NoCompaction)
+        .unwrap();
+    storage
+        .put_cf("users", &key_of(1), &value_of(1, 0))
+        .unwrap();
+    let snapshot = storage.new_txn().unwrap();
+
+    let path = write_sst(files.path(), "users.sst", 0..10, &[5], 1, &options);
+    assert!(
+        storage
+            .ingest_external_files_cf("missing", &[&path])
+            .is_err()
+    );
+    let ts = storage.ingest_external_files_cf("users", &[path]).unwrap();
+    {
+        let state = storage.inner.state.read();
+        assert!(state.levels[0].1.is_empty());
+        let family = &state.column_families["users"];
+        assert_eq!(family.state.levels[0].1.len(), 1);
+        let sst = &family.state.sstables[&family.state.levels[0].1[0]];
+        assert_eq!(sst.global_ts(), Some(ts));
+        assert_eq!(sst.column_family(), Some(family.id));
+        assert_eq!(sst.first_key().ts(), ts);
+        assert_eq!(sst.max_ts(), ts);
+    }
+
+    let check = |storage: &MiniLsm| {
+        assert_eq!(storage.get(&key_of(1)).unwrap(), None);
+        for idx in 0..10 {
+            let expected = (idx != 5).then(|| Bytes::from(value_of(idx, 1)));
+            assert_eq!(storage.get_cf("users", &key_of(idx)).unwrap(), expected);
+        }
+    };
+    check(&storage);
+    // A snapshot taken before the ingestion does not see the ingested keys.
+    assert_eq!(
+        snapshot.get_cf("users", &key_of(1)).unwrap(),
+        Some(Bytes::from(value_of(1, 0)))
+    );
+    assert_eq!(snapshot.get_cf("users", &key_of(2)).unwrap(), None);
+    let mut iter = snapshot
+        .scan_cf("users", Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    assert_eq!(iter.key(), key_of(1).as_slice());
+    assert_eq!(iter.value(), value_of(1, 0).as_slice());
+    iter.next().unwrap();
+    assert!(!iter.is_valid());
+    drop(iter);
+    drop(snapshot);
+
+    // The values are written back with the timestamp of the ingestion.
+    storage.force_value_log_gc().unwrap();
+    check(&storage);
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    check(&storage);
+    storage
+        .put_cf("users", &key_of(2), &value_of(2, 2))
+        .unwrap();
+    assert_eq!(
+        storage.get_cf("users", &key_of(2)).unwrap(),
+        Some(Bytes::from(value_of(2, 2)))
+    );
+}
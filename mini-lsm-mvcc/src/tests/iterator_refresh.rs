@@ -0,0 +1,132 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+fn value_of(idx: usize, version: usize) -> Vec<u8> {
+    format!("value_{:05}_{}", idx, version).into_bytes()
+}
+
+fn options(iterator_refresh_interval: Option<usize>) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.iterator_refresh_interval = iterator_refresh_interval;
+    options
+}
+
+#[test]
+fn test_refresh_releases_old_state() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(None)).unwrap();
+    for idx in 0..50 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in 50..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    let (memtable, table) = {
+        let state = storage.inner.state.read();
+        let table = state.sstables[&state.l0_sstables[0]].clone();
+        (Arc::downgrade(&state.memtable.map), Arc::downgrade(&table))
+    };
+
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    for idx in 0..30 {
+        assert_eq!(iter.key(), key_of(idx));
+        iter.next().unwrap();
+    }
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert!(memtable.upgrade().is_some());
+    assert!(table.upgrade().is_some());
+
+    iter.refresh().unwrap();
+    assert!(memtable.upgrade().is_none());
+    assert!(table.upgrade().is_none());
+    // The iterator keeps its position and reads at the same timestamp.
+    for idx in 30..100 {
+        assert_eq!(iter.key(), key_of(idx));
+        assert_eq!(iter.value(), value_of(idx, 0));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+    iter.refresh().unwrap();
+    assert!(!iter.is_valid());
+    iter.seek(&key_of(10)).unwrap();
+    assert_eq!(iter.key(), key_of(10));
+    assert_eq!(iter.value(), value_of(10, 0));
+}
+
+#[test]
+fn test_refresh_after_interval() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(Some(10))).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    let memtable = Arc::downgrade(&storage.inner.state.read().memtable.map);
+    let txn = storage.new_txn().unwrap();
+    txn.put(&key_of(15), &value_of(15, 2));
+    txn.delete(&key_of(16));
+
+    let mut iter = txn
+        .scan_rev(Bound::Unbounded, Bound::Excluded(&key_of(90)))
+        .unwrap();
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.prev().unwrap();
+        if entries.len() % 20 == 0 {
+            for idx in 0..100 {
+                storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+            }
+            storage.delete(&key_of(entries.len())).unwrap();
+            storage.force_flush().unwrap();
+            storage.force_full_compaction().unwrap();
+        }
+    }
+    assert!(memtable.upgrade().is_none());
+    entries.reverse();
+    let expected = (0..90)
+        .filter(|idx| *idx != 16)
+        .map(|idx| {
+            let version = if idx == 15 { 2 } else { 0 };
+            (
+                Bytes::from(key_of(idx)),
+                Bytes::from(value_of(idx, version)),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(entries, expected);
+}
@@ -0,0 +1,168 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    mvcc::txn::TxnIterator,
+};
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+fn value_of(idx: usize, version: usize) -> Vec<u8> {
+    format!("value_{:05}_{}", idx, version).into_bytes()
+}
+
+/// Collect the entries from the current position of `iter`, moving it with `prev` if `reverse`.
+fn collect(iter: &mut TxnIterator, reverse: bool) -> Vec<(Bytes, Bytes)> {
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        if reverse {
+            iter.prev().unwrap();
+        } else {
+            iter.next().unwrap();
+        }
+    }
+    entries
+}
+
+/// Write versions of the keys across the levels, L0 and the memtable.
+fn open_with_levels(dir: &tempfile::TempDir) -> std::sync::Arc<MiniLsm> {
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(dir, options).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    for idx in (0..100).step_by(3) {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (0..100).step_by(7) {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage
+}
+
+#[test]
+fn test_seek_matches_new_scan() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_levels(&dir);
+    let (lower, upper) = (key_of(10), key_of(90));
+    let bounds = (Bound::Excluded(&lower[..]), Bound::Included(&upper[..]));
+    let mut iter = storage.scan(bounds.0, bounds.1).unwrap();
+    let mut rev_iter = storage.scan_rev(bounds.0, bounds.1).unwrap();
+    for idx in [50, 14, 0, 10, 11, 89, 90, 99, 42] {
+        let key = key_of(idx);
+        iter.seek(&key).unwrap();
+        let expected_lower = if idx > 10 {
+            Bound::Included(&key[..])
+        } else {
+            bounds.0
+        };
+        let expected = collect(&mut storage.scan(expected_lower, bounds.1).unwrap(), false);
+        assert_eq!(collect(&mut iter, false), expected, "seek to {}", idx);
+
+        rev_iter.seek(&key).unwrap();
+        let expected_upper = if idx < 90 {
+            Bound::Included(&key[..])
+        } else {
+            bounds.1
+        };
+        let expected = collect(
+            &mut storage.scan_rev(bounds.0, expected_upper).unwrap(),
+            true,
+        );
+        assert_eq!(collect(&mut rev_iter, true), expected, "seek to {}", idx);
+    }
+}
+
+#[test]
+fn test_seek_keeps_snapshot() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_levels(&dir);
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert_eq!(iter.key(), key_of(1));
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 2)).unwrap();
+    }
+    storage.force_flush().unwrap();
+
+    iter.seek(&key_of(21)).unwrap();
+    // The key 21 was deleted before the iterator is created.
+    assert_eq!(iter.key(), key_of(22));
+    assert_eq!(iter.value(), value_of(22, 0));
+    iter.next().unwrap();
+    assert_eq!(iter.key(), key_of(23));
+    assert_eq!(iter.value(), value_of(23, 0));
+    iter.next().unwrap();
+    assert_eq!(iter.key(), key_of(24));
+    assert_eq!(iter.value(), value_of(24, 1));
+
+    // Seek back after the iterator is exhausted.
+    while iter.is_valid() {
+        iter.next().unwrap();
+    }
+    iter.seek(&key_of(0)).unwrap();
+    assert_eq!(iter.key(), key_of(1));
+    assert_eq!(iter.value(), value_of(1, 0));
+}
+
+#[test]
+fn test_seek_txn_local_writes() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_levels(&dir);
+    let txn = storage.new_txn().unwrap();
+    txn.put(&key_of(5), &value_of(5, 3));
+    txn.put(&key_of(7), &value_of(7, 3));
+    txn.delete(&key_of(6));
+    txn.delete_range(&key_of(8), &key_of(12));
+
+    let mut iter = txn
+        .scan(Bound::Unbounded, Bound::Excluded(&key_of(20)))
+        .unwrap();
+    iter.seek(&key_of(4)).unwrap();
+    let keys = collect(&mut iter, false)
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect::<Vec<_>>();
+    let expected = [4, 5, 7, 12, 13, 15, 16, 17, 18, 19].map(|idx| Bytes::from(key_of(idx)));
+    assert_eq!(keys, expected);
+
+    iter.seek(&key_of(6)).unwrap();
+    assert_eq!(iter.key(), key_of(7));
+    assert_eq!(iter.value(), value_of(7, 3));
+    iter.seek(&key_of(30)).unwrap();
+    assert!(!iter.is_valid());
+
+    let mut iter = txn.scan_rev(Bound::Unbounded, Bound::Unbounded).unwrap();
+    iter.seek(&key_of(11)).unwrap();
+    assert_eq!(iter.key(), key_of(7));
+    iter.prev().unwrap();
+    assert_eq!(iter.key(), key_of(5));
+    assert_eq!(iter.value(), value_of(5, 3));
+}
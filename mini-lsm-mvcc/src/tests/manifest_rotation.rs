@@ -0,0 +1,164 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn options(enable_wal: bool, max_manifest_size: u64) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = enable_wal;
+    options.value_separation_threshold = Some(32);
+    options.max_manifest_size = max_manifest_size;
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+/// Even keys get values that are stored in the value log.
+fn value_of(idx: usize, version: usize) -> Vec<u8> {
+    let value = format!("value_{:05}_{}", idx, version);
+    if idx.is_multiple_of(2) {
+        value.repeat(4).into_bytes()
+    } else {
+        value.into_bytes()
+    }
+}
+
+fn current(path: &Path) -> String {
+    std::fs::read_to_string(path.join("CURRENT"))
+        .unwrap()
+        .trim_end()
+        .to_string()
+}
+
+fn manifests(path: &Path) -> Vec<String> {
+    let mut manifests = std::fs::read_dir(path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.starts_with("MANIFEST") || name.starts_with("CURRENT"))
+        .collect::<Vec<_>>();
+    manifests.sort();
+    manifests
+}
+
+fn check(storage: &MiniLsm, version: usize, num_keys: usize) {
+    for idx in 0..num_keys {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(Bytes::from(value_of(idx, version))),
+            "key {idx}"
+        );
+    }
+    assert_eq!(storage.get(&key_of(num_keys)).unwrap(), None);
+}
+
+#[test]
+fn test_manifest_rotation_recovers_state() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(true, u64::MAX)).unwrap();
+    storage
+        .create_column_family("users", CompactionOptions::NoCompaction)
+        .unwrap();
+    for round in 0..3 {
+        for idx in 0..20 {
+            storage.put(&key_of(idx), &value_of(idx, round)).unwrap();
+        }
+        storage.put_cf("users", b"a", &value_of(0, round)).unwrap();
+        storage.force_flush().unwrap();
+    }
+    // The value log files outlive the SSTs they were written with.
+    storage.force_full_compaction().unwrap();
+    storage.put(&key_of(20), &value_of(20, 2)).unwrap();
+    assert_eq!(manifests(dir.path()), vec!["MANIFEST"]);
+
+    storage.inner.force_manifest_rotation().unwrap();
+    assert_eq!(manifests(dir.path()), vec!["CURRENT", "MANIFEST-000001"]);
+    assert_eq!(current(dir.path()), "MANIFEST-000001");
+    // The records written after the rotation go to the new manifest.
+    storage.put(&key_of(21), &value_of(21, 2)).unwrap();
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options(true, u64::MAX)).unwrap();
+    check(&storage, 2, 22);
+    assert_eq!(
+        storage.get_cf("users", b"a").unwrap(),
+        Some(Bytes::from(value_of(0, 2)))
+    );
+    storage.inner.force_manifest_rotation().unwrap();
+    assert_eq!(manifests(dir.path()), vec!["CURRENT", "MANIFEST-000002"]);
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options(true, u64::MAX)).unwrap();
+    check(&storage, 2, 22);
+}
+
+#[test]
+fn test_unfinished_manifest_rotation_is_ignored() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false, u64::MAX)).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    // A rotation that crashed before switching CURRENT leaves the new manifest behind.
+    std::fs::write(dir.path().join("MANIFEST-000001"), b"partial").unwrap();
+    std::fs::write(dir.path().join("CURRENT.tmp"), b"MANIFEST-000001\n").unwrap();
+    let storage = MiniLsm::open(&dir, options(false, u64::MAX)).unwrap();
+    assert_eq!(manifests(dir.path()), vec!["MANIFEST"]);
+    check(&storage, 1, 10);
+    storage.inner.force_manifest_rotation().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    // A rotation that crashed after switching CURRENT leaves the old manifest behind.
+    std::fs::write(dir.path().join("MANIFEST"), b"stale").unwrap();
+    let storage = MiniLsm::open(&dir, options(false, u64::MAX)).unwrap();
+    assert_eq!(manifests(dir.path()), vec!["CURRENT", "MANIFEST-000001"]);
+    check(&storage, 1, 10);
+}
+
+#[test]
+fn test_manifest_is_rotated_beyond_size_threshold() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(true, 1)).unwrap();
+    // The manifest is rotated as the database is opened, and by the compaction thread.
+    assert_eq!(current(dir.path()), "MANIFEST-000001");
+    for round in 0..5 {
+        for idx in 0..20 {
+            storage.put(&key_of(idx), &value_of(idx, round)).unwrap();
+        }
+        storage.force_flush().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(60));
+    }
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options(true, 1)).unwrap();
+    check(&storage, 4, 20);
+    assert_eq!(manifests(dir.path()).len(), 2);
+}
@@ -0,0 +1,278 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+    merge_operator::{MergeOperator, StringAppendOperator, U64AddOperator},
+    table::SsTableIterator,
+};
+
+fn options(enable_wal: bool, merge_operator: Arc<dyn MergeOperator>) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = enable_wal;
+    options.merge_operator = Some(merge_operator);
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{idx:04}").into_bytes()
+}
+
+fn counter(value: u64) -> Bytes {
+    Bytes::copy_from_slice(&value.to_be_bytes())
+}
+
+fn collect(mut iter: impl for<'a> StorageIterator<KeyType<'a> = &'a [u8]>) -> Vec<(Bytes, Bytes)> {
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    result
+}
+
+/// Check every key with `get` and a full scan against the expected values.
+fn check(storage: &MiniLsm, expected: &[Option<Bytes>]) {
+    for (idx, value) in expected.iter().enumerate() {
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), *value, "key {idx}");
+    }
+    let expected_scan = expected
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, value)| {
+            value
+                .as_ref()
+                .map(|value| (Bytes::from(key_of(idx)), value.clone()))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        collect(storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap()),
+        expected_scan
+    );
+}
+
+fn sst_entries(storage: &MiniLsm) -> usize {
+    let state = storage.inner.state.read();
+    let mut entries = 0;
+    for table in state.sstables.values() {
+        let mut iter = SsTableIterator::create_and_seek_to_first(table.clone()).unwrap();
+        while iter.is_valid() {
+            entries += 1;
+            iter.next().unwrap();
+        }
+    }
+    entries
+}
+
+#[test]
+fn test_merge_counters_across_memtables_ssts_and_wal() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(true, Arc::new(U64AddOperator))).unwrap();
+    storage.put(&key_of(0), &counter(100)).unwrap();
+    for round in 1..=3 {
+        for idx in 0..10 {
+            storage.merge(&key_of(idx), &counter(idx as u64)).unwrap();
+        }
+        if round < 3 {
+            storage.force_flush().unwrap();
+        }
+    }
+    storage
+        .write_batch(&[
+            WriteBatchRecord::Merge(key_of(3), counter(1000).to_vec()),
+            WriteBatchRecord::Merge(key_of(3), counter(1000).to_vec()),
+        ])
+        .unwrap();
+    let mut expected = (0..10)
+        .map(|idx| Some(counter(3 * idx as u64)))
+        .collect::<Vec<_>>();
+    expected[0] = Some(counter(100));
+    expected[3] = Some(counter(2009));
+    check(&storage, &expected);
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options(true, Arc::new(U64AddOperator))).unwrap();
+    check(&storage, &expected);
+}
+
+#[test]
+fn test_merge_with_put_and_delete_bases() {
+    let dir = tempdir().unwrap();
+    let storage =
+        MiniLsm::open(&dir, options(false, Arc::new(StringAppendOperator(b',')))).unwrap();
+    storage.put(&key_of(0), b"a").unwrap();
+    storage.put(&key_of(1), b"a").unwrap();
+    storage.force_flush().unwrap();
+    storage.delete(&key_of(1)).unwrap();
+    storage.merge(&key_of(0), b"b").unwrap();
+    storage.merge(&key_of(1), b"b").unwrap();
+    storage.merge(&key_of(2), b"b").unwrap();
+    storage.force_flush().unwrap();
+    storage.merge(&key_of(0), b"c").unwrap();
+    storage.merge(&key_of(1), b"c").unwrap();
+    storage.put(&key_of(2), b"x").unwrap();
+    storage.merge(&key_of(3), b"c").unwrap();
+    storage.delete(&key_of(3)).unwrap();
+    check(
+        &storage,
+        &[
+            Some(Bytes::from_static(b"a,b,c")),
+            Some(Bytes::from_static(b"b,c")),
+            Some(Bytes::from_static(b"x")),
+            None,
+        ],
+    );
+}
+
+#[test]
+fn test_delete_range_hides_merge_operands() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false, Arc::new(U64AddOperator))).unwrap();
+    for idx in 0..4 {
+        storage.put(&key_of(idx), &counter(10)).unwrap();
+        storage.merge(&key_of(idx), &counter(1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.delete_range(&key_of(1), &key_of(3)).unwrap();
+    storage.merge(&key_of(2), &counter(5)).unwrap();
+    check(
+        &storage,
+        &[Some(counter(11)), None, Some(counter(5)), Some(counter(11))],
+    );
+    storage.force_full_compaction().unwrap();
+    check(
+        &storage,
+        &[Some(counter(11)), None, Some(counter(5)), Some(counter(11))],
+    );
+}
+
+#[test]
+fn test_compaction_folds_merge_operands_below_watermark() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false, Arc::new(U64AddOperator))).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &counter(1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for _ in 0..3 {
+        for idx in 0..10 {
+            storage.merge(&key_of(idx), &counter(1)).unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    // A reader that started before the last merges keeps the operands above its timestamp.
+    let snapshot = storage.new_txn().unwrap();
+    for idx in 0..10 {
+        storage.merge(&key_of(idx), &counter(1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    let expected = vec![Some(counter(5)); 10];
+    check(&storage, &expected);
+
+    storage.force_full_compaction().unwrap();
+    assert_eq!(sst_entries(&storage), 20);
+    check(&storage, &expected);
+    assert_eq!(snapshot.get(&key_of(0)).unwrap(), Some(counter(4)));
+    drop(snapshot);
+
+    storage.force_full_compaction().unwrap();
+    assert_eq!(sst_entries(&storage), 10);
+    check(&storage, &expected);
+}
+
+#[test]
+fn test_merge_requires_merge_operator() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        &dir,
+        LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction),
+    )
+    .unwrap();
+    assert!(storage.merge(b"key", &counter(1)).is_err());
+    assert!(
+        storage
+            .write_batch(&[WriteBatchRecord::Merge(&b"key"[..], &b"value"[..])])
+            .is_err()
+    );
+    assert_eq!(storage.get(b"key").unwrap(), None);
+}
+
+/// Appends the operands like [`StringAppendOperator`], without combining the operands of a batch.
+#[derive(Debug)]
+struct FullMergeOnlyOperator;
+
+impl MergeOperator for FullMergeOnlyOperator {
+    fn name(&self) -> &str {
+        "full-merge-only"
+    }
+
+    fn full_merge(
+        &self,
+        key: &[u8],
+        existing: Option<&[u8]>,
+        operands: &[&[u8]],
+    ) -> anyhow::Result<Vec<u8>> {
+        StringAppendOperator(b',').full_merge(key, existing, operands)
+    }
+}
+
+#[test]
+fn test_merge_key_several_times_in_batch_without_partial_merge() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(true, Arc::new(FullMergeOnlyOperator))).unwrap();
+    storage.put(&key_of(0), b"a").unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::Merge(key_of(0), b"b".to_vec()),
+            WriteBatchRecord::Merge(key_of(1), b"b".to_vec()),
+            WriteBatchRecord::Merge(key_of(0), b"c".to_vec()),
+            WriteBatchRecord::Merge(key_of(1), b"c".to_vec()),
+            WriteBatchRecord::Merge(key_of(0), b"d".to_vec()),
+            WriteBatchRecord::Merge(key_of(2), b"b".to_vec()),
+            WriteBatchRecord::Merge(key_of(2), b"c".to_vec()),
+            WriteBatchRecord::Put(key_of(2), b"x".to_vec()),
+        ])
+        .unwrap();
+    // A transaction writes the operands of a key it has not read as a batch of merges.
+    let txn = storage.new_txn().unwrap();
+    txn.merge(&key_of(3), b"b").unwrap();
+    txn.merge(&key_of(3), b"c").unwrap();
+    txn.commit().unwrap();
+    let expected = [
+        Some(Bytes::from_static(b"a,b,c,d")),
+        Some(Bytes::from_static(b"b,c")),
+        Some(Bytes::from_static(b"x")),
+        Some(Bytes::from_static(b"b,c")),
+    ];
+    check(&storage, &expected);
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options(true, Arc::new(FullMergeOnlyOperator))).unwrap();
+    check(&storage, &expected);
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    check(&storage, &expected);
+}
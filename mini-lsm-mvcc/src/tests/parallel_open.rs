@@ -0,0 +1,88 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageInner, LsmStorageOptions, MiniLsm},
+};
+
+fn options(recovery_threads: usize) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.recovery_threads = recovery_threads;
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+fn value_of(idx: usize) -> Vec<u8> {
+    format!("value_{:05}", idx).into_bytes()
+}
+
+/// Write a database with an SST for every ten keys, returning the ids of the SSTs.
+fn write_ssts(options: LsmStorageOptions, path: &std::path::Path) -> Vec<usize> {
+    let storage = MiniLsm::open(path, options).unwrap();
+    for idx in 0..200 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+        if idx % 10 == 9 {
+            storage.force_flush().unwrap();
+        }
+    }
+    let ssts = storage.inner.state.read().l0_sstables.clone();
+    storage.close().unwrap();
+    ssts
+}
+
+#[test]
+fn test_ssts_are_opened_in_parallel() {
+    let dir = tempdir().unwrap();
+    let ssts = write_ssts(options(4), dir.path());
+    assert_eq!(ssts.len(), 20);
+    for recovery_threads in [0, 1, 4, 64] {
+        let storage = MiniLsm::open(&dir, options(recovery_threads)).unwrap();
+        {
+            let state = storage.inner.state.read();
+            assert_eq!(state.l0_sstables, ssts);
+            assert_eq!(state.sstables.len(), ssts.len());
+        }
+        for idx in 0..200 {
+            assert_eq!(
+                storage.get(&key_of(idx)).unwrap(),
+                Some(Bytes::from(value_of(idx)))
+            );
+        }
+        storage.close().unwrap();
+    }
+}
+
+#[test]
+fn test_corrupt_sst_fails_parallel_open() {
+    let dir = tempdir().unwrap();
+    let ssts = write_ssts(options(4), dir.path());
+    let path = LsmStorageInner::path_of_sst_static(dir.path(), ssts[7]);
+    let data = std::fs::read(&path).unwrap();
+    // Cut off the footer, so that the table cannot be opened.
+    std::fs::write(&path, &data[..data.len() / 2]).unwrap();
+    let sequential = MiniLsm::open(&dir, options(1)).err().unwrap();
+    let parallel = MiniLsm::open(&dir, options(4)).err().unwrap();
+    assert_eq!(format!("{:#}", parallel), format!("{:#}", sequential));
+
+    std::fs::remove_file(&path).unwrap();
+    let err = MiniLsm::open(&dir, options(4)).err().unwrap();
+    assert!(format!("{:#}", err).contains("failed to open SST"));
+}
@@ -0,0 +1,165 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    prefix::{FixedPrefix, prefix_upper_bound},
+};
+
+fn options(prefix_len: Option<usize>) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.prefix_extractor = prefix_len.map(|len| Arc::new(FixedPrefix(len)) as _);
+    options
+}
+
+fn key_of(user: usize, idx: usize) -> Vec<u8> {
+    format!("user{user}:{idx:04}").into_bytes()
+}
+
+fn value_of(user: usize, idx: usize) -> Vec<u8> {
+    format!("value_{user}_{idx}").into_bytes()
+}
+
+fn collect(mut iter: impl for<'a> StorageIterator<KeyType<'a> = &'a [u8]>) -> Vec<(Bytes, Bytes)> {
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    result
+}
+
+fn check_prefix_scans(storage: &MiniLsm) {
+    for prefix in [
+        b"".as_slice(),
+        b"user",
+        b"user1",
+        b"user2:",
+        b"user3:00",
+        b"user3:0042",
+        b"user4",
+    ] {
+        let upper = prefix_upper_bound(prefix);
+        let expected = collect(
+            storage
+                .scan(
+                    Bound::Included(prefix),
+                    upper.as_deref().map_or(Bound::Unbounded, Bound::Excluded),
+                )
+                .unwrap(),
+        );
+        let actual = collect(storage.prefix_scan(prefix).unwrap());
+        assert_eq!(
+            actual,
+            expected,
+            "prefix {:?}",
+            Bytes::copy_from_slice(prefix)
+        );
+        assert!(actual.iter().all(|(key, _)| key.starts_with(prefix)));
+    }
+}
+
+/// Users 1 and 3 are written to the first SST and user 2 to the second, so the key range of the
+/// first SST covers user 2.
+fn write_users(storage: &MiniLsm) {
+    for user in [1, 3] {
+        for idx in 0..100 {
+            storage
+                .put(&key_of(user, idx), &value_of(user, idx))
+                .unwrap();
+        }
+    }
+    storage.force_flush().unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(2, idx), &value_of(2, idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (0..100).step_by(7) {
+        storage.delete(&key_of(3, idx)).unwrap();
+    }
+}
+
+#[test]
+fn test_prefix_upper_bound() {
+    assert_eq!(prefix_upper_bound(b"abc"), Some(b"abd".to_vec()));
+    assert_eq!(prefix_upper_bound(b"ab\xff\xff"), Some(b"ac".to_vec()));
+    assert_eq!(prefix_upper_bound(b"\xff"), None);
+    assert_eq!(prefix_upper_bound(b""), None);
+}
+
+#[test]
+fn test_prefix_scan_skips_ssts_by_bloom_filter() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(Some(5))).unwrap();
+    write_users(&storage);
+    check_prefix_scans(&storage);
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options(Some(5))).unwrap();
+    let sst_starting_with = |prefix: &[u8]| {
+        let state = storage.inner.state.read();
+        *state
+            .l0_sstables
+            .iter()
+            .find(|id| {
+                state.sstables[*id]
+                    .first_key()
+                    .key_ref()
+                    .starts_with(prefix)
+            })
+            .unwrap()
+    };
+    let (first, second) = (sst_starting_with(b"user1"), sst_starting_with(b"user2"));
+    assert_eq!(
+        storage.inner.state.read().sstables[&first].prefix_extractor(),
+        Some("fixed:5")
+    );
+    let result = collect(storage.prefix_scan(b"user2:").unwrap());
+    assert_eq!(result.len(), 100);
+    assert!(storage.inner.block_cache.contains_data_block(second, 0));
+    assert!(!storage.inner.block_cache.contains_data_block(first, 0));
+    check_prefix_scans(&storage);
+}
+
+#[test]
+fn test_prefix_scan_ignores_bloom_filters_of_other_extractors() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(None)).unwrap();
+    write_users(&storage);
+    check_prefix_scans(&storage);
+    storage.close().unwrap();
+    drop(storage);
+
+    // The SSTs have no prefixes in their bloom filters, and then prefixes of a different length.
+    let storage = MiniLsm::open(&dir, options(Some(5))).unwrap();
+    check_prefix_scans(&storage);
+    storage.force_full_compaction().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options(Some(6))).unwrap();
+    check_prefix_scans(&storage);
+}
@@ -0,0 +1,351 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+};
+
+fn options(enable_wal: bool) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = enable_wal;
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{idx:04}").into_bytes()
+}
+
+fn value_of(idx: usize, version: usize) -> Vec<u8> {
+    format!("value_{idx}_{version}").into_bytes()
+}
+
+fn collect(mut iter: impl for<'a> StorageIterator<KeyType<'a> = &'a [u8]>) -> Vec<(Bytes, Bytes)> {
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    result
+}
+
+/// Check every key with `get` and a full scan against the expected values.
+fn check(storage: &MiniLsm, expected: &[Option<Vec<u8>>]) {
+    for (idx, value) in expected.iter().enumerate() {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap().as_deref(),
+            value.as_deref(),
+            "key {idx}"
+        );
+    }
+    let expected_scan = expected
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, value)| {
+            value
+                .as_ref()
+                .map(|value| (Bytes::from(key_of(idx)), Bytes::from(value.clone())))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        collect(storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap()),
+        expected_scan
+    );
+}
+
+#[test]
+fn test_delete_range_hides_keys_in_memtables_and_ssts() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(true)).unwrap();
+    let mut expected = vec![None; 100];
+    for (idx, value) in expected.iter_mut().enumerate().take(80) {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+        *value = Some(value_of(idx, 0));
+    }
+    storage.force_flush().unwrap();
+    for (idx, value) in expected.iter_mut().enumerate().skip(60) {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+        *value = Some(value_of(idx, 1));
+    }
+
+    storage.delete_range(&key_of(10), &key_of(70)).unwrap();
+    expected[10..70].fill(None);
+    storage
+        .write_batch(&[
+            WriteBatchRecord::DelRange(key_of(90), key_of(95)),
+            WriteBatchRecord::Put(key_of(92), value_of(92, 2)),
+        ])
+        .unwrap();
+    expected[90..95].fill(None);
+    expected[92] = Some(value_of(92, 2));
+    storage.put(&key_of(20), &value_of(20, 3)).unwrap();
+    expected[20] = Some(value_of(20, 3));
+    check(&storage, &expected);
+    assert_eq!(
+        collect(
+            storage
+                .scan(Bound::Excluded(&key_of(5)), Bound::Included(&key_of(25)))
+                .unwrap()
+        )
+        .len(),
+        5
+    );
+    assert!(storage.delete_range(&key_of(5), &key_of(5)).is_err());
+
+    // The tombstones are recovered from the WAL, and then read from the SSTs.
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options(true)).unwrap();
+    check(&storage, &expected);
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    assert!(
+        storage
+            .inner
+            .state
+            .read()
+            .sstables
+            .values()
+            .any(|table| !table.range_tombstones().is_empty())
+    );
+    check(&storage, &expected);
+}
+
+#[test]
+fn test_delete_range_respects_snapshots_and_transactions() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    let snapshot = storage.new_txn().unwrap();
+    storage.delete_range(&key_of(2), &key_of(8)).unwrap();
+    assert_eq!(
+        snapshot.get(&key_of(5)).unwrap(),
+        Some(Bytes::from(value_of(5, 0)))
+    );
+    assert_eq!(
+        collect(snapshot.scan(Bound::Unbounded, Bound::Unbounded).unwrap()).len(),
+        10
+    );
+
+    // Local writes before the deletion are deleted, and the ones after it are kept.
+    let txn = storage.new_txn().unwrap();
+    txn.put(&key_of(0), &value_of(0, 1));
+    txn.put(&key_of(1), &value_of(1, 1));
+    txn.delete_range(&key_of(1), &key_of(9));
+    txn.put(&key_of(8), &value_of(8, 1));
+    let expected = vec![
+        Some(value_of(0, 1)),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(value_of(8, 1)),
+        Some(value_of(9, 0)),
+    ];
+    for (idx, value) in expected.iter().enumerate() {
+        assert_eq!(txn.get(&key_of(idx)).unwrap().as_deref(), value.as_deref());
+    }
+    let scanned = collect(txn.scan(Bound::Unbounded, Bound::Unbounded).unwrap());
+    assert_eq!(
+        scanned
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>(),
+        [0, 8, 9].map(|idx| Bytes::from(key_of(idx)))
+    );
+    assert_eq!(
+        storage.get(&key_of(8)).unwrap(),
+        Some(Bytes::from(value_of(8, 0)))
+    );
+    txn.commit().unwrap();
+    check(&storage, &expected);
+}
+
+#[test]
+fn test_serializable_delete_range_conflicts_with_reads() {
+    let dir = tempdir().unwrap();
+    let mut options = options(false);
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(&key_of(1), &value_of(1, 0)).unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.get(&key_of(1)).unwrap();
+    txn.put(&key_of(2), &value_of(2, 0));
+    storage.delete_range(&key_of(0), &key_of(5)).unwrap();
+    assert!(txn.commit().is_err());
+    assert_eq!(storage.get(&key_of(1)).unwrap(), None);
+}
+
+#[test]
+fn test_compaction_drops_range_tombstones_below_watermark() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    let mut expected = (0..50)
+        .map(|idx| Some(value_of(idx, 0)))
+        .collect::<Vec<_>>();
+    for idx in 0..50 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    // A reader that started before the deletion keeps the tombstone and the versions it covers.
+    let snapshot = storage.new_txn().unwrap();
+    // The second SST only has the range tombstone.
+    storage.delete_range(&key_of(10), &key_of(40)).unwrap();
+    storage.force_flush().unwrap();
+    expected[10..40].fill(None);
+    check(&storage, &expected);
+
+    let range_tombstones = |storage: &MiniLsm| {
+        storage
+            .inner
+            .state
+            .read()
+            .sstables
+            .values()
+            .map(|table| table.range_tombstones().len())
+            .sum::<usize>()
+    };
+    let entries = |storage: &MiniLsm| {
+        let state = storage.inner.state.read();
+        let mut entries = 0;
+        for table in state.sstables.values() {
+            let mut iter =
+                crate::table::SsTableIterator::create_and_seek_to_first(table.clone()).unwrap();
+            while iter.is_valid() {
+                entries += 1;
+                iter.next().unwrap();
+            }
+        }
+        entries
+    };
+
+    storage.put(&key_of(20), &value_of(20, 1)).unwrap();
+    storage.force_flush().unwrap();
+    expected[20] = Some(value_of(20, 1));
+    storage.force_full_compaction().unwrap();
+    assert_eq!(range_tombstones(&storage), 1);
+    check(&storage, &expected);
+    assert_eq!(
+        snapshot.get(&key_of(30)).unwrap(),
+        Some(Bytes::from(value_of(30, 0)))
+    );
+    drop(snapshot);
+
+    storage.force_full_compaction().unwrap();
+    assert_eq!(range_tombstones(&storage), 0);
+    assert_eq!(entries(&storage), 21);
+    check(&storage, &expected);
+}
+
+/// Flush every mem-table, including the ones frozen because they reached the SST size.
+fn flush_all(storage: &MiniLsm) {
+    storage.force_flush().unwrap();
+    while !storage.inner.state.read().imm_memtables.is_empty() {
+        storage.inner.force_flush_next_imm_memtable().unwrap();
+    }
+}
+
+#[test]
+fn test_sst_with_only_range_tombstones() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.delete_range(&key_of(5), &key_of(20)).unwrap();
+    storage.force_flush().unwrap();
+
+    let table = {
+        let state = storage.inner.state.read();
+        state.sstables[&state.l0_sstables[0]].clone()
+    };
+    assert_eq!(table.num_of_blocks(), 0);
+    assert_eq!(table.first_key().key_ref(), key_of(5));
+    assert_eq!(table.last_key().key_ref(), key_of(20));
+    let mut expected = (0..10)
+        .map(|idx| Some(value_of(idx, 0)))
+        .collect::<Vec<_>>();
+    expected[5..].fill(None);
+    check(&storage, &expected);
+
+    storage.close().unwrap();
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    check(&storage, &expected);
+}
+
+#[test]
+fn test_compaction_splits_range_tombstones_across_ssts() {
+    let dir = tempdir().unwrap();
+    let mut options = options(false);
+    options.block_size = 256;
+    options.target_sst_size = 2048;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let mut expected = (0..400)
+        .map(|idx| Some(value_of(idx, 0)))
+        .collect::<Vec<_>>();
+    for idx in 0..400 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    flush_all(&storage);
+    // The reader keeps the tombstone and the versions it covers through the compaction.
+    let snapshot = storage.new_txn().unwrap();
+    storage.delete_range(&key_of(50), &key_of(350)).unwrap();
+    flush_all(&storage);
+    expected[50..350].fill(None);
+    storage.force_full_compaction().unwrap();
+
+    let state = storage.inner.state.read().clone();
+    let tables = &state.levels[0].1;
+    assert!(tables.len() > 2);
+    let mut tombstones = Vec::new();
+    for id in tables {
+        let table = &state.sstables[id];
+        for tombstone in table.range_tombstones() {
+            assert!(table.first_key().key_ref() <= &tombstone.start[..]);
+            assert!(&tombstone.end[..] <= table.last_key().key_ref());
+            tombstones.push((tombstone.start.clone(), tombstone.end.clone()));
+        }
+    }
+    // The pieces are contiguous and cover the whole deleted range.
+    assert!(tombstones.len() > 1);
+    assert_eq!(tombstones.first().unwrap().0, key_of(50));
+    assert_eq!(tombstones.last().unwrap().1, key_of(350));
+    for pair in tombstones.windows(2) {
+        assert_eq!(pair[0].1, pair[1].0);
+    }
+
+    check(&storage, &expected);
+    for idx in [0, 50, 200, 349, 350, 399] {
+        assert_eq!(
+            snapshot.get(&key_of(idx)).unwrap(),
+            Some(Bytes::from(value_of(idx, 0)))
+        );
+    }
+}
@@ -21,7 +21,7 @@ use tempfile::tempdir;
 use crate::{
     compact::CompactionOptions,
     key::KeySlice,
-    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
     manifest::{Manifest, ManifestRecord},
     table::{FileObject, SsTable, SsTableBuilder},
 };
@@ -166,30 +166,15 @@ fn test_release_sst_corruption_and_truncation_never_panic() {
 fn test_release_public_write_size_boundaries() {
     let max_key = vec![b'k'; usize::from(u16::MAX)];
     let max_value = vec![b'v'; usize::from(u16::MAX)];
+    let large_key = vec![b'k'; 100 << 10];
+    let large_value = vec![b'v'; 1 << 20];
     for enable_wal in [false, true] {
         for serializable in [false, true] {
             assert_round_trip(enable_wal, serializable, b"value-boundary", &max_value);
             assert_round_trip(enable_wal, serializable, &max_key, b"key-boundary");
-
-            let dir = tempdir().unwrap();
-            let storage = MiniLsm::open(&dir, options(enable_wal, serializable)).unwrap();
-            let oversized = vec![0; usize::from(u16::MAX) + 1];
-            assert!(storage.put(b"oversized-value", &oversized).is_err());
-            assert!(storage.put(&oversized, b"oversized-key").is_err());
-            let batch = [
-                WriteBatchRecord::Put(b"would-be-partial".as_slice(), b"value".as_slice()),
-                WriteBatchRecord::Put(b"oversized".as_slice(), oversized.as_slice()),
-            ];
-            assert!(storage.write_batch(&batch).is_err());
-            assert_eq!(storage.get(b"would-be-partial").unwrap(), None);
-            assert_eq!(storage.get(b"oversized-value").unwrap(), None);
-            storage.close().unwrap();
-            drop(storage);
-
-            let storage = MiniLsm::open(&dir, options(enable_wal, serializable)).unwrap();
-            assert_eq!(storage.get(b"would-be-partial").unwrap(), None);
-            assert_eq!(storage.get(b"oversized-value").unwrap(), None);
-            storage.close().unwrap();
+            assert_round_trip(enable_wal, serializable, b"large-value", &large_value);
+            assert_round_trip(enable_wal, serializable, &large_key, b"large-key");
+            assert_round_trip(enable_wal, serializable, &large_key, &large_value);
         }
     }
 }
@@ -0,0 +1,233 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, LeveledCompactionOptions},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    repair::{LOST_DIR, repair},
+};
+
+fn options(compaction_options: CompactionOptions) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(compaction_options);
+    options.enable_wal = true;
+    options.value_separation_threshold = Some(32);
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+/// Even keys get values that are stored in the value log.
+fn value_of(idx: usize, version: usize) -> Bytes {
+    let value = format!("value_{:05}_{}", idx, version);
+    if idx.is_multiple_of(2) {
+        Bytes::from(value.repeat(4))
+    } else {
+        Bytes::from(value)
+    }
+}
+
+fn remove_manifests(path: &Path) {
+    for entry in std::fs::read_dir(path).unwrap() {
+        let entry = entry.unwrap();
+        let name = entry.file_name().into_string().unwrap();
+        if name.starts_with("MANIFEST") || name == "CURRENT" {
+            std::fs::remove_file(entry.path()).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_repair_lost_manifest() {
+    let dir = tempdir().unwrap();
+    let options = options(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for version in 0..2 {
+        for idx in 0..40 {
+            storage.put(&key_of(idx), &value_of(idx, version)).unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    storage.force_full_compaction().unwrap();
+    storage.delete(&key_of(0)).unwrap();
+    for idx in 1..10 {
+        storage.put(&key_of(idx), &value_of(idx, 2)).unwrap();
+    }
+    storage.close().unwrap();
+    drop(storage);
+
+    remove_manifests(dir.path());
+    let report = repair(&dir, &options).unwrap();
+    assert!(report.corrupted.is_empty(), "{:?}", report.corrupted);
+    assert_eq!(report.wals, 1);
+    assert_eq!(report.archived.len(), 1);
+    assert!(dir.path().join(LOST_DIR).join(&report.archived[0]).exists());
+
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    assert_eq!(storage.get(&key_of(0)).unwrap(), None);
+    for idx in 1..40 {
+        let version = if idx < 10 { 2 } else { 1 };
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(value_of(idx, version)),
+            "key {idx}"
+        );
+    }
+    // The database is writable after the repair.
+    storage.put(&key_of(40), &value_of(40, 0)).unwrap();
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.get(&key_of(40)).unwrap(), Some(value_of(40, 0)));
+}
+
+#[test]
+fn test_repair_corrupted_files() {
+    let dir = tempdir().unwrap();
+    let options = options(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage
+        .create_column_family("users", CompactionOptions::NoCompaction)
+        .unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put_cf("users", b"b", b"2").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"c", b"3").unwrap();
+    storage.force_flush().unwrap();
+    storage.put_cf("users", b"d", b"4").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    // Corrupt the SST of the second flush, which has the key `c`.
+    let corrupted = "00001.sst";
+    let mut data = std::fs::read(dir.path().join(corrupted)).unwrap();
+    data[2] ^= 0xff;
+    std::fs::write(dir.path().join(corrupted), data).unwrap();
+    // Corrupt the last record of the manifest, which is the second flush.
+    let manifest = dir.path().join("MANIFEST");
+    let mut data = std::fs::read(&manifest).unwrap();
+    let len = data.len();
+    data[len - 1] ^= 0xff;
+    std::fs::write(&manifest, data).unwrap();
+
+    let report = repair(&dir, &options).unwrap();
+    let corrupted_files = report
+        .corrupted
+        .iter()
+        .map(|(file, _)| file.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(corrupted_files, vec!["MANIFEST", corrupted]);
+    assert!(report.archived.iter().any(|name| name == corrupted));
+    assert!(report.archived.iter().any(|name| name == "MANIFEST"));
+    assert_eq!(report.tables, 3);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"1")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap(), None);
+    assert_eq!(
+        storage.get_cf("users", b"b").unwrap(),
+        Some(Bytes::from_static(b"2"))
+    );
+    assert_eq!(
+        storage.get_cf("users", b"d").unwrap(),
+        Some(Bytes::from_static(b"4"))
+    );
+}
+
+#[test]
+fn test_repair_unknown_column_family() {
+    let dir = tempdir().unwrap();
+    let options = options(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage
+        .create_column_family("users", CompactionOptions::NoCompaction)
+        .unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put_cf("users", b"b", b"2").unwrap();
+    storage.force_flush().unwrap();
+    storage.put_cf("users", b"c", b"3").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    // Without a manifest, the column family is unknown. Its SST is moved away rather than merged
+    // into the default column family, like its WAL entries are dropped.
+    remove_manifests(dir.path());
+    let report = repair(&dir, &options).unwrap();
+    let corrupted_files = report
+        .corrupted
+        .iter()
+        .map(|(file, _)| file.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(corrupted_files, vec!["00002.sst", "00001.wal"]);
+    assert!(report.archived.iter().any(|name| name == "00002.sst"));
+    assert!(dir.path().join(LOST_DIR).join("00002.sst").exists());
+    assert_eq!(report.tables, 1);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"1")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap(), None);
+}
+
+#[test]
+fn test_repair_level_layout() {
+    let dir = tempdir().unwrap();
+    let options = options(CompactionOptions::Leveled(LeveledCompactionOptions {
+        level0_file_num_compaction_trigger: 100,
+        level_size_multiplier: 2,
+        base_level_size_mb: 1,
+        max_levels: 3,
+    }));
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    // Two overlapping SSTs and one that overlaps no other SST.
+    for (idx, version) in [(0, 0), (0, 1), (100, 0)] {
+        for idx in idx..idx + 10 {
+            storage.put(&key_of(idx), &value_of(idx, version)).unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    storage.close().unwrap();
+    drop(storage);
+
+    remove_manifests(dir.path());
+    let report = repair(&dir, &options).unwrap();
+    assert_eq!(report.tables, 3);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    {
+        let state = storage.inner.state.read();
+        assert_eq!(state.l0_sstables.len(), 2);
+        assert!(state.levels[0].1.is_empty());
+        assert!(state.levels[1].1.is_empty());
+        assert_eq!(state.levels[2].1.len(), 1);
+        // The latest SST is on top of L0.
+        let latest = state.sstables[&state.l0_sstables[0]].max_ts();
+        let earliest = state.sstables[&state.l0_sstables[1]].max_ts();
+        assert!(latest > earliest);
+    }
+    for idx in (0..10).chain(100..110) {
+        let version = if idx < 10 { 1 } else { 0 };
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(value_of(idx, version))
+        );
+    }
+}
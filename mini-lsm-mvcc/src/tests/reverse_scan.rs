@@ -0,0 +1,271 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    key::{KeySlice, TS_RANGE_BEGIN},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    merge_operator::StringAppendOperator,
+    mvcc::txn::TxnIterator,
+    table::SsTableIterator,
+    tests::harness::generate_sst_with_ts,
+};
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+fn value_of(idx: usize, version: usize) -> Vec<u8> {
+    format!("value_{:05}_{}", idx, version).into_bytes()
+}
+
+fn collect(mut iter: TxnIterator) -> Vec<(Bytes, Bytes)> {
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    entries
+}
+
+fn collect_rev(mut iter: TxnIterator) -> Vec<(Bytes, Bytes)> {
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.prev().unwrap();
+    }
+    entries.reverse();
+    entries
+}
+
+/// Check that the reverse scans return the entries of the forward scans for a few bounds.
+fn check_against_scan(storage: &MiniLsm) {
+    let (first, middle, last) = (key_of(0), key_of(50), key_of(99));
+    let bounds = [
+        (Bound::Unbounded, Bound::Unbounded),
+        (Bound::Included(&first[..]), Bound::Included(&last[..])),
+        (Bound::Excluded(&first[..]), Bound::Excluded(&last[..])),
+        (Bound::Included(&middle[..]), Bound::Unbounded),
+        (Bound::Unbounded, Bound::Excluded(&middle[..])),
+        (Bound::Excluded(&middle[..]), Bound::Included(&middle[..])),
+    ];
+    for (lower, upper) in bounds {
+        let expected = collect(storage.scan(lower, upper).unwrap());
+        let actual = collect_rev(storage.scan_rev(lower, upper).unwrap());
+        assert_eq!(actual, expected, "bounds {:?}..{:?}", lower, upper);
+    }
+}
+
+#[test]
+fn test_sst_iterator_prev() {
+    let dir = tempdir().unwrap();
+    let data = (0..100)
+        .flat_map(|idx| {
+            [2, 1].map(|ts| {
+                (
+                    (Bytes::from(key_of(idx)), ts),
+                    Bytes::from(value_of(idx, ts as usize)),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    let table = Arc::new(generate_sst_with_ts(
+        1,
+        dir.path().join("1.sst"),
+        data.clone(),
+        None,
+    ));
+    assert!(table.num_of_blocks() > 1);
+
+    let mut iter = SsTableIterator::create_and_seek_to_last(table.clone()).unwrap();
+    for ((key, ts), value) in data.iter().rev() {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key().key_ref(), key);
+        assert_eq!(iter.key().ts(), *ts);
+        assert_eq!(iter.value(), value);
+        iter.prev().unwrap();
+    }
+    assert!(!iter.is_valid());
+
+    let key = key_of(40);
+    let iter = SsTableIterator::create_and_seek_for_prev(
+        table.clone(),
+        KeySlice::for_testing_from_slice_with_ts(&key, TS_RANGE_BEGIN),
+    )
+    .unwrap();
+    assert_eq!(iter.key().key_ref(), key_of(39));
+    assert_eq!(iter.key().ts(), 1);
+    let iter = SsTableIterator::create_and_seek_for_prev(
+        table.clone(),
+        KeySlice::for_testing_from_slice_with_ts(&key, 2),
+    )
+    .unwrap();
+    assert_eq!(iter.key().key_ref(), key);
+    assert_eq!(iter.key().ts(), 2);
+    let iter = SsTableIterator::create_and_seek_for_prev(
+        table,
+        KeySlice::for_testing_from_slice_with_ts(b"key", TS_RANGE_BEGIN),
+    )
+    .unwrap();
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_scan_rev_across_levels() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    for idx in (0..100).step_by(3) {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    for idx in (0..100).step_by(7) {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (0..100).step_by(5) {
+        storage.put(&key_of(idx), &value_of(idx, 2)).unwrap();
+    }
+    storage.delete(&key_of(99)).unwrap();
+    check_against_scan(&storage);
+
+    let mut iter = storage
+        .scan_rev(Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    assert_eq!(iter.key(), key_of(97));
+    assert_eq!(iter.value(), value_of(97, 0));
+    iter.prev().unwrap();
+    // The key 98 is deleted.
+    assert_eq!(iter.key(), key_of(96));
+    assert_eq!(iter.value(), value_of(96, 1));
+    assert!(iter.next().is_err());
+}
+
+#[test]
+fn test_scan_rev_snapshot() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    let snapshot = storage.new_txn().unwrap();
+    storage.force_flush().unwrap();
+    for idx in 0..10 {
+        if idx % 2 == 0 {
+            storage.delete(&key_of(idx)).unwrap();
+        } else {
+            storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+        }
+    }
+    storage.put(&key_of(10), &value_of(10, 1)).unwrap();
+    storage.force_flush().unwrap();
+
+    let expected = (0..10)
+        .map(|idx| (Bytes::from(key_of(idx)), Bytes::from(value_of(idx, 0))))
+        .collect::<Vec<_>>();
+    let iter = snapshot
+        .scan_rev(Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    assert_eq!(collect_rev(iter), expected);
+
+    let expected = (1..11)
+        .step_by(2)
+        .map(|idx| (Bytes::from(key_of(idx)), Bytes::from(value_of(idx, 1))))
+        .chain([(Bytes::from(key_of(10)), Bytes::from(value_of(10, 1)))])
+        .collect::<Vec<_>>();
+    let iter = storage
+        .scan_rev(Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    assert_eq!(collect_rev(iter), expected);
+}
+
+#[test]
+fn test_scan_rev_range_tombstones_and_merge_operands() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.merge_operator = Some(Arc::new(StringAppendOperator(b',')));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.delete_range(&key_of(20), &key_of(40)).unwrap();
+    for idx in (0..100).step_by(4) {
+        storage.merge(&key_of(idx), b"a").unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (0..100).step_by(8) {
+        storage.merge(&key_of(idx), b"b").unwrap();
+    }
+    storage.delete_range(&key_of(60), &key_of(70)).unwrap();
+    check_against_scan(&storage);
+
+    let lower = key_of(16);
+    let upper = key_of(24);
+    let iter = storage
+        .scan_rev(Bound::Included(&lower), Bound::Included(&upper))
+        .unwrap();
+    let mut expected = (16..20)
+        .map(|idx| (Bytes::from(key_of(idx)), Bytes::from(value_of(idx, 0))))
+        .collect::<Vec<_>>();
+    expected[0].1 = Bytes::from(format!(
+        "{},a,b",
+        String::from_utf8(value_of(16, 0)).unwrap()
+    ));
+    // The operands written after the range deletion have no base value.
+    expected.push((Bytes::from(key_of(20)), Bytes::from_static(b"a")));
+    expected.push((Bytes::from(key_of(24)), Bytes::from_static(b"a,b")));
+    assert_eq!(collect_rev(iter), expected);
+}
+
+#[test]
+fn test_scan_rev_txn_local_writes() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(&key_of(3), &value_of(3, 1));
+    txn.put(&key_of(12), &value_of(12, 1));
+    txn.delete(&key_of(5));
+    txn.delete_range(&key_of(7), &key_of(9));
+
+    let expected = collect(txn.scan(Bound::Unbounded, Bound::Unbounded).unwrap());
+    let actual = collect_rev(txn.scan_rev(Bound::Unbounded, Bound::Unbounded).unwrap());
+    assert_eq!(actual, expected);
+    let keys = actual.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+    let expected_keys = [0, 1, 2, 3, 4, 6, 9, 12].map(|idx| Bytes::from(key_of(idx)));
+    assert_eq!(keys, expected_keys);
+}
@@ -0,0 +1,236 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    wal::WAL_HEADER_SIZE,
+};
+
+fn options(enable_wal: bool) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = enable_wal;
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+fn value_of(idx: usize, version: usize) -> Bytes {
+    Bytes::from(format!("value_{:05}_{}", idx, version))
+}
+
+fn files_with_extension(path: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut files = std::fs::read_dir(path)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+fn flip_byte(path: &Path, offset: usize) {
+    let mut data = std::fs::read(path).unwrap();
+    data[offset] ^= 0xff;
+    std::fs::write(path, data).unwrap();
+}
+
+#[test]
+fn test_salvage_corrupted_sst() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    for idx in 0..20 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in 0..5 {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let ssts = files_with_extension(dir.path(), "sst");
+    assert_eq!(ssts.len(), 2);
+    flip_byte(&ssts[1], 2);
+    // The blocks are only verified when they are read.
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    assert!(storage.get(&key_of(0)).is_err());
+    drop(storage);
+
+    let (storage, report) = MiniLsm::open_read_only(&dir, options(false)).unwrap();
+    assert_eq!(report.unavailable.len(), 1);
+    let range = &report.unavailable[0];
+    assert_eq!(
+        range.file,
+        ssts[1].file_name().unwrap().to_string_lossy().as_ref()
+    );
+    assert_eq!(range.column_family, None);
+    assert_eq!(range.lower, Bound::Included(Bytes::from(key_of(0))));
+    assert_eq!(range.upper, Bound::Included(Bytes::from(key_of(4))));
+    // The keys of the corrupted SST fall back to their older versions.
+    for idx in 0..20 {
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), Some(value_of(idx, 0)));
+    }
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut cnt = 0;
+    while iter.is_valid() {
+        cnt += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(cnt, 20);
+
+    // The database is left untouched.
+    drop(storage);
+    assert_eq!(files_with_extension(dir.path(), "sst"), ssts);
+}
+
+#[test]
+fn test_salvage_corrupted_sst_with_range_tombstone() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    for idx in 0..20 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in 0..5 {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.delete_range(&key_of(10), &key_of(15)).unwrap();
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let ssts = files_with_extension(dir.path(), "sst");
+    flip_byte(&ssts[1], 2);
+    let (storage, report) = MiniLsm::open_read_only(&dir, options(false)).unwrap();
+    assert_eq!(report.unavailable.len(), 1);
+    // The keys deleted by the range tombstone of the SST are unavailable too.
+    let range = &report.unavailable[0];
+    assert_eq!(range.lower, Bound::Included(Bytes::from(key_of(0))));
+    assert_eq!(range.upper, Bound::Included(Bytes::from(key_of(15))));
+    assert_eq!(storage.get(&key_of(12)).unwrap(), Some(value_of(12, 0)));
+}
+
+#[test]
+fn test_salvage_corrupted_wal_frame() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(true)).unwrap();
+    storage
+        .create_column_family("users", CompactionOptions::NoCompaction)
+        .unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"2").unwrap();
+    storage.put_cf("users", b"c", b"3").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let wals = files_with_extension(dir.path(), "wal");
+    let wal = wals.last().unwrap();
+    // The first byte of the body of the first frame.
+    flip_byte(wal, WAL_HEADER_SIZE + 4);
+    assert!(MiniLsm::open(&dir, options(true)).is_err());
+
+    let (storage, report) = MiniLsm::open_read_only(&dir, options(true)).unwrap();
+    assert_eq!(report.unavailable.len(), 1);
+    let range = &report.unavailable[0];
+    assert_eq!(
+        range.file,
+        wal.file_name().unwrap().to_string_lossy().as_ref()
+    );
+    assert_eq!(range.column_family, None);
+    assert_eq!(range.lower, Bound::Unbounded);
+    assert_eq!(range.upper, Bound::Unbounded);
+    assert!(range.reason.contains("byte offset"), "{}", range.reason);
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from_static(b"2")));
+    assert_eq!(
+        storage.get_cf("users", b"c").unwrap(),
+        Some(Bytes::from_static(b"3"))
+    );
+
+    // The WAL is not truncated or rewritten.
+    let data = std::fs::read(wal).unwrap();
+    drop(storage);
+    assert_eq!(std::fs::read(wal).unwrap(), data);
+}
+
+#[test]
+fn test_salvage_corrupted_manifest() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"b", b"2").unwrap();
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    // Corrupt the last record, which is the flush of the second SST.
+    let manifest = dir.path().join("MANIFEST");
+    let len = std::fs::metadata(&manifest).unwrap().len() as usize;
+    flip_byte(&manifest, len - 1);
+    assert!(MiniLsm::open(&dir, options(false)).is_err());
+
+    let (storage, report) = MiniLsm::open_read_only(&dir, options(false)).unwrap();
+    assert_eq!(report.unavailable.len(), 1);
+    assert_eq!(report.unavailable[0].file, "MANIFEST");
+    assert_eq!(report.unavailable[0].lower, Bound::Unbounded);
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"1")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    drop(storage);
+    // The SST of the lost record is not removed.
+    assert_eq!(files_with_extension(dir.path(), "sst").len(), 2);
+}
+
+#[test]
+fn test_read_only_rejects_writes() {
+    let dir = tempdir().unwrap();
+    assert!(MiniLsm::open_read_only(dir.path().join("missing"), options(true)).is_err());
+    let storage = MiniLsm::open(&dir, options(true)).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let (storage, report) = MiniLsm::open_read_only(&dir, options(true)).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from_static(b"1")));
+    assert!(storage.put(b"b", b"2").is_err());
+    assert!(storage.delete(b"a").is_err());
+    assert!(
+        storage
+            .create_column_family("users", CompactionOptions::NoCompaction)
+            .is_err()
+    );
+    assert!(storage.force_flush().is_err());
+    assert!(
+        storage
+            .create_checkpoint(dir.path().join("checkpoint"))
+            .is_err()
+    );
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"c", b"3");
+    assert!(txn.commit().is_err());
+    storage.close().unwrap();
+    assert_eq!(storage.get(b"b").unwrap(), None);
+}
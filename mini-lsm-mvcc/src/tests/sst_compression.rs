@@ -0,0 +1,160 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    key::KeySlice,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::{CompressionType, FileObject, SsTable, SsTableBuilder, SsTableIterator},
+};
+
+use super::harness::check_lsm_iter_result_by_key;
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+fn value_of(idx: usize) -> Vec<u8> {
+    format!("value_{:05}_", idx).repeat(8).into_bytes()
+}
+
+fn build_table(compression: CompressionType, path: &std::path::Path) -> SsTable {
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.block_size = 1024;
+    options.compression = compression;
+    let mut builder = SsTableBuilder::new_with_options(&options);
+    for idx in 0..1000 {
+        builder.add(
+            KeySlice::for_testing_from_slice_with_ts(&key_of(idx), 1),
+            &value_of(idx),
+        );
+    }
+    builder.build_for_test(path).unwrap()
+}
+
+#[test]
+fn test_sst_compression_round_trip() {
+    let dir = tempdir().unwrap();
+    let uncompressed_size =
+        build_table(CompressionType::None, &dir.path().join("none.sst")).table_size();
+    for compression in [
+        CompressionType::Lz4,
+        CompressionType::Snappy,
+        CompressionType::Zstd,
+    ] {
+        let path = dir.path().join(format!("{:?}.sst", compression));
+        let sst = build_table(compression, &path);
+        assert!(
+            sst.table_size() < uncompressed_size,
+            "{:?} did not shrink the table: {} >= {}",
+            compression,
+            sst.table_size(),
+            uncompressed_size
+        );
+        drop(sst);
+
+        let sst = SsTable::open_for_test(FileObject::open(&path).unwrap()).unwrap();
+        let mut iter = SsTableIterator::create_and_seek_to_first(sst.into()).unwrap();
+        for idx in 0..1000 {
+            assert!(iter.is_valid());
+            assert_eq!(iter.key().for_testing_key_ref(), key_of(idx).as_slice());
+            assert_eq!(iter.value(), value_of(idx).as_slice());
+            iter.next().unwrap();
+        }
+        assert!(!iter.is_valid());
+    }
+}
+
+#[test]
+fn test_sst_compression_incompressible_blocks_stay_raw() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new_with_options(&{
+        let mut options = LsmStorageOptions::default_for_week1_test();
+        options.block_size = 256;
+        options.compression = CompressionType::Lz4;
+        options
+    });
+    // Alternate compressible and incompressible values, so that the table mixes raw and
+    // compressed blocks.
+    let mut state = 0x2545f491u32;
+    let mut expected = Vec::new();
+    for idx in 0..200 {
+        let value = if idx % 20 < 10 {
+            vec![b'x'; 64]
+        } else {
+            (0..64)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect()
+        };
+        builder.add(
+            KeySlice::for_testing_from_slice_with_ts(&key_of(idx), 1),
+            &value,
+        );
+        expected.push(value);
+    }
+    let path = dir.path().join("mixed.sst");
+    drop(builder.build_for_test(&path).unwrap());
+
+    let sst = SsTable::open_for_test(FileObject::open(&path).unwrap()).unwrap();
+    let mut iter = SsTableIterator::create_and_seek_to_first(sst.into()).unwrap();
+    for (idx, value) in expected.iter().enumerate() {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key().for_testing_key_ref(), key_of(idx).as_slice());
+        assert_eq!(iter.value(), value.as_slice());
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_sst_compression_change_codec_across_restarts() {
+    let dir = tempdir().unwrap();
+    let mut expected = Vec::new();
+    for (round, compression) in [
+        CompressionType::None,
+        CompressionType::Snappy,
+        CompressionType::Zstd,
+        CompressionType::Lz4,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut options =
+            LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+        options.compression = compression;
+        let storage = MiniLsm::open(&dir, options).unwrap();
+        for idx in (round * 100)..((round + 1) * 100) {
+            storage.put(&key_of(idx), &value_of(idx)).unwrap();
+            expected.push((Bytes::from(key_of(idx)), Bytes::from(value_of(idx))));
+        }
+        storage.force_flush().unwrap();
+        if round == 3 {
+            storage.force_full_compaction().unwrap();
+        }
+        let mut iter = storage
+            .scan(std::ops::Bound::Unbounded, std::ops::Bound::Unbounded)
+            .unwrap();
+        check_lsm_iter_result_by_key(&mut iter, expected.clone());
+        storage.close().unwrap();
+    }
+}
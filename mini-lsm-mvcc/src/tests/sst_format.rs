@@ -0,0 +1,110 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::{
+    iterators::{StorageIterator, ValueKind},
+    key::KeySlice,
+    table::{
+        FileObject, Footer, SST_FORMAT_VERSION, SST_MAGIC, SsTable, SsTableBuilder, SsTableIterator,
+    },
+};
+
+fn build_sst(path: &std::path::Path) -> Vec<u8> {
+    let mut builder = SsTableBuilder::new(128);
+    for idx in 0..100 {
+        builder.add(
+            KeySlice::for_testing_from_slice_with_ts(format!("key_{:03}", idx).as_bytes(), 1),
+            format!("value_{:03}", idx).as_bytes(),
+        );
+    }
+    drop(builder.build_for_test(path).unwrap());
+    std::fs::read(path).unwrap()
+}
+
+fn open_err(path: &std::path::Path) -> String {
+    match SsTable::open_for_test(FileObject::open(path).unwrap()) {
+        Ok(_) => panic!("{} should not open", path.display()),
+        Err(e) => format!("{e:#}"),
+    }
+}
+
+#[test]
+fn test_sst_footer_round_trip() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let encoded = build_sst(&path);
+    assert_eq!(
+        u64::from_be_bytes(encoded[encoded.len() - 8..].try_into().unwrap()),
+        SST_MAGIC
+    );
+
+    let footer = Footer::read(&FileObject::open(&path).unwrap()).unwrap();
+    assert_eq!(footer.version, SST_FORMAT_VERSION);
+    assert_eq!(footer.index.end().unwrap(), footer.bloom.offset);
+
+    let sst = SsTable::open_for_test(FileObject::open(&path).unwrap()).unwrap();
+    assert_eq!(sst.format_version(), SST_FORMAT_VERSION);
+    assert!(sst.num_of_index_partitions().is_some());
+}
+
+#[test]
+fn test_sst_footer_rejects_garbage() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("garbage.sst");
+    std::fs::write(&path, vec![0x42; 4096]).unwrap();
+    assert!(open_err(&path).contains("bad magic number"));
+
+    let path = dir.path().join("tiny.sst");
+    std::fs::write(&path, b"mini-lsm").unwrap();
+    assert!(open_err(&path).contains("truncated"));
+}
+
+#[test]
+fn test_sst_footer_rejects_unknown_version() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let mut encoded = build_sst(&path);
+    let version_offset = encoded.len() - 16;
+    encoded[version_offset..version_offset + 4].copy_from_slice(&999u32.to_be_bytes());
+    std::fs::write(&path, encoded).unwrap();
+    assert!(open_err(&path).contains("unsupported SST format version 999"));
+}
+
+#[test]
+fn test_sst_keeps_the_whole_timestamp() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let mut builder = SsTableBuilder::new(128);
+    builder.add_with_kind(
+        KeySlice::for_testing_from_slice_with_ts(b"a", u64::MAX - 1),
+        ValueKind::MergeOperand,
+        b"1",
+    );
+    builder.add(
+        KeySlice::for_testing_from_slice_with_ts(b"a", 1 << 60),
+        b"2",
+    );
+    let sst = Arc::new(builder.build_for_test(&path).unwrap());
+    let mut iter = SsTableIterator::create_and_seek_to_first(sst).unwrap();
+    assert_eq!(iter.key().ts(), u64::MAX - 1);
+    assert_eq!(iter.value_kind(), ValueKind::MergeOperand);
+    iter.next().unwrap();
+    assert_eq!(iter.key().ts(), 1 << 60);
+    assert_eq!(iter.value_kind(), ValueKind::Value);
+    assert_eq!(iter.value(), b"2");
+}
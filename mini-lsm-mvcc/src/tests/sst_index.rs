@@ -0,0 +1,253 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::BufMut;
+use tempfile::tempdir;
+
+use crate::{
+    block::{Block, BlockFormat, BlockIterator, encode_entry_ts},
+    block_cache::MetaCacheKey,
+    comparator::BYTEWISE_COMPARATOR,
+    iterators::StorageIterator,
+    key::KeySlice,
+    lsm_storage::BlockCache,
+    table::{
+        BlockHandle, BlockMeta, CompressionType, FileObject, Footer, SsTable, SsTableBuilder,
+        SsTableIterator,
+    },
+};
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx * 5).into_bytes()
+}
+
+fn value_of(idx: usize) -> Vec<u8> {
+    format!("value_{:010}", idx).into_bytes()
+}
+
+const NUM_KEYS: usize = 2000;
+
+fn build_sst(path: &std::path::Path, block_cache: Option<Arc<BlockCache>>) -> SsTable {
+    let mut builder = SsTableBuilder::new(256);
+    for idx in 0..NUM_KEYS {
+        builder.add(
+            KeySlice::for_testing_from_slice_with_ts(&key_of(idx), 1),
+            &value_of(idx),
+        );
+    }
+    builder.build(1, block_cache, path).unwrap()
+}
+
+/// Re-encode the data blocks of `sst` with u16 lengths, as SST format versions 0 to 3 store them.
+/// Version 0 blocks have no compression type in their trailer. Returns the blocks and their
+/// metadata.
+fn encode_fixed_blocks(sst: &SsTable, compression_type: bool) -> (Vec<u8>, Vec<BlockMeta>) {
+    let mut buf = Vec::new();
+    let mut block_meta = Vec::new();
+    for idx in 0..sst.num_of_blocks() {
+        let mut iter = BlockIterator::create_and_seek_to_first(sst.read_block(idx).unwrap());
+        let first_key = iter.key().to_key_vec();
+        let mut data = Vec::new();
+        let mut offsets: Vec<u16> = Vec::new();
+        while iter.is_valid() {
+            let key = iter.key();
+            let overlap = if offsets.is_empty() {
+                0
+            } else {
+                first_key
+                    .key_ref()
+                    .iter()
+                    .zip(key.key_ref())
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            };
+            offsets.push(data.len() as u16);
+            data.put_u16(overlap as u16);
+            data.put_u16((key.key_len() - overlap) as u16);
+            data.put_slice(&key.key_ref()[overlap..]);
+            data.put_u64(encode_entry_ts(key.ts(), iter.value_kind()));
+            data.put_u16(iter.value().len() as u16);
+            data.put_slice(iter.value());
+            iter.next();
+        }
+        let block_start = buf.len();
+        let fixed = Block {
+            data,
+            offsets,
+            restarts: Vec::new(),
+            hash_buckets: Vec::new(),
+            format: BlockFormat::Fixed,
+            comparator: &BYTEWISE_COMPARATOR,
+        };
+        buf.put_slice(&fixed.encode());
+        if compression_type {
+            buf.put_u8(CompressionType::None.to_u8());
+        }
+        buf.put_u32(crc32fast::hash(&buf[block_start..]));
+        block_meta.push(BlockMeta {
+            offset: block_start,
+            ..sst.block_meta(idx).unwrap()
+        });
+    }
+    (buf, block_meta)
+}
+
+fn check_seek(sst: Arc<SsTable>) {
+    for idx in 0..NUM_KEYS {
+        let key = key_of(idx);
+        let mut iter = SsTableIterator::create_and_seek_to_key(
+            sst.clone(),
+            KeySlice::for_testing_from_slice_with_ts(&key, 1),
+        )
+        .unwrap();
+        assert!(iter.is_valid());
+        assert_eq!(iter.key().for_testing_key_ref(), key.as_slice());
+        assert_eq!(iter.value(), value_of(idx).as_slice());
+
+        // A key between `idx` and `idx + 1` seeks to `idx + 1`.
+        let mut between = key.clone();
+        between.push(b'0');
+        iter.seek_to_key(KeySlice::for_testing_from_slice_with_ts(&between, 1))
+            .unwrap();
+        if idx + 1 == NUM_KEYS {
+            assert!(!iter.is_valid());
+        } else {
+            assert!(iter.is_valid());
+            assert_eq!(iter.key().for_testing_key_ref(), key_of(idx + 1).as_slice());
+        }
+    }
+    let iter = SsTableIterator::create_and_seek_to_key(
+        sst,
+        KeySlice::for_testing_from_slice_with_ts(b"key_99999", 1),
+    )
+    .unwrap();
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_sst_index_partitioned_seek() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let sst = build_sst(&path, None);
+    let num_partitions = sst.num_of_index_partitions().unwrap();
+    assert!(num_partitions > 1, "expect multiple index partitions");
+    assert!(num_partitions < sst.num_of_blocks());
+    check_seek(Arc::new(sst));
+
+    let sst = SsTable::open_for_test(FileObject::open(&path).unwrap()).unwrap();
+    assert_eq!(sst.num_of_index_partitions(), Some(num_partitions));
+    check_seek(Arc::new(sst));
+}
+
+#[test]
+fn test_sst_index_partitions_use_block_cache() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    drop(build_sst(&path, None));
+    let block_cache = Arc::new(BlockCache::new(1 << 20, 1 << 20));
+    let sst = Arc::new(
+        SsTable::open(
+            1,
+            Some(block_cache.clone()),
+            FileObject::open(&path).unwrap(),
+        )
+        .unwrap(),
+    );
+    let num_partitions = sst.num_of_index_partitions().unwrap();
+    assert!((0..num_partitions).all(|idx| !block_cache.contains_meta(MetaCacheKey::Index(1, idx))));
+
+    let iter = SsTableIterator::create_and_seek_to_key(
+        sst.clone(),
+        KeySlice::for_testing_from_slice_with_ts(&key_of(NUM_KEYS / 2), 1),
+    )
+    .unwrap();
+    assert!(iter.is_valid());
+    let cached_partitions = (0..num_partitions)
+        .filter(|idx| block_cache.contains_meta(MetaCacheKey::Index(1, *idx)))
+        .count();
+    assert_eq!(cached_partitions, 1);
+}
+
+#[test]
+fn test_sst_index_reads_flat_v1_tables() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let sst = build_sst(&path, None);
+
+    // Rewrite the table with the version 1 layout: data blocks with u16 lengths, followed by the
+    // metadata of every block, the bloom filter and a version 1 footer.
+    let (mut buf, block_meta) = encode_fixed_blocks(&sst, true);
+    let meta_offset = buf.len();
+    BlockMeta::encode_block_meta(&block_meta, sst.max_ts(), &mut buf).unwrap();
+    let bloom_offset = buf.len();
+    sst.bloom().unwrap().unwrap().encode(&mut buf);
+    Footer {
+        version: 1,
+        index: BlockHandle {
+            offset: meta_offset as u64,
+            len: (bloom_offset - meta_offset) as u64,
+        },
+        bloom: BlockHandle {
+            offset: bloom_offset as u64,
+            len: (buf.len() - bloom_offset) as u64,
+        },
+        range_tombstones: None,
+        global_ts: None,
+    }
+    .encode(&mut buf);
+    let v1_path = dir.path().join("v1.sst");
+    std::fs::write(&v1_path, buf).unwrap();
+
+    let v1 = SsTable::open_for_test(FileObject::open(&v1_path).unwrap()).unwrap();
+    assert_eq!(v1.format_version(), 1);
+    assert_eq!(v1.num_of_index_partitions(), None);
+    assert_eq!(v1.num_of_blocks(), block_meta.len());
+    for (idx, meta) in block_meta.iter().enumerate() {
+        assert_eq!(&v1.block_meta(idx).unwrap(), meta);
+    }
+    check_seek(Arc::new(v1));
+}
+
+#[test]
+fn test_sst_index_reads_baseline_v0_tables() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let sst = build_sst(&path, None);
+
+    // Rewrite the table with the layout from before the footer: data blocks without a compression
+    // type, the metadata of every block and its offset, then the bloom filter and its offset.
+    let (mut buf, block_meta) = encode_fixed_blocks(&sst, false);
+    let meta_offset = buf.len();
+    BlockMeta::encode_block_meta(&block_meta, sst.max_ts(), &mut buf).unwrap();
+    buf.put_u32(meta_offset as u32);
+    let bloom_offset = buf.len();
+    sst.bloom().unwrap().unwrap().encode(&mut buf);
+    buf.put_u32(bloom_offset as u32);
+    let v0_path = dir.path().join("v0.sst");
+    std::fs::write(&v0_path, buf).unwrap();
+
+    let footer = Footer::read(&FileObject::open(&v0_path).unwrap()).unwrap();
+    assert_eq!(footer.version, 0);
+    let v0 = SsTable::open_for_test(FileObject::open(&v0_path).unwrap()).unwrap();
+    assert_eq!(v0.format_version(), 0);
+    assert_eq!(v0.num_of_index_partitions(), None);
+    assert_eq!(v0.num_of_blocks(), block_meta.len());
+    assert_eq!(v0.max_ts(), sst.max_ts());
+    for (idx, meta) in block_meta.iter().enumerate() {
+        assert_eq!(&v0.block_meta(idx).unwrap(), meta);
+    }
+    check_seek(Arc::new(v0));
+}
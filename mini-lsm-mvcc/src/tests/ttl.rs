@@ -0,0 +1,227 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+    merge_operator::StringAppendOperator,
+    table::SsTableIterator,
+};
+
+const LIVE: Duration = Duration::from_secs(3600);
+/// A value written with no TTL has expired by the time it is read.
+const EXPIRED: Duration = Duration::ZERO;
+
+fn options(enable_wal: bool) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = enable_wal;
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{idx:04}").into_bytes()
+}
+
+fn value_of(idx: usize) -> Vec<u8> {
+    format!("value_{idx}").into_bytes()
+}
+
+fn collect(mut iter: impl for<'a> StorageIterator<KeyType<'a> = &'a [u8]>) -> Vec<(Bytes, Bytes)> {
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    result
+}
+
+/// Check every key with `get` and a full scan against the expected values.
+fn check(storage: &MiniLsm, expected: &[Option<Vec<u8>>]) {
+    for (idx, value) in expected.iter().enumerate() {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap().as_deref(),
+            value.as_deref(),
+            "key {idx}"
+        );
+    }
+    let expected_scan = expected
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, value)| {
+            value
+                .as_ref()
+                .map(|value| (Bytes::from(key_of(idx)), Bytes::from(value.clone())))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        collect(storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap()),
+        expected_scan
+    );
+}
+
+fn sst_entries(storage: &MiniLsm) -> usize {
+    let state = storage.inner.state.read();
+    let mut entries = 0;
+    for table in state.sstables.values() {
+        let mut iter = SsTableIterator::create_and_seek_to_first(table.clone()).unwrap();
+        while iter.is_valid() {
+            entries += 1;
+            iter.next().unwrap();
+        }
+    }
+    entries
+}
+
+#[test]
+fn test_expired_values_are_hidden() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(true)).unwrap();
+    let mut expected = vec![None; 40];
+    for (idx, value) in expected.iter_mut().enumerate() {
+        storage.put(&key_of(idx), b"old").unwrap();
+        if idx % 2 == 0 {
+            storage
+                .put_with_ttl(&key_of(idx), &value_of(idx), LIVE)
+                .unwrap();
+            *value = Some(value_of(idx));
+        } else {
+            // The expired value deletes the earlier version of the key.
+            storage
+                .put_with_ttl(&key_of(idx), &value_of(idx), EXPIRED)
+                .unwrap();
+        }
+        if idx == 20 {
+            storage.force_flush().unwrap();
+        }
+    }
+    check(&storage, &expected);
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options(true)).unwrap();
+    check(&storage, &expected);
+
+    // A later write replaces the expiring value.
+    storage.put(&key_of(1), &value_of(1)).unwrap();
+    storage.put(&key_of(2), &value_of(2)).unwrap();
+    expected[1] = Some(value_of(1));
+    check(&storage, &expected);
+}
+
+#[test]
+fn test_compaction_drops_expired_values_without_tombstones() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(false)).unwrap();
+    for idx in 0..20 {
+        storage.put(&key_of(idx), b"old").unwrap();
+    }
+    storage.force_flush().unwrap();
+    let mut expected = vec![None; 20];
+    for (idx, value) in expected.iter_mut().enumerate() {
+        if idx < 5 {
+            storage
+                .put_with_ttl(&key_of(idx), &value_of(idx), LIVE)
+                .unwrap();
+            *value = Some(value_of(idx));
+        } else {
+            storage
+                .put_with_ttl(&key_of(idx), &value_of(idx), EXPIRED)
+                .unwrap();
+        }
+    }
+    storage.force_flush().unwrap();
+    check(&storage, &expected);
+
+    storage.force_full_compaction().unwrap();
+    assert_eq!(sst_entries(&storage), 5);
+    check(&storage, &expected);
+}
+
+#[test]
+fn test_ttl_in_write_batches_and_transactions() {
+    let dir = tempdir().unwrap();
+    let mut options = options(false);
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::PutWithTtl(key_of(0), value_of(0), LIVE),
+            WriteBatchRecord::PutWithTtl(key_of(1), value_of(1), EXPIRED),
+            WriteBatchRecord::PutWithTtl(key_of(2), value_of(2), EXPIRED),
+            WriteBatchRecord::Put(key_of(2), value_of(2)),
+        ])
+        .unwrap();
+    storage
+        .put_with_ttl(&key_of(3), &value_of(3), LIVE)
+        .unwrap();
+
+    let txn = storage.new_txn().unwrap();
+    txn.put_with_ttl(&key_of(4), &value_of(4), EXPIRED);
+    // The value expires after the commit, so the transaction still sees it.
+    assert_eq!(txn.get(&key_of(4)).unwrap(), Some(Bytes::from(value_of(4))));
+    txn.put_with_ttl(&key_of(5), &value_of(5), EXPIRED);
+    txn.put(&key_of(5), &value_of(5));
+    txn.commit().unwrap();
+
+    check(
+        &storage,
+        &[
+            Some(value_of(0)),
+            None,
+            Some(value_of(2)),
+            Some(value_of(3)),
+            None,
+            Some(value_of(5)),
+        ],
+    );
+}
+
+#[test]
+fn test_merge_into_expiring_values() {
+    let dir = tempdir().unwrap();
+    let mut options = options(false);
+    options.merge_operator = Some(Arc::new(StringAppendOperator(b',')));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put_with_ttl(&key_of(0), b"a", LIVE).unwrap();
+    storage.put(&key_of(1), b"old").unwrap();
+    storage.put_with_ttl(&key_of(1), b"a", EXPIRED).unwrap();
+    storage.force_flush().unwrap();
+    storage.merge(&key_of(0), b"b").unwrap();
+    storage.merge(&key_of(1), b"b").unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::PutWithTtl(&key_of(2)[..], b"a", EXPIRED),
+            WriteBatchRecord::Merge(&key_of(2)[..], b"b"),
+        ])
+        .unwrap();
+    let expected = [
+        Some(b"a,b".to_vec()),
+        Some(b"b".to_vec()),
+        Some(b"b".to_vec()),
+    ];
+    check(&storage, &expected);
+
+    storage.force_full_compaction().unwrap();
+    check(&storage, &expected);
+}
@@ -0,0 +1,266 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::{StorageIterator, ValueKind},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::SsTableIterator,
+};
+
+const THRESHOLD: usize = 64;
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    options.value_separation_threshold = Some(THRESHOLD);
+    options.value_log_gc_discard_ratio = 0.3;
+    options
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+/// Even keys get values that are separated, odd keys get small values that stay in the SSTs.
+fn value_of(idx: usize, version: usize) -> Vec<u8> {
+    let value = format!("value_{:05}_{}_", idx, version);
+    if idx.is_multiple_of(2) {
+        value.repeat(8).into_bytes()
+    } else {
+        value.into_bytes()
+    }
+}
+
+fn value_log_files(path: &Path) -> Vec<String> {
+    let mut files = std::fs::read_dir(path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".vlog"))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+fn check(storage: &MiniLsm, num_keys: usize, version_of: impl Fn(usize) -> usize) {
+    for idx in 0..num_keys {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(Bytes::from(value_of(idx, version_of(idx)))),
+            "key {idx}"
+        );
+    }
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    for idx in 0..num_keys {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key(), key_of(idx).as_slice());
+        assert_eq!(iter.value(), value_of(idx, version_of(idx)).as_slice());
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_value_log_separates_large_values() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    assert_eq!(value_log_files(dir.path()).len(), 1);
+
+    let sst = {
+        let state = storage.inner.state.read();
+        state.sstables[&state.l0_sstables[0]].clone()
+    };
+    let mut iter = SsTableIterator::create_and_seek_to_first(Arc::clone(&sst)).unwrap();
+    let mut idx = 0usize;
+    while iter.is_valid() {
+        let expected = if idx.is_multiple_of(2) {
+            ValueKind::ValuePointer
+        } else {
+            ValueKind::Value
+        };
+        assert_eq!(iter.value_kind(), expected);
+        assert!(iter.value().len() < THRESHOLD);
+        idx += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(idx, 100);
+    check(&storage, 100, |_| 0);
+}
+
+#[test]
+fn test_value_log_survives_compaction_and_restart() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (0..100).step_by(3) {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    let version_of = |idx: usize| usize::from(idx.is_multiple_of(3));
+    check(&storage, 100, version_of);
+    storage.close().unwrap();
+    drop(storage);
+
+    // A value log file that no SST was written with is left over from an interrupted flush.
+    std::fs::write(dir.path().join("99999.vlog"), b"garbage").unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    check(&storage, 100, version_of);
+    assert_eq!(value_log_files(dir.path()).len(), 2);
+}
+
+#[test]
+fn test_value_log_gc_rewrites_live_values() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    let [first] = value_log_files(dir.path()).try_into().unwrap();
+
+    // Overwrite half of the separated values, and drop the old versions in a compaction.
+    for idx in (0..100).step_by(4) {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    let version_of = |idx: usize| usize::from(idx.is_multiple_of(4));
+
+    storage.force_value_log_gc().unwrap();
+    let files = value_log_files(dir.path());
+    assert!(!files.contains(&first), "{first} should be collected");
+    assert_eq!(files.len(), 2);
+    check(&storage, 100, version_of);
+
+    // Nothing has been discarded from the remaining files.
+    storage.force_value_log_gc().unwrap();
+    assert_eq!(value_log_files(dir.path()), files);
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    check(&storage, 100, version_of);
+    assert_eq!(value_log_files(dir.path()), files);
+}
+
+#[test]
+fn test_value_log_gc_while_freezing_memtables() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    for idx in 0..1000 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (0..1000).step_by(4) {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    let version_of = |idx: usize| usize::from(idx.is_multiple_of(4));
+
+    // Freeze and flush the mem-tables while the live values are written back to them.
+    std::thread::scope(|scope| {
+        let freezer = scope.spawn(|| {
+            for idx in 1000..1200 {
+                storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+                storage.force_flush().unwrap();
+            }
+        });
+        storage.force_value_log_gc().unwrap();
+        freezer.join().unwrap();
+    });
+    let version_of = |idx: usize| if idx < 1000 { version_of(idx) } else { 0 };
+    check(&storage, 1200, version_of);
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    check(&storage, 1200, version_of);
+}
+
+#[test]
+fn test_value_log_discards_survive_restart() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    let [first] = value_log_files(dir.path()).try_into().unwrap();
+    for idx in (0..100).step_by(4) {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    // The compaction discarded enough of the first file before the restart.
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    storage.force_value_log_gc().unwrap();
+    assert!(!value_log_files(dir.path()).contains(&first));
+    check(&storage, 100, |idx| usize::from(idx.is_multiple_of(4)));
+}
+
+#[test]
+fn test_value_log_gc_drops_values_under_range_tombstones() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (60..100).step_by(2) {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+
+    // The range tombstone is still in the mem-table when the garbage collector runs.
+    storage.delete_range(&key_of(0), &key_of(20)).unwrap();
+    storage.force_value_log_gc().unwrap();
+    let rewritten = storage
+        .inner
+        .value_log
+        .snapshot()
+        .values()
+        .flat_map(|file| file.records().unwrap())
+        .map(|record| record.key.key_ref().to_vec())
+        .collect::<Vec<_>>();
+    assert!(rewritten.contains(&key_of(20)));
+    assert!(!rewritten.iter().any(|key| key < &key_of(20)));
+    for idx in 0..100usize {
+        let expected = match idx {
+            0..20 => None,
+            60..100 if idx.is_multiple_of(2) => Some(Bytes::from(value_of(idx, 1))),
+            _ => Some(Bytes::from(value_of(idx, 0))),
+        };
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), expected, "key {idx}");
+    }
+}
@@ -23,7 +23,7 @@ use crate::{
     key::{KeyBytes, KeySlice},
     lsm_storage::{LsmStorageInner, LsmStorageOptions, MiniLsm},
     tests::harness::check_lsm_iter_result_by_key,
-    wal::Wal,
+    wal::{WAL_HEADER_SIZE, Wal},
 };
 
 #[test]
@@ -170,7 +170,10 @@ fn test_task4_wal_ignores_and_truncates_incomplete_final_batch() {
         let map = SkipMap::<KeyBytes, Bytes>::new();
         drop(Wal::recover(&truncated_path, &map).unwrap());
         assert!(map.is_empty());
-        assert_eq!(std::fs::metadata(&truncated_path).unwrap().len(), 0);
+        assert_eq!(
+            std::fs::metadata(&truncated_path).unwrap().len(),
+            WAL_HEADER_SIZE as u64
+        );
     }
 }
 
@@ -280,16 +283,82 @@ fn test_task4_wal_recovery_is_atomic_across_frames() {
 }
 
 #[test]
-fn test_task4_wal_rejects_oversized_fields() {
+fn test_task4_wal_round_trips_large_fields() {
     let dir = tempdir().unwrap();
     let path = dir.path().join("test.wal");
     let wal = Wal::create(&path).unwrap();
-    let oversized_value = vec![0; usize::from(u16::MAX) + 1];
+    let large_key = vec![b'k'; usize::from(u16::MAX) + 1];
+    let large_value = vec![b'v'; 3 * usize::from(u16::MAX)];
+    wal.put_batch(&[
+        (
+            KeySlice::from_slice_with_ts(b"key", 1),
+            large_value.as_slice(),
+        ),
+        (KeySlice::from_slice_with_ts(&large_key, 1), b"value"),
+    ])
+    .unwrap();
+    wal.sync().unwrap();
+    drop(wal);
+
+    let map = SkipMap::<KeyBytes, Bytes>::new();
+    drop(Wal::recover(&path, &map).unwrap());
+    assert_eq!(map.len(), 2);
+    assert_eq!(
+        map.get(&KeyBytes::from_bytes_with_ts(Bytes::from_static(b"key"), 1))
+            .unwrap()
+            .value(),
+        &Bytes::from(large_value)
+    );
+    assert_eq!(
+        map.get(&KeyBytes::from_bytes_with_ts(Bytes::from(large_key), 1))
+            .unwrap()
+            .value(),
+        &Bytes::from_static(b"value")
+    );
+}
+
+#[test]
+fn test_task4_wal_recovers_legacy_frames() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("legacy.wal");
+    // A version 1 WAL has no header, and its frames use u16 lengths.
+    let mut batch = Vec::new();
+    for (key, value) in [(b"a", b"1"), (b"b", b"2")] {
+        batch.extend_from_slice(&(key.len() as u16).to_be_bytes());
+        batch.extend_from_slice(key);
+        batch.extend_from_slice(&3u64.to_be_bytes());
+        batch.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        batch.extend_from_slice(value);
+    }
+    let mut encoded = (batch.len() as u32).to_be_bytes().to_vec();
+    encoded.extend_from_slice(&batch);
+    encoded.extend_from_slice(&crc32fast::hash(&batch).to_be_bytes());
+    std::fs::write(&path, &encoded).unwrap();
+
+    let map = SkipMap::<KeyBytes, Bytes>::new();
+    let wal = Wal::recover(&path, &map).unwrap();
+    assert_eq!(map.len(), 2);
+    assert_eq!(
+        map.get(&KeyBytes::from_bytes_with_ts(Bytes::from_static(b"b"), 3))
+            .unwrap()
+            .value(),
+        &Bytes::from_static(b"2")
+    );
+
+    // Appending to a legacy WAL keeps its format.
+    wal.put_batch(&[(KeySlice::from_slice_with_ts(b"c", 4), b"3")])
+        .unwrap();
+    let large_value = vec![0; usize::from(u16::MAX) + 1];
     assert!(
         wal.put_batch(&[(
-            KeySlice::from_slice_with_ts(b"key", 1),
-            oversized_value.as_slice(),
+            KeySlice::from_slice_with_ts(b"d", 4),
+            large_value.as_slice()
         )])
         .is_err()
     );
+    wal.sync().unwrap();
+    drop(wal);
+    let map = SkipMap::<KeyBytes, Bytes>::new();
+    drop(Wal::recover(&path, &map).unwrap());
+    assert_eq!(map.len(), 3);
 }
@@ -0,0 +1,55 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Time to live. A value written with a TTL is stored as an expiring value: its expiry time, in
+//! milliseconds since the Unix epoch, followed by the value. Reads hide the values that have
+//! expired, and compaction drops them once they reach the bottom level.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, ensure};
+use bytes::{Buf, BufMut};
+
+const EXPIRY_SIZE: usize = std::mem::size_of::<u64>();
+
+/// The current time in milliseconds since the Unix epoch.
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis().try_into().unwrap_or(u64::MAX))
+}
+
+/// The expiry time of a value written now with `ttl`.
+pub(crate) fn expiry_after(ttl: Duration) -> u64 {
+    now_millis().saturating_add(ttl.as_millis().try_into().unwrap_or(u64::MAX))
+}
+
+pub(crate) fn encode_expiring_value(expire_at: u64, value: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(EXPIRY_SIZE + value.len());
+    buf.put_u64(expire_at);
+    buf.put_slice(value);
+    buf
+}
+
+/// Split an expiring value into its expiry time and the value.
+pub(crate) fn decode_expiring_value(mut value: &[u8]) -> Result<(u64, &[u8])> {
+    ensure!(value.len() > EXPIRY_SIZE, "expiring value is too short");
+    let expire_at = value.get_u64();
+    Ok((expire_at, value))
+}
+
+/// Returns the value of an expiring value, or `None` if it has expired at `now`.
+pub(crate) fn unexpired_value(value: &[u8], now: u64) -> Result<Option<&[u8]>> {
+    let (expire_at, value) = decode_expiring_value(value)?;
+    Ok((now < expire_at).then_some(value))
+}
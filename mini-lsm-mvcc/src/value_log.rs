@@ -0,0 +1,559 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Key-value separation.
+//!
+//! When a memtable is flushed, values at least as large as the value separation threshold are
+//! written to a value log file that shares its id with the SST, and the SST stores a pointer to
+//! the record instead of the value. Compaction copies the pointers, so large values are written
+//! only once. A value log file is append-only and consists of records:
+//!
+//! ```text
+//! | key_len (u16) | key | ts (u64) | value_len (u32) | value | checksum (u32) |
+//! ```
+//!
+//! The lengths are big-endian, and the checksum covers the rest of the record. Values whose records
+//! would not fit these lengths are kept in the SST.
+//!
+//! Compaction counts the bytes of the records whose pointers it drops. Once enough of a file is
+//! discarded, the garbage collector writes the live values back to the LSM tree and deletes the
+//! file. The counters of the files that a compaction discarded from are written to the manifest
+//! with the compaction, so they survive a restart.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result, ensure};
+use bytes::{BufMut, Bytes};
+use parking_lot::{Mutex, RwLock};
+
+use crate::iterators::{StorageIterator, ValueKind};
+use crate::key::{KeyBytes, KeySlice};
+use crate::lsm_storage::{DEFAULT_COLUMN_FAMILY, LsmStorageInner, LsmStorageState};
+use crate::manifest::ManifestRecord;
+use crate::range_tombstone::is_covered;
+use crate::table::FileObject;
+
+/// The bytes of a record besides its key and value.
+const VALUE_LOG_RECORD_OVERHEAD: usize =
+    std::mem::size_of::<u16>() + std::mem::size_of::<u64>() + std::mem::size_of::<u32>() * 2;
+
+/// Locates a record in a value log file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValuePointer {
+    pub file_id: usize,
+    /// Offset of the record in the file.
+    pub offset: u64,
+    /// Length of the whole record, including the key and the checksum.
+    pub len: u32,
+}
+
+impl ValuePointer {
+    pub const ENCODED_SIZE: usize = std::mem::size_of::<u64>() * 2 + std::mem::size_of::<u32>();
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::ENCODED_SIZE);
+        buf.put_u64(self.file_id as u64);
+        buf.put_u64(self.offset);
+        buf.put_u32(self.len);
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        ensure!(
+            buf.len() == Self::ENCODED_SIZE,
+            "value pointer has an invalid length"
+        );
+        Ok(Self {
+            file_id: usize::try_from(u64::from_be_bytes(buf[0..8].try_into()?))
+                .context("value log file id overflow")?,
+            offset: u64::from_be_bytes(buf[8..16].try_into()?),
+            len: u32::from_be_bytes(buf[16..20].try_into()?),
+        })
+    }
+}
+
+/// A record decoded from a value log file.
+pub struct ValueLogRecord {
+    pub key: KeyBytes,
+    pub value: Bytes,
+    pub pointer: ValuePointer,
+}
+
+fn decode_record(buf: &[u8], pointer: ValuePointer) -> Result<ValueLogRecord> {
+    ensure!(
+        buf.len() >= std::mem::size_of::<u32>(),
+        "value log record is truncated"
+    );
+    let (body, checksum) = buf.split_at(buf.len() - std::mem::size_of::<u32>());
+    ensure!(
+        u32::from_be_bytes(checksum.try_into()?) == crc32fast::hash(body),
+        "value log record checksum mismatched"
+    );
+    let mut cursor = body;
+    let key_len = usize::from(u16::from_be_bytes(
+        take(&mut cursor, std::mem::size_of::<u16>())?.try_into()?,
+    ));
+    let key = take(&mut cursor, key_len)?;
+    let ts = u64::from_be_bytes(take(&mut cursor, std::mem::size_of::<u64>())?.try_into()?);
+    let value_len =
+        u32::from_be_bytes(take(&mut cursor, std::mem::size_of::<u32>())?.try_into()?) as usize;
+    let value = take(&mut cursor, value_len)?;
+    ensure!(cursor.is_empty(), "value log record has trailing bytes");
+    Ok(ValueLogRecord {
+        key: KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(key), ts),
+        value: Bytes::copy_from_slice(value),
+        pointer,
+    })
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    ensure!(buf.len() >= len, "value log record is truncated");
+    let (value, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(value)
+}
+
+/// Buffers the records of a value log file that is written together with an SST.
+pub struct ValueLogWriter {
+    file_id: usize,
+    threshold: usize,
+    data: Vec<u8>,
+}
+
+impl ValueLogWriter {
+    pub fn new(file_id: usize, threshold: usize) -> Self {
+        Self {
+            file_id,
+            threshold,
+            data: Vec::new(),
+        }
+    }
+
+    /// Returns true if a value should be stored in the value log. Records that do not fit the
+    /// value log format stay in the SST.
+    pub fn should_separate(&self, key: KeySlice, value: &[u8]) -> bool {
+        value.len() >= self.threshold
+            && key.key_len() <= usize::from(u16::MAX)
+            && value.len() <= (u32::MAX as usize) - key.key_len() - VALUE_LOG_RECORD_OVERHEAD
+    }
+
+    /// Append a record and return the pointer to it. Fails if the record does not fit the value
+    /// log format, which `should_separate` checks.
+    pub fn add(&mut self, key: KeySlice, value: &[u8]) -> Result<ValuePointer> {
+        let offset = self.data.len();
+        let key_len = u16::try_from(key.key_len()).context("key is too large for the value log")?;
+        let value_len =
+            u32::try_from(value.len()).context("value is too large for the value log")?;
+        let len =
+            u32::try_from(usize::from(key_len) + value_len as usize + VALUE_LOG_RECORD_OVERHEAD)
+                .context("value log record is too large")?;
+        self.data.put_u16(key_len);
+        self.data.put_slice(key.key_ref());
+        self.data.put_u64(key.ts());
+        self.data.put_u32(value_len);
+        self.data.put_slice(value);
+        self.data.put_u32(crc32fast::hash(&self.data[offset..]));
+        Ok(ValuePointer {
+            file_id: self.file_id,
+            offset: offset as u64,
+            len,
+        })
+    }
+
+    pub fn file_id(&self) -> usize {
+        self.file_id
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Write the file to the disk.
+    pub fn finish(self, path: &Path) -> Result<()> {
+        FileObject::create(path, self.data)?;
+        Ok(())
+    }
+}
+
+/// A value log file that has been written.
+pub struct ValueLogFile {
+    id: usize,
+    file: FileObject,
+    /// Bytes of the records that are no longer referenced by the LSM tree.
+    discarded: AtomicU64,
+}
+
+impl ValueLogFile {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn size(&self) -> u64 {
+        self.file.size()
+    }
+
+    /// The fraction of the file that is no longer referenced.
+    pub fn discard_ratio(&self) -> f64 {
+        if self.size() == 0 {
+            return 0.0;
+        }
+        self.discarded.load(Ordering::Relaxed) as f64 / self.size() as f64
+    }
+
+    /// Read the value of a record, checking that the record belongs to `key`.
+    pub fn read_value(&self, pointer: ValuePointer, key: &[u8]) -> Result<Bytes> {
+        let end = pointer
+            .offset
+            .checked_add(u64::from(pointer.len))
+            .context("value pointer overflow")?;
+        ensure!(
+            end <= self.size(),
+            "value pointer is outside {:05}.vlog",
+            self.id
+        );
+        let buf = self.file.read(pointer.offset, u64::from(pointer.len))?;
+        let record = decode_record(&buf, pointer)
+            .with_context(|| format!("invalid record in {:05}.vlog", self.id))?;
+        ensure!(
+            record.key.key_ref() == key,
+            "value pointer does not belong to the key"
+        );
+        Ok(record.value)
+    }
+
+    /// Read every record of the file.
+    pub fn records(&self) -> Result<Vec<ValueLogRecord>> {
+        let buf = self.file.read(0, self.size())?;
+        let mut records = Vec::new();
+        let mut offset = 0;
+        while offset < buf.len() {
+            let remaining = &buf[offset..];
+            ensure!(
+                remaining.len() >= std::mem::size_of::<u16>(),
+                "value log record is truncated"
+            );
+            let key_len = usize::from(u16::from_be_bytes([remaining[0], remaining[1]]));
+            let value_len_offset =
+                std::mem::size_of::<u16>() + key_len + std::mem::size_of::<u64>();
+            let value_len_end = value_len_offset + std::mem::size_of::<u32>();
+            ensure!(
+                remaining.len() >= value_len_end,
+                "value log record is truncated"
+            );
+            let value_len =
+                u32::from_be_bytes(remaining[value_len_offset..value_len_end].try_into()?) as usize;
+            let len = value_len_end + value_len + std::mem::size_of::<u32>();
+            ensure!(remaining.len() >= len, "value log record is truncated");
+            let pointer = ValuePointer {
+                file_id: self.id,
+                offset: offset as u64,
+                len: u32::try_from(len).context("value log record is too large")?,
+            };
+            records.push(
+                decode_record(&remaining[..len], pointer)
+                    .with_context(|| format!("invalid record in {:05}.vlog", self.id))?,
+            );
+            offset += len;
+        }
+        Ok(records)
+    }
+}
+
+/// The value log files that are referenced by the LSM tree.
+pub struct ValueLog {
+    path: PathBuf,
+    files: RwLock<Arc<BTreeMap<usize, Arc<ValueLogFile>>>>,
+    /// Serializes garbage collection runs. It is taken before the write lock of the transactions
+    /// and the state lock.
+    pub(crate) gc_lock: Mutex<()>,
+    /// The files whose discard counters changed since they were last written to the manifest.
+    updated: Mutex<BTreeSet<usize>>,
+}
+
+impl ValueLog {
+    pub(crate) fn path_of_file_static(path: impl AsRef<Path>, id: usize) -> PathBuf {
+        path.as_ref().join(format!("{:05}.vlog", id))
+    }
+
+    /// Open the value log files in a directory. Files that are not written together with one of
+    /// the `known_ssts`, or that have been garbage collected, are deleted.
+    pub fn open(
+        path: impl AsRef<Path>,
+        known_ssts: &HashSet<usize>,
+        collected: &HashSet<usize>,
+    ) -> Result<Self> {
+        Self::open_with_mode(path.as_ref(), known_ssts, collected, false)
+    }
+
+    /// Open the value log files in a directory like [`ValueLog::open`], without deleting the files
+    /// that are not referenced.
+    pub fn open_read_only(
+        path: impl AsRef<Path>,
+        known_ssts: &HashSet<usize>,
+        collected: &HashSet<usize>,
+    ) -> Result<Self> {
+        Self::open_with_mode(path.as_ref(), known_ssts, collected, true)
+    }
+
+    fn open_with_mode(
+        path: &Path,
+        known_ssts: &HashSet<usize>,
+        collected: &HashSet<usize>,
+        read_only: bool,
+    ) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        for entry in std::fs::read_dir(path).context("failed to list the value log files")? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(id) = file_name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".vlog"))
+                .and_then(|id| id.parse::<usize>().ok())
+            else {
+                continue;
+            };
+            if !known_ssts.contains(&id) || collected.contains(&id) {
+                if !read_only {
+                    std::fs::remove_file(entry.path())?;
+                }
+                continue;
+            }
+            let file = FileObject::open(&entry.path()).context("failed to open value log")?;
+            files.insert(
+                id,
+                Arc::new(ValueLogFile {
+                    id,
+                    file,
+                    discarded: AtomicU64::new(0),
+                }),
+            );
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            files: RwLock::new(Arc::new(files)),
+            gc_lock: Mutex::new(()),
+            updated: Mutex::new(BTreeSet::new()),
+        })
+    }
+
+    pub(crate) fn path_of_file(&self, id: usize) -> PathBuf {
+        Self::path_of_file_static(&self.path, id)
+    }
+
+    /// Get the files that are currently referenced. Readers take the snapshot before the LSM state,
+    /// so that the snapshot contains every file the state may point to.
+    pub fn snapshot(&self) -> Arc<BTreeMap<usize, Arc<ValueLogFile>>> {
+        self.files.read().clone()
+    }
+
+    /// Start tracking a file that has just been written.
+    pub fn add_file(&self, id: usize) -> Result<()> {
+        let file = FileObject::open(&self.path_of_file(id)).context("failed to open value log")?;
+        let mut guard = self.files.write();
+        let mut files = guard.as_ref().clone();
+        files.insert(
+            id,
+            Arc::new(ValueLogFile {
+                id,
+                file,
+                discarded: AtomicU64::new(0),
+            }),
+        );
+        *guard = Arc::new(files);
+        Ok(())
+    }
+
+    /// Stop tracking a file, and delete it from the disk.
+    pub fn remove_file(&self, id: usize) -> Result<()> {
+        {
+            let mut guard = self.files.write();
+            let mut files = guard.as_ref().clone();
+            files.remove(&id);
+            *guard = Arc::new(files);
+        }
+        std::fs::remove_file(self.path_of_file(id))?;
+        Ok(())
+    }
+
+    /// Record that a pointer has been dropped from the LSM tree.
+    pub fn discard(&self, pointer: ValuePointer) {
+        if let Some(file) = self.files.read().get(&pointer.file_id) {
+            file.discarded
+                .fetch_add(u64::from(pointer.len), Ordering::Relaxed);
+            self.updated.lock().insert(pointer.file_id);
+        }
+    }
+
+    /// Set the discard counters recovered from the manifest.
+    pub(crate) fn restore_discarded(&self, discarded: &BTreeMap<usize, u64>) {
+        let files = self.files.read();
+        for (id, bytes) in discarded {
+            if let Some(file) = files.get(id) {
+                file.discarded.store(*bytes, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// The discard counters of the files with the ids.
+    pub(crate) fn discarded(&self, ids: impl IntoIterator<Item = usize>) -> Vec<(usize, u64)> {
+        let files = self.files.read();
+        ids.into_iter()
+            .filter_map(|id| {
+                let file = files.get(&id)?;
+                Some((id, file.discarded.load(Ordering::Relaxed)))
+            })
+            .collect()
+    }
+
+    /// Take the discard counters that changed since the last call, to write them to the manifest.
+    pub(crate) fn take_updated_discarded(&self) -> Vec<(usize, u64)> {
+        let updated = std::mem::take(&mut *self.updated.lock());
+        self.discarded(updated)
+    }
+}
+
+/// Resolve a value pointer with a snapshot of the value log files.
+pub(crate) fn resolve_value(
+    files: &BTreeMap<usize, Arc<ValueLogFile>>,
+    key: &[u8],
+    pointer: &[u8],
+) -> Result<Bytes> {
+    let pointer = ValuePointer::decode(pointer)?;
+    let file = files
+        .get(&pointer.file_id)
+        .with_context(|| format!("value log file {:05}.vlog is missing", pointer.file_id))?;
+    file.read_value(pointer, key)
+}
+
+impl LsmStorageInner {
+    /// Returns the timestamp of the version of the key that still refers to a value log record, if
+    /// no range tombstone that every reader sees has deleted it. The version is found by its
+    /// pointer, as an ingested table gives its keys a timestamp after the record is written.
+    fn live_record_ts(
+        &self,
+        snapshot: &LsmStorageState,
+        record: &ValueLogRecord,
+    ) -> Result<Option<u64>> {
+        let key = record.key.key_ref();
+        let mut iter = self.point_lookup_iter(snapshot, key)?;
+        while iter.is_valid() && iter.key().key_ref() == key {
+            // The timestamp of a version is never older than the one written with its record.
+            if iter.key().ts() < record.key.ts() {
+                break;
+            }
+            if iter.value_kind() == ValueKind::ValuePointer
+                && ValuePointer::decode(iter.value())? == record.pointer
+            {
+                let ts = iter.key().ts();
+                let range_tombstones = self.range_tombstones(
+                    snapshot,
+                    Bound::Included(key),
+                    Bound::Included(key),
+                    self.mvcc().watermark(),
+                );
+                return Ok(
+                    (!is_covered(&range_tombstones, key, ts, self.options.comparator))
+                        .then_some(ts),
+                );
+            }
+            iter.next()?;
+        }
+        Ok(None)
+    }
+
+    /// Garbage collect the value log files whose discard ratio has reached the configured ratio.
+    /// The live values are written back to the memtable with the timestamps of their versions and
+    /// flushed, which moves them to a new value log file, before the old files are deleted.
+    pub(crate) fn trigger_value_log_gc(&self) -> Result<()> {
+        self.ensure_writable()?;
+        let _gc_lock = self.value_log.gc_lock.lock();
+        let candidates = self
+            .value_log
+            .snapshot()
+            .values()
+            .filter(|file| file.discard_ratio() >= self.options.value_log_gc_discard_ratio)
+            .cloned()
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Ok(());
+        }
+
+        let snapshot = self.state.read().clone();
+        let column_families = std::iter::once((DEFAULT_COLUMN_FAMILY, snapshot.as_ref()))
+            .chain(
+                snapshot
+                    .column_families
+                    .iter()
+                    .map(|(name, family)| (name.as_str(), family.state.as_ref())),
+            )
+            .collect::<Vec<_>>();
+        let mut rewritten = 0;
+        for file in &candidates {
+            for record in file.records()? {
+                // A value pointer is only written to one SST, so at most one column family owns
+                // the record, and the value is written back to it.
+                for (name, state) in &column_families {
+                    if let Some(ts) = self.live_record_ts(state, &record)? {
+                        // Hold the state like a write does, so that the mem-table is not frozen
+                        // and flushed before the value is in it.
+                        let guard = self.state.read();
+                        let (_, family_state) = guard.column_family(name)?;
+                        family_state.memtable.put(
+                            KeySlice::from_slice_with_ts(record.key.key_ref(), ts),
+                            &record.value,
+                        )?;
+                        rewritten += 1;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if rewritten > 0 {
+            let state_lock = self.state_lock.lock();
+            if !self.is_memtable_empty() {
+                self.force_freeze_memtable(&state_lock)?;
+            }
+            let memtable_id = self.state.read().memtable.id();
+            drop(state_lock);
+            // Flush every memtable that may hold a rewritten value.
+            while self
+                .state
+                .read()
+                .imm_memtables
+                .last()
+                .is_some_and(|memtable| memtable.id() < memtable_id)
+            {
+                self.force_flush_next_imm_memtable()?;
+            }
+        }
+
+        let ids = candidates.iter().map(|file| file.id()).collect::<Vec<_>>();
+        for id in &ids {
+            self.manifest()
+                .add_record(&self.state_lock.lock(), ManifestRecord::ValueLogGc(*id))?;
+            self.value_log.remove_file(*id)?;
+        }
+        self.sync_dir()?;
+        println!(
+            "value log gc finished: {} values rewritten, files removed: {:?}",
+            rewritten, ids
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Variable-length integers (LEB128), used for the lengths of keys and values on the disk. Each
+//! byte holds 7 bits of the value, least significant first, and the top bit is set on every byte
+//! but the last.
+
+use anyhow::{Result, bail};
+use bytes::BufMut;
+
+/// The longest encoding of a u64.
+pub const MAX_VARINT_LEN: usize = 10;
+
+/// Append a varint to a buffer.
+pub fn put_uvarint(buf: &mut impl BufMut, mut value: u64) {
+    while value >= 0x80 {
+        buf.put_u8((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.put_u8(value as u8);
+}
+
+/// The number of bytes that `value` takes when encoded as a varint.
+pub fn uvarint_len(value: u64) -> usize {
+    let bits = (u64::BITS - value.leading_zeros()).max(1) as usize;
+    bits.div_ceil(7)
+}
+
+/// Decode a varint from the front of a buffer and advance the buffer past it.
+pub fn get_uvarint(buf: &mut &[u8], what: &str) -> Result<u64> {
+    let mut value = 0u64;
+    for (idx, byte) in buf.iter().enumerate() {
+        if idx == MAX_VARINT_LEN || (idx == MAX_VARINT_LEN - 1 && *byte > 1) {
+            bail!("{what} overflows");
+        }
+        value |= u64::from(byte & 0x7f) << (idx * 7);
+        if byte & 0x80 == 0 {
+            *buf = &buf[idx + 1..];
+            return Ok(value);
+        }
+    }
+    bail!("{what} is truncated")
+}
+
+/// Decode a varint length.
+pub fn get_uvarint_len(buf: &mut &[u8], what: &str) -> Result<usize> {
+    let value = get_uvarint(buf, what)?;
+    usize::try_from(value).map_err(|_| anyhow::anyhow!("{what} overflows"))
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
 use std::hash::Hasher;
 use std::io::{BufWriter, Read, Write};
@@ -23,27 +24,289 @@ use bytes::{Buf, BufMut, Bytes};
 use crossbeam_skiplist::SkipMap;
 use parking_lot::Mutex;
 
+use crate::iterators::ValueKind;
 use crate::key::{KeyBytes, KeySlice};
+use crate::range_tombstone::RangeTombstone;
+use crate::varint::{get_uvarint_len, put_uvarint};
+
+/// Every WAL written by this build starts with this magic number ("mwal" in ASCII) followed by the
+/// format version. Version 2 encodes the lengths of keys and values as varints. WALs without the
+/// header are version 1, which uses u16 lengths. Version 3 also logs range tombstones, which are
+/// entries whose timestamp has `RANGE_TOMBSTONE_FLAG` set, with the start of the range as the key
+/// and its end as the value. Version 4 logs merge operands, whose timestamps have
+/// `MERGE_OPERAND_FLAG` set. Version 5 logs expiring values, whose timestamps have
+/// `EXPIRING_VALUE_FLAG` set. Version 6 logs the entries and the range tombstones of column
+/// families, whose timestamps have `COLUMN_FAMILY_FLAG` set and whose keys start with the varint
+/// id of their column family.
+const WAL_MAGIC: u32 = 0x6d77_616c;
+const WAL_FORMAT_VERSION: u32 = 6;
+const RANGE_TOMBSTONE_FLAG: u64 = 1 << 63;
+const MERGE_OPERAND_FLAG: u64 = 1 << 62;
+const EXPIRING_VALUE_FLAG: u64 = 1 << 61;
+const COLUMN_FAMILY_FLAG: u64 = 1 << 60;
+const TS_FLAGS: u64 =
+    RANGE_TOMBSTONE_FLAG | MERGE_OPERAND_FLAG | EXPIRING_VALUE_FLAG | COLUMN_FAMILY_FLAG;
+pub(crate) const WAL_HEADER_SIZE: usize = std::mem::size_of::<u32>() * 2;
+
+fn wal_header() -> [u8; WAL_HEADER_SIZE] {
+    let mut header = [0; WAL_HEADER_SIZE];
+    header[..4].copy_from_slice(&WAL_MAGIC.to_be_bytes());
+    header[4..].copy_from_slice(&WAL_FORMAT_VERSION.to_be_bytes());
+    header
+}
+
+/// The format version in the header of a WAL, if the WAL starts with one.
+fn header_version(buf: &[u8]) -> Option<u32> {
+    let magic = buf.get(..4)?;
+    let version = buf.get(4..WAL_HEADER_SIZE)?;
+    if magic != WAL_MAGIC.to_be_bytes() {
+        return None;
+    }
+    let version = u32::from_be_bytes(version.try_into().unwrap());
+    (2..=WAL_FORMAT_VERSION)
+        .contains(&version)
+        .then_some(version)
+}
+
+type WalRecord = (Bytes, u64, Bytes);
+
+/// The entries and the range tombstones of a write batch to the column family with the id.
+pub(crate) type ColumnFamilyBatch<'a> = (
+    u32,
+    &'a [(KeySlice<'a>, ValueKind, &'a [u8])],
+    &'a [RangeTombstone],
+);
+
+/// The entries and the range tombstones of a column family recovered from a WAL. The entries are
+/// in the order they were logged, so a later entry of a key replaces an earlier one.
+#[derive(Default)]
+pub(crate) struct WalColumnFamily {
+    pub(crate) entries: Vec<(KeyBytes, ValueKind, Bytes)>,
+    pub(crate) range_tombstones: Vec<RangeTombstone>,
+}
+
+/// Split the id of the column family from the key of a version 6 record.
+fn split_column_family(mut key: Bytes) -> Result<(u32, Bytes)> {
+    let mut buf = &key[..];
+    let id = get_uvarint_len(&mut buf, "WAL column family id")?;
+    let id = u32::try_from(id).context("WAL column family id is too large")?;
+    let prefix_len = key.len() - buf.len();
+    Ok((id, key.split_off(prefix_len)))
+}
+
+/// Decode the records of a version 1 batch.
+fn decode_batch_v1(mut batch_buf: &[u8]) -> Result<Vec<WalRecord>> {
+    let mut kv_pairs = Vec::new();
+    let mut hasher = crc32fast::Hasher::new();
+    // The checksum computed from the individual components should be the same as a direct checksum on the buffer.
+    // Students' implementation only needs to do a single checksum on the buffer. We compute both for verification purpose.
+    let single_checksum = crc32fast::hash(batch_buf);
+    while batch_buf.has_remaining() {
+        ensure!(
+            batch_buf.remaining() >= std::mem::size_of::<u16>(),
+            "incomplete WAL key length"
+        );
+        let key_len = batch_buf.get_u16() as usize;
+        hasher.write(&(key_len as u16).to_be_bytes());
+        ensure!(
+            batch_buf.remaining()
+                >= key_len + std::mem::size_of::<u64>() + std::mem::size_of::<u16>(),
+            "incomplete WAL key"
+        );
+        let key = Bytes::copy_from_slice(&batch_buf[..key_len]);
+        hasher.write(&key);
+        batch_buf.advance(key_len);
+        let ts = batch_buf.get_u64();
+        hasher.write(&ts.to_be_bytes());
+        let value_len = batch_buf.get_u16() as usize;
+        hasher.write(&(value_len as u16).to_be_bytes());
+        ensure!(batch_buf.remaining() >= value_len, "incomplete WAL value");
+        let value = Bytes::copy_from_slice(&batch_buf[..value_len]);
+        hasher.write(&value);
+        kv_pairs.push((key, ts, value));
+        batch_buf.advance(value_len);
+    }
+    ensure!(
+        hasher.finalize() == single_checksum,
+        "WAL component checksum disagrees with frame checksum"
+    );
+    Ok(kv_pairs)
+}
+
+/// Decode the records of a batch of version 2 or later.
+fn decode_batch_v2(mut batch_buf: &[u8]) -> Result<Vec<WalRecord>> {
+    let mut kv_pairs = Vec::new();
+    while batch_buf.has_remaining() {
+        let key_len = get_uvarint_len(&mut batch_buf, "WAL key length")?;
+        ensure!(
+            batch_buf.remaining() >= key_len.saturating_add(std::mem::size_of::<u64>()),
+            "incomplete WAL key"
+        );
+        let key = Bytes::copy_from_slice(&batch_buf[..key_len]);
+        batch_buf.advance(key_len);
+        let ts = batch_buf.get_u64();
+        let value_len = get_uvarint_len(&mut batch_buf, "WAL value length")?;
+        ensure!(batch_buf.remaining() >= value_len, "incomplete WAL value");
+        let value = Bytes::copy_from_slice(&batch_buf[..value_len]);
+        batch_buf.advance(value_len);
+        kv_pairs.push((key, ts, value));
+    }
+    Ok(kv_pairs)
+}
 
 pub struct Wal {
     file: Arc<Mutex<BufWriter<File>>>,
+    version: u32,
+}
+
+/// Decode a frame and add its entries to `column_families` once its checksum is verified. Nothing is
+/// added if the frame is invalid.
+fn recover_frame(
+    version: u32,
+    batch_buf: &[u8],
+    expected_checksum: u32,
+    column_families: &mut HashMap<u32, WalColumnFamily>,
+) -> Result<()> {
+    let checksum = crc32fast::hash(batch_buf);
+    let kv_pairs = if version == 1 {
+        decode_batch_v1(batch_buf)?
+    } else {
+        decode_batch_v2(batch_buf)?
+    };
+    if checksum != expected_checksum {
+        bail!("checksum mismatch");
+    }
+    let mut records = Vec::with_capacity(kv_pairs.len());
+    for (key, ts, value) in kv_pairs {
+        if version >= 6 && ts & COLUMN_FAMILY_FLAG != 0 {
+            let (id, key) = split_column_family(key)?;
+            records.push((id, key, ts & !COLUMN_FAMILY_FLAG, value));
+        } else {
+            records.push((0, key, ts, value));
+        }
+    }
+    for (id, key, ts, value) in records {
+        let column_family = column_families.entry(id).or_default();
+        if version >= 3 && ts & RANGE_TOMBSTONE_FLAG != 0 {
+            column_family.range_tombstones.push(RangeTombstone::new(
+                key,
+                value,
+                ts & !RANGE_TOMBSTONE_FLAG,
+            ));
+        } else {
+            let (ts, kind) = if version >= 4 && ts & MERGE_OPERAND_FLAG != 0 {
+                (ts & !MERGE_OPERAND_FLAG, ValueKind::MergeOperand)
+            } else if version >= 5 && ts & EXPIRING_VALUE_FLAG != 0 {
+                (ts & !EXPIRING_VALUE_FLAG, ValueKind::ExpiringValue)
+            } else {
+                (ts, ValueKind::Value)
+            };
+            column_family
+                .entries
+                .push((KeyBytes::from_bytes_with_ts(key, ts), kind, value));
+        }
+    }
+    Ok(())
+}
+
+/// Decode the frames of a WAL that start at `frames_offset` into `column_families`, returning the
+/// length of the complete frames. A corrupted frame fails the recovery, unless `corrupted_frames`
+/// is given, which collects the byte offsets and the errors of the frames that are skipped.
+fn recover_frames(
+    buf: &[u8],
+    frames_offset: usize,
+    version: u32,
+    column_families: &mut HashMap<u32, WalColumnFamily>,
+    mut corrupted_frames: Option<&mut Vec<(usize, anyhow::Error)>>,
+) -> Result<usize> {
+    let mut rbuf: &[u8] = &buf[frames_offset..];
+    let mut valid_len = frames_offset;
+    while rbuf.has_remaining() {
+        if rbuf.remaining() < std::mem::size_of::<u32>() {
+            break;
+        }
+        let batch_size = rbuf.get_u32() as usize;
+        if batch_size > rbuf.remaining().saturating_sub(std::mem::size_of::<u32>()) {
+            break;
+        }
+        let batch_buf = &rbuf[..batch_size];
+        rbuf.advance(batch_size);
+        let expected_checksum = rbuf.get_u32();
+        if let Err(e) = recover_frame(version, batch_buf, expected_checksum, column_families) {
+            match corrupted_frames.as_mut() {
+                Some(corrupted_frames) => corrupted_frames.push((valid_len, e)),
+                None => return Err(e),
+            }
+        }
+        valid_len = buf.len() - rbuf.len();
+    }
+    Ok(valid_len)
 }
 
 impl Wal {
     pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = BufWriter::new(
+            OpenOptions::new()
+                .read(true)
+                .create_new(true)
+                .write(true)
+                .open(path)
+                .context("failed to create WAL")?,
+        );
+        file.write_all(&wal_header())?;
         Ok(Self {
-            file: Arc::new(Mutex::new(BufWriter::new(
-                OpenOptions::new()
-                    .read(true)
-                    .create_new(true)
-                    .write(true)
-                    .open(path)
-                    .context("failed to create WAL")?,
-            ))),
+            file: Arc::new(Mutex::new(file)),
+            version: WAL_FORMAT_VERSION,
         })
     }
 
     pub fn recover(path: impl AsRef<Path>, skiplist: &SkipMap<KeyBytes, Bytes>) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut range_tombstones = Vec::new();
+        let wal = Self::recover_with_range_tombstones(path, &mut entries, &mut range_tombstones);
+        // The frames before a corrupted one are recovered even if the recovery fails.
+        for (key, kind, value) in entries {
+            ensure!(
+                kind == ValueKind::Value,
+                "WAL has merge operands or expiring values, which must be recovered with their kinds"
+            );
+            skiplist.insert(key, value);
+        }
+        let wal = wal?;
+        ensure!(
+            range_tombstones.is_empty(),
+            "WAL has range tombstones, which must be recovered with the entries"
+        );
+        Ok(wal)
+    }
+
+    /// Recover the entries of a WAL into `entries`, in the order they were logged, and its range
+    /// tombstones into `range_tombstones`.
+    pub fn recover_with_range_tombstones(
+        path: impl AsRef<Path>,
+        entries: &mut Vec<(KeyBytes, ValueKind, Bytes)>,
+        range_tombstones: &mut Vec<RangeTombstone>,
+    ) -> Result<Self> {
+        let mut column_families = HashMap::new();
+        let wal = Self::recover_column_families(path, &mut column_families);
+        if let Some(default) = column_families.remove(&0) {
+            entries.extend(default.entries);
+            range_tombstones.extend(default.range_tombstones);
+        }
+        let wal = wal?;
+        ensure!(
+            column_families.is_empty(),
+            "WAL has entries of column families, which must be recovered with their ids"
+        );
+        Ok(wal)
+    }
+
+    /// Recover the entries and the range tombstones of a WAL into `column_families` by the ids of
+    /// their column families, where the default column family is 0.
+    pub(crate) fn recover_column_families(
+        path: impl AsRef<Path>,
+        column_families: &mut HashMap<u32, WalColumnFamily>,
+    ) -> Result<Self> {
         let path = path.as_ref();
         let mut file = OpenOptions::new()
             .read(true)
@@ -52,65 +315,21 @@ impl Wal {
             .context("failed to recover from WAL")?;
         let mut buf = Vec::new();
         file.read_to_end(&mut buf)?;
-        let mut rbuf: &[u8] = buf.as_slice();
-        let mut valid_len = 0;
-        let mut has_truncated_tail = false;
-        while rbuf.has_remaining() {
-            if rbuf.remaining() < std::mem::size_of::<u32>() {
-                has_truncated_tail = true;
-                break;
-            }
-            let batch_size = rbuf.get_u32() as usize;
-            if batch_size > rbuf.remaining().saturating_sub(std::mem::size_of::<u32>()) {
-                has_truncated_tail = true;
-                break;
-            }
-            let mut batch_buf = &rbuf[..batch_size];
-            let mut kv_pairs = Vec::new();
-            let mut hasher = crc32fast::Hasher::new();
-            // The checksum computed from the individual components should be the same as a direct checksum on the buffer.
-            // Students' implementation only needs to do a single checksum on the buffer. We compute both for verification purpose.
-            let single_checksum = crc32fast::hash(batch_buf);
-            while batch_buf.has_remaining() {
-                ensure!(
-                    batch_buf.remaining() >= std::mem::size_of::<u16>(),
-                    "incomplete WAL key length"
-                );
-                let key_len = batch_buf.get_u16() as usize;
-                hasher.write(&(key_len as u16).to_be_bytes());
-                ensure!(
-                    batch_buf.remaining()
-                        >= key_len + std::mem::size_of::<u64>() + std::mem::size_of::<u16>(),
-                    "incomplete WAL key"
-                );
-                let key = Bytes::copy_from_slice(&batch_buf[..key_len]);
-                hasher.write(&key);
-                batch_buf.advance(key_len);
-                let ts = batch_buf.get_u64();
-                hasher.write(&ts.to_be_bytes());
-                let value_len = batch_buf.get_u16() as usize;
-                hasher.write(&(value_len as u16).to_be_bytes());
-                ensure!(batch_buf.remaining() >= value_len, "incomplete WAL value");
-                let value = Bytes::copy_from_slice(&batch_buf[..value_len]);
-                hasher.write(&value);
-                kv_pairs.push((key, ts, value));
-                batch_buf.advance(value_len);
-            }
-            rbuf.advance(batch_size);
-            let expected_checksum = rbuf.get_u32();
-            let component_checksum = hasher.finalize();
-            ensure!(
-                component_checksum == single_checksum,
-                "WAL component checksum disagrees with frame checksum"
-            );
-            if single_checksum != expected_checksum {
-                bail!("checksum mismatch");
-            }
-            for (key, ts, value) in kv_pairs {
-                skiplist.insert(KeyBytes::from_bytes_with_ts(key, ts), value);
-            }
-            valid_len = buf.len() - rbuf.len();
-        }
+        let header = wal_header();
+        let (version, frames_offset) = if let Some(version) = header_version(&buf) {
+            (version, WAL_HEADER_SIZE)
+        } else if header.starts_with(&buf) {
+            // The WAL was created but its header did not reach the disk.
+            file.set_len(0).context("failed to reset WAL")?;
+            file.write_all(&header)?;
+            file.sync_all().context("failed to sync WAL header")?;
+            buf = header.to_vec();
+            (WAL_FORMAT_VERSION, WAL_HEADER_SIZE)
+        } else {
+            (1, 0)
+        };
+        let valid_len = recover_frames(&buf, frames_offset, version, column_families, None)?;
+        let has_truncated_tail = valid_len < buf.len();
         if has_truncated_tail {
             eprintln!("warning: ignoring incomplete WAL frame at byte offset {valid_len}");
             file.set_len(valid_len as u64)
@@ -120,21 +339,60 @@ impl Wal {
         }
         Ok(Self {
             file: Arc::new(Mutex::new(BufWriter::new(file))),
+            version,
         })
     }
 
+    /// Recover the entries of a WAL like [`Wal::recover_column_families`], without modifying the
+    /// WAL. The corrupted frames are skipped, and their byte offsets and errors are returned.
+    pub(crate) fn salvage_column_families(
+        path: impl AsRef<Path>,
+        column_families: &mut HashMap<u32, WalColumnFamily>,
+    ) -> Result<Vec<(usize, anyhow::Error)>> {
+        let buf = std::fs::read(path).context("failed to recover from WAL")?;
+        let (version, frames_offset) = if let Some(version) = header_version(&buf) {
+            (version, WAL_HEADER_SIZE)
+        } else if wal_header().starts_with(&buf) {
+            return Ok(Vec::new());
+        } else {
+            (1, 0)
+        };
+        let mut corrupted_frames = Vec::new();
+        recover_frames(
+            &buf,
+            frames_offset,
+            version,
+            column_families,
+            Some(&mut corrupted_frames),
+        )?;
+        Ok(corrupted_frames)
+    }
+
     /// Implement this in week 3, day 5.
     pub fn put_batch(&self, data: &[(KeySlice, &[u8])]) -> Result<()> {
-        let mut file = self.file.lock();
+        let data = data
+            .iter()
+            .map(|(key, value)| (*key, ValueKind::Value, *value))
+            .collect::<Vec<_>>();
+        self.put_batch_with_range_tombstones(&data, &[])
+    }
+
+    /// Log the entries and the range tombstones of a batch in a single frame. The entries are
+    /// values, merge operands or expiring values.
+    pub fn put_batch_with_range_tombstones(
+        &self,
+        data: &[(KeySlice, ValueKind, &[u8])],
+        range_tombstones: &[RangeTombstone],
+    ) -> Result<()> {
+        self.put_column_family_batches(&[(0, data, range_tombstones)])
+    }
+
+    /// Log the batches to several column families in a single frame, so that they are recovered
+    /// together. The default column family is 0.
+    pub(crate) fn put_column_family_batches(&self, batches: &[ColumnFamilyBatch]) -> Result<()> {
         let mut buf = Vec::<u8>::new();
-        for (key, value) in data {
-            let key_len = u16::try_from(key.key_len()).context("WAL key is too large")?;
-            let value_len = u16::try_from(value.len()).context("WAL value is too large")?;
-            buf.put_u16(key_len);
-            buf.put_slice(key.key_ref());
-            buf.put_u64(key.ts());
-            buf.put_u16(value_len);
-            buf.put_slice(value);
+        for (id, data, range_tombstones) in batches {
+            self.encode_batch(&mut buf, *id, data, range_tombstones)?;
         }
         let batch_size = u32::try_from(buf.len()).context("WAL batch is too large")?;
         let checksum = crc32fast::hash(&buf);
@@ -142,7 +400,88 @@ impl Wal {
         record.put_u32(batch_size);
         record.put_slice(&buf);
         record.put_u32(checksum);
-        file.write_all(&record)?;
+        self.file.lock().write_all(&record)?;
+        Ok(())
+    }
+
+    /// Encode the records of the entries and the range tombstones of a column family.
+    fn encode_batch(
+        &self,
+        buf: &mut Vec<u8>,
+        column_family: u32,
+        data: &[(KeySlice, ValueKind, &[u8])],
+        range_tombstones: &[RangeTombstone],
+    ) -> Result<()> {
+        ensure!(
+            self.version >= 3 || range_tombstones.is_empty(),
+            "version {} WAL cannot log range tombstones",
+            self.version
+        );
+        ensure!(
+            self.version >= 6 || column_family == 0,
+            "version {} WAL cannot log column families",
+            self.version
+        );
+        // The records of the default column family are the same as before column families.
+        let (prefix, family_flag) = if column_family == 0 {
+            (Vec::new(), 0)
+        } else {
+            let mut prefix = Vec::new();
+            put_uvarint(&mut prefix, column_family as u64);
+            (prefix, COLUMN_FAMILY_FLAG)
+        };
+        for (key, kind, value) in data {
+            let ts = match kind {
+                ValueKind::Value => key.ts(),
+                ValueKind::MergeOperand => {
+                    ensure!(
+                        self.version >= 4,
+                        "version {} WAL cannot log merge operands",
+                        self.version
+                    );
+                    key.ts() | MERGE_OPERAND_FLAG
+                }
+                ValueKind::ExpiringValue => {
+                    ensure!(
+                        self.version >= 5,
+                        "version {} WAL cannot log expiring values",
+                        self.version
+                    );
+                    key.ts() | EXPIRING_VALUE_FLAG
+                }
+                ValueKind::ValuePointer => bail!("value pointers are not logged in the WAL"),
+            };
+            ensure!(key.ts() & TS_FLAGS == 0, "WAL entry timestamp is too large");
+            let ts = ts | family_flag;
+            if self.version == 1 {
+                let key_len = u16::try_from(key.key_len()).context("WAL key is too large")?;
+                let value_len = u16::try_from(value.len()).context("WAL value is too large")?;
+                buf.put_u16(key_len);
+                buf.put_slice(key.key_ref());
+                buf.put_u64(ts);
+                buf.put_u16(value_len);
+                buf.put_slice(value);
+            } else {
+                put_uvarint(buf, (prefix.len() + key.key_len()) as u64);
+                buf.put_slice(&prefix);
+                buf.put_slice(key.key_ref());
+                buf.put_u64(ts);
+                put_uvarint(buf, value.len() as u64);
+                buf.put_slice(value);
+            }
+        }
+        for tombstone in range_tombstones {
+            ensure!(
+                tombstone.ts & TS_FLAGS == 0,
+                "range tombstone timestamp is too large"
+            );
+            put_uvarint(buf, (prefix.len() + tombstone.start.len()) as u64);
+            buf.put_slice(&prefix);
+            buf.put_slice(&tombstone.start);
+            buf.put_u64(tombstone.ts | RANGE_TOMBSTONE_FLAG | family_flag);
+            put_uvarint(buf, tombstone.end.len() as u64);
+            buf.put_slice(&tombstone.end);
+        }
         Ok(())
     }
 
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod options;
 mod wrapper;
 
 use rustyline::DefaultEditor;
@@ -19,35 +20,17 @@ use wrapper::mini_lsm_wrapper;
 
 use anyhow::Result;
 use bytes::Bytes;
-use clap::{Parser, ValueEnum};
-use mini_lsm_wrapper::compact::{
-    CompactionOptions, LeveledCompactionOptions, SimpleLeveledCompactionOptions,
-    TieredCompactionOptions,
-};
+use clap::Parser;
 use mini_lsm_wrapper::iterators::StorageIterator;
-use mini_lsm_wrapper::lsm_storage::{LsmStorageOptions, MiniLsm};
-use std::path::PathBuf;
+use mini_lsm_wrapper::lsm_storage::MiniLsm;
+use options::StorageArgs;
 use std::sync::Arc;
 
-#[derive(Debug, Clone, ValueEnum)]
-enum CompactionStrategy {
-    Simple,
-    Leveled,
-    Tiered,
-    None,
-}
-
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    #[arg(long, default_value = "lsm.db")]
-    path: PathBuf,
-    #[arg(long, default_value = "leveled")]
-    compaction: CompactionStrategy,
-    #[arg(long)]
-    enable_wal: bool,
-    #[arg(long)]
-    serializable: bool,
+    #[command(flatten)]
+    storage: StorageArgs,
 }
 
 struct ReplHandler {
@@ -311,41 +294,7 @@ impl ReplBuilder {
 
 fn main() -> Result<()> {
     let args = Args::parse();
-    let lsm = MiniLsm::open(
-        args.path,
-        LsmStorageOptions {
-            block_size: 4096,
-            target_sst_size: 2 << 20, // 2MB
-            num_memtable_limit: 3,
-            compaction_options: match args.compaction {
-                CompactionStrategy::None => CompactionOptions::NoCompaction,
-                CompactionStrategy::Simple => {
-                    CompactionOptions::Simple(SimpleLeveledCompactionOptions {
-                        size_ratio_percent: 200,
-                        level0_file_num_compaction_trigger: 2,
-                        max_levels: 4,
-                    })
-                }
-                CompactionStrategy::Tiered => CompactionOptions::Tiered(TieredCompactionOptions {
-                    num_tiers: 3,
-                    max_size_amplification_percent: 200,
-                    size_ratio: 1,
-                    min_merge_width: 2,
-                    max_merge_width: None,
-                }),
-                CompactionStrategy::Leveled => {
-                    CompactionOptions::Leveled(LeveledCompactionOptions {
-                        level0_file_num_compaction_trigger: 2,
-                        max_levels: 4,
-                        base_level_size_mb: 128,
-                        level_size_multiplier: 2,
-                    })
-                }
-            },
-            enable_wal: args.enable_wal,
-            serializable: args.serializable,
-        },
-    )?;
+    let lsm = MiniLsm::open(&args.storage.path, args.storage.to_options()?)?;
 
     let repl = ReplBuilder::new()
         .app_name("mini-lsm-cli")
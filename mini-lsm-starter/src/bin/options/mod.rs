@@ -0,0 +1,97 @@
+// Copyright (c) 2022-2026 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The command line options of the binaries that open a database.
+
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+
+use anyhow::Result;
+
+use crate::wrapper::mini_lsm_wrapper::compact::{
+    CompactionOptions, LeveledCompactionOptions, SimpleLeveledCompactionOptions,
+    TieredCompactionOptions,
+};
+use crate::wrapper::mini_lsm_wrapper::lsm_storage::LsmStorageOptions;
+use crate::wrapper::{default_options, set_compression};
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum CompactionStrategy {
+    Simple,
+    Leveled,
+    Tiered,
+    None,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Compression {
+    None,
+    Lz4,
+    Snappy,
+    Zstd,
+}
+
+#[derive(Args, Debug)]
+pub struct StorageArgs {
+    #[arg(long, default_value = "lsm.db")]
+    pub path: PathBuf,
+    #[arg(long, default_value = "leveled")]
+    pub compaction: CompactionStrategy,
+    #[arg(long)]
+    pub enable_wal: bool,
+    #[arg(long)]
+    pub serializable: bool,
+    #[arg(long, default_value = "none")]
+    pub compression: Compression,
+}
+
+impl StorageArgs {
+    /// The options of the database, which are the defaults of the crate with the ones given on
+    /// the command line.
+    pub fn to_options(&self) -> Result<LsmStorageOptions> {
+        let mut options = default_options();
+        options.block_size = 4096;
+        options.target_sst_size = 2 << 20; // 2MB
+        options.num_memtable_limit = 3;
+        options.compaction_options = match self.compaction {
+            CompactionStrategy::None => CompactionOptions::NoCompaction,
+            CompactionStrategy::Simple => {
+                CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+                    size_ratio_percent: 200,
+                    level0_file_num_compaction_trigger: 2,
+                    max_levels: 4,
+                })
+            }
+            CompactionStrategy::Tiered => CompactionOptions::Tiered(TieredCompactionOptions {
+                num_tiers: 3,
+                max_size_amplification_percent: 200,
+                size_ratio: 1,
+                min_merge_width: 2,
+                max_merge_width: None,
+            }),
+            CompactionStrategy::Leveled => CompactionOptions::Leveled(LeveledCompactionOptions {
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 4,
+                base_level_size_mb: 128,
+                level_size_multiplier: 2,
+            }),
+        };
+        options.enable_wal = self.enable_wal;
+        options.serializable = self.serializable;
+        let compression = self.compression.to_possible_value().unwrap();
+        set_compression(&mut options, compression.get_name())?;
+        Ok(options)
+    }
+}
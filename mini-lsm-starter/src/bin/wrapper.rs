@@ -16,5 +16,25 @@ pub mod mini_lsm_wrapper {
     pub use mini_lsm_starter::*;
 }
 
+/// The options that the binaries open a database with, before the command line options apply.
+#[allow(dead_code)]
+pub fn default_options() -> mini_lsm_wrapper::lsm_storage::LsmStorageOptions {
+    mini_lsm_wrapper::lsm_storage::LsmStorageOptions::default_for_week1_test()
+}
+
+/// Set the block compression named on the command line. The blocks of this crate are never
+/// compressed.
+#[allow(dead_code)]
+pub fn set_compression(
+    _options: &mut mini_lsm_wrapper::lsm_storage::LsmStorageOptions,
+    compression: &str,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        compression == "none",
+        "block compression is not supported by this crate"
+    );
+    Ok(())
+}
+
 #[allow(dead_code)]
 fn main() {}
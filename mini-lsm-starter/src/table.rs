@@ -23,7 +23,7 @@ use std::fs::File;
 use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 pub use builder::SsTableBuilder;
 use bytes::Buf;
 pub use iterator::SsTableIterator;
@@ -167,6 +167,14 @@ impl SsTable {
         self.block_meta.len()
     }
 
+    /// Get the metadata of a data block.
+    pub fn block_meta(&self, block_idx: usize) -> Result<BlockMeta> {
+        self.block_meta
+            .get(block_idx)
+            .cloned()
+            .context("SST block index is out of bounds")
+    }
+
     pub fn first_key(&self) -> &KeyBytes {
         &self.first_key
     }
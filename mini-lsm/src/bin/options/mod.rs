@@ -0,0 +1 @@
+../../../../mini-lsm-starter/src/bin/options/mod.rs
\ No newline at end of file
@@ -354,6 +354,14 @@ impl SsTable {
         self.block_meta.len()
     }
 
+    /// Get the metadata of a data block.
+    pub fn block_meta(&self, block_idx: usize) -> Result<BlockMeta> {
+        self.block_meta
+            .get(block_idx)
+            .cloned()
+            .context("SST block index is out of bounds")
+    }
+
     pub fn first_key(&self) -> &KeyBytes {
         &self.first_key
     }
@@ -83,7 +83,8 @@ fn test_sst_block_metadata_matches_block_contents() {
     let (_dir, sst) = generate_sst();
     assert!(sst.num_of_blocks() > 1);
 
-    for (block_idx, meta) in sst.block_meta.iter().enumerate() {
+    for block_idx in 0..sst.num_of_blocks() {
+        let meta = sst.block_meta(block_idx).unwrap();
         let mut iter = BlockIterator::create_and_seek_to_first(sst.read_block(block_idx).unwrap());
         let first_key = Bytes::copy_from_slice(iter.key().for_testing_key_ref());
         let mut last_key = first_key.clone();
@@ -100,9 +101,14 @@ fn test_sst_block_metadata_matches_block_contents() {
 #[test]
 fn test_sst_decode() {
     let (_dir, sst) = generate_sst();
-    let meta = sst.block_meta.clone();
+    let meta = (0..sst.num_of_blocks())
+        .map(|idx| sst.block_meta(idx).unwrap())
+        .collect::<Vec<_>>();
     let new_sst = SsTable::open_for_test(sst.file).unwrap();
-    assert_eq!(new_sst.block_meta, meta);
+    assert_eq!(new_sst.num_of_blocks(), meta.len());
+    for (idx, meta) in meta.iter().enumerate() {
+        assert_eq!(&new_sst.block_meta(idx).unwrap(), meta);
+    }
     assert_eq!(
         new_sst.first_key().for_testing_key_ref(),
         key_of(0).for_testing_key_ref()
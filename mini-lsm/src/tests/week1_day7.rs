@@ -91,15 +91,15 @@ fn test_task3_block_key_compression() {
     let sst = builder.build_for_test(path).unwrap();
     if TS_ENABLED {
         assert!(
-            sst.block_meta.len() <= 34,
+            sst.num_of_blocks() <= 34,
             "you have {} blocks, expect 34",
-            sst.block_meta.len()
+            sst.num_of_blocks()
         );
     } else {
         assert!(
-            sst.block_meta.len() <= 25,
+            sst.num_of_blocks() <= 25,
             "you have {} blocks, expect 25",
-            sst.block_meta.len()
+            sst.num_of_blocks()
         );
     }
 }